@@ -900,6 +900,7 @@ async fn run_list_custom_agents(cmd: AgentsCommand) -> anyhow::Result<()> {
                         .map(|m| match m {
                             codex_protocol::protocol::SubagentMode::Explore => "explore",
                             codex_protocol::protocol::SubagentMode::General => "general",
+                            codex_protocol::protocol::SubagentMode::Review => "review",
                         })
                         .unwrap_or("-");
                     let model = agent.model.as_deref().unwrap_or("-");
@@ -907,11 +908,18 @@ async fn run_list_custom_agents(cmd: AgentsCommand) -> anyhow::Result<()> {
                         codex_protocol::protocol::CustomAgentToolsPolicy::Inherit => "inherit",
                         codex_protocol::protocol::CustomAgentToolsPolicy::None => "none",
                         codex_protocol::protocol::CustomAgentToolsPolicy::Allowlist => "allowlist",
+                        codex_protocol::protocol::CustomAgentToolsPolicy::Denylist => "denylist",
                     };
-                    let allowed = if agent.allowed_tools.is_empty() {
+                    let listed_tools = match agent.tools_policy {
+                        codex_protocol::protocol::CustomAgentToolsPolicy::Denylist => {
+                            &agent.denied_tools
+                        }
+                        _ => &agent.allowed_tools,
+                    };
+                    let allowed = if listed_tools.is_empty() {
                         "-".to_string()
                     } else {
-                        agent.allowed_tools.join(",")
+                        listed_tools.join(",")
                     };
                     let description = agent.description.unwrap_or_default();
                     println!(
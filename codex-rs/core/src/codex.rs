@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::PathBuf;
@@ -60,6 +61,7 @@ use serde_json::Value;
 use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 use tracing::debug;
@@ -164,6 +166,9 @@ pub struct Codex {
     pub(crate) next_id: AtomicU64,
     pub(crate) tx_sub: Sender<Submission>,
     pub(crate) rx_event: Receiver<Event>,
+    /// Task running the session's submission loop, when this handle spawned
+    /// it. Taken by [`Codex::session_panic`].
+    pub(crate) session_task: std::sync::Mutex<Option<JoinHandle<()>>>,
 }
 
 /// Wrapper returned by [`Codex::spawn`] containing the spawned [`Codex`],
@@ -216,6 +221,12 @@ impl Codex {
         let (tx_event, rx_event) = async_channel::unbounded();
 
         crate::subagents::init_global_subagent_limiter(config.subagents.max_concurrency);
+        crate::subagents::init_global_delegate_limiter(
+            config
+                .subagents
+                .delegate_concurrency
+                .or(config.subagents.max_concurrency),
+        );
 
         let loaded_skills = if config.features.enabled(Feature::Skills) {
             Some(skills_manager.skills_for_cwd(&config.cwd))
@@ -291,11 +302,12 @@ impl Codex {
         let conversation_id = session.conversation_id;
 
         // This task will run until Op::Shutdown is received.
-        tokio::spawn(submission_loop(session, config, rx_sub));
+        let session_task = tokio::spawn(submission_loop(session, config, rx_sub));
         let codex = Codex {
             next_id: AtomicU64::new(0),
             tx_sub,
             rx_event,
+            session_task: std::sync::Mutex::new(Some(session_task)),
         };
 
         Ok(CodexSpawnOk {
@@ -333,6 +345,17 @@ impl Codex {
             .map_err(|_| CodexErr::InternalAgentDied)?;
         Ok(event)
     }
+
+    /// Waits for the session task to end and returns its panic payload if it
+    /// panicked. Returns `None` when the task ended normally, was already
+    /// reaped, or was not spawned by this handle.
+    pub(crate) async fn session_panic(&self) -> Option<Box<dyn Any + Send>> {
+        let task = self.session_task.lock().ok()?.take()?;
+        match task.await {
+            Err(err) if err.is_panic() => Some(err.into_panic()),
+            _ => None,
+        }
+    }
 }
 
 /// Context for an initialized model agent
@@ -525,6 +548,7 @@ impl Session {
             model_family: &model_family,
             features: &per_turn_config.features,
             tool_name_allowlist: per_turn_config.tool_name_allowlist.as_deref(),
+            tool_name_denylist: per_turn_config.tool_name_denylist.as_deref(),
         });
 
         TurnContext {
@@ -596,7 +620,15 @@ impl Session {
         // - initialize RolloutRecorder with new or resumed session info
         // - perform default shell discovery
         // - load history metadata
-        let rollout_fut = RolloutRecorder::new(&config, rollout_params);
+        let rollout_fut = async {
+            if config.record_rollout {
+                RolloutRecorder::new(&config, rollout_params)
+                    .await
+                    .map(Some)
+            } else {
+                Ok(None)
+            }
+        };
 
         let history_meta_fut = crate::message_history::history_metadata(&config);
         let auth_statuses_fut = compute_auth_statuses(
@@ -612,7 +644,12 @@ impl Session {
             error!("failed to initialize rollout recorder: {e:#}");
             anyhow::Error::from(e)
         })?;
-        let rollout_path = rollout_recorder.rollout_path.clone();
+        // Without a recorder, `SessionConfigured` carries an empty path; see
+        // `SessionConfiguredEvent::rollout_path`.
+        let rollout_path = rollout_recorder
+            .as_ref()
+            .map(|recorder| recorder.rollout_path.clone())
+            .unwrap_or_default();
 
         let mut post_session_configured_events = Vec::<Event>::new();
 
@@ -673,7 +710,7 @@ impl Session {
             mcp_startup_cancellation_token: CancellationToken::new(),
             unified_exec_manager: UnifiedExecSessionManager::default(),
             notifier: UserNotifier::new(config.notify.clone()),
-            rollout: Mutex::new(Some(rollout_recorder)),
+            rollout: Mutex::new(rollout_recorder),
             user_shell: Arc::new(default_shell),
             show_raw_agent_reasoning: config.show_raw_agent_reasoning,
             auth_manager: Arc::clone(&auth_manager),
@@ -681,7 +718,9 @@ impl Session {
             models_manager: Arc::clone(&models_manager),
             tool_approvals: Mutex::new(ApprovalStore::default()),
             skills_manager,
-            subagent_manager: Arc::new(crate::subagents::SubagentManager::default()),
+            subagent_manager: Arc::new(crate::subagents::SubagentManager::new(
+                config.subagents.max_concurrency,
+            )),
         };
 
         let sess = Arc::new(Session {
@@ -817,6 +856,19 @@ impl Session {
                     }
                 }
 
+                // Bring back subagents spawned before the session was restarted.
+                if let InitialHistory::Resumed(_) = conversation_history
+                    && let Some(registry) = rollout_items.iter().rev().find_map(|it| match it {
+                        RolloutItem::SubagentRegistry(registry) => Some(registry),
+                        _ => None,
+                    })
+                {
+                    self.services
+                        .subagent_manager
+                        .restore(registry, &turn_context.client.config().subagents)
+                        .await;
+                }
+
                 // Always add response items to conversation history
                 let reconstructed_history =
                     self.reconstruct_history_from_rollout(&turn_context, &rollout_items);
@@ -2143,33 +2195,20 @@ mod handlers {
                 .await;
             let parent_config = parent_turn.client.config().as_ref().clone();
 
-            let outcome = crate::custom_agents::discover_agents(&parent_config).await;
-            let agent = outcome
-                .agents
-                .iter()
-                .find(|agent| agent.name == agent_name)
-                .cloned();
-            let Some(agent) = agent else {
-                let known = outcome
-                    .agents
-                    .iter()
-                    .map(|agent| agent.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let message = if known.is_empty() {
-                    format!("unknown agent `{agent_name}` (no agents discovered)")
-                } else {
-                    format!("unknown agent `{agent_name}` (available: {known})")
-                };
-                let event = Event {
-                    id: sub_id,
-                    msg: EventMsg::Error(ErrorEvent {
-                        message,
-                        codex_error_info: Some(CodexErrorInfo::Other),
-                    }),
-                };
-                parent_session.send_event_raw(event).await;
-                return;
+            let agent = crate::custom_agents::find_agent(&parent_config, &agent_name).await;
+            let agent = match agent {
+                Ok(agent) => agent,
+                Err(message) => {
+                    let event = Event {
+                        id: sub_id,
+                        msg: EventMsg::Error(ErrorEvent {
+                            message,
+                            codex_error_info: Some(CodexErrorInfo::Other),
+                        }),
+                    };
+                    parent_session.send_event_raw(event).await;
+                    return;
+                }
             };
 
             let auth_manager = parent_session.services.auth_manager.clone();
@@ -2179,27 +2218,22 @@ mod handlers {
 
             let agent_label = agent.name.clone();
             let mut subagent_config = parent_config.clone();
-            if let Some(model) = agent.model.as_ref() {
-                subagent_config.model = Some(model.clone());
-            }
-            subagent_config.tool_name_allowlist =
-                super::agent_tools_policy_to_allowlist(&agent.tools);
-
-            let agent_prompt = agent.prompt.trim();
-            if !agent_prompt.is_empty() {
-                subagent_config.developer_instructions =
-                    Some(match subagent_config.developer_instructions.take() {
-                        Some(existing) => {
-                            format!("{existing}\n\n# Custom agent: {agent_label}\n\n{agent_prompt}")
-                        }
-                        None => format!("# Custom agent: {agent_label}\n\n{agent_prompt}"),
-                    });
+            agent.apply_to_config(&mut subagent_config);
+            let mode = agent.mode_or_default();
+            let available = skills_manager.skills_for_cwd(&parent_turn.cwd);
+            if let Err(message) = agent.check_skills(&available.skills) {
+                let event = Event {
+                    id: sub_id,
+                    msg: EventMsg::Error(ErrorEvent {
+                        message,
+                        codex_error_info: Some(CodexErrorInfo::Other),
+                    }),
+                };
+                parent_session.send_event_raw(event).await;
+                return;
             }
 
-            let mode = agent
-                .mode
-                .unwrap_or(crate::subagents::SubagentMode::Explore);
-
+            let timeout_ms = timeout_ms.or(agent.timeout_ms);
             let resp = subagent_manager
                 .spawn_one_shot(
                     crate::subagents::SubagentSpawnRequest {
@@ -2207,9 +2241,15 @@ mod handlers {
                         mode,
                         label: agent.name.clone(),
                         prompt: task,
-                        skills: Vec::new(),
+                        skills: agent.merged_skills(Vec::new()),
                         timeout_ms,
                         resume_rollout_path: None,
+                        max_retries: agent.max_retries,
+                        retry_backoff_ms: agent.retry_backoff_ms,
+                        max_per_label: agent.max_concurrency,
+                        tools_policy: agent.tools.clone(),
+                        model: agent.model.clone(),
+                        ..Default::default()
                     },
                     Arc::clone(&parent_session),
                     Arc::clone(&parent_turn),
@@ -2298,7 +2338,12 @@ mod handlers {
                 .final_output
                 .map(|text| super::cap_string(text, max_output_chars))
                 .or_else(|| {
-                    let joined = poll.recent_events.join("\n");
+                    let joined = poll
+                        .recent_events
+                        .iter()
+                        .map(|event| event.message.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n");
                     if joined.trim().is_empty() {
                         None
                     } else {
@@ -2321,7 +2366,7 @@ mod handlers {
 
     pub async fn list_subagents(sess: &Session, sub_id: String) {
         let enabled = sess.enabled(Feature::Subagents);
-        let agents = sess.services.subagent_manager.list().await;
+        let agents = sess.services.subagent_manager.list(None).await;
         let subagents: Vec<SubagentSummary> =
             agents.iter().map(super::subagent_poll_to_summary).collect();
         let event = Event {
@@ -2365,7 +2410,7 @@ mod handlers {
         if sess
             .services
             .subagent_manager
-            .cancel(&agent_id)
+            .cancel(&agent_id, Some("cancelled by the user"))
             .await
             .is_none()
         {
@@ -2472,6 +2517,7 @@ mod handlers {
                             skills: Vec::new(),
                             timeout_ms: Some(orchestration_timeout_ms),
                             resume_rollout_path: None,
+                            ..Default::default()
                         },
                         Arc::clone(&parent_session),
                         Arc::clone(&parent_turn),
@@ -2548,7 +2594,9 @@ mod handlers {
                     Some(crate::subagents::SubagentStatus::Queued)
                         | Some(crate::subagents::SubagentStatus::Running)
                 ) {
-                    let _ = subagent_manager.cancel(&agent_id).await;
+                    let _ = subagent_manager
+                        .cancel(&agent_id, Some("orchestration timeout reached"))
+                        .await;
                 }
 
                 out = codex_utils_string::take_bytes_at_char_boundary(&out, max_output_chars)
@@ -2680,6 +2728,7 @@ mod handlers {
                             skills: Vec::new(),
                             timeout_ms: Some(orchestration_timeout_ms),
                             resume_rollout_path: None,
+                            ..Default::default()
                         },
                         Arc::clone(&parent_session),
                         Arc::clone(&parent_turn),
@@ -2756,7 +2805,9 @@ mod handlers {
                     Some(crate::subagents::SubagentStatus::Queued)
                         | Some(crate::subagents::SubagentStatus::Running)
                 ) {
-                    let _ = subagent_manager.cancel(&agent_id).await;
+                    let _ = subagent_manager
+                        .cancel(&agent_id, Some("orchestration timeout reached"))
+                        .await;
                 }
 
                 out = codex_utils_string::take_bytes_at_char_boundary(&out, max_output_chars)
@@ -2918,6 +2969,7 @@ async fn spawn_review_thread(
         model_family: &review_model_family,
         features: &review_features,
         tool_name_allowlist: config.tool_name_allowlist.as_deref(),
+        tool_name_denylist: config.tool_name_denylist.as_deref(),
     });
 
     let base_instructions = REVIEW_PROMPT.to_string();
@@ -3020,39 +3072,38 @@ fn sanitize_custom_agent_query(raw: &str) -> Option<String> {
     if out.is_empty() { None } else { Some(out) }
 }
 
-fn agent_tools_policy_to_allowlist(
-    policy: &crate::custom_agents::AgentToolsPolicy,
-) -> Option<Vec<String>> {
-    match policy {
-        crate::custom_agents::AgentToolsPolicy::Inherit => None,
-        crate::custom_agents::AgentToolsPolicy::None => Some(Vec::new()),
-        crate::custom_agents::AgentToolsPolicy::Allowlist(names) => Some(names.clone()),
-    }
-}
-
 fn custom_agents_to_info(
     agents: &[crate::custom_agents::CustomAgent],
 ) -> Vec<codex_protocol::protocol::CustomAgentMetadata> {
     agents
         .iter()
         .map(|agent| {
-            let (tools_policy, allowed_tools) = match &agent.tools {
+            let (tools_policy, allowed_tools, denied_tools) = match &agent.tools {
                 crate::custom_agents::AgentToolsPolicy::Inherit => (
                     codex_protocol::protocol::CustomAgentToolsPolicy::Inherit,
                     Vec::new(),
+                    Vec::new(),
                 ),
                 crate::custom_agents::AgentToolsPolicy::None => (
                     codex_protocol::protocol::CustomAgentToolsPolicy::None,
                     Vec::new(),
+                    Vec::new(),
                 ),
                 crate::custom_agents::AgentToolsPolicy::Allowlist(names) => (
                     codex_protocol::protocol::CustomAgentToolsPolicy::Allowlist,
                     names.clone(),
+                    Vec::new(),
+                ),
+                crate::custom_agents::AgentToolsPolicy::Denylist(names) => (
+                    codex_protocol::protocol::CustomAgentToolsPolicy::Denylist,
+                    Vec::new(),
+                    names.clone(),
                 ),
             };
 
             let scope = match agent.scope {
-                crate::custom_agents::AgentScope::User => {
+                crate::custom_agents::AgentScope::User
+                | crate::custom_agents::AgentScope::Extra => {
                     codex_protocol::protocol::CustomAgentScope::User
                 }
                 crate::custom_agents::AgentScope::Repo => {
@@ -3069,6 +3120,9 @@ fn custom_agents_to_info(
                 mode: agent.mode.map(subagent_mode_to_info),
                 tools_policy,
                 allowed_tools,
+                denied_tools,
+                color: agent.color.clone(),
+                icon: agent.icon.clone(),
             }
         })
         .collect()
@@ -3099,6 +3153,7 @@ fn subagent_mode_to_info(
     match mode {
         crate::subagents::SubagentMode::Explore => codex_protocol::protocol::SubagentMode::Explore,
         crate::subagents::SubagentMode::General => codex_protocol::protocol::SubagentMode::General,
+        crate::subagents::SubagentMode::Review => codex_protocol::protocol::SubagentMode::Review,
     }
 }
 
@@ -3143,7 +3198,11 @@ fn subagent_poll_to_detail(
         mode: subagent_mode_to_info(poll.mode),
         rollout_path: poll.rollout_path,
         final_output: poll.final_output,
-        recent_events: poll.recent_events,
+        recent_events: poll
+            .recent_events
+            .into_iter()
+            .map(|event| event.message)
+            .collect(),
     }
 }
 
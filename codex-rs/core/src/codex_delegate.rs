@@ -88,6 +88,7 @@ pub(crate) async fn run_codex_conversation_interactive(
         next_id: AtomicU64::new(0),
         tx_sub: tx_ops,
         rx_event: rx_sub,
+        session_task: std::sync::Mutex::new(None),
     })
 }
 
@@ -158,6 +159,7 @@ pub(crate) async fn run_codex_conversation_one_shot(
         next_id: AtomicU64::new(0),
         rx_event: rx_bridge,
         tx_sub: tx_closed,
+        session_task: std::sync::Mutex::new(None),
     })
 }
 
@@ -368,6 +370,7 @@ mod tests {
             next_id: AtomicU64::new(0),
             tx_sub,
             rx_event: rx_events,
+            session_task: std::sync::Mutex::new(None),
         });
 
         let (session, ctx, _rx_evt) = crate::codex::make_session_and_context_with_rx();
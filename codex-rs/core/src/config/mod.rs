@@ -5,8 +5,13 @@ use crate::config::types::DEFAULT_SUBAGENTS_MAX_EVENT_CHARS;
 use crate::config::types::DEFAULT_SUBAGENTS_MAX_EVENTS;
 use crate::config::types::DEFAULT_SUBAGENTS_MAX_OUTPUT_CHARS;
 use crate::config::types::DEFAULT_SUBAGENTS_ORCHESTRATION_TIMEOUT_MS;
+use crate::config::types::DEFAULT_SUBAGENTS_SPAWN_RETRIES;
 use crate::config::types::DEFAULT_SUBAGENTS_TIMEOUT_MS;
 use crate::config::types::History;
+use crate::config::types::MAX_SUBAGENTS_MAX_EVENTS;
+use crate::config::types::MAX_SUBAGENTS_MAX_OUTPUT_CHARS;
+use crate::config::types::MIN_SUBAGENTS_MAX_EVENTS;
+use crate::config::types::MIN_SUBAGENTS_MAX_OUTPUT_CHARS;
 use crate::config::types::McpServerConfig;
 use crate::config::types::Notice;
 use crate::config::types::Notifications;
@@ -288,6 +293,15 @@ pub struct Config {
     /// enforce a narrower tool surface than the parent session.
     pub(crate) tool_name_allowlist: Option<Vec<String>>,
 
+    /// Optional denylist of tools hidden from the model, applied after the
+    /// allowlist. Same matching rules as `tool_name_allowlist`.
+    pub(crate) tool_name_denylist: Option<Vec<String>>,
+
+    /// Whether the session writes a rollout file. Internal flows (e.g.
+    /// ephemeral subagents) turn this off to avoid disk churn; the session
+    /// then cannot be resumed.
+    pub(crate) record_rollout: bool,
+
     /// Subagent orchestration and budgeting settings.
     pub subagents: SubagentsConfig,
 
@@ -1177,6 +1191,9 @@ impl Config {
 
             SubagentsConfig {
                 max_concurrency: toml.and_then(|t| t.max_concurrency).map(|v| v.clamp(1, 64)),
+                delegate_concurrency: toml
+                    .and_then(|t| t.delegate_concurrency)
+                    .map(|v| v.clamp(1, 64)),
                 max_agents: toml
                     .and_then(|t| t.max_agents)
                     .unwrap_or(DEFAULT_SUBAGENTS_MAX_AGENTS)
@@ -1200,7 +1217,7 @@ impl Config {
                 max_events: toml
                     .and_then(|t| t.max_events)
                     .unwrap_or(DEFAULT_SUBAGENTS_MAX_EVENTS)
-                    .clamp(1, 1024),
+                    .clamp(MIN_SUBAGENTS_MAX_EVENTS, MAX_SUBAGENTS_MAX_EVENTS),
                 max_event_chars: toml
                     .and_then(|t| t.max_event_chars)
                     .unwrap_or(DEFAULT_SUBAGENTS_MAX_EVENT_CHARS)
@@ -1208,7 +1225,47 @@ impl Config {
                 max_output_chars: toml
                     .and_then(|t| t.max_output_chars)
                     .unwrap_or(DEFAULT_SUBAGENTS_MAX_OUTPUT_CHARS)
-                    .clamp(1024, 1024 * 1024),
+                    .clamp(
+                        MIN_SUBAGENTS_MAX_OUTPUT_CHARS,
+                        MAX_SUBAGENTS_MAX_OUTPUT_CHARS,
+                    ),
+                cooperative_budget: toml
+                    .and_then(|t| t.cooperative_budget)
+                    .filter(|budget| *budget > 0),
+                max_skill_bytes: toml
+                    .and_then(|t| t.max_skill_bytes)
+                    .filter(|bytes| *bytes > 0),
+                join_messages: toml.and_then(|t| t.join_messages).unwrap_or(false),
+                share_approvals: toml.and_then(|t| t.share_approvals).unwrap_or(false),
+                total_event_memory_bytes: toml
+                    .and_then(|t| t.total_event_memory_bytes)
+                    .filter(|bytes| *bytes > 0),
+                extra_agent_dirs: toml
+                    .and_then(|t| t.extra_agent_dirs.as_ref())
+                    .map(|dirs| dirs.iter().map(AbsolutePathBuf::to_path_buf).collect())
+                    .unwrap_or_default(),
+                resume_model_policy: toml.and_then(|t| t.resume_model_policy).unwrap_or_default(),
+                output_postprocess: toml.and_then(|t| t.output_postprocess).unwrap_or_default(),
+                overflow: toml.and_then(|t| t.overflow).unwrap_or_default(),
+                spawn_retries: toml
+                    .and_then(|t| t.spawn_retries)
+                    .unwrap_or(DEFAULT_SUBAGENTS_SPAWN_RETRIES)
+                    .min(10),
+                resume_known_rollouts_only: toml
+                    .and_then(|t| t.resume_known_rollouts_only)
+                    .unwrap_or(false),
+                explore_auto_deny: toml.and_then(|t| t.explore_auto_deny).unwrap_or(true),
+                retain_terminated: toml.and_then(|t| t.retain_terminated),
+                retain_ttl: toml
+                    .and_then(|t| t.retain_ttl_ms)
+                    .map(std::time::Duration::from_millis),
+                isolated_workspace: toml.and_then(|t| t.isolated_workspace).unwrap_or(false),
+                idle_timeout: toml
+                    .and_then(|t| t.idle_timeout_ms)
+                    .filter(|ms| *ms > 0)
+                    .map(std::time::Duration::from_millis),
+                dedupe_spawns: toml.and_then(|t| t.dedupe_spawns).unwrap_or(false),
+                require_unique_labels: toml.and_then(|t| t.require_unique_labels).unwrap_or(false),
             }
         };
 
@@ -1286,6 +1343,8 @@ impl Config {
             ghost_snapshot,
             features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
+            record_rollout: true,
             subagents,
             active_profile: active_profile_name,
             active_project,
@@ -1421,6 +1480,9 @@ mod tests {
     use crate::config::types::HistoryPersistence;
     use crate::config::types::McpServerTransportConfig;
     use crate::config::types::Notifications;
+    use crate::config::types::OutputOverflow;
+    use crate::config::types::OutputPostprocess;
+    use crate::config::types::ResumeModelPolicy;
     use crate::features::Feature;
 
     use super::*;
@@ -1433,6 +1495,7 @@ mod tests {
     fn default_subagents_config() -> SubagentsConfig {
         SubagentsConfig {
             max_concurrency: None,
+            delegate_concurrency: None,
             max_agents: DEFAULT_SUBAGENTS_MAX_AGENTS,
             default_timeout: Duration::from_millis(DEFAULT_SUBAGENTS_TIMEOUT_MS),
             orchestration_timeout: Duration::from_millis(
@@ -1441,6 +1504,24 @@ mod tests {
             max_events: DEFAULT_SUBAGENTS_MAX_EVENTS,
             max_event_chars: DEFAULT_SUBAGENTS_MAX_EVENT_CHARS,
             max_output_chars: DEFAULT_SUBAGENTS_MAX_OUTPUT_CHARS,
+            cooperative_budget: None,
+            max_skill_bytes: None,
+            join_messages: false,
+            share_approvals: false,
+            total_event_memory_bytes: None,
+            extra_agent_dirs: Vec::new(),
+            resume_model_policy: ResumeModelPolicy::Warn,
+            output_postprocess: OutputPostprocess::None,
+            overflow: OutputOverflow::Truncate,
+            spawn_retries: DEFAULT_SUBAGENTS_SPAWN_RETRIES,
+            resume_known_rollouts_only: false,
+            explore_auto_deny: true,
+            retain_terminated: None,
+            retain_ttl: None,
+            isolated_workspace: false,
+            idle_timeout: None,
+            dedupe_spawns: false,
+            require_unique_labels: false,
         }
     }
 
@@ -3064,6 +3145,8 @@ model_verbosity = "high"
                 ghost_snapshot: GhostSnapshotConfig::default(),
                 features: Features::with_defaults(),
                 tool_name_allowlist: None,
+                tool_name_denylist: None,
+                record_rollout: true,
                 subagents: default_subagents_config(),
                 active_profile: Some("o3".to_string()),
                 active_project: ProjectConfig { trust_level: None },
@@ -3141,6 +3224,8 @@ model_verbosity = "high"
             ghost_snapshot: GhostSnapshotConfig::default(),
             features: Features::with_defaults(),
             tool_name_allowlist: None,
+            tool_name_denylist: None,
+            record_rollout: true,
             subagents: default_subagents_config(),
             active_profile: Some("gpt3".to_string()),
             active_project: ProjectConfig { trust_level: None },
@@ -3233,6 +3318,8 @@ model_verbosity = "high"
             ghost_snapshot: GhostSnapshotConfig::default(),
             features: Features::with_defaults(),
             tool_name_allowlist: None,
+            tool_name_denylist: None,
+            record_rollout: true,
             subagents: default_subagents_config(),
             active_profile: Some("zdr".to_string()),
             active_project: ProjectConfig { trust_level: None },
@@ -3311,6 +3398,8 @@ model_verbosity = "high"
             ghost_snapshot: GhostSnapshotConfig::default(),
             features: Features::with_defaults(),
             tool_name_allowlist: None,
+            tool_name_denylist: None,
+            record_rollout: true,
             subagents: default_subagents_config(),
             active_profile: Some("gpt5".to_string()),
             active_project: ProjectConfig { trust_level: None },
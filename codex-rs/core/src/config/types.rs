@@ -21,8 +21,15 @@ pub const DEFAULT_SUBAGENTS_MAX_AGENTS: usize = 128;
 pub const DEFAULT_SUBAGENTS_MAX_EVENTS: usize = 64;
 pub const DEFAULT_SUBAGENTS_MAX_EVENT_CHARS: usize = 2 * 1024;
 pub const DEFAULT_SUBAGENTS_MAX_OUTPUT_CHARS: usize = 32 * 1024;
+/// Bounds for `max_events`, from config or a spawn request.
+pub const MIN_SUBAGENTS_MAX_EVENTS: usize = 1;
+pub const MAX_SUBAGENTS_MAX_EVENTS: usize = 1024;
+/// Bounds for `max_output_chars`, from config or a spawn request.
+pub const MIN_SUBAGENTS_MAX_OUTPUT_CHARS: usize = 1024;
+pub const MAX_SUBAGENTS_MAX_OUTPUT_CHARS: usize = 1024 * 1024;
 pub const DEFAULT_SUBAGENTS_TIMEOUT_MS: u64 = 30 * 60 * 1000;
 pub const DEFAULT_SUBAGENTS_ORCHESTRATION_TIMEOUT_MS: u64 = 3 * 60 * 1000;
+pub const DEFAULT_SUBAGENTS_SPAWN_RETRIES: u32 = 2;
 
 /// Subagent settings loaded from config.toml. Fields are optional so we can apply defaults.
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -30,6 +37,11 @@ pub struct SubagentsConfigToml {
     /// Maximum number of concurrently running subagents across the whole process.
     pub max_concurrency: Option<usize>,
 
+    /// Maximum number of concurrent synchronous `delegate` calls across the whole
+    /// process. Delegates use their own pool so they cannot starve background
+    /// subagents (and vice versa). Defaults to `max_concurrency`.
+    pub delegate_concurrency: Option<usize>,
+
     /// Maximum number of subagents tracked by the session before older completed
     /// entries are pruned.
     pub max_agents: Option<usize>,
@@ -48,6 +60,117 @@ pub struct SubagentsConfigToml {
 
     /// Maximum number of characters kept for a subagent's final output.
     pub max_output_chars: Option<usize>,
+
+    /// Token threshold (summed across the session's subagents) at which running
+    /// subagents are asked to wrap up. Unset disables the cooperative warning.
+    pub cooperative_budget: Option<u64>,
+
+    /// Maximum combined size (bytes) of skill files injected into a single subagent.
+    pub max_skill_bytes: Option<u64>,
+
+    /// When true, a subagent's final output is every agent message of its
+    /// final turn joined together, instead of only the last one.
+    pub join_messages: Option<bool>,
+
+    /// When true, subagent exec approvals first consult the parent session's
+    /// "approved for session" cache before prompting the user again.
+    pub share_approvals: Option<bool>,
+
+    /// Ceiling (bytes) on the combined size of every subagent's retained
+    /// events. When exceeded, the oldest finished agents' events are trimmed.
+    pub total_event_memory_bytes: Option<usize>,
+
+    /// Additional directories scanned for custom agent definitions. Relative
+    /// paths resolve against `$CODEX_HOME`. Agents found here override user
+    /// agents and are overridden by repo agents.
+    pub extra_agent_dirs: Option<Vec<AbsolutePathBuf>>,
+
+    /// What to do when a spawn's `resume_rollout_path` seeds a rollout
+    /// recorded with a different model than the subagent will run with.
+    pub resume_model_policy: Option<ResumeModelPolicy>,
+
+    /// Cleanup applied to every subagent's final output before it is stored.
+    pub output_postprocess: Option<OutputPostprocess>,
+
+    /// What to do with a final output longer than `max_output_chars`.
+    pub overflow: Option<OutputOverflow>,
+
+    /// How many times starting a subagent's session is retried after a
+    /// transient failure (network, timeout, 5xx) before the agent errors.
+    pub spawn_retries: Option<u32>,
+
+    /// When true, `resume_rollout_path` only accepts rollout paths of subagents
+    /// known to this session (as reported by `subagent_poll`/`subagent_list`).
+    pub resume_known_rollouts_only: Option<bool>,
+
+    /// When true (the default), exec and patch approval requests from
+    /// explore-mode subagents are denied without asking the user.
+    pub explore_auto_deny: Option<bool>,
+
+    /// How many finished (complete, aborted, or error) agents to keep. Older
+    /// ones are removed on the next spawn or poll.
+    pub retain_terminated: Option<usize>,
+
+    /// Finished agents whose last update is older than this are removed on
+    /// the next spawn or poll.
+    pub retain_ttl_ms: Option<u64>,
+
+    /// When true, general-mode subagents run in a fresh writable temp
+    /// directory instead of the parent's workspace. The directory is removed
+    /// when the agent's run ends.
+    pub isolated_workspace: Option<bool>,
+
+    /// Stop a running subagent that produces no event for this long, even
+    /// if its total timeout has not been reached. Unset disables the check.
+    pub idle_timeout_ms: Option<u64>,
+
+    /// When true, a spawn whose label, mode, prompt, and skills match a
+    /// queued or running agent returns that agent instead of starting a copy.
+    pub dedupe_spawns: Option<bool>,
+
+    /// When true, a spawn is rejected if a queued or running agent already
+    /// has its label.
+    pub require_unique_labels: Option<bool>,
+}
+
+/// Built-in post-processors for subagent final outputs.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputPostprocess {
+    /// Store the output as produced.
+    #[default]
+    None,
+    /// Trim surrounding and trailing whitespace and collapse runs of blank lines.
+    Trim,
+    /// Remove common markdown syntax (headings, emphasis, code fences, links),
+    /// then trim.
+    StripMarkdown,
+}
+
+/// Handling of subagent final outputs longer than `max_output_chars`.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputOverflow {
+    /// Cut the output at the cap.
+    #[default]
+    Truncate,
+    /// Ask the model for a summary that fits under the cap, truncating if
+    /// that call fails.
+    Summarize,
+}
+
+/// Handling of a model mismatch between a resumed rollout and the subagent's
+/// configured model.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResumeModelPolicy {
+    /// Record a warning event and continue with the configured model.
+    #[default]
+    Warn,
+    /// Fail the resume.
+    Strict,
+    /// Resume without checking.
+    Ignore,
 }
 
 /// Effective subagent settings after defaults are applied.
@@ -55,12 +178,32 @@ pub struct SubagentsConfigToml {
 pub struct SubagentsConfig {
     /// When unset, Codex chooses a default based on available CPU cores.
     pub max_concurrency: Option<usize>,
+    /// When unset, falls back to `max_concurrency`.
+    pub delegate_concurrency: Option<usize>,
     pub max_agents: usize,
     pub default_timeout: Duration,
     pub orchestration_timeout: Duration,
     pub max_events: usize,
     pub max_event_chars: usize,
     pub max_output_chars: usize,
+    pub cooperative_budget: Option<u64>,
+    pub max_skill_bytes: Option<u64>,
+    pub join_messages: bool,
+    pub share_approvals: bool,
+    pub total_event_memory_bytes: Option<usize>,
+    pub extra_agent_dirs: Vec<PathBuf>,
+    pub resume_model_policy: ResumeModelPolicy,
+    pub output_postprocess: OutputPostprocess,
+    pub overflow: OutputOverflow,
+    pub spawn_retries: u32,
+    pub resume_known_rollouts_only: bool,
+    pub explore_auto_deny: bool,
+    pub retain_terminated: Option<usize>,
+    pub retain_ttl: Option<Duration>,
+    pub isolated_workspace: bool,
+    pub idle_timeout: Option<Duration>,
+    pub dedupe_spawns: bool,
+    pub require_unique_labels: bool,
 }
 
 #[derive(Serialize, Debug, Clone, PartialEq)]
@@ -1,4 +1,7 @@
 use crate::git_info::resolve_root_git_project_for_trust;
+use crate::skills::SkillMetadata;
+use crate::skills::describe_unmatched_skill;
+use crate::skills::resolve_requested_skills;
 use crate::subagents::SubagentMode;
 use dunce::canonicalize as normalize_path;
 use serde::Deserialize;
@@ -14,21 +17,69 @@ const MAX_DESCRIPTION_LEN: usize = 1024;
 const MAX_PROMPT_BYTES: usize = 64 * 1024;
 const MAX_ALLOWED_TOOLS: usize = 128;
 const MAX_TOOL_NAME_LEN: usize = 128;
+const MAX_RETRIES: u32 = 10;
+const MAX_RETRY_BACKOFF_MS: u64 = 10 * 60 * 1000;
+pub(crate) const DEFAULT_RETRY_BACKOFF_MS: u64 = 1000;
+const MAX_TIMEOUT_MS: u64 = 24 * 60 * 60 * 1000;
+const MAX_CONCURRENCY: usize = 64;
+const MAX_ICON_LEN: usize = 32;
+/// Named colors accepted for `color`, besides `#rrggbb`.
+const AGENT_COLORS: &[&str] = &[
+    "red", "green", "yellow", "blue", "magenta", "cyan", "white", "gray",
+];
+/// Tools that `mode: explore` removes from the subagent's registry.
+const EXPLORE_DISABLED_TOOLS: &[&str] = &[
+    "apply_patch",
+    "exec_command",
+    "local_shell",
+    "shell",
+    "shell_command",
+    "view_image",
+    "web_search",
+    "write_stdin",
+];
+/// Review mode keeps `view_image` and `web_search`.
+const REVIEW_DISABLED_TOOLS: &[&str] = &[
+    "apply_patch",
+    "exec_command",
+    "local_shell",
+    "shell",
+    "shell_command",
+    "write_stdin",
+];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where an agent was discovered. Variants are ordered by precedence: when two
+/// roots define the same name, the later scope wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum AgentScope {
     User,
+    /// A directory listed in `[subagents].extra_agent_dirs`.
+    Extra,
     Repo,
 }
 
+impl AgentScope {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Extra => "extra",
+            Self::Repo => "repo",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum AgentToolsPolicy {
     /// No extra restrictions (inherit the parent session's configured tools).
     Inherit,
     /// Disable all tools.
     None,
-    /// Restrict the tool registry to this allowlist of tool names.
+    /// Restrict the tool registry to this allowlist of tool names. Entries
+    /// containing `*` are glob patterns (e.g. `read_*`).
     Allowlist(Vec<String>),
+    /// Inherit the parent's tools minus this denylist, matched like the
+    /// allowlist. Written as `tools: { deny: [...] }`.
+    Denylist(Vec<String>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -40,9 +91,94 @@ pub(crate) struct CustomAgent {
     pub(crate) model: Option<String>,
     pub(crate) mode: Option<SubagentMode>,
     pub(crate) tools: AgentToolsPolicy,
+    /// Number of times a failed run is restarted before reporting the error.
+    pub(crate) max_retries: u32,
+    /// Delay before each retry.
+    pub(crate) retry_backoff_ms: u64,
+    /// Run deadline used when the caller does not pass `timeout_ms`.
+    pub(crate) timeout_ms: Option<u64>,
+    /// Cap on this agent's queued and running instances, used when the
+    /// caller does not pass `max_per_label`.
+    pub(crate) max_concurrency: Option<usize>,
+    /// Display hints for UIs: a palette name or `#rrggbb`, and an icon slug.
+    pub(crate) color: Option<String>,
+    pub(crate) icon: Option<String>,
+    /// Skills injected into every spawn of this agent, before any passed at
+    /// call time.
+    pub(crate) skills: Vec<String>,
     pub(crate) prompt: String,
 }
 
+impl AgentToolsPolicy {
+    /// Tool name allowlist for the subagent config (`None` inherits).
+    pub(crate) fn to_allowlist(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Inherit | Self::Denylist(_) => None,
+            Self::None => Some(Vec::new()),
+            Self::Allowlist(names) => Some(names.clone()),
+        }
+    }
+
+    /// Tool name denylist for the subagent config (`None` removes nothing).
+    pub(crate) fn to_denylist(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Denylist(names) => Some(names.clone()),
+            Self::Inherit | Self::None | Self::Allowlist(_) => None,
+        }
+    }
+}
+
+impl CustomAgent {
+    /// Mode used when the agent file does not set one.
+    pub(crate) fn mode_or_default(&self) -> SubagentMode {
+        self.mode.unwrap_or(SubagentMode::Explore)
+    }
+
+    /// The agent's declared skills followed by `requested`, without duplicates.
+    pub(crate) fn merged_skills(&self, requested: Vec<String>) -> Vec<String> {
+        let mut merged: Vec<String> = Vec::with_capacity(self.skills.len() + requested.len());
+        for skill in self.skills.iter().cloned().chain(requested) {
+            if !merged.contains(&skill) {
+                merged.push(skill);
+            }
+        }
+        merged
+    }
+
+    /// Checks the agent's declared skills against the skills available to
+    /// the spawn, so a stale definition fails before anything runs.
+    pub(crate) fn check_skills(&self, available: &[SkillMetadata]) -> Result<(), String> {
+        let unmatched = resolve_requested_skills(&self.skills, available).unmatched;
+        if unmatched.is_empty() {
+            return Ok(());
+        }
+        let problems = unmatched
+            .iter()
+            .map(|entry| describe_unmatched_skill(entry))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(format!(
+            "custom agent `{}` declares skills that are not available: {problems}",
+            self.name
+        ))
+    }
+
+    /// Applies the agent's prompt to the config its subagent is spawned with,
+    /// appending it to the developer instructions under a `# Custom agent`
+    /// heading. The model override and tools policy travel on the spawn
+    /// request instead.
+    pub(crate) fn apply_to_config(&self, config: &mut crate::config::Config) {
+        let agent_prompt = self.prompt.trim();
+        if !agent_prompt.is_empty() {
+            let name = &self.name;
+            config.developer_instructions = Some(match config.developer_instructions.take() {
+                Some(existing) => format!("{existing}\n\n# Custom agent: {name}\n\n{agent_prompt}"),
+                None => format!("# Custom agent: {name}\n\n{agent_prompt}"),
+            });
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct AgentLoadOutcome {
     pub(crate) agents: Vec<CustomAgent>,
@@ -69,9 +205,23 @@ struct AgentFrontmatter {
     mode: Option<String>,
     #[serde(default)]
     tools: Option<serde_yaml::Value>,
+    #[serde(default)]
+    max_retries: Option<u32>,
+    #[serde(default)]
+    retry_backoff_ms: Option<u64>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    max_concurrency: Option<usize>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    icon: Option<String>,
+    #[serde(default)]
+    skills: Option<serde_yaml::Value>,
 }
 
-fn sanitize_agent_name(raw: &str) -> Option<String> {
+pub(crate) fn sanitize_agent_name(raw: &str) -> Option<String> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return None;
@@ -115,6 +265,27 @@ fn sanitize_model(raw: Option<String>) -> Option<String> {
     Some(trimmed.to_string())
 }
 
+/// Lowercased palette name or `#rrggbb`; anything else is dropped.
+fn sanitize_color(raw: Option<String>) -> Option<String> {
+    let color = raw?.trim().to_ascii_lowercase();
+    let is_hex = color.len() == 7
+        && color
+            .strip_prefix('#')
+            .is_some_and(|hex| hex.chars().all(|ch| ch.is_ascii_hexdigit()));
+    (is_hex || AGENT_COLORS.contains(&color.as_str())).then_some(color)
+}
+
+/// Short lowercase slug (`a-z`, `0-9`, `-`, `_`); anything else is dropped.
+fn sanitize_icon(raw: Option<String>) -> Option<String> {
+    let icon = raw?.trim().to_ascii_lowercase();
+    let valid = !icon.is_empty()
+        && icon.len() <= MAX_ICON_LEN
+        && icon
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-' || ch == '_');
+    valid.then_some(icon)
+}
+
 fn parse_mode(raw: Option<String>) -> Option<SubagentMode> {
     let text = raw?;
     SubagentMode::from_str(&text)
@@ -134,28 +305,143 @@ fn parse_tools_policy(raw: Option<serde_yaml::Value>) -> AgentToolsPolicy {
             "none" | "off" | "disabled" | "read-only" | "readonly" => AgentToolsPolicy::None,
             _ => AgentToolsPolicy::Inherit,
         },
-        serde_yaml::Value::Sequence(items) => {
-            let mut out: Vec<String> = Vec::new();
-            for item in items.into_iter().take(MAX_ALLOWED_TOOLS) {
-                let serde_yaml::Value::String(tool) = item else {
-                    continue;
-                };
-                let trimmed = tool.trim();
-                if trimmed.is_empty() || trimmed.len() > MAX_TOOL_NAME_LEN {
-                    continue;
-                }
-                out.push(trimmed.to_ascii_lowercase());
-            }
-            if out.is_empty() {
-                AgentToolsPolicy::Inherit
-            } else {
-                AgentToolsPolicy::Allowlist(out)
+        serde_yaml::Value::Sequence(items) => match parse_tool_names(items) {
+            // A bare `*` pattern allows every tool.
+            Some(names) if names.is_empty() => AgentToolsPolicy::Inherit,
+            Some(names) => AgentToolsPolicy::Allowlist(names),
+            None => AgentToolsPolicy::Inherit,
+        },
+        serde_yaml::Value::Mapping(map) => {
+            let items = match map.get("deny") {
+                Some(serde_yaml::Value::Sequence(items)) => items.clone(),
+                Some(value @ serde_yaml::Value::String(_)) => vec![value.clone()],
+                _ => return AgentToolsPolicy::Inherit,
+            };
+            match parse_tool_names(items) {
+                Some(names) if names.is_empty() => AgentToolsPolicy::Inherit,
+                Some(names) => AgentToolsPolicy::Denylist(names),
+                // Denying `*` leaves no tools.
+                None => AgentToolsPolicy::None,
             }
         }
         _ => AgentToolsPolicy::Inherit,
     }
 }
 
+/// Lowercased tool names from a frontmatter list, skipping invalid entries.
+/// Returns `None` when the list contains a bare `*` pattern.
+fn parse_tool_names(items: Vec<serde_yaml::Value>) -> Option<Vec<String>> {
+    let mut out: Vec<String> = Vec::new();
+    for item in items.into_iter().take(MAX_ALLOWED_TOOLS) {
+        let serde_yaml::Value::String(tool) = item else {
+            continue;
+        };
+        let trimmed = tool.trim();
+        if trimmed.is_empty() || trimmed.len() > MAX_TOOL_NAME_LEN {
+            continue;
+        }
+        if trimmed.chars().all(|ch| ch == '*') {
+            return None;
+        }
+        out.push(trimmed.to_ascii_lowercase());
+    }
+    Some(out)
+}
+
+/// Parses the `skills` list with the same limits as tool names. A single
+/// string names one skill. Case is kept, since skill names match exactly.
+fn parse_skills(raw: Option<serde_yaml::Value>) -> Vec<String> {
+    let items = match raw {
+        Some(serde_yaml::Value::Sequence(items)) => items,
+        Some(value @ serde_yaml::Value::String(_)) => vec![value],
+        _ => return Vec::new(),
+    };
+    let mut out: Vec<String> = Vec::new();
+    for item in items.into_iter().take(MAX_ALLOWED_TOOLS) {
+        let serde_yaml::Value::String(skill) = item else {
+            continue;
+        };
+        let trimmed = skill.trim();
+        if trimmed.is_empty() || trimmed.len() > MAX_TOOL_NAME_LEN {
+            continue;
+        }
+        if !out.iter().any(|existing| existing == trimmed) {
+            out.push(trimmed.to_string());
+        }
+    }
+    out
+}
+
+fn parse_max_retries(raw: Option<u32>) -> Result<u32, String> {
+    match raw {
+        None => Ok(0),
+        Some(n) if n <= MAX_RETRIES => Ok(n),
+        Some(n) => Err(format!(
+            "max_retries must be at most {MAX_RETRIES} (got {n})"
+        )),
+    }
+}
+
+fn parse_retry_backoff_ms(raw: Option<u64>) -> Result<u64, String> {
+    match raw {
+        None => Ok(DEFAULT_RETRY_BACKOFF_MS),
+        Some(ms) if ms <= MAX_RETRY_BACKOFF_MS => Ok(ms),
+        Some(ms) => Err(format!(
+            "retry_backoff_ms must be at most {MAX_RETRY_BACKOFF_MS} (got {ms})"
+        )),
+    }
+}
+
+fn parse_timeout_ms(raw: Option<u64>) -> Result<Option<u64>, String> {
+    match raw {
+        None => Ok(None),
+        Some(ms) if (1..=MAX_TIMEOUT_MS).contains(&ms) => Ok(Some(ms)),
+        Some(ms) => Err(format!(
+            "timeout_ms must be between 1 and {MAX_TIMEOUT_MS} (got {ms})"
+        )),
+    }
+}
+
+fn parse_max_concurrency(raw: Option<usize>) -> Result<Option<usize>, String> {
+    match raw {
+        None => Ok(None),
+        Some(n) if (1..=MAX_CONCURRENCY).contains(&n) => Ok(Some(n)),
+        Some(n) => Err(format!(
+            "max_concurrency must be between 1 and {MAX_CONCURRENCY} (got {n})"
+        )),
+    }
+}
+
+/// Rejects allowlists that name tools the agent's mode removes, since such an
+/// agent would silently run without them.
+fn check_mode_tools_conflict(
+    mode: Option<SubagentMode>,
+    tools: &AgentToolsPolicy,
+) -> Result<(), String> {
+    let (Some(mode), AgentToolsPolicy::Allowlist(names)) = (mode, tools) else {
+        return Ok(());
+    };
+    let disabled = match mode {
+        SubagentMode::Explore => EXPLORE_DISABLED_TOOLS,
+        SubagentMode::Review => REVIEW_DISABLED_TOOLS,
+        SubagentMode::General => return Ok(()),
+    };
+    let conflicting: Vec<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| disabled.contains(name))
+        .collect();
+    if conflicting.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "mode `{}` disables tools listed in `tools`: {}",
+            mode.as_str(),
+            conflicting.join(", ")
+        ))
+    }
+}
+
 fn sanitize_prompt(mut prompt: String) -> String {
     if prompt.len() > MAX_PROMPT_BYTES {
         prompt.truncate(MAX_PROMPT_BYTES);
@@ -223,6 +509,17 @@ async fn load_agent_from_path(path: &Path, scope: AgentScope) -> Result<CustomAg
         .await
         .map_err(|e| format!("failed to read: {e}"))?;
 
+    parse_agent(content, file_stem, path, scope)
+}
+
+/// Parses agent markdown (frontmatter + body) the same way discovery does.
+/// `file_stem` is the fallback name when the frontmatter has none.
+fn parse_agent(
+    content: String,
+    file_stem: &str,
+    path: &Path,
+    scope: AgentScope,
+) -> Result<CustomAgent, String> {
     let (frontmatter_text, body) = match split_frontmatter(&content) {
         Some((fm, body)) => (Some(fm), body),
         None => (None, content),
@@ -239,6 +536,13 @@ async fn load_agent_from_path(path: &Path, scope: AgentScope) -> Result<CustomAg
             model: None,
             mode: None,
             tools: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            timeout_ms: None,
+            max_concurrency: None,
+            color: None,
+            icon: None,
+            skills: None,
         }
     };
 
@@ -253,6 +557,11 @@ async fn load_agent_from_path(path: &Path, scope: AgentScope) -> Result<CustomAg
     let model = sanitize_model(frontmatter.model);
     let mode = parse_mode(frontmatter.mode);
     let tools = parse_tools_policy(frontmatter.tools);
+    check_mode_tools_conflict(mode, &tools)?;
+    let max_retries = parse_max_retries(frontmatter.max_retries)?;
+    let retry_backoff_ms = parse_retry_backoff_ms(frontmatter.retry_backoff_ms)?;
+    let timeout_ms = parse_timeout_ms(frontmatter.timeout_ms)?;
+    let max_concurrency = parse_max_concurrency(frontmatter.max_concurrency)?;
 
     Ok(CustomAgent {
         name,
@@ -262,10 +571,71 @@ async fn load_agent_from_path(path: &Path, scope: AgentScope) -> Result<CustomAg
         model,
         mode,
         tools,
+        max_retries,
+        retry_backoff_ms,
+        timeout_ms,
+        max_concurrency,
+        color: sanitize_color(frontmatter.color),
+        icon: sanitize_icon(frontmatter.icon),
+        skills: parse_skills(frontmatter.skills),
         prompt: sanitize_prompt(body),
     })
 }
 
+/// Validates agent markdown that has not been saved yet. `name_hint` plays the
+/// role of the filename stem.
+pub(crate) fn validate_agent_content(
+    content: &str,
+    name_hint: &str,
+) -> Result<CustomAgent, String> {
+    let path = PathBuf::from(format!("{name_hint}.md"));
+    parse_agent(content.to_string(), name_hint, &path, AgentScope::Repo)
+}
+
+/// Validates an agent file on disk. The path must resolve inside the user or
+/// repo agents directory.
+pub(crate) async fn validate_agent_path(
+    config: &crate::config::Config,
+    path: &Path,
+) -> Result<CustomAgent, String> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        config.cwd.join(path)
+    };
+    let path = normalize_path(&path).map_err(|e| format!("failed to resolve path: {e}"))?;
+
+    for (scope, root) in agent_roots(config) {
+        let Ok(root) = normalize_path(root) else {
+            continue;
+        };
+        if path.starts_with(&root) {
+            return load_agent_from_path(&path, scope).await;
+        }
+    }
+    Err(
+        "path must be inside .codex/agents, $CODEX_HOME/agents, or an extra_agent_dirs entry"
+            .to_string(),
+    )
+}
+
+/// Discovery roots in precedence order: user, then each configured extra
+/// directory, then the repo.
+fn agent_roots(config: &crate::config::Config) -> Vec<(AgentScope, PathBuf)> {
+    let mut roots = vec![(AgentScope::User, user_agents_root(&config.codex_home))];
+    roots.extend(
+        config
+            .subagents
+            .extra_agent_dirs
+            .iter()
+            .map(|dir| (AgentScope::Extra, dir.clone())),
+    );
+    if let Some(repo_root) = repo_agents_root(&config.cwd) {
+        roots.push((AgentScope::Repo, repo_root));
+    }
+    roots
+}
+
 fn user_agents_root(codex_home: &Path) -> PathBuf {
     codex_home.join(AGENTS_DIR_NAME)
 }
@@ -282,13 +652,7 @@ pub(crate) async fn discover_agents(config: &crate::config::Config) -> AgentLoad
     let mut out = AgentLoadOutcome::default();
     let mut by_name: BTreeMap<String, CustomAgent> = BTreeMap::new();
 
-    let mut roots: Vec<(AgentScope, PathBuf)> =
-        vec![(AgentScope::User, user_agents_root(&config.codex_home))];
-    if let Some(repo_root) = repo_agents_root(&config.cwd) {
-        roots.push((AgentScope::Repo, repo_root));
-    }
-
-    for (scope, root) in roots {
+    for (scope, root) in agent_roots(config) {
         let Ok(root) = normalize_path(root) else {
             continue;
         };
@@ -314,8 +678,9 @@ pub(crate) async fn discover_agents(config: &crate::config::Config) -> AgentLoad
                             v.insert(agent);
                         }
                         std::collections::btree_map::Entry::Occupied(mut e) => {
-                            // Repo agents override user agents with the same name.
-                            if scope == AgentScope::Repo {
+                            // Higher-precedence scopes override earlier ones; within
+                            // the same scope the first definition wins.
+                            if scope > e.get().scope {
                                 e.insert(agent);
                             }
                         }
@@ -332,6 +697,29 @@ pub(crate) async fn discover_agents(config: &crate::config::Config) -> AgentLoad
     out
 }
 
+/// Looks up a discovered agent by (already sanitized) name. The error lists
+/// the available agents so the caller can correct the name.
+pub(crate) async fn find_agent(
+    config: &crate::config::Config,
+    name: &str,
+) -> Result<CustomAgent, String> {
+    let outcome = discover_agents(config).await;
+    if let Some(agent) = outcome.agents.iter().find(|agent| agent.name == name) {
+        return Ok(agent.clone());
+    }
+    let known = outcome
+        .agents
+        .iter()
+        .map(|agent| agent.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(if known.is_empty() {
+        format!("unknown agent `{name}` (no agents discovered)")
+    } else {
+        format!("unknown agent `{name}` (available: {known})")
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -396,6 +784,53 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn extra_dirs_override_user_but_not_repo() {
+        let tmp = TempDir::new().expect("TempDir");
+        let out = Command::new("git")
+            .args(["init"])
+            .current_dir(tmp.path())
+            .output()
+            .expect("git init");
+        assert!(out.status.success());
+        fs::create_dir_all(tmp.path().join(".codex/agents")).unwrap();
+        fs::create_dir_all(tmp.path().join("home/agents")).unwrap();
+        fs::create_dir_all(tmp.path().join("shared")).unwrap();
+
+        fs::write(tmp.path().join("home/agents/a.md"), "user").unwrap();
+        fs::write(tmp.path().join("shared/a.md"), "extra").unwrap();
+        fs::write(tmp.path().join("home/agents/b.md"), "user").unwrap();
+        fs::write(tmp.path().join("shared/b.md"), "extra").unwrap();
+        fs::write(tmp.path().join(".codex/agents/b.md"), "repo").unwrap();
+
+        let mut cfg = test_config();
+        cfg.cwd = tmp.path().to_path_buf();
+        cfg.codex_home = tmp.path().join("home");
+        cfg.subagents.extra_agent_dirs = vec![tmp.path().join("shared")];
+
+        let found = discover_agents(&cfg).await;
+        let paths: Vec<(String, PathBuf, AgentScope)> = found
+            .agents
+            .into_iter()
+            .map(|agent| (agent.name, agent.path, agent.scope))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "a".to_string(),
+                    normalize_path(tmp.path().join("shared/a.md")).expect("canonicalize"),
+                    AgentScope::Extra,
+                ),
+                (
+                    "b".to_string(),
+                    normalize_path(tmp.path().join(".codex/agents/b.md")).expect("canonicalize"),
+                    AgentScope::Repo,
+                ),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn tools_allowlist_parses() {
         let tmp = TempDir::new().expect("TempDir");
@@ -412,4 +847,203 @@ mod tests {
             AgentToolsPolicy::Allowlist(vec!["read_file".to_string(), "list_dir".to_string()])
         );
     }
+
+    #[tokio::test]
+    async fn tools_denylist_parses() {
+        let tmp = TempDir::new().expect("TempDir");
+        let file = tmp.path().join("a.md");
+        fs::write(
+            &file,
+            "---\nname: a\ntools:\n  deny: [Shell, apply_patch]\n---\nbody",
+        )
+        .unwrap();
+
+        let agent = load_agent_from_path(&file, AgentScope::Repo).await.unwrap();
+        assert_eq!(
+            agent.tools,
+            AgentToolsPolicy::Denylist(vec!["shell".to_string(), "apply_patch".to_string()])
+        );
+        assert_eq!(agent.tools.to_allowlist(), None);
+        assert_eq!(
+            agent.tools.to_denylist(),
+            Some(vec!["shell".to_string(), "apply_patch".to_string()])
+        );
+
+        fs::write(&file, "---\nname: a\ntools:\n  deny: \"*\"\n---\nbody").unwrap();
+        let agent = load_agent_from_path(&file, AgentScope::Repo).await.unwrap();
+        assert_eq!(agent.tools, AgentToolsPolicy::None);
+    }
+
+    #[tokio::test]
+    async fn find_agent_applies_definition_to_config() {
+        let tmp = TempDir::new().expect("TempDir");
+        fs::create_dir_all(tmp.path().join("home/agents")).unwrap();
+        fs::write(
+            tmp.path().join("home/agents/scout.md"),
+            "---\nmodel: gpt-scout\ntools:\n  - read_file\n---\nMap the repo.",
+        )
+        .unwrap();
+
+        let mut cfg = test_config();
+        cfg.cwd = tmp.path().to_path_buf();
+        cfg.codex_home = tmp.path().join("home");
+        cfg.developer_instructions = None;
+
+        assert_eq!(
+            find_agent(&cfg, "missing").await,
+            Err("unknown agent `missing` (available: scout)".to_string())
+        );
+
+        let agent = find_agent(&cfg, "scout").await.expect("agent");
+        assert_eq!(agent.mode_or_default(), SubagentMode::Explore);
+        agent.apply_to_config(&mut cfg);
+        assert_eq!(agent.model.as_deref(), Some("gpt-scout"));
+        assert_eq!(cfg.model, None);
+        assert_eq!(cfg.tool_name_allowlist, None);
+        assert_eq!(
+            agent.tools.to_allowlist(),
+            Some(vec!["read_file".to_string()])
+        );
+        assert_eq!(
+            cfg.developer_instructions.as_deref(),
+            Some("# Custom agent: scout\n\nMap the repo.")
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_settings_parse_and_validate() {
+        let tmp = TempDir::new().expect("TempDir");
+        let file = tmp.path().join("flaky.md");
+        fs::write(
+            &file,
+            "---\nmax_retries: 3\nretry_backoff_ms: 250\n---\nbody",
+        )
+        .unwrap();
+        let agent = load_agent_from_path(&file, AgentScope::Repo).await.unwrap();
+        assert_eq!((agent.max_retries, agent.retry_backoff_ms), (3, 250));
+
+        let file = tmp.path().join("greedy.md");
+        fs::write(&file, "---\nmax_retries: 50\n---\nbody").unwrap();
+        assert_eq!(
+            load_agent_from_path(&file, AgentScope::Repo).await,
+            Err("max_retries must be at most 10 (got 50)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn run_defaults_parse_and_validate() {
+        let tmp = TempDir::new().expect("TempDir");
+        let file = tmp.path().join("deep-research.md");
+        fs::write(
+            &file,
+            "---\ntimeout_ms: 7200000\nmax_concurrency: 2\n---\nbody",
+        )
+        .unwrap();
+        let agent = load_agent_from_path(&file, AgentScope::Repo).await.unwrap();
+        assert_eq!(
+            (agent.timeout_ms, agent.max_concurrency),
+            (Some(7_200_000), Some(2))
+        );
+
+        let file = tmp.path().join("forever.md");
+        fs::write(&file, "---\ntimeout_ms: 0\n---\nbody").unwrap();
+        assert_eq!(
+            load_agent_from_path(&file, AgentScope::Repo).await,
+            Err("timeout_ms must be between 1 and 86400000 (got 0)".to_string())
+        );
+
+        let file = tmp.path().join("swarm.md");
+        fs::write(&file, "---\nmax_concurrency: 500\n---\nbody").unwrap();
+        assert_eq!(
+            load_agent_from_path(&file, AgentScope::Repo).await,
+            Err("max_concurrency must be between 1 and 64 (got 500)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn explore_mode_rejects_write_tools() {
+        let tmp = TempDir::new().expect("TempDir");
+        let file = tmp.path().join("scout.md");
+        fs::write(
+            &file,
+            "---\nmode: explore\ntools:\n  - read_file\n  - shell\n  - apply_patch\n---\nbody",
+        )
+        .unwrap();
+
+        assert_eq!(
+            load_agent_from_path(&file, AgentScope::Repo).await,
+            Err("mode `explore` disables tools listed in `tools`: shell, apply_patch".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_agent_content_reports_resolved_fields() {
+        let agent = validate_agent_content(
+            "---\ndescription: scout\nmode: explore\ntools: [read_*]\n---\nLook around.",
+            "Repo Scout",
+        )
+        .unwrap();
+        assert_eq!(agent.name, "repo-scout");
+        assert_eq!(agent.mode, Some(SubagentMode::Explore));
+        assert_eq!(
+            agent.tools,
+            AgentToolsPolicy::Allowlist(vec!["read_*".to_string()])
+        );
+        assert_eq!(agent.prompt, "Look around.");
+
+        let err = validate_agent_content("---\nmax_retries: [1]\n---\n", "a").unwrap_err();
+        assert!(err.starts_with("invalid YAML frontmatter"), "{err}");
+    }
+
+    #[test]
+    fn color_and_icon_are_sanitized_without_errors() {
+        let agent =
+            validate_agent_content("---\ncolor: Cyan\nicon: magnifier\n---\n", "a").unwrap();
+        assert_eq!(
+            (agent.color.as_deref(), agent.icon.as_deref()),
+            (Some("cyan"), Some("magnifier"))
+        );
+
+        let agent = validate_agent_content("---\ncolor: '#A1b2C3'\n---\n", "a").unwrap();
+        assert_eq!(agent.color.as_deref(), Some("#a1b2c3"));
+
+        let agent =
+            validate_agent_content("---\ncolor: chartreuse\nicon: big icon!\n---\n", "a").unwrap();
+        assert_eq!((agent.color, agent.icon), (None, None));
+    }
+
+    #[test]
+    fn declared_skills_merge_with_requested_and_are_checked() {
+        let agent = validate_agent_content(
+            "---\nname: db-migration\nskills:\n  - db/migrate\n  - ' db/migrate '\n  - 7\n  - db/seed\n---\nMigrate.",
+            "db-migration",
+        )
+        .unwrap();
+        assert_eq!(agent.skills, vec!["db/migrate", "db/seed"]);
+        assert_eq!(
+            agent.merged_skills(vec!["lint".to_string(), "db/seed".to_string()]),
+            vec!["db/migrate", "db/seed", "lint"]
+        );
+
+        let available: Vec<SkillMetadata> = ["db/migrate", "lint"]
+            .into_iter()
+            .map(|name| SkillMetadata {
+                name: name.to_string(),
+                description: String::new(),
+                path: PathBuf::from(format!("/skills/{name}/SKILL.md")),
+                scope: codex_protocol::protocol::SkillScope::Repo,
+            })
+            .collect();
+        assert_eq!(
+            agent.check_skills(&available),
+            Err(
+                "custom agent `db-migration` declares skills that are not available: unknown skill requested: db/seed"
+                    .to_string()
+            )
+        );
+
+        let agent = validate_agent_content("---\nskills: lint\n---\n", "a").unwrap();
+        assert_eq!(agent.skills, vec!["lint"]);
+        assert_eq!(agent.check_skills(&available), Ok(()));
+    }
 }
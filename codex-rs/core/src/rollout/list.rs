@@ -420,7 +420,7 @@ async fn read_head_summary(path: &Path, head_limit: usize) -> io::Result<HeadTai
             RolloutItem::TurnContext(_) => {
                 // Not included in `head`; skip.
             }
-            RolloutItem::Compacted(_) => {
+            RolloutItem::Compacted(_) | RolloutItem::SubagentRegistry(_) => {
                 // Not included in `head`; skip.
             }
             RolloutItem::EventMsg(ev) => {
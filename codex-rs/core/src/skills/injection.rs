@@ -6,6 +6,7 @@ use crate::user_instructions::SkillInstructions;
 use codex_protocol::models::ResponseItem;
 use codex_protocol::user_input::UserInput;
 use tokio::fs;
+use wildmatch::WildMatchPattern;
 
 #[derive(Debug, Default)]
 pub(crate) struct SkillInjections {
@@ -13,6 +14,66 @@ pub(crate) struct SkillInjections {
     pub(crate) warnings: Vec<String>,
 }
 
+/// Skills selected by a list of requested skill entries.
+#[derive(Debug, Default)]
+pub(crate) struct ResolvedSkills<'a> {
+    /// Matching skills in request order, without duplicates.
+    pub(crate) matched: Vec<&'a SkillMetadata>,
+    /// Requested entries that matched no skill.
+    pub(crate) unmatched: Vec<String>,
+}
+
+/// Returns true when a requested skill entry is a glob pattern rather than an
+/// exact skill name.
+pub(crate) fn is_skill_pattern(entry: &str) -> bool {
+    entry.contains('*')
+}
+
+/// Resolves requested skill entries against `skills`.
+///
+/// Entries containing `*` are glob patterns (e.g. `db/*`) that expand to every
+/// skill whose name matches; all other entries must match a skill name exactly.
+pub(crate) fn resolve_requested_skills<'a>(
+    requested: &[String],
+    skills: &'a [SkillMetadata],
+) -> ResolvedSkills<'a> {
+    let mut resolved = ResolvedSkills::default();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for entry in requested {
+        let matches: Vec<&SkillMetadata> = if is_skill_pattern(entry) {
+            let pattern = WildMatchPattern::<'*', '\0'>::new(entry);
+            skills
+                .iter()
+                .filter(|skill| pattern.matches(&skill.name))
+                .collect()
+        } else {
+            skills
+                .iter()
+                .find(|skill| skill.name == *entry)
+                .into_iter()
+                .collect()
+        };
+        if matches.is_empty() {
+            resolved.unmatched.push(entry.clone());
+        }
+        for skill in matches {
+            if seen.insert(skill.name.as_str()) {
+                resolved.matched.push(skill);
+            }
+        }
+    }
+    resolved
+}
+
+/// Describes a requested skill entry that matched nothing.
+pub(crate) fn describe_unmatched_skill(entry: &str) -> String {
+    if is_skill_pattern(entry) {
+        format!("no skills match pattern `{entry}`")
+    } else {
+        format!("unknown skill requested: {entry}")
+    }
+}
+
 pub(crate) async fn build_skill_injections(
     inputs: &[UserInput],
     skills: Option<&SkillLoadOutcome>,
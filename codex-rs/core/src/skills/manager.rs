@@ -29,7 +29,11 @@ impl SkillsManager {
         if let Some(outcome) = cached {
             return outcome;
         }
+        self.reload_for_cwd(cwd)
+    }
 
+    /// Re-scans the skill roots for `cwd`, bypassing (and refreshing) the cache.
+    pub fn reload_for_cwd(&self, cwd: &Path) -> SkillLoadOutcome {
         let mut roots = vec![user_skills_root(&self.codex_home)];
         if let Some(repo_root) = repo_skills_root(cwd) {
             roots.push(repo_root);
@@ -6,6 +6,9 @@ pub mod render;
 
 pub(crate) use injection::SkillInjections;
 pub(crate) use injection::build_skill_injections;
+pub(crate) use injection::describe_unmatched_skill;
+pub(crate) use injection::is_skill_pattern;
+pub(crate) use injection::resolve_requested_skills;
 pub use loader::load_skills;
 pub use manager::SkillsManager;
 pub use model::SkillError;
@@ -0,0 +1,248 @@
+//! Approval requests raised by subagents: forwarded to the parent, answered
+//! from its session cache, auto-denied in explore mode, or recorded as
+//! proposals.
+
+use std::path::Path;
+use std::time::Instant;
+
+use codex_protocol::models::SandboxPermissions;
+use codex_protocol::protocol::ApplyPatchApprovalRequestEvent;
+use codex_protocol::protocol::ExecApprovalRequestEvent;
+use codex_protocol::protocol::Op;
+use codex_protocol::protocol::ReviewDecision;
+use serde::Serialize;
+
+use super::ProposedPatch;
+use super::SubagentEventKind;
+use super::SubagentHandle;
+use super::events::push_event;
+use crate::codex::Codex;
+use crate::codex::Session;
+
+/// Same shape as the shell/unified exec runtime approval keys, so lookups hit
+/// the entries the parent stored when the user chose "approve for session".
+#[derive(Serialize)]
+pub(super) struct ParentExecApprovalKey<'a> {
+    pub(super) command: &'a [String],
+    pub(super) cwd: &'a Path,
+    pub(super) sandbox_permissions: SandboxPermissions,
+}
+
+/// Key for session approvals granted through a forwarded subagent request.
+/// Kept distinct from the runtime keys so approving a subagent command never
+/// lets the parent itself skip a prompt.
+#[derive(Serialize)]
+struct SubagentExecApprovalKey<'a> {
+    subagent_command: &'a [String],
+    cwd: &'a Path,
+}
+
+/// Returns true when the parent already approved `command` in `cwd` for the
+/// rest of the session. The subagent's request does not say whether it wants
+/// to escalate, so only escalated parent approvals (or earlier subagent
+/// approvals) count.
+pub(super) async fn parent_approved_for_session(
+    parent_session: &Session,
+    command: &[String],
+    cwd: &Path,
+) -> bool {
+    let store = parent_session.services.tool_approvals.lock().await;
+    let parent_key = ParentExecApprovalKey {
+        command,
+        cwd,
+        sandbox_permissions: SandboxPermissions::RequireEscalated,
+    };
+    let subagent_key = SubagentExecApprovalKey {
+        subagent_command: command,
+        cwd,
+    };
+    [store.get(&parent_key), store.get(&subagent_key)]
+        .into_iter()
+        .flatten()
+        .any(|decision| matches!(decision, ReviewDecision::ApprovedForSession))
+}
+
+pub(super) async fn push_approval_event(handle: &SubagentHandle, message: String) {
+    let mut state = handle.state.lock().await;
+    push_event(handle, &mut state, SubagentEventKind::Approval, message);
+    drop(state);
+    handle.notify.notify_waiters();
+}
+
+pub(super) fn review_decision_str(decision: &ReviewDecision) -> &'static str {
+    match decision {
+        ReviewDecision::Approved => "approved",
+        ReviewDecision::ApprovedExecpolicyAmendment { .. } => "approved with execpolicy amendment",
+        ReviewDecision::ApprovedForSession => "approved for session",
+        ReviewDecision::Denied => "denied",
+        ReviewDecision::Abort => "aborted",
+    }
+}
+
+pub(super) async fn handle_exec_approval_request(
+    handle: &SubagentHandle,
+    codex: &Codex,
+    parent_session: &Session,
+    subagent_turn_id: &str,
+    ev: ExecApprovalRequestEvent,
+    share_approvals: bool,
+) {
+    handle.state.lock().await.approvals.exec += 1;
+    if share_approvals && parent_approved_for_session(parent_session, &ev.command, &ev.cwd).await {
+        {
+            let mut state = handle.state.lock().await;
+            push_event(
+                handle,
+                &mut state,
+                SubagentEventKind::Approval,
+                format!(
+                    "exec approved from parent session cache: {}",
+                    ev.command.join(" ")
+                ),
+            );
+        }
+        let _ = codex
+            .submit(Op::ExecApproval {
+                id: subagent_turn_id.to_string(),
+                decision: ReviewDecision::ApprovedForSession,
+            })
+            .await;
+        return;
+    }
+
+    let approval_id = format!("subagent-{}-exec-{}", handle.id, subagent_turn_id);
+    let command = ev.command.clone();
+    let cwd = ev.cwd.clone();
+    push_approval_event(
+        handle,
+        format!("exec approval requested: {}", command.join(" ")),
+    )
+    .await;
+    let decision = parent_session
+        .request_command_approval_background(
+            approval_id,
+            ev.call_id,
+            ev.command,
+            ev.cwd,
+            ev.reason,
+            ev.proposed_execpolicy_amendment,
+        )
+        .await;
+    push_approval_event(
+        handle,
+        format!(
+            "exec {}: {}",
+            review_decision_str(&decision),
+            command.join(" ")
+        ),
+    )
+    .await;
+    if share_approvals && matches!(decision, ReviewDecision::ApprovedForSession) {
+        let mut store = parent_session.services.tool_approvals.lock().await;
+        store.put(
+            SubagentExecApprovalKey {
+                subagent_command: &command,
+                cwd: &cwd,
+            },
+            ReviewDecision::ApprovedForSession,
+        );
+    }
+    let _ = codex
+        .submit(Op::ExecApproval {
+            id: subagent_turn_id.to_string(),
+            decision: decision.clone(),
+        })
+        .await;
+    if matches!(decision, ReviewDecision::Abort) {
+        handle.state.lock().await.approvals.aborted += 1;
+        handle.cancel.cancel();
+    }
+}
+
+pub(super) async fn handle_patch_approval_request(
+    handle: &SubagentHandle,
+    codex: &Codex,
+    parent_session: &Session,
+    subagent_turn_id: &str,
+    ev: ApplyPatchApprovalRequestEvent,
+) {
+    handle.state.lock().await.approvals.patch += 1;
+    let approval_id = format!("subagent-{}-patch-{}", handle.id, subagent_turn_id);
+    let files = ev.changes.len();
+    push_approval_event(
+        handle,
+        format!("patch approval requested ({files} file(s))"),
+    )
+    .await;
+    let decision_rx = parent_session
+        .request_patch_approval_background(
+            approval_id,
+            ev.call_id,
+            ev.changes,
+            ev.reason,
+            ev.grant_root,
+        )
+        .await;
+    let decision = decision_rx.await.unwrap_or_default();
+    push_approval_event(
+        handle,
+        format!("patch {} ({files} file(s))", review_decision_str(&decision)),
+    )
+    .await;
+    let _ = codex
+        .submit(Op::PatchApproval {
+            id: subagent_turn_id.to_string(),
+            decision: decision.clone(),
+        })
+        .await;
+    if matches!(decision, ReviewDecision::Abort) {
+        handle.state.lock().await.approvals.aborted += 1;
+        handle.cancel.cancel();
+    }
+}
+
+/// Denies an approval request from an explore-mode subagent without asking
+/// the user; explore agents are read-only and should not need to write.
+pub(super) async fn auto_deny_approval(
+    handle: &SubagentHandle,
+    codex: &Codex,
+    decision: Op,
+    what: String,
+) {
+    push_approval_event(
+        handle,
+        format!("{what} auto-denied: explore agents are read-only"),
+    )
+    .await;
+    let _ = codex.submit(decision).await;
+}
+
+/// Records the patch for review and denies it so nothing is written.
+pub(super) async fn record_proposed_patch(
+    handle: &SubagentHandle,
+    codex: &Codex,
+    subagent_turn_id: &str,
+    ev: ApplyPatchApprovalRequestEvent,
+) {
+    {
+        let mut state = handle.state.lock().await;
+        push_event(
+            handle,
+            &mut state,
+            SubagentEventKind::Control,
+            format!("patch proposed ({} file(s)); not applied", ev.changes.len()),
+        );
+        state.proposed_patches.push(ProposedPatch {
+            call_id: ev.call_id,
+            changes: ev.changes,
+        });
+        state.last_update = Some(Instant::now());
+    }
+    handle.notify.notify_waiters();
+    let _ = codex
+        .submit(Op::PatchApproval {
+            id: subagent_turn_id.to_string(),
+            decision: ReviewDecision::Denied,
+        })
+        .await;
+}
@@ -0,0 +1,89 @@
+//! The bounded per-agent event buffer and the run summary pushed at the end
+//! of each run.
+
+use std::time::Instant;
+
+use chrono::Utc;
+use codex_protocol::protocol::EventMsg;
+
+use super::SubagentEvent;
+use super::SubagentEventKind;
+use super::SubagentHandle;
+use super::SubagentRunSummary;
+use super::SubagentState;
+use super::millis_between;
+use super::truncate_to_char_boundary;
+
+pub(super) fn push_event(
+    handle: &SubagentHandle,
+    state: &mut SubagentState,
+    kind: SubagentEventKind,
+    mut message: String,
+) {
+    if message.len() > handle.max_event_chars {
+        truncate_to_char_boundary(&mut message, handle.max_event_chars);
+        state.truncated = true;
+    }
+    push_event_entry(handle, state, kind, message, None);
+}
+
+fn push_event_entry(
+    handle: &SubagentHandle,
+    state: &mut SubagentState,
+    kind: SubagentEventKind,
+    message: String,
+    summary: Option<SubagentRunSummary>,
+) {
+    if state.recent_events.len() >= handle.max_events {
+        state.recent_events.pop_front();
+    }
+    let seq = state.next_event_seq;
+    state.next_event_seq += 1;
+    state.recent_events.push_back(SubagentEvent {
+        seq,
+        ts: Utc::now(),
+        at_ms: millis_between(handle.created_at, Instant::now()),
+        kind,
+        message,
+        summary,
+    });
+}
+
+/// Pushes the capstone `Summary` event describing how the run ended.
+pub(super) fn push_summary_event(handle: &SubagentHandle, state: &mut SubagentState, run_ms: u64) {
+    state.summary_pending = false;
+    let summary = SubagentRunSummary {
+        status: state.status,
+        error_kind: state.error_kind,
+        run_ms,
+        tokens: state.tokens_used,
+        tool_calls: state.tool_calls.clone(),
+        truncated: state.truncated,
+    };
+    let tool_calls: u64 = summary.tool_calls.values().sum();
+    let message = format!(
+        "summary: {} in {run_ms}ms, {} tokens, {tool_calls} tool call(s){}",
+        summary.status.as_str(),
+        summary.tokens,
+        if summary.truncated { ", truncated" } else { "" },
+    );
+    push_event_entry(
+        handle,
+        state,
+        SubagentEventKind::Summary,
+        message,
+        Some(summary),
+    );
+}
+
+/// Tool kind counted in the run summary, if `msg` starts a tool call.
+pub(super) fn tool_call_kind(msg: &EventMsg) -> Option<&'static str> {
+    match msg {
+        EventMsg::ExecCommandBegin(_) => Some("exec"),
+        EventMsg::PatchApplyBegin(_) => Some("apply_patch"),
+        EventMsg::McpToolCallBegin(_) => Some("mcp"),
+        EventMsg::WebSearchBegin(_) => Some("web_search"),
+        EventMsg::ViewImageToolCall(_) => Some("view_image"),
+        _ => None,
+    }
+}
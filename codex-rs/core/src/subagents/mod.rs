@@ -0,0 +1,3121 @@
+mod approvals;
+mod events;
+mod output;
+mod queue;
+mod registry;
+mod run;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::DateTime;
+use chrono::Utc;
+use codex_protocol::openai_models::ReasoningEffort;
+use codex_protocol::protocol::FileChange;
+use codex_protocol::protocol::InitialHistory;
+use codex_protocol::protocol::Op;
+use codex_protocol::protocol::RolloutItem;
+use codex_protocol::user_input::UserInput;
+use mcp_types::Tool as McpTool;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::sync::RwLock;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::AuthManager;
+use crate::codex::Codex;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::config::types::OutputPostprocess;
+use crate::config::types::ResumeModelPolicy;
+use crate::custom_agents::AgentToolsPolicy;
+use crate::features::Feature;
+use crate::git_info::get_git_repo_root;
+use crate::openai_models::model_family::ModelFamily;
+use crate::openai_models::models_manager::ModelsManager;
+use crate::protocol::AskForApproval;
+use crate::protocol::SandboxPolicy;
+use crate::rollout::RolloutRecorder;
+use crate::rollout::SESSIONS_SUBDIR;
+use crate::skills::SkillLoadOutcome;
+use crate::skills::SkillsManager;
+use crate::skills::describe_unmatched_skill;
+use crate::skills::resolve_requested_skills;
+use crate::tools::router::ToolRouter;
+use crate::tools::spec::ToolsConfig;
+use crate::tools::spec::ToolsConfigParams;
+
+use events::push_event;
+use output::materialize_output;
+use output::render_transcript;
+use output::slice_output;
+use queue::QueuedAgent;
+use queue::SessionLimiter;
+use run::create_isolated_workspace;
+use run::run_subagent_in_workspace;
+
+const SESSION_CONFIGURED_TIMEOUT: Duration = Duration::from_secs(2);
+/// Budget for interrupting and shutting down a finished agent's session.
+const SUBAGENT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+const SKILL_RESCAN_INTERVAL: Duration = Duration::from_millis(100);
+const SPAWN_RETRY_BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Bound on the model call that condenses an oversized final output.
+const OVERFLOW_SUMMARY_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_AGENT_ID_LEN: usize = 64;
+pub(crate) const MAX_TRACE_ID_LEN: usize = 128;
+/// Header carrying `SubagentSpawnRequest::trace_id`, sent next to
+/// `x-openai-subagent`.
+pub(crate) const SUBAGENT_TRACE_ID_HEADER: &str = "x-codex-trace-id";
+const DEFAULT_SUBAGENT_PRIORITY: i32 = 0;
+const MAX_AUDIT_EVENTS: usize = 512;
+/// `abort_reason` of agents stopped without an explicit reason.
+const DEFAULT_ABORT_REASON: &str = "cancelled";
+const MAX_ABORT_REASON_LEN: usize = 256;
+const MAX_EXTRA_INSTRUCTIONS_LEN: usize = 4 * 1024;
+/// Bounds of the `suggested_next_poll_ms` hint.
+const MIN_SUGGESTED_POLL_MS: u64 = 1_000;
+const MAX_SUGGESTED_POLL_MS: u64 = 30_000;
+/// Tool arguments and outputs longer than this are cut in transcripts.
+const TRANSCRIPT_TOOL_TEXT_BYTES: usize = 2 * 1024;
+const ABORT_SIBLINGS_MARKER: &str = "<<abort-siblings:";
+const CONFIDENCE_MARKER: &str = "<<confidence:";
+const ABORT_SIBLINGS_INSTRUCTIONS: &str = "- Fatal findings: if you find a critical issue that makes the rest of this fan-out moot, include `<<abort-siblings: short reason>>` in a message to stop sibling agents.\n";
+const CONFIDENCE_INSTRUCTIONS: &str = "- Confidence: end your final answer with `<<confidence: X>>`, where X between 0.0 and 1.0 is how confident you are that it is correct.\n";
+const PROPOSE_ONLY_INSTRUCTIONS: &str = "- Proposal only: your patches are recorded for human review and are never applied. A denied patch is expected; do not retry it or work around it. Propose each change once and describe it in your final answer.\n";
+const LAZY_OUTPUT_MARKER: &str =
+    "ready (lazy_output: call subagent_result with materialize: true for the full answer)";
+const COOPERATIVE_BUDGET_MESSAGE: &str = "The session's subagent token budget is nearly exhausted. Conserve tokens and wrap up soon: finish the current step and respond with your final answer.";
+
+static SUBAGENT_CONCURRENCY_LIMITER: OnceLock<Arc<Semaphore>> = OnceLock::new();
+static DELEGATE_CONCURRENCY_LIMITER: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+fn default_max_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(std::num::NonZero::get)
+        .unwrap_or(2)
+        .clamp(1, 4)
+}
+
+fn resolve_max_concurrency(max_concurrency: Option<usize>) -> usize {
+    max_concurrency
+        .unwrap_or_else(default_max_concurrency)
+        .clamp(1, 64)
+}
+
+pub(crate) fn init_global_subagent_limiter(max_concurrency: Option<usize>) {
+    if SUBAGENT_CONCURRENCY_LIMITER.get().is_some() {
+        return;
+    }
+
+    let max_concurrency = resolve_max_concurrency(max_concurrency);
+    let _ = SUBAGENT_CONCURRENCY_LIMITER.set(Arc::new(Semaphore::new(max_concurrency)));
+}
+
+pub(crate) fn global_subagent_limiter() -> Arc<Semaphore> {
+    SUBAGENT_CONCURRENCY_LIMITER
+        .get_or_init(|| Arc::new(Semaphore::new(default_max_concurrency())))
+        .clone()
+}
+
+pub(crate) fn init_global_delegate_limiter(delegate_concurrency: Option<usize>) {
+    if DELEGATE_CONCURRENCY_LIMITER.get().is_some() {
+        return;
+    }
+
+    let delegate_concurrency = resolve_max_concurrency(delegate_concurrency);
+    let _ = DELEGATE_CONCURRENCY_LIMITER.set(Arc::new(Semaphore::new(delegate_concurrency)));
+}
+
+/// Limiter for synchronous `delegate` calls, separate from the background
+/// subagent pool.
+pub(crate) fn global_delegate_limiter() -> Arc<Semaphore> {
+    DELEGATE_CONCURRENCY_LIMITER
+        .get_or_init(|| Arc::new(Semaphore::new(default_max_concurrency())))
+        .clone()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SubagentMode {
+    /// Read-only, tool-light profile meant for exploration and planning.
+    Explore,
+    /// General-purpose profile that can edit and run tools (subject to approvals).
+    #[default]
+    General,
+    /// Read-only code review: looks for bugs, security issues, and missing
+    /// tests; can search the web but cannot patch files or run commands.
+    Review,
+}
+
+impl SubagentMode {
+    pub(crate) fn from_str(mode: &str) -> Option<Self> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "explore" | "explorer" | "read-only" | "readonly" => Some(Self::Explore),
+            "general" | "default" | "worker" => Some(Self::General),
+            "review" | "reviewer" => Some(Self::Review),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Explore => "explore",
+            Self::General => "general",
+            Self::Review => "review",
+        }
+    }
+}
+
+impl From<SubagentMode> for codex_protocol::protocol::SubagentMode {
+    fn from(mode: SubagentMode) -> Self {
+        match mode {
+            SubagentMode::Explore => Self::Explore,
+            SubagentMode::General => Self::General,
+            SubagentMode::Review => Self::Review,
+        }
+    }
+}
+
+impl From<codex_protocol::protocol::SubagentMode> for SubagentMode {
+    fn from(mode: codex_protocol::protocol::SubagentMode) -> Self {
+        match mode {
+            codex_protocol::protocol::SubagentMode::Explore => Self::Explore,
+            codex_protocol::protocol::SubagentMode::General => Self::General,
+            codex_protocol::protocol::SubagentMode::Review => Self::Review,
+        }
+    }
+}
+
+/// Shape the parent expects the subagent's final answer to take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SubagentOutputFormat {
+    /// Free-form prose (the default).
+    #[default]
+    Text,
+    /// A single valid JSON value and nothing else.
+    Json,
+}
+
+impl SubagentOutputFormat {
+    pub(crate) fn from_str(format: &str) -> Option<Self> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "text" | "markdown" | "prose" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// How the subagent should cite its sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SubagentCitationStyle {
+    /// No citation guidance (the default).
+    #[default]
+    None,
+    /// Put the source URL (or file path) right after each claim.
+    InlineUrls,
+    /// Numbered `[n]` markers with a `Sources` list at the end.
+    Numbered,
+}
+
+impl SubagentCitationStyle {
+    pub(crate) fn from_str(style: &str) -> Option<Self> {
+        match style.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "inline-urls" | "inline_urls" | "inline" => Some(Self::InlineUrls),
+            "numbered" => Some(Self::Numbered),
+            _ => None,
+        }
+    }
+
+    fn instructions(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::InlineUrls => Some(
+                "- Citations: cite every sourced claim inline by putting its URL (or file path with line numbers) in parentheses right after the claim.\n",
+            ),
+            Self::Numbered => Some(
+                "- Citations: mark sourced claims with numbered references like [1], [2] and end your answer with a `Sources` list mapping each number to its URL or file path.\n",
+            ),
+        }
+    }
+}
+
+/// Post-processing applied to a finished subagent's output before it is
+/// returned to the parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SubagentResultFormat {
+    /// The output as the subagent wrote it (the default).
+    #[default]
+    Raw,
+    /// Only the contents of the first fenced code block.
+    FirstCodeBlock,
+    /// The first JSON object in the output, re-serialized compactly.
+    Json,
+}
+
+impl SubagentResultFormat {
+    pub(crate) fn from_str(format: &str) -> Option<Self> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "raw" => Some(Self::Raw),
+            "first_code_block" => Some(Self::FirstCodeBlock),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Applies the format to `text`. Errors describe why the output has no
+    /// part of the requested shape; callers fall back to the raw text.
+    pub(crate) fn apply(self, text: &str) -> Result<String, String> {
+        match self {
+            Self::Raw => Ok(text.to_string()),
+            Self::FirstCodeBlock => first_code_block(text)
+                .map(str::to_string)
+                .ok_or_else(|| "output has no fenced code block".to_string()),
+            Self::Json => first_json_object(text)
+                .map(|value| value.to_string())
+                .ok_or_else(|| "output has no JSON object".to_string()),
+        }
+    }
+}
+
+/// Contents of the first ```-fenced block, without the info string. An
+/// unclosed fence runs to the end of the text.
+fn first_code_block(text: &str) -> Option<&str> {
+    let start = text.find("```")?;
+    let after_fence = &text[start + 3..];
+    let body_start = after_fence.find('\n')? + 1;
+    let body = &after_fence[body_start..];
+    let body = match body.find("```") {
+        Some(end) => &body[..end],
+        None => body,
+    };
+    Some(body.strip_suffix('\n').unwrap_or(body))
+}
+
+/// The first `{` in `text` that starts a complete JSON object.
+fn first_json_object(text: &str) -> Option<serde_json::Value> {
+    text.match_indices('{').find_map(|(start, _)| {
+        let mut values =
+            serde_json::Deserializer::from_str(&text[start..]).into_iter::<serde_json::Value>();
+        match values.next() {
+            Some(Ok(value @ serde_json::Value::Object(_))) => Some(value),
+            _ => None,
+        }
+    })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubagentSpawnRequest {
+    pub(crate) agent_id: Option<String>,
+    pub(crate) mode: SubagentMode,
+    pub(crate) label: String,
+    pub(crate) prompt: String,
+    pub(crate) skills: Vec<String>,
+    pub(crate) timeout_ms: Option<u64>,
+    /// Absolute stop time (Unix epoch milliseconds). The run is aborted at
+    /// this moment or after `timeout_ms`, whichever comes first.
+    pub(crate) deadline_unix_ms: Option<u64>,
+    pub(crate) resume_rollout_path: Option<PathBuf>,
+    pub(crate) output_format: SubagentOutputFormat,
+    pub(crate) citation_style: SubagentCitationStyle,
+    /// Lets this agent cancel other live agents sharing its label by emitting
+    /// an `<<abort-siblings: reason>>` marker.
+    pub(crate) allow_sibling_abort: bool,
+    /// Ask the agent to end its answer with a `<<confidence: X>>` marker, which
+    /// is stripped from its messages and reported as `confidence`.
+    pub(crate) report_confidence: bool,
+    /// Auto-deny every patch approval and record the patch instead, so the
+    /// agent's edits are collected for review without touching the workspace.
+    pub(crate) propose_only: bool,
+    /// Number of times a run that ends in `Error` is restarted.
+    pub(crate) max_retries: u32,
+    /// Delay before each retry.
+    pub(crate) retry_backoff_ms: u64,
+    /// Write a rollout file for the subagent. When false nothing hits disk,
+    /// `rollout_path` stays `None`, and the agent cannot be resumed.
+    pub(crate) record_rollout: bool,
+    /// Refuse the spawn when this many queued or running agents already share
+    /// the label.
+    pub(crate) max_per_label: Option<usize>,
+    /// Stable telemetry category used for `SessionSource::SubAgent` tagging
+    /// instead of the label.
+    pub(crate) source_category: Option<String>,
+    /// Store only `LAZY_OUTPUT_MARKER` as the final output; the full answer is
+    /// read back from the rollout on demand.
+    pub(crate) lazy_output: bool,
+    /// Forget the agent on the next maintenance pass once its final output
+    /// has been returned by a poll.
+    pub(crate) ephemeral: bool,
+    /// How long to keep re-scanning for requested skills that are not
+    /// discovered yet before failing (0 fails immediately).
+    pub(crate) await_skills_ms: u64,
+    /// Only one agent per group runs at a time; the rest wait after getting
+    /// a concurrency permit.
+    pub(crate) mutex_group: Option<String>,
+    /// Correlation id sent as `SUBAGENT_TRACE_ID_HEADER` on every provider
+    /// request the subagent makes.
+    pub(crate) trace_id: Option<String>,
+    /// Tool restriction from a custom agent definition. `Allowlist` filters
+    /// the subagent's tool registry and `None` removes every tool.
+    pub(crate) tools_policy: AgentToolsPolicy,
+    /// Model override from a custom agent definition. Checked against the
+    /// available models at spawn time.
+    pub(crate) model: Option<String>,
+    /// Working directory for the agent, absolute or relative to the parent's
+    /// cwd. Must resolve inside the parent's repository root.
+    pub(crate) cwd: Option<PathBuf>,
+    /// Agents that must complete before this one starts. If one of them
+    /// errors or is aborted, this agent is aborted too.
+    pub(crate) depends_on: Vec<String>,
+    /// Queue priority for a concurrency slot; higher values start first and
+    /// equal priorities start in spawn order.
+    pub(crate) priority: i32,
+    /// Parent tool call that requested the spawn; `None` for agents started
+    /// outside a model turn (e.g. by a user command).
+    pub(crate) spawned_by: Option<SubagentSpawnedBy>,
+    /// Overrides of `[subagents].max_events` / `max_output_chars` for this
+    /// agent, already clamped to the configurable range.
+    pub(crate) max_events: Option<usize>,
+    pub(crate) max_output_chars: Option<usize>,
+    /// Task-specific guidance appended after the generated instructions;
+    /// already sanitized with [`sanitize_extra_instructions`].
+    pub(crate) extra_instructions: Option<String>,
+    /// Reasoning effort override; `None` inherits the parent's.
+    pub(crate) reasoning_effort: Option<ReasoningEffort>,
+}
+
+/// The parent turn and tool call that spawned an agent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SubagentSpawnedBy {
+    pub(crate) turn_id: String,
+    pub(crate) call_id: String,
+}
+
+impl Default for SubagentSpawnRequest {
+    fn default() -> Self {
+        Self {
+            agent_id: None,
+            mode: SubagentMode::default(),
+            label: String::new(),
+            prompt: String::new(),
+            skills: Vec::new(),
+            timeout_ms: None,
+            deadline_unix_ms: None,
+            resume_rollout_path: None,
+            output_format: SubagentOutputFormat::default(),
+            citation_style: SubagentCitationStyle::default(),
+            allow_sibling_abort: false,
+            report_confidence: false,
+            propose_only: false,
+            max_retries: 0,
+            retry_backoff_ms: 0,
+            record_rollout: true,
+            max_per_label: None,
+            source_category: None,
+            lazy_output: false,
+            ephemeral: false,
+            await_skills_ms: 0,
+            mutex_group: None,
+            trace_id: None,
+            tools_policy: AgentToolsPolicy::Inherit,
+            model: None,
+            cwd: None,
+            depends_on: Vec::new(),
+            priority: DEFAULT_SUBAGENT_PRIORITY,
+            spawned_by: None,
+            max_events: None,
+            max_output_chars: None,
+            extra_instructions: None,
+            reasoning_effort: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubagentSpawnResponse {
+    pub(crate) agent_id: String,
+    pub(crate) status: SubagentStatus,
+    pub(crate) label: String,
+    pub(crate) mode: SubagentMode,
+    /// True when an identical active agent was returned instead of spawning
+    /// a new one (`dedupe_spawns`).
+    pub(crate) deduplicated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SubagentStatus {
+    Queued,
+    Running,
+    Complete,
+    Aborted,
+    Error,
+}
+
+impl Default for SubagentStatus {
+    fn default() -> Self {
+        Self::Queued
+    }
+}
+
+impl SubagentStatus {
+    pub(crate) fn from_str(status: &str) -> Option<Self> {
+        match status.trim().to_ascii_lowercase().as_str() {
+            "queued" => Some(Self::Queued),
+            "running" => Some(Self::Running),
+            "complete" => Some(Self::Complete),
+            "aborted" => Some(Self::Aborted),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Complete => "complete",
+            Self::Aborted => "aborted",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl From<SubagentStatus> for codex_protocol::protocol::SubagentStatus {
+    fn from(status: SubagentStatus) -> Self {
+        match status {
+            SubagentStatus::Queued => Self::Queued,
+            SubagentStatus::Running => Self::Running,
+            SubagentStatus::Complete => Self::Complete,
+            SubagentStatus::Aborted => Self::Aborted,
+            SubagentStatus::Error => Self::Error,
+        }
+    }
+}
+
+impl From<codex_protocol::protocol::SubagentStatus> for SubagentStatus {
+    fn from(status: codex_protocol::protocol::SubagentStatus) -> Self {
+        match status {
+            codex_protocol::protocol::SubagentStatus::Queued => Self::Queued,
+            codex_protocol::protocol::SubagentStatus::Running => Self::Running,
+            codex_protocol::protocol::SubagentStatus::Complete => Self::Complete,
+            codex_protocol::protocol::SubagentStatus::Aborted => Self::Aborted,
+            codex_protocol::protocol::SubagentStatus::Error => Self::Error,
+        }
+    }
+}
+
+/// One entry of a fenced `checklist` block emitted by a subagent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChecklistItem {
+    pub(crate) text: String,
+    pub(crate) done: bool,
+}
+
+/// Why a subagent ended in `Error`, when the failure was classified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SubagentErrorKind {
+    /// The driver panicked; the panic message is in `final_output`.
+    Panic,
+    /// The provider rejected requests with a rate limit (HTTP 429).
+    RateLimited,
+    /// The subagent's session could not be created or given its prompt.
+    Spawn,
+    /// The event stream failed or the session went away mid-run.
+    Stream,
+    /// The session reported an error for the turn (model or provider).
+    Model,
+    /// The run exceeded its `timeout_ms`.
+    Timeout,
+    /// No event arrived within `idle_timeout_ms`.
+    IdleTimeout,
+    /// A requested skill was not available.
+    SkillResolution,
+    /// The rollout to resume could not be read or was rejected.
+    ResumeHistory,
+}
+
+impl SubagentErrorKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Panic => "panic",
+            Self::RateLimited => "rate_limited",
+            Self::Spawn => "spawn",
+            Self::Stream => "stream",
+            Self::Model => "model",
+            Self::Timeout => "timeout",
+            Self::IdleTimeout => "idle_timeout",
+            Self::SkillResolution => "skill_resolution",
+            Self::ResumeHistory => "resume_history",
+        }
+    }
+}
+
+/// Category of an entry in a subagent's `recent_events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SubagentEventKind {
+    /// Lifecycle transitions (running, complete, cancelled, retrying, ...).
+    Status,
+    /// Messages produced by the subagent itself.
+    Message,
+    /// Failures reported by or about the subagent.
+    Error,
+    /// Actions taken on the subagent's behalf (budget warnings, sibling aborts, proposed patches).
+    Control,
+    /// Approval requests forwarded to the parent session and their decisions.
+    Approval,
+    /// Final outcome record pushed once a run ends; carries a `SubagentRunSummary`.
+    Summary,
+}
+
+impl SubagentEventKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Message => "message",
+            Self::Error => "error",
+            Self::Control => "control",
+            Self::Approval => "approval",
+            Self::Summary => "summary",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubagentEvent {
+    /// Per-agent sequence number, starting at 0 and never reused.
+    pub(crate) seq: u64,
+    pub(crate) ts: DateTime<Utc>,
+    /// Milliseconds since the agent was spawned.
+    pub(crate) at_ms: u64,
+    pub(crate) kind: SubagentEventKind,
+    pub(crate) message: String,
+    /// Set only on the final `Summary` event.
+    pub(crate) summary: Option<SubagentRunSummary>,
+}
+
+/// Outcome of one subagent run, recorded as its last event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SubagentRunSummary {
+    pub(crate) status: SubagentStatus,
+    pub(crate) error_kind: Option<SubagentErrorKind>,
+    /// Time from acquiring a concurrency slot to the end of the run.
+    pub(crate) run_ms: u64,
+    pub(crate) tokens: u64,
+    /// Tool calls started by the subagent, keyed by tool kind.
+    pub(crate) tool_calls: BTreeMap<&'static str, u64>,
+    /// Whether the final output or any event message was truncated.
+    pub(crate) truncated: bool,
+}
+
+/// Tokens used by an agent, summed over its runs and follow-up turns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SubagentTokenUsage {
+    /// Prompt tokens, cached ones included.
+    pub(crate) input: u64,
+    /// Completion tokens, reasoning included.
+    pub(crate) output: u64,
+    pub(crate) total: u64,
+}
+
+/// Approval requests an agent forwarded to the parent session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SubagentApprovalCounts {
+    pub(crate) exec: u64,
+    pub(crate) patch: u64,
+    /// Requests answered with `Abort`, which also cancels the agent.
+    pub(crate) aborted: u64,
+}
+
+impl SubagentTokenUsage {
+    fn saturating_add(self, other: Self) -> Self {
+        Self {
+            input: self.input.saturating_add(other.input),
+            output: self.output.saturating_add(other.output),
+            total: self.total.saturating_add(other.total),
+        }
+    }
+}
+
+/// A patch captured (and denied) from a `propose_only` agent.
+#[derive(Debug, Clone)]
+pub(crate) struct ProposedPatch {
+    pub(crate) call_id: String,
+    pub(crate) changes: HashMap<PathBuf, FileChange>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubagentPollResponse {
+    pub(crate) agent_id: String,
+    pub(crate) status: SubagentStatus,
+    pub(crate) label: String,
+    pub(crate) mode: SubagentMode,
+    pub(crate) rollout_path: Option<PathBuf>,
+    pub(crate) final_output: Option<String>,
+    /// Text streamed so far in the current run; `None` once `final_output`
+    /// is set or before anything was streamed.
+    pub(crate) partial_output: Option<String>,
+    /// The agent's own output cap: its `max_output_chars` override, if any.
+    pub(crate) max_output_chars: usize,
+    pub(crate) recent_events: Vec<SubagentEvent>,
+    /// `seq` the next event will get; pass it back as `since_event_index` to
+    /// poll only newer events.
+    pub(crate) next_event_index: u64,
+    pub(crate) checklist: Vec<ChecklistItem>,
+    pub(crate) proposed_patches: Vec<ProposedPatch>,
+    pub(crate) error_kind: Option<SubagentErrorKind>,
+    /// Self-reported score from the agent's latest `<<confidence: X>>` marker.
+    pub(crate) confidence: Option<f32>,
+    /// Time from prompt submission to the first streamed token.
+    pub(crate) ttft_ms: Option<u64>,
+    /// Time from prompt submission to task completion.
+    pub(crate) total_ms: Option<u64>,
+    /// Time since the agent was spawned.
+    pub(crate) elapsed_ms: u64,
+    /// Time since the current run started `Running`; `None` while queued.
+    pub(crate) running_ms: Option<u64>,
+    pub(crate) tokens: SubagentTokenUsage,
+    pub(crate) approvals: SubagentApprovalCounts,
+    /// Isolated scratch directory, while it exists.
+    pub(crate) workspace: Option<PathBuf>,
+    /// Sandbox policy the agent runs with (e.g. `read-only`); `None` until
+    /// its run starts.
+    pub(crate) sandbox: Option<String>,
+    /// Approval policy the agent runs with (e.g. `on-request`).
+    pub(crate) approval: Option<String>,
+    /// Reasoning effort the agent runs with, when one is configured.
+    pub(crate) reasoning_effort: Option<ReasoningEffort>,
+    /// Why an `Aborted` agent was stopped (`cancelled` when no reason was
+    /// recorded).
+    pub(crate) abort_reason: Option<String>,
+    pub(crate) spawned_by: Option<SubagentSpawnedBy>,
+    /// Advisory wait before polling a queued or running agent again.
+    pub(crate) suggested_next_poll_ms: Option<u64>,
+}
+
+/// What a spawn would start, as reported by [`SubagentManager::dry_run_spawn`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubagentDryRun {
+    /// Sanitized requested id; without one, an id is generated at spawn time.
+    pub(crate) agent_id: Option<String>,
+    pub(crate) label: String,
+    pub(crate) mode: SubagentMode,
+    /// Names of the skills the request resolves to.
+    pub(crate) skills: Vec<String>,
+}
+
+/// Output of [`SubagentManager::result`].
+#[derive(Debug, Clone)]
+pub(crate) struct SubagentResult {
+    pub(crate) status: SubagentStatus,
+    pub(crate) final_output: Option<String>,
+    /// True when `final_output` was read back from the rollout.
+    pub(crate) materialized: bool,
+}
+
+/// One slice of an agent's full final output, for `subagent_result_chunk`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubagentOutputChunk {
+    pub(crate) status: SubagentStatus,
+    /// Byte offset of `chunk` (snapped back to a char boundary).
+    pub(crate) offset: usize,
+    pub(crate) chunk: String,
+    /// Total length of the full output in bytes.
+    pub(crate) total_len: usize,
+    /// Offset to request next, or `None` once the end has been returned.
+    pub(crate) next_offset: Option<usize>,
+    /// True when the full output was read back from the rollout.
+    pub(crate) from_rollout: bool,
+}
+
+/// One slice of an agent's rendered rollout transcript, for `subagent_logs`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubagentTranscriptChunk {
+    pub(crate) status: SubagentStatus,
+    /// Byte offset of `chunk` (snapped back to a char boundary).
+    pub(crate) offset: usize,
+    pub(crate) chunk: String,
+    /// Total length of the rendered transcript in bytes.
+    pub(crate) total_len: usize,
+    /// Offset to request next, or `None` once the end has been returned.
+    pub(crate) next_offset: Option<usize>,
+}
+
+#[derive(Default)]
+struct SubagentState {
+    status: SubagentStatus,
+    rollout_path: Option<PathBuf>,
+    final_output: Option<String>,
+    recent_events: VecDeque<SubagentEvent>,
+    next_event_seq: u64,
+    last_update: Option<Instant>,
+    /// Live conversation handle while the subagent is running.
+    codex: Option<Arc<Codex>>,
+    /// Total tokens reported by the subagent's most recent token count event.
+    tokens_used: u64,
+    /// Input and output halves of `tokens_used`.
+    input_tokens: u64,
+    output_tokens: u64,
+    /// Usage of earlier runs of this agent (before retries).
+    earlier_runs_tokens: SubagentTokenUsage,
+    /// Built-in tool names exposed to the subagent after profile/policy overrides.
+    tools: Vec<String>,
+    /// Remaining-steps checklist parsed from the final output, if any.
+    checklist: Vec<ChecklistItem>,
+    /// Patches recorded instead of applied (`propose_only` agents).
+    proposed_patches: Vec<ProposedPatch>,
+    error_kind: Option<SubagentErrorKind>,
+    /// When the current run moved to `Running`.
+    running_since: Option<Instant>,
+    /// Set while a run is in flight and cleared once its `Summary` event is
+    /// pushed, which happens after the session shuts down. Awaited polls keep
+    /// waiting on a terminal agent until then so they see the summary.
+    summary_pending: bool,
+    /// When the prompt was submitted to the subagent's conversation.
+    submitted_at: Option<Instant>,
+    /// When the first agent message delta (or message) arrived.
+    first_token_at: Option<Instant>,
+    /// When the subagent reported `TaskComplete`.
+    completed_at: Option<Instant>,
+    /// Tool calls started during the current run, keyed by tool kind.
+    tool_calls: BTreeMap<&'static str, u64>,
+    /// Set when the final output or an event message had to be truncated.
+    truncated: bool,
+    /// Set when the stored `final_output` itself was cut to `max_output_chars`.
+    output_truncated: bool,
+    /// Set once a poll has returned this agent's terminal `final_output`.
+    consumed: bool,
+    /// Working directory set via `set_cwd`; overrides the inherited cwd.
+    cwd: Option<PathBuf>,
+    /// Scratch directory the agent works in under `isolated_workspace`;
+    /// cleared once the directory is removed.
+    workspace: Option<PathBuf>,
+    /// Sandbox and approval policy of the current run, after mode overrides.
+    sandbox: Option<String>,
+    approval: Option<String>,
+    /// Reasoning effort of the current run; `None` uses the model default.
+    reasoning_effort: Option<ReasoningEffort>,
+    /// Why the agent was stopped, reported by polls once it is `Aborted`.
+    /// The first recorded reason wins.
+    abort_reason: Option<String>,
+    /// Approval requests forwarded to the parent, over all runs.
+    approvals: SubagentApprovalCounts,
+    /// Latest `<<confidence: X>>` score reported by the agent.
+    confidence: Option<f32>,
+    /// Provider-suggested delay from the latest rate-limit error.
+    retry_after: Option<Duration>,
+    /// Follow-up messages sent before the prompt was submitted; delivered
+    /// right after it.
+    queued_messages: Vec<String>,
+    /// Agents this one waits for before starting (`depends_on`).
+    depends_on: Vec<String>,
+    /// Agent messages of the current run, followed by the deltas of the one
+    /// still streaming. Capped at `max_output_chars`.
+    partial_output: String,
+    /// Length of `partial_output` covered by completed messages.
+    partial_committed_len: usize,
+}
+
+impl SubagentState {
+    /// Bytes held by the retained event messages.
+    fn event_bytes(&self) -> usize {
+        self.recent_events
+            .iter()
+            .map(|event| event.message.len())
+            .sum()
+    }
+
+    /// Appends a streamed delta to the in-progress message.
+    fn push_partial_delta(&mut self, delta: &str, max_chars: usize) {
+        if self.partial_output.len() >= max_chars {
+            return;
+        }
+        // First delta of a new message: separate it from the committed ones.
+        if self.partial_committed_len > 0 && self.partial_output.len() == self.partial_committed_len
+        {
+            self.partial_output.push_str("\n\n");
+        }
+        self.partial_output.push_str(delta);
+        truncate_to_char_boundary(&mut self.partial_output, max_chars);
+    }
+
+    /// Replaces the streamed deltas of the current message with its full text.
+    fn commit_partial_message(&mut self, message: &str, max_chars: usize) {
+        self.partial_output.truncate(self.partial_committed_len);
+        if !self.partial_output.is_empty() {
+            self.partial_output.push_str("\n\n");
+        }
+        self.partial_output.push_str(message);
+        truncate_to_char_boundary(&mut self.partial_output, max_chars);
+        self.partial_committed_len = self.partial_output.len();
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        if self.final_output.is_some() || self.partial_output.is_empty() {
+            None
+        } else {
+            Some(self.partial_output.clone())
+        }
+    }
+
+    /// Usage of the current run plus every earlier one.
+    fn token_usage(&self) -> SubagentTokenUsage {
+        self.earlier_runs_tokens.saturating_add(SubagentTokenUsage {
+            input: self.input_tokens,
+            output: self.output_tokens,
+            total: self.tokens_used,
+        })
+    }
+
+    /// Records why the agent is being stopped unless a reason is already set.
+    fn note_abort_reason(&mut self, reason: impl Into<String>) {
+        if self.abort_reason.is_none() {
+            self.abort_reason = Some(reason.into());
+        }
+    }
+
+    fn latency_ms(&self, until: Option<Instant>) -> Option<u64> {
+        let elapsed = until?.checked_duration_since(self.submitted_at?)?;
+        Some(u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX))
+    }
+}
+
+struct SubagentHandle {
+    id: String,
+    label: String,
+    mode: SubagentMode,
+    cancel: CancellationToken,
+    notify: Notify,
+    state: Mutex<SubagentState>,
+    created_at: Instant,
+    max_events: usize,
+    max_event_chars: usize,
+    max_output_chars: usize,
+    lazy_output: bool,
+    join_messages: bool,
+    ephemeral: bool,
+    output_postprocess: OutputPostprocess,
+    /// The request this agent was spawned with, replayed by `restart`. `None`
+    /// for agents restored from the parent rollout.
+    request: Option<SubagentSpawnRequest>,
+}
+
+impl SubagentHandle {
+    fn spawned_by(&self) -> Option<&SubagentSpawnedBy> {
+        self.request.as_ref()?.spawned_by.as_ref()
+    }
+}
+
+/// Kind of manager-level action recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ManagerEventKind {
+    Spawned,
+    Pruned,
+    Cancelled,
+    BudgetWarning,
+    SiblingsAborted,
+    Reprioritized,
+    Retried,
+    CachesCleared,
+    EventsTrimmed,
+    Forgotten,
+    CwdChanged,
+    MessageSent,
+    Restored,
+    Restarted,
+}
+
+impl ManagerEventKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Spawned => "spawned",
+            Self::Pruned => "pruned",
+            Self::Cancelled => "cancelled",
+            Self::BudgetWarning => "budget_warning",
+            Self::SiblingsAborted => "siblings_aborted",
+            Self::Reprioritized => "reprioritized",
+            Self::Retried => "retried",
+            Self::CachesCleared => "caches_cleared",
+            Self::EventsTrimmed => "events_trimmed",
+            Self::Forgotten => "forgotten",
+            Self::CwdChanged => "cwd_changed",
+            Self::MessageSent => "message_sent",
+            Self::Restored => "restored",
+            Self::Restarted => "restarted",
+        }
+    }
+}
+
+/// One entry of the manager's audit log.
+#[derive(Debug, Clone)]
+pub(crate) struct ManagerEvent {
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) kind: ManagerEventKind,
+    pub(crate) agent_id: Option<String>,
+    pub(crate) detail: String,
+}
+
+/// Outcome of [`SubagentManager::set_priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SetPriorityOutcome {
+    /// The agent was waiting for a permit and has been re-ordered.
+    Updated,
+    /// The agent already holds a permit (or has finished); nothing changed.
+    NotQueued,
+}
+
+/// How `SubagentManager::send` handed off a follow-up message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SendOutcome {
+    /// Submitted to the live conversation; the model sees it at its next step.
+    Delivered,
+    /// The agent has not submitted its prompt yet; the message follows it.
+    Queued,
+}
+
+impl SendOutcome {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Queued => "queued",
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SubagentManager {
+    agents: RwLock<HashMap<String, Arc<SubagentHandle>>>,
+    /// Set once the cooperative budget warning has been delivered to running agents.
+    budget_warning_sent: AtomicBool,
+    /// Tokens used by agents that were pruned or forgotten, so they keep
+    /// counting toward the cooperative budget after leaving `agents`.
+    retired_tokens: AtomicU64,
+    /// Latest partial output of in-flight `delegate` calls, keyed by the
+    /// call id of the `delegate` tool call.
+    delegate_progress: Mutex<HashMap<String, DelegateProgress>>,
+    /// Agents waiting for a concurrency permit.
+    permit_queue: Mutex<Vec<QueuedAgent>>,
+    permit_queue_changed: Notify,
+    permit_queue_seq: AtomicU64,
+    /// Chronological log of manager-level actions, capped at `MAX_AUDIT_EVENTS`.
+    audit_log: Mutex<VecDeque<ManagerEvent>>,
+    caches: Mutex<SubagentCaches>,
+    /// One single-permit semaphore per `mutex_group`, created on first use.
+    mutex_groups: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// This session's share of the global pool; set by `SubagentManager::new`.
+    session_limiter: OnceLock<SessionLimiter>,
+}
+
+/// Partial output published by one running `delegate` call.
+struct DelegateProgress {
+    label: String,
+    partial: String,
+}
+
+/// Free concurrency slots, for `subagent_list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConcurrencySnapshot {
+    pub(crate) session_free: usize,
+    pub(crate) session_limit: usize,
+    pub(crate) global_free: usize,
+}
+
+/// Agent counts by status, for `subagent_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SubagentStatusCounts {
+    pub(crate) queued: usize,
+    pub(crate) running: usize,
+    pub(crate) complete: usize,
+    pub(crate) aborted: usize,
+    pub(crate) error: usize,
+}
+
+impl SubagentStatusCounts {
+    pub(crate) fn total(&self) -> usize {
+        self.queued + self.running + self.complete + self.aborted + self.error
+    }
+}
+
+/// Entry in one of the manager's lookup tables. Keys are hashes of the
+/// request that produced the entry, never the raw prompt.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheEntry {
+    pub(crate) agent_id: String,
+    pub(crate) hits: u64,
+    pub(crate) inserted_at: Instant,
+}
+
+/// Point-in-time copy of one lookup table, for `subagent_cache_info`.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheTableSnapshot {
+    pub(crate) name: &'static str,
+    pub(crate) entries: Vec<(String, CacheEntry)>,
+}
+
+/// Lookup tables that let the manager reuse earlier agents instead of
+/// spawning new ones. Kept together so they can be inspected and reset as a
+/// unit.
+#[derive(Default)]
+pub(crate) struct SubagentCaches {
+    /// Spawn requests already in flight, keyed by request hash.
+    dedup: HashMap<String, CacheEntry>,
+}
+
+impl SubagentCaches {
+    /// Notes the agent started for a `dedupe_spawns` fingerprint.
+    fn record_spawn(&mut self, fingerprint: u64, agent_id: &str) {
+        self.dedup.insert(
+            format!("{fingerprint:016x}"),
+            CacheEntry {
+                agent_id: agent_id.to_string(),
+                hits: 0,
+                inserted_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Counts a spawn answered by the existing agent `agent_id`.
+    fn record_hit(&mut self, fingerprint: u64, agent_id: &str) {
+        let entry = self
+            .dedup
+            .entry(format!("{fingerprint:016x}"))
+            .or_insert_with(|| CacheEntry {
+                agent_id: agent_id.to_string(),
+                hits: 0,
+                inserted_at: Instant::now(),
+            });
+        entry.agent_id = agent_id.to_string();
+        entry.hits += 1;
+    }
+
+    fn snapshot(&self) -> Vec<CacheTableSnapshot> {
+        let mut entries: Vec<(String, CacheEntry)> = self
+            .dedup
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        vec![CacheTableSnapshot {
+            name: "dedup",
+            entries,
+        }]
+    }
+
+    /// Empties every table and returns how many entries were dropped.
+    fn clear(&mut self) -> usize {
+        let cleared = self.dedup.len();
+        self.dedup.clear();
+        cleared
+    }
+}
+
+fn sanitize_agent_id(agent_id: &str) -> Option<String> {
+    let trimmed = agent_id.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let mut out = String::new();
+    for ch in trimmed.chars() {
+        if out.len() >= MAX_AGENT_ID_LEN {
+            break;
+        }
+        match ch {
+            'a'..='z' | '0'..='9' | '-' | '_' => out.push(ch),
+            'A'..='Z' => out.push(ch.to_ascii_lowercase()),
+            _ => {}
+        }
+    }
+
+    if out.is_empty() { None } else { Some(out) }
+}
+
+impl SubagentManager {
+    /// Looks up an agent by id. Ids are stored sanitized, so callers may pass
+    /// either the id they requested at spawn (e.g. `My Agent`) or the one the
+    /// spawn returned (`myagent`).
+    async fn get_handle(&self, agent_id: &str) -> Option<Arc<SubagentHandle>> {
+        let agents = self.agents.read().await;
+        if let Some(handle) = agents.get(agent_id) {
+            return Some(Arc::clone(handle));
+        }
+        agents.get(&sanitize_agent_id(agent_id)?).cloned()
+    }
+
+    /// Creates a manager whose agents may run at most `max_concurrency` at a
+    /// time (the same clamp as the global limiter).
+    pub(crate) fn new(max_concurrency: Option<usize>) -> Self {
+        let manager = Self::default();
+        let _ = manager
+            .session_limiter
+            .set(SessionLimiter::new(max_concurrency));
+        manager
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn spawn_one_shot(
+        self: &Arc<Self>,
+        mut req: SubagentSpawnRequest,
+        parent_session: Arc<Session>,
+        parent_turn: Arc<TurnContext>,
+        auth_manager: Arc<AuthManager>,
+        models_manager: Arc<ModelsManager>,
+        skills_manager: Arc<SkillsManager>,
+        parent_config: crate::config::Config,
+    ) -> Result<SubagentSpawnResponse, String> {
+        let max_agents = parent_config.subagents.max_agents;
+        let mut prune_candidates: Vec<(Instant, String)> = Vec::new();
+
+        let label = req.label.clone();
+        let mode = req.mode;
+        let agent_id = if let Some(requested) = req.agent_id.as_deref() {
+            sanitize_agent_id(requested).ok_or_else(|| "invalid agent_id".to_string())?
+        } else {
+            Uuid::new_v4().to_string()
+        };
+
+        if max_agents == 0 {
+            return Err("subagents.max_agents must be >= 1".to_string());
+        }
+        if parent_config.subagents.dedupe_spawns
+            && let Some(existing) = self
+                .find_duplicate_spawn(&req, req.agent_id.is_some().then_some(agent_id.as_str()))
+                .await
+        {
+            self.caches
+                .lock()
+                .await
+                .record_hit(spawn_fingerprint(&req), &existing.id);
+            let status = existing.state.lock().await.status;
+            return Ok(SubagentSpawnResponse {
+                agent_id: existing.id.clone(),
+                status,
+                label: existing.label.clone(),
+                mode: existing.mode,
+                deduplicated: true,
+            });
+        }
+        self.run_maintenance().await;
+        self.prune_terminated(
+            parent_config.subagents.retain_terminated,
+            parent_config.subagents.retain_ttl,
+        )
+        .await;
+
+        self.check_spawn_request(
+            &req,
+            Some(&agent_id),
+            &skills_manager,
+            &parent_turn.cwd,
+            &parent_config,
+            0,
+        )
+        .await?;
+        req.depends_on = self.check_dependencies(&agent_id, &req.depends_on).await?;
+        check_requested_model(&req, &models_manager, &parent_config).await?;
+
+        let current_len = { self.agents.read().await.len() };
+        if current_len + 1 > max_agents {
+            let snapshot: Vec<(String, Arc<SubagentHandle>)> = {
+                self.agents
+                    .read()
+                    .await
+                    .iter()
+                    .map(|(id, handle)| (id.clone(), Arc::clone(handle)))
+                    .collect()
+            };
+            for (id, handle) in snapshot {
+                let state = handle.state.lock().await;
+                if matches!(
+                    state.status,
+                    SubagentStatus::Complete | SubagentStatus::Aborted | SubagentStatus::Error
+                ) {
+                    prune_candidates.push((state.last_update.unwrap_or(handle.created_at), id));
+                }
+            }
+            prune_candidates.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let remove_needed = (current_len + 1).saturating_sub(max_agents);
+            if remove_needed > 0 && !prune_candidates.is_empty() {
+                let mut pruned = Vec::new();
+                let mut removed = Vec::new();
+                {
+                    let mut agents = self.agents.write().await;
+                    for (_, id) in prune_candidates.into_iter().take(remove_needed) {
+                        removed.extend(agents.remove(&id));
+                        pruned.push(id);
+                    }
+                }
+                self.retire_handles(&removed).await;
+                for id in pruned {
+                    self.record_audit(
+                        ManagerEventKind::Pruned,
+                        Some(&id),
+                        format!("evicted to make room (max_agents {max_agents})"),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        let current_len = { self.agents.read().await.len() };
+        if current_len + 1 > max_agents {
+            return Err(format!(
+                "too many subagents in this session (max {max_agents}); wait for some to finish or increase [subagents].max_agents"
+            ));
+        }
+
+        // Already validated by `check_spawn_request`; resolved again to keep
+        // the canonical path.
+        let cwd = match &req.cwd {
+            Some(path) => {
+                Some(resolve_subagent_cwd(&parent_turn.cwd.join(path), &parent_turn.cwd).await?)
+            }
+            None => None,
+        };
+
+        let workspace = create_isolated_workspace(&parent_config, mode)?;
+
+        let cancel = CancellationToken::new();
+        let handle = Arc::new(SubagentHandle {
+            id: agent_id.clone(),
+            label: label.clone(),
+            mode,
+            cancel: cancel.clone(),
+            notify: Notify::new(),
+            state: Mutex::new(SubagentState {
+                status: SubagentStatus::Queued,
+                cwd,
+                workspace: workspace.as_ref().map(|dir| dir.path().to_path_buf()),
+                depends_on: req.depends_on.clone(),
+                ..Default::default()
+            }),
+            created_at: Instant::now(),
+            max_events: req.max_events.unwrap_or(parent_config.subagents.max_events),
+            max_event_chars: parent_config.subagents.max_event_chars,
+            max_output_chars: req
+                .max_output_chars
+                .unwrap_or(parent_config.subagents.max_output_chars),
+            lazy_output: req.lazy_output,
+            join_messages: parent_config.subagents.join_messages,
+            ephemeral: req.ephemeral,
+            output_postprocess: parent_config.subagents.output_postprocess,
+            request: Some(req.clone()),
+        });
+
+        self.agents
+            .write()
+            .await
+            .insert(agent_id.clone(), Arc::clone(&handle));
+        if parent_config.subagents.dedupe_spawns {
+            self.caches
+                .lock()
+                .await
+                .record_spawn(spawn_fingerprint(&req), &agent_id);
+        }
+        self.record_audit(
+            ManagerEventKind::Spawned,
+            Some(&agent_id),
+            match &req.spawned_by {
+                Some(by) => format!(
+                    "label={label} mode={} turn_id={} call_id={}",
+                    mode.as_str(),
+                    by.turn_id,
+                    by.call_id
+                ),
+                None => format!("label={label} mode={}", mode.as_str()),
+            },
+        )
+        .await;
+        self.persist_registry(&parent_session).await;
+
+        tokio::spawn(run_subagent_in_workspace(
+            workspace,
+            Arc::clone(self),
+            handle,
+            req,
+            parent_session,
+            parent_turn,
+            auth_manager,
+            models_manager,
+            skills_manager,
+            parent_config,
+        ));
+
+        Ok(SubagentSpawnResponse {
+            agent_id,
+            status: SubagentStatus::Queued,
+            label,
+            mode,
+            deduplicated: false,
+        })
+    }
+
+    /// Finds a queued or running agent spawned with the same label, mode,
+    /// prompt, and skills as `req`, so a duplicated tool call does not launch
+    /// a second copy. With an explicit `agent_id`, only that agent matches.
+    async fn find_duplicate_spawn(
+        &self,
+        req: &SubagentSpawnRequest,
+        agent_id: Option<&str>,
+    ) -> Option<Arc<SubagentHandle>> {
+        let fingerprint = spawn_fingerprint(req);
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        for handle in handles {
+            if agent_id.is_some_and(|id| id != handle.id) {
+                continue;
+            }
+            if handle.request.as_ref().map(spawn_fingerprint) != Some(fingerprint) {
+                continue;
+            }
+            if matches!(
+                handle.state.lock().await.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                return Some(handle);
+            }
+        }
+        None
+    }
+
+    /// Checks `spawn_one_shot` runs before registering an agent. Has no side
+    /// effects. `batch_same_label` counts earlier items of a validated batch
+    /// that share this request's label.
+    async fn check_spawn_request(
+        &self,
+        req: &SubagentSpawnRequest,
+        agent_id: Option<&str>,
+        skills_manager: &SkillsManager,
+        cwd: &Path,
+        parent_config: &crate::config::Config,
+        batch_same_label: usize,
+    ) -> Result<(), String> {
+        if req.lazy_output && !req.record_rollout {
+            return Err(
+                "lazy_output needs record_rollout: the full output is read back from the rollout"
+                    .to_string(),
+            );
+        }
+
+        if let Some(agent_id) = agent_id
+            && self.agents.read().await.contains_key(agent_id)
+        {
+            return Err("agent_id already exists".to_string());
+        }
+
+        if let Some(path) = &req.resume_rollout_path {
+            let known = if parent_config.subagents.resume_known_rollouts_only {
+                Some(self.known_rollout_paths().await)
+            } else {
+                None
+            };
+            check_resume_rollout_path(path, &parent_config.codex_home, known.as_deref()).await?;
+        }
+
+        let cwd = match &req.cwd {
+            Some(path) => resolve_subagent_cwd(&cwd.join(path), cwd).await?,
+            None => cwd.to_path_buf(),
+        };
+
+        if let Some(max_skill_bytes) = parent_config.subagents.max_skill_bytes
+            && !req.skills.is_empty()
+        {
+            check_skill_budget(skills_manager, &cwd, &req.skills, max_skill_bytes).await?;
+        }
+
+        if parent_config.subagents.require_unique_labels {
+            let label = &req.label;
+            if batch_same_label > 0 {
+                return Err(format!(
+                    "label `{label}` is used by an earlier item in this batch; labels must be unique (require_unique_labels)"
+                ));
+            }
+            if self.active_count_for_label(label).await > 0 {
+                return Err(format!(
+                    "a queued or running subagent is already labelled `{label}`; pick a distinct label (require_unique_labels)"
+                ));
+            }
+        }
+
+        if let Some(max_per_label) = req.max_per_label {
+            let label = &req.label;
+            let active = self.active_count_for_label(label).await + batch_same_label;
+            if active >= max_per_label {
+                return Err(format!(
+                    "too many active subagents labelled `{label}` ({active}, max_per_label {max_per_label}); wait for some to finish"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the checks `spawn_one_shot` would, without registering, pruning,
+    /// or launching anything, and reports what the agent would start with.
+    /// Capacity counts only queued and running agents, since finished ones
+    /// would be evicted to make room.
+    pub(crate) async fn dry_run_spawn(
+        &self,
+        req: &SubagentSpawnRequest,
+        skills_manager: &SkillsManager,
+        models_manager: &ModelsManager,
+        cwd: &Path,
+        parent_config: &crate::config::Config,
+    ) -> Result<SubagentDryRun, String> {
+        let agent_id = req
+            .agent_id
+            .as_deref()
+            .map(|requested| {
+                sanitize_agent_id(requested).ok_or_else(|| "invalid agent_id".to_string())
+            })
+            .transpose()?;
+        if let Some(result) = self
+            .validate_batch(vec![Ok(req.clone())], skills_manager, cwd, parent_config)
+            .await
+            .pop()
+        {
+            result?;
+        }
+        self.check_dependencies(agent_id.as_deref().unwrap_or_default(), &req.depends_on)
+            .await?;
+        check_requested_model(req, models_manager, parent_config).await?;
+
+        let outcome = skills_manager.skills_for_cwd(cwd);
+        let skills = resolve_requested_skills(&req.skills, &outcome.skills)
+            .matched
+            .into_iter()
+            .map(|skill| skill.name.clone())
+            .collect();
+        Ok(SubagentDryRun {
+            agent_id,
+            label: req.label.clone(),
+            mode: req.mode,
+            skills,
+        })
+    }
+
+    /// Rollout paths recorded by this session's subagents.
+    async fn known_rollout_paths(&self) -> Vec<PathBuf> {
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut paths = Vec::new();
+        for handle in handles {
+            if let Some(path) = handle.state.lock().await.rollout_path.clone() {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Validates a batch of spawn requests as if they were spawned in order,
+    /// without launching or pruning anything. Besides the per-spawn checks,
+    /// this reports unknown skills (unless the item waits for late skills),
+    /// duplicate ids within the batch, and items that would not fit under
+    /// `max_agents` even after evicting every finished agent.
+    pub(crate) async fn validate_batch(
+        &self,
+        reqs: Vec<Result<SubagentSpawnRequest, String>>,
+        skills_manager: &SkillsManager,
+        cwd: &Path,
+        parent_config: &crate::config::Config,
+    ) -> Vec<Result<(), String>> {
+        let max_agents = parent_config.subagents.max_agents;
+        if max_agents == 0 {
+            return reqs
+                .iter()
+                .map(|_| Err("subagents.max_agents must be >= 1".to_string()))
+                .collect();
+        }
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut live = 0;
+        for handle in handles {
+            if matches!(
+                handle.state.lock().await.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                live += 1;
+            }
+        }
+        let skills = skills_manager.skills_for_cwd(cwd);
+
+        let mut seen_ids: HashSet<String> = HashSet::new();
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        let mut accepted = 0;
+        let mut results = Vec::with_capacity(reqs.len());
+        for req in reqs {
+            let result = match req {
+                Ok(req) => self
+                    .check_batch_item(
+                        &req,
+                        &mut seen_ids,
+                        &labels,
+                        live + accepted,
+                        &skills,
+                        skills_manager,
+                        cwd,
+                        parent_config,
+                    )
+                    .await
+                    .map(|()| {
+                        accepted += 1;
+                        *labels.entry(req.label.clone()).or_default() += 1;
+                    }),
+                Err(err) => Err(err),
+            };
+            results.push(result);
+        }
+        results
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn check_batch_item(
+        &self,
+        req: &SubagentSpawnRequest,
+        seen_ids: &mut HashSet<String>,
+        labels: &HashMap<String, usize>,
+        occupied: usize,
+        skills: &SkillLoadOutcome,
+        skills_manager: &SkillsManager,
+        cwd: &Path,
+        parent_config: &crate::config::Config,
+    ) -> Result<(), String> {
+        let max_agents = parent_config.subagents.max_agents;
+        let agent_id = match req.agent_id.as_deref() {
+            Some(requested) => {
+                let id =
+                    sanitize_agent_id(requested).ok_or_else(|| "invalid agent_id".to_string())?;
+                if !seen_ids.insert(id.clone()) {
+                    return Err(format!("agent_id `{id}` is repeated in this batch"));
+                }
+                Some(id)
+            }
+            None => None,
+        };
+        let same_label = labels.get(&req.label).copied().unwrap_or(0);
+        self.check_spawn_request(
+            req,
+            agent_id.as_deref(),
+            skills_manager,
+            cwd,
+            parent_config,
+            same_label,
+        )
+        .await?;
+        if req.await_skills_ms == 0
+            && let Some(entry) = resolve_requested_skills(&req.skills, &skills.skills)
+                .unmatched
+                .first()
+        {
+            return Err(describe_unmatched_skill(entry));
+        }
+        if occupied + 1 > max_agents {
+            return Err(format!(
+                "too many subagents in this session (max {max_agents}) once earlier batch items are spawned"
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn poll(
+        &self,
+        agent_id: &str,
+        await_ms: Option<u64>,
+    ) -> Option<SubagentPollResponse> {
+        self.poll_until(agent_id, await_ms, None).await
+    }
+
+    /// Like [`Self::poll`], but with `until_status` the wait ends once the
+    /// agent reaches that status (e.g. `Running` to confirm a queued agent
+    /// started) instead of a terminal one. A terminal status always ends the
+    /// wait, since the agent cannot move on from it, once the run's `Summary`
+    /// event has been pushed.
+    pub(crate) async fn poll_until(
+        &self,
+        agent_id: &str,
+        await_ms: Option<u64>,
+        until_status: Option<SubagentStatus>,
+    ) -> Option<SubagentPollResponse> {
+        let handle = self.get_handle(agent_id).await?;
+        let mut remaining = await_ms.map(Duration::from_millis);
+        loop {
+            // Register before reading the state so a change in between still
+            // wakes us.
+            let notified = handle.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let (snapshot, summary_pending) = {
+                let mut state = handle.state.lock().await;
+                mark_consumed(&handle, &mut state, false);
+                let now = Instant::now();
+                let snapshot = SubagentPollResponse {
+                    agent_id: handle.id.clone(),
+                    status: state.status,
+                    label: handle.label.clone(),
+                    mode: handle.mode,
+                    rollout_path: state.rollout_path.clone(),
+                    final_output: state.final_output.clone(),
+                    partial_output: state.partial_output(),
+                    max_output_chars: handle.max_output_chars,
+                    recent_events: state.recent_events.iter().cloned().collect(),
+                    next_event_index: state.next_event_seq,
+                    checklist: state.checklist.clone(),
+                    proposed_patches: state.proposed_patches.clone(),
+                    error_kind: state.error_kind,
+                    confidence: state.confidence,
+                    ttft_ms: state.latency_ms(state.first_token_at),
+                    total_ms: state.latency_ms(state.completed_at),
+                    elapsed_ms: millis_between(handle.created_at, now),
+                    running_ms: state.running_since.map(|since| millis_between(since, now)),
+                    tokens: state.token_usage(),
+                    approvals: state.approvals,
+                    workspace: state.workspace.clone(),
+                    sandbox: state.sandbox.clone(),
+                    approval: state.approval.clone(),
+                    reasoning_effort: state.reasoning_effort,
+                    abort_reason: (state.status == SubagentStatus::Aborted).then(|| {
+                        state
+                            .abort_reason
+                            .clone()
+                            .unwrap_or_else(|| DEFAULT_ABORT_REASON.to_string())
+                    }),
+                    spawned_by: handle.spawned_by().cloned(),
+                    suggested_next_poll_ms: matches!(
+                        state.status,
+                        SubagentStatus::Queued | SubagentStatus::Running
+                    )
+                    .then(|| suggested_next_poll_ms(millis_between(handle.created_at, now))),
+                };
+                (snapshot, state.summary_pending)
+            };
+
+            let Some(left) = remaining else {
+                return Some(snapshot);
+            };
+            let finished = !matches!(
+                snapshot.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) && !summary_pending;
+            if finished || until_status == Some(snapshot.status) {
+                return Some(snapshot);
+            }
+
+            let started = Instant::now();
+            let _ = timeout(left, notified).await;
+            let elapsed = started.elapsed();
+            remaining = left.checked_sub(elapsed);
+        }
+    }
+
+    /// Waits up to `await_ms` for the first of `agent_ids` to reach a terminal
+    /// status and returns its poll response. Agents are checked in order, so
+    /// an already-finished one wins immediately. Returns `Ok(None)` on timeout.
+    pub(crate) async fn wait_any(
+        &self,
+        agent_ids: &[String],
+        await_ms: Option<u64>,
+    ) -> Result<Option<SubagentPollResponse>, String> {
+        if agent_ids.is_empty() {
+            return Err("agent_ids must not be empty".to_string());
+        }
+        let mut handles: Vec<Arc<SubagentHandle>> = Vec::with_capacity(agent_ids.len());
+        for id in agent_ids {
+            let handle = self.get_handle(id).await;
+            handles.push(handle.ok_or_else(|| format!("unknown agent_id `{id}`"))?);
+        }
+        let deadline = Instant::now() + Duration::from_millis(await_ms.unwrap_or(0));
+        loop {
+            // Register every waiter before reading the statuses so a finish in
+            // between still wakes us.
+            let mut waiters: Vec<_> = handles
+                .iter()
+                .map(|handle| Box::pin(handle.notify.notified()))
+                .collect();
+            for waiter in &mut waiters {
+                waiter.as_mut().enable();
+            }
+            // Out of time, an agent still pushing its summary counts as done.
+            let left = deadline.saturating_duration_since(Instant::now());
+            for handle in &handles {
+                let state = handle.state.lock().await;
+                let finished = !matches!(
+                    state.status,
+                    SubagentStatus::Queued | SubagentStatus::Running
+                ) && (!state.summary_pending || left.is_zero());
+                drop(state);
+                if finished {
+                    return Ok(self.poll(&handle.id, None).await);
+                }
+            }
+            if left.is_zero() {
+                return Ok(None);
+            }
+            let _ = timeout(left, futures::future::select_all(waiters)).await;
+        }
+    }
+
+    /// Number of queued or running agents carrying `label`.
+    async fn active_count_for_label(&self, label: &str) -> usize {
+        let handles: Vec<Arc<SubagentHandle>> = self
+            .agents
+            .read()
+            .await
+            .values()
+            .filter(|handle| handle.label == label)
+            .cloned()
+            .collect();
+        let mut active = 0;
+        for handle in handles {
+            if matches!(
+                handle.state.lock().await.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                active += 1;
+            }
+        }
+        active
+    }
+
+    /// Cancels a queued or running agent. `reason` (default `cancelled`) is
+    /// reported as the agent's `abort_reason` once it has stopped.
+    pub(crate) async fn cancel(&self, agent_id: &str, reason: Option<&str>) -> Option<()> {
+        let handle = self.get_handle(agent_id).await?;
+        let mut reason = reason
+            .map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .unwrap_or(DEFAULT_ABORT_REASON)
+            .to_string();
+        truncate_to_char_boundary(&mut reason, MAX_ABORT_REASON_LEN);
+        {
+            let mut state = handle.state.lock().await;
+            if matches!(
+                state.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                state.note_abort_reason(reason.clone());
+            }
+        }
+        handle.cancel.cancel();
+        self.record_audit(
+            ManagerEventKind::Cancelled,
+            Some(&handle.id),
+            format!("cancel requested: {reason}"),
+        )
+        .await;
+        Some(())
+    }
+
+    /// Starts a fresh run of a finished agent under the same `agent_id`,
+    /// replaying the request it was spawned with (label, mode, prompt, skills,
+    /// and options). The agent keeps its current cwd and its token totals.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn restart(
+        self: &Arc<Self>,
+        agent_id: &str,
+        parent_session: Arc<Session>,
+        parent_turn: Arc<TurnContext>,
+        auth_manager: Arc<AuthManager>,
+        models_manager: Arc<ModelsManager>,
+        skills_manager: Arc<SkillsManager>,
+        parent_config: crate::config::Config,
+    ) -> Result<SubagentSpawnResponse, String> {
+        let mode = self
+            .get_handle(agent_id)
+            .await
+            .map(|handle| handle.mode)
+            .ok_or_else(|| "unknown agent_id".to_string())?;
+        let workspace = create_isolated_workspace(&parent_config, mode)?;
+        let (handle, req) = self.replace_for_restart(agent_id).await?;
+        if let Some(dir) = &workspace {
+            handle.state.lock().await.workspace = Some(dir.path().to_path_buf());
+        }
+        self.record_audit(
+            ManagerEventKind::Restarted,
+            Some(&handle.id),
+            format!("label={} mode={}", handle.label, handle.mode.as_str()),
+        )
+        .await;
+        self.persist_registry(&parent_session).await;
+
+        let response = SubagentSpawnResponse {
+            agent_id: handle.id.clone(),
+            status: SubagentStatus::Queued,
+            label: handle.label.clone(),
+            mode: handle.mode,
+            deduplicated: false,
+        };
+        tokio::spawn(run_subagent_in_workspace(
+            workspace,
+            Arc::clone(self),
+            handle,
+            req,
+            parent_session,
+            parent_turn,
+            auth_manager,
+            models_manager,
+            skills_manager,
+            parent_config,
+        ));
+        Ok(response)
+    }
+
+    /// Swaps a finished agent's handle for a fresh queued one with the same
+    /// settings and returns it with the request to run.
+    async fn replace_for_restart(
+        &self,
+        agent_id: &str,
+    ) -> Result<(Arc<SubagentHandle>, SubagentSpawnRequest), String> {
+        let old = self
+            .get_handle(agent_id)
+            .await
+            .ok_or_else(|| "unknown agent_id".to_string())?;
+        let state = old.state.lock().await;
+        if matches!(
+            state.status,
+            SubagentStatus::Queued | SubagentStatus::Running
+        ) {
+            return Err(format!(
+                "agent `{agent_id}` is still {}; wait for it to finish or cancel it first",
+                state.status.as_str()
+            ));
+        }
+        let Some(mut req) = old.request.clone() else {
+            return Err(format!(
+                "agent `{agent_id}` was restored from an earlier session and cannot be restarted; use subagent_spawn with its rollout_path as resume_rollout_path"
+            ));
+        };
+        // Dependencies were satisfied by the first run.
+        req.depends_on.clear();
+
+        let handle = Arc::new(SubagentHandle {
+            id: old.id.clone(),
+            label: old.label.clone(),
+            mode: old.mode,
+            cancel: CancellationToken::new(),
+            notify: Notify::new(),
+            state: Mutex::new(SubagentState {
+                status: SubagentStatus::Queued,
+                cwd: state.cwd.clone(),
+                earlier_runs_tokens: state.token_usage(),
+                last_update: Some(Instant::now()),
+                ..Default::default()
+            }),
+            created_at: Instant::now(),
+            max_events: old.max_events,
+            max_event_chars: old.max_event_chars,
+            max_output_chars: old.max_output_chars,
+            lazy_output: old.lazy_output,
+            join_messages: old.join_messages,
+            ephemeral: old.ephemeral,
+            output_postprocess: old.output_postprocess,
+            request: old.request.clone(),
+        });
+        drop(state);
+        {
+            let mut state = handle.state.lock().await;
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                "restarted".to_string(),
+            );
+        }
+        {
+            let mut agents = self.agents.write().await;
+            // Another restart (or a forget) may have replaced the entry meanwhile.
+            if !agents
+                .get(&old.id)
+                .is_some_and(|current| Arc::ptr_eq(current, &old))
+            {
+                return Err(format!(
+                    "agent `{agent_id}` changed while restarting; try again"
+                ));
+            }
+            agents.insert(old.id.clone(), Arc::clone(&handle));
+        }
+        // Wake anyone still waiting on the replaced handle.
+        old.notify.notify_waiters();
+        Ok((handle, req))
+    }
+
+    /// Cancels every queued or running agent and returns how many were
+    /// cancelled. Finished agents are left alone.
+    pub(crate) async fn cancel_all(&self) -> usize {
+        // Snapshot the handles so no agent lock is taken under the map lock.
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+
+        let mut cancelled = 0;
+        for handle in handles {
+            // Check and record under one guard, so an agent that finishes in
+            // between is not counted or tagged as cancelled.
+            let mut state = handle.state.lock().await;
+            if !matches!(
+                state.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                continue;
+            }
+            state.note_abort_reason(DEFAULT_ABORT_REASON);
+            drop(state);
+            handle.cancel.cancel();
+            cancelled += 1;
+        }
+        self.record_audit(
+            ManagerEventKind::Cancelled,
+            None,
+            format!("cancel all: {cancelled} agent(s)"),
+        )
+        .await;
+        cancelled
+    }
+
+    /// Cancels every queued or running agent that shares `origin`'s label.
+    /// Returns the number of agents cancelled.
+    async fn abort_siblings(&self, origin: &SubagentHandle, reason: &str) -> usize {
+        let siblings: Vec<Arc<SubagentHandle>> = self
+            .agents
+            .read()
+            .await
+            .values()
+            .filter(|handle| handle.id != origin.id && handle.label == origin.label)
+            .cloned()
+            .collect();
+
+        let mut cancelled = 0;
+        for sibling in siblings {
+            let mut state = sibling.state.lock().await;
+            if !matches!(
+                state.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                continue;
+            }
+            let message = format!("aborted by sibling {}: {reason}", origin.id);
+            state.note_abort_reason(message.clone());
+            push_event(&sibling, &mut state, SubagentEventKind::Control, message);
+            drop(state);
+            sibling.cancel.cancel();
+            sibling.notify.notify_waiters();
+            cancelled += 1;
+        }
+        self.record_audit(
+            ManagerEventKind::SiblingsAborted,
+            Some(&origin.id),
+            format!(
+                "cancelled {cancelled} sibling(s) labelled {}: {reason}",
+                origin.label
+            ),
+        )
+        .await;
+        cancelled
+    }
+
+    /// Maintenance pass: forgets ephemeral agents whose output has been consumed.
+    async fn run_maintenance(&self) {
+        let handles: Vec<Arc<SubagentHandle>> = self
+            .agents
+            .read()
+            .await
+            .values()
+            .filter(|handle| handle.ephemeral)
+            .cloned()
+            .collect();
+        let mut consumed = Vec::new();
+        for handle in handles {
+            if handle.state.lock().await.consumed {
+                consumed.push(handle.id.clone());
+            }
+        }
+        if consumed.is_empty() {
+            return;
+        }
+        let mut removed = Vec::new();
+        {
+            let mut agents = self.agents.write().await;
+            for id in &consumed {
+                removed.extend(agents.remove(id));
+            }
+        }
+        self.retire_handles(&removed).await;
+        for id in consumed {
+            self.record_audit(
+                ManagerEventKind::Forgotten,
+                Some(&id),
+                "ephemeral agent forgotten after its output was consumed".to_string(),
+            )
+            .await;
+        }
+    }
+
+    /// Removes finished agents beyond the newest `retain` or last updated more
+    /// than `ttl` ago, and returns their ids. Queued and running agents are
+    /// never removed.
+    pub(crate) async fn prune_terminated(
+        &self,
+        retain: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Vec<String> {
+        if retain.is_none() && ttl.is_none() {
+            return Vec::new();
+        }
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut finished: Vec<(Instant, Arc<SubagentHandle>)> = Vec::new();
+        for handle in handles {
+            let state = handle.state.lock().await;
+            if matches!(
+                state.status,
+                SubagentStatus::Complete | SubagentStatus::Aborted | SubagentStatus::Error
+            ) {
+                finished.push((
+                    state.last_update.unwrap_or(handle.created_at),
+                    Arc::clone(&handle),
+                ));
+            }
+        }
+        // Newest first, so everything past `retain` is the oldest.
+        finished.sort_by(|a, b| b.0.cmp(&a.0));
+        let now = Instant::now();
+        let doomed: Vec<Arc<SubagentHandle>> = finished
+            .into_iter()
+            .enumerate()
+            .filter(|(index, (last_update, _))| {
+                retain.is_some_and(|retain| *index >= retain)
+                    || ttl.is_some_and(|ttl| now.saturating_duration_since(*last_update) > ttl)
+            })
+            .map(|(_, (_, handle))| handle)
+            .collect();
+        if doomed.is_empty() {
+            return Vec::new();
+        }
+
+        let mut pruned = Vec::new();
+        {
+            let mut agents = self.agents.write().await;
+            for handle in doomed {
+                // Skip entries replaced (e.g. restarted) since the snapshot.
+                if agents
+                    .get(&handle.id)
+                    .is_some_and(|current| Arc::ptr_eq(current, &handle))
+                {
+                    agents.remove(&handle.id);
+                    pruned.push(handle);
+                }
+            }
+        }
+        self.retire_handles(&pruned).await;
+        let pruned: Vec<String> = pruned.into_iter().map(|handle| handle.id.clone()).collect();
+        for id in &pruned {
+            self.record_audit(
+                ManagerEventKind::Pruned,
+                Some(id),
+                "removed by the retention policy".to_string(),
+            )
+            .await;
+        }
+        pruned
+    }
+
+    /// Keeps the combined size of all retained events under `budget` bytes by
+    /// trimming finished agents, oldest first: first down to their final
+    /// (summary) event, then entirely. Running agents are never touched.
+    async fn trim_event_memory(&self, budget: usize) {
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+
+        let mut total = 0;
+        let mut finished: Vec<(Instant, Arc<SubagentHandle>)> = Vec::new();
+        for handle in handles {
+            let state = handle.state.lock().await;
+            total += state.event_bytes();
+            if matches!(
+                state.status,
+                SubagentStatus::Complete | SubagentStatus::Aborted | SubagentStatus::Error
+            ) {
+                finished.push((
+                    state.last_update.unwrap_or(handle.created_at),
+                    Arc::clone(&handle),
+                ));
+            }
+        }
+        if total <= budget {
+            return;
+        }
+        finished.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for keep in [1, 0] {
+            for (_, handle) in &finished {
+                if total <= budget {
+                    return;
+                }
+                let mut state = handle.state.lock().await;
+                let before = state.event_bytes();
+                let dropped = state.recent_events.len().saturating_sub(keep);
+                if dropped == 0 {
+                    continue;
+                }
+                state.recent_events.drain(..dropped);
+                let freed = before - state.event_bytes();
+                drop(state);
+                total -= freed;
+                self.record_audit(
+                    ManagerEventKind::EventsTrimmed,
+                    Some(&handle.id),
+                    format!(
+                        "dropped {dropped} event(s) ({freed} bytes) to stay under total_event_memory_bytes {budget}"
+                    ),
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn record_audit(&self, kind: ManagerEventKind, agent_id: Option<&str>, detail: String) {
+        let mut log = self.audit_log.lock().await;
+        if log.len() >= MAX_AUDIT_EVENTS {
+            log.pop_front();
+        }
+        log.push_back(ManagerEvent {
+            at: Utc::now(),
+            kind,
+            agent_id: agent_id.map(str::to_string),
+            detail,
+        });
+    }
+
+    /// Returns the most recent `limit` audit entries (all when `None`), oldest first.
+    pub(crate) async fn audit_log(&self, limit: Option<usize>) -> Vec<ManagerEvent> {
+        let log = self.audit_log.lock().await;
+        let skip = limit.map_or(0, |limit| log.len().saturating_sub(limit));
+        log.iter().skip(skip).cloned().collect()
+    }
+
+    pub(crate) async fn record_delegate_progress(
+        &self,
+        call_id: &str,
+        label: &str,
+        partial: String,
+    ) {
+        self.delegate_progress.lock().await.insert(
+            call_id.to_string(),
+            DelegateProgress {
+                label: label.to_string(),
+                partial,
+            },
+        );
+    }
+
+    pub(crate) async fn clear_delegate_progress(&self, call_id: &str) {
+        self.delegate_progress.lock().await.remove(call_id);
+    }
+
+    /// Latest partial output of the running delegate labelled `label`, as
+    /// `(call_id, partial)`. When several delegates share the label, `call_id`
+    /// picks one; without it the lookup is ambiguous and fails.
+    pub(crate) async fn delegate_progress(
+        &self,
+        label: &str,
+        call_id: Option<&str>,
+    ) -> Result<Option<(String, String)>, String> {
+        let progress = self.delegate_progress.lock().await;
+        let mut matches: Vec<(&String, &DelegateProgress)> = progress
+            .iter()
+            .filter(|(id, entry)| {
+                entry.label == label && call_id.is_none_or(|call_id| id.as_str() == call_id)
+            })
+            .collect();
+        if matches.len() > 1 {
+            let mut ids: Vec<&str> = matches.iter().map(|(id, _)| id.as_str()).collect();
+            ids.sort_unstable();
+            return Err(format!(
+                "{} delegate calls labelled `{label}` are running ({}); pass call_id to pick one",
+                ids.len(),
+                ids.join(", ")
+            ));
+        }
+        Ok(matches
+            .pop()
+            .map(|(id, entry)| (id.clone(), entry.partial.clone())))
+    }
+
+    pub(crate) async fn cache_info(&self) -> Vec<CacheTableSnapshot> {
+        self.caches.lock().await.snapshot()
+    }
+
+    pub(crate) async fn clear_caches(&self) -> usize {
+        let cleared = self.caches.lock().await.clear();
+        self.record_audit(
+            ManagerEventKind::CachesCleared,
+            None,
+            format!("{cleared} entries dropped"),
+        )
+        .await;
+        cleared
+    }
+
+    /// Returns the agent's final output. For a completed `lazy_output` agent,
+    /// `materialize` reads the full answer back from its rollout instead of
+    /// returning the stored marker.
+    pub(crate) async fn result(
+        &self,
+        agent_id: &str,
+        materialize: bool,
+    ) -> Option<Result<SubagentResult, String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let (status, final_output, rollout_path) = {
+            let mut state = handle.state.lock().await;
+            mark_consumed(&handle, &mut state, materialize);
+            (
+                state.status,
+                state.final_output.clone(),
+                state.rollout_path.clone(),
+            )
+        };
+        if !(materialize && handle.lazy_output && status == SubagentStatus::Complete) {
+            return Some(Ok(SubagentResult {
+                status,
+                final_output,
+                materialized: false,
+            }));
+        }
+        let Some(path) = rollout_path else {
+            return Some(Err("agent has no rollout to materialize from".to_string()));
+        };
+        Some(
+            materialize_output(&path, &handle)
+                .await
+                .map(|output| SubagentResult {
+                    status,
+                    final_output: output.map(|mut text| {
+                        truncate_to_char_boundary(&mut text, handle.max_output_chars);
+                        text
+                    }),
+                    materialized: true,
+                }),
+        )
+    }
+
+    /// Returns up to `length` bytes of the agent's untruncated final output
+    /// starting at `offset`. Completed agents whose stored output is a lazy
+    /// marker or was capped at `max_output_chars` are read back from their
+    /// rollout; everything else slices the stored output. Returning the last
+    /// slice counts as consuming the output.
+    pub(crate) async fn result_chunk(
+        &self,
+        agent_id: &str,
+        offset: usize,
+        length: usize,
+    ) -> Option<Result<SubagentOutputChunk, String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let (status, stored, rollout_path, from_rollout) = {
+            let state = handle.state.lock().await;
+            let from_rollout = state.status == SubagentStatus::Complete
+                && (handle.lazy_output || state.output_truncated);
+            (
+                state.status,
+                state.final_output.clone(),
+                state.rollout_path.clone(),
+                from_rollout,
+            )
+        };
+        let full = if from_rollout {
+            let Some(path) = rollout_path else {
+                return Some(Err(
+                    "the full output is only kept in the rollout, and this agent has none"
+                        .to_string(),
+                ));
+            };
+            match materialize_output(&path, &handle).await {
+                Ok(output) => output.unwrap_or_default(),
+                Err(err) => return Some(Err(err)),
+            }
+        } else {
+            stored.unwrap_or_default()
+        };
+
+        let (offset, chunk, next_offset) = slice_output(&full, offset, length);
+        if next_offset.is_none() {
+            let mut state = handle.state.lock().await;
+            mark_consumed(&handle, &mut state, true);
+        }
+        Some(Ok(SubagentOutputChunk {
+            status,
+            offset,
+            chunk: chunk.to_string(),
+            total_len: full.len(),
+            next_offset,
+            from_rollout,
+        }))
+    }
+
+    /// Returns up to `max_bytes` of the agent's rollout rendered as a compact
+    /// transcript (messages, reasoning, and tool calls), starting at
+    /// `offset`. Read from the rollout on every call, so a running agent's
+    /// transcript grows between calls.
+    pub(crate) async fn transcript(
+        &self,
+        agent_id: &str,
+        offset: usize,
+        max_bytes: usize,
+    ) -> Option<Result<SubagentTranscriptChunk, String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let (status, rollout_path) = {
+            let state = handle.state.lock().await;
+            (state.status, state.rollout_path.clone())
+        };
+        let Some(path) = rollout_path else {
+            return Some(Err(
+                "agent has no rollout yet (still queued, or spawned with record_rollout=false)"
+                    .to_string(),
+            ));
+        };
+        let history = match RolloutRecorder::get_rollout_history(&path).await {
+            Ok(history) => history,
+            Err(e) => return Some(Err(format!("failed to read subagent rollout: {e}"))),
+        };
+        let transcript = render_transcript(&history.get_rollout_items());
+        let (offset, chunk, next_offset) = slice_output(&transcript, offset, max_bytes);
+        Some(Ok(SubagentTranscriptChunk {
+            status,
+            offset,
+            chunk: chunk.to_string(),
+            total_len: transcript.len(),
+            next_offset,
+        }))
+    }
+
+    /// Moves a queued or running agent to `path` (relative paths resolve
+    /// against its current cwd). The path must stay inside the repository
+    /// containing `base` (or `base` itself outside a repository). A running
+    /// agent gets the new cwd for subsequent turns plus a note to use it now;
+    /// a queued agent (or a retry) starts there.
+    pub(crate) async fn set_cwd(
+        &self,
+        agent_id: &str,
+        path: &Path,
+        base: &Path,
+    ) -> Option<Result<PathBuf, String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let current = {
+            let state = handle.state.lock().await;
+            if !matches!(
+                state.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                return Some(Err("agent has already finished".to_string()));
+            }
+            if state.workspace.is_some() {
+                return Some(Err(
+                    "agent runs in an isolated workspace; its cwd cannot be changed".to_string(),
+                ));
+            }
+            state.cwd.clone().unwrap_or_else(|| base.to_path_buf())
+        };
+        let cwd = match resolve_subagent_cwd(&current.join(path), base).await {
+            Ok(cwd) => cwd,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let codex = {
+            let mut state = handle.state.lock().await;
+            state.cwd = Some(cwd.clone());
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Control,
+                format!("cwd changed to {}", cwd.display()),
+            );
+            state.codex.clone()
+        };
+        if let Some(codex) = codex {
+            let _ = codex
+                .submit(Op::OverrideTurnContext {
+                    cwd: Some(cwd.clone()),
+                    approval_policy: None,
+                    sandbox_policy: None,
+                    model: None,
+                    effort: None,
+                    summary: None,
+                })
+                .await;
+            let _ = codex
+                .submit(Op::UserInput {
+                    items: vec![UserInput::Text {
+                        text: format!(
+                            "Your working directory is now {}. Focus on it and run commands from there (pass it as the working directory where tools accept one).",
+                            cwd.display()
+                        ),
+                    }],
+                })
+                .await;
+        }
+        handle.notify.notify_waiters();
+        self.record_audit(
+            ManagerEventKind::CwdChanged,
+            Some(&handle.id),
+            cwd.display().to_string(),
+        )
+        .await;
+        Some(Ok(cwd))
+    }
+
+    /// Pushes a follow-up user message into a queued or running agent, keeping
+    /// its conversation context. Finished agents reject the message.
+    pub(crate) async fn send(
+        &self,
+        agent_id: &str,
+        text: String,
+    ) -> Option<Result<SendOutcome, String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let codex = {
+            let mut state = handle.state.lock().await;
+            if !matches!(
+                state.status,
+                SubagentStatus::Queued | SubagentStatus::Running
+            ) {
+                return Some(Err(format!("agent is already {}", state.status.as_str())));
+            }
+            let codex = state.codex.clone().filter(|_| state.submitted_at.is_some());
+            if codex.is_none() {
+                state.queued_messages.push(text.clone());
+            }
+            codex
+        };
+        let outcome = match codex {
+            Some(codex) => {
+                if let Err(e) = codex
+                    .submit(Op::UserInput {
+                        items: vec![UserInput::Text { text }],
+                    })
+                    .await
+                {
+                    return Some(Err(format!("failed to deliver message: {e}")));
+                }
+                SendOutcome::Delivered
+            }
+            None => SendOutcome::Queued,
+        };
+
+        {
+            let mut state = handle.state.lock().await;
+            state.last_update = Some(Instant::now());
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Control,
+                format!("follow-up message {}", outcome.as_str()),
+            );
+        }
+        handle.notify.notify_waiters();
+        self.record_audit(
+            ManagerEventKind::MessageSent,
+            Some(&handle.id),
+            outcome.as_str().to_string(),
+        )
+        .await;
+        Some(Ok(outcome))
+    }
+
+    pub(crate) async fn tools(&self, agent_id: &str) -> Option<Vec<String>> {
+        let handle = self.get_handle(agent_id).await?;
+        let tools = handle.state.lock().await.tools.clone();
+        Some(tools)
+    }
+
+    /// Adds the token usage of handles just removed from `agents` to
+    /// `retired_tokens`.
+    async fn retire_handles(&self, removed: &[Arc<SubagentHandle>]) {
+        for handle in removed {
+            let total = handle.state.lock().await.token_usage().total;
+            self.retired_tokens.fetch_add(total, Ordering::AcqRel);
+        }
+    }
+
+    /// Asks every running subagent to wrap up once the session's subagents have
+    /// collectively used `budget` tokens. The warning is delivered at most once.
+    ///
+    /// Every agent ever registered in this session counts: finished ones,
+    /// every run of a restarted agent, and agents since pruned or forgotten.
+    /// Only agents still running receive the warning.
+    async fn maybe_send_budget_warning(&self, budget: u64) {
+        if self.budget_warning_sent.load(Ordering::Acquire) {
+            return;
+        }
+
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut used = self.retired_tokens.load(Ordering::Acquire);
+        for handle in &handles {
+            used = used.saturating_add(handle.state.lock().await.token_usage().total);
+        }
+        if used < budget || self.budget_warning_sent.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.record_audit(
+            ManagerEventKind::BudgetWarning,
+            None,
+            format!("{used}/{budget} tokens used"),
+        )
+        .await;
+
+        for handle in handles {
+            let codex = {
+                let state = handle.state.lock().await;
+                if state.status != SubagentStatus::Running {
+                    continue;
+                }
+                state.codex.clone()
+            };
+            let Some(codex) = codex else {
+                continue;
+            };
+            let submitted = codex
+                .submit(Op::UserInput {
+                    items: vec![UserInput::Text {
+                        text: COOPERATIVE_BUDGET_MESSAGE.to_string(),
+                    }],
+                })
+                .await
+                .is_ok();
+            if submitted {
+                let mut state = handle.state.lock().await;
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Control,
+                    format!("budget warning sent ({used}/{budget} tokens used)"),
+                );
+                handle.notify.notify_waiters();
+            }
+        }
+    }
+
+    /// Finished agents (optionally only those labelled `label`), most
+    /// confident first. Agents without a confidence score sort last.
+    pub(crate) async fn ranked_results(&self, label: Option<&str>) -> Vec<SubagentPollResponse> {
+        let mut results: Vec<SubagentPollResponse> = self
+            .list(Some(&[
+                SubagentStatus::Complete,
+                SubagentStatus::Aborted,
+                SubagentStatus::Error,
+            ]))
+            .await
+            .into_iter()
+            .filter(|poll| label.is_none_or(|label| poll.label == label))
+            .collect();
+        results.sort_by(|a, b| {
+            let score = |poll: &SubagentPollResponse| poll.confidence.unwrap_or(-1.0);
+            score(b)
+                .total_cmp(&score(a))
+                .then_with(|| a.agent_id.cmp(&b.agent_id))
+        });
+        results
+    }
+
+    /// Polls every agent, or only those whose status is in `statuses`.
+    pub(crate) async fn list(
+        &self,
+        statuses: Option<&[SubagentStatus]>,
+    ) -> Vec<SubagentPollResponse> {
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut out = Vec::with_capacity(handles.len());
+        for handle in handles {
+            // Filter before polling so skipped agents are not marked consumed.
+            if let Some(statuses) = statuses {
+                let status = handle.state.lock().await.status;
+                if !statuses.contains(&status) {
+                    continue;
+                }
+            }
+            if let Some(poll) = self.poll(&handle.id, None).await {
+                out.push(poll);
+            }
+        }
+        out
+    }
+
+    /// Counts agents by status from one snapshot of the agent table, without
+    /// polling them (so nothing is marked consumed).
+    pub(crate) async fn status_counts(&self) -> SubagentStatusCounts {
+        // Snapshot the handles so no agent lock is taken under the map lock.
+        let handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        let mut counts = SubagentStatusCounts::default();
+        for handle in handles {
+            let count = match handle.state.lock().await.status {
+                SubagentStatus::Queued => &mut counts.queued,
+                SubagentStatus::Running => &mut counts.running,
+                SubagentStatus::Complete => &mut counts.complete,
+                SubagentStatus::Aborted => &mut counts.aborted,
+                SubagentStatus::Error => &mut counts.error,
+            };
+            *count += 1;
+        }
+        counts
+    }
+}
+
+fn subagent_base_instructions(
+    label: &str,
+    mode: SubagentMode,
+    output_format: SubagentOutputFormat,
+) -> String {
+    let safety = match mode {
+        SubagentMode::Explore => "- Scope: read-only exploration; do not modify files.\n",
+        SubagentMode::General => {
+            "- Scope: you may propose changes and (if tools are enabled) apply them.\n"
+        }
+        SubagentMode::Review => {
+            "- Scope: code review; do not modify files or run commands.\n\
+- Focus: look for bugs, security issues (injection, unsafe input handling, leaked secrets), and missing or weak tests. Skip style nits unless they hide a defect.\n\
+- Findings: for each issue give the file and line, why it is wrong, and a suggested fix; say so plainly if you found nothing.\n"
+        }
+    };
+    // Prose guidance (checklists, bullet points) contradicts a JSON-only contract,
+    // so structured formats get format-specific guidance instead.
+    let format = match output_format {
+        SubagentOutputFormat::Text => {
+            "- Efficiency: keep responses short; prefer checklists and concrete next steps.\n\
+- Remaining steps: if work is left over, list it in a fenced ```checklist block using `- [ ]` / `- [x]` items.\n"
+        }
+        SubagentOutputFormat::Json => {
+            "- Format: your final answer must be a single valid JSON value, with no Markdown fences or prose around it.\n"
+        }
+    };
+    format!(
+        "You are a focused subagent named \"{label}\".\n\
+Your job is to help the parent Codex session by producing concise, actionable results.\n\
+\n\
+Requirements:\n\
+- Output: respond with only your final answer (no meta commentary).\n\
+{safety}\
+{format}"
+    )
+}
+
+/// Cleans orchestrator-supplied instructions: drops control characters other
+/// than newlines and tabs, trims, and caps the text at
+/// [`MAX_EXTRA_INSTRUCTIONS_LEN`] bytes. Returns `None` when nothing is left.
+pub(crate) fn sanitize_extra_instructions(raw: &str) -> Option<String> {
+    let mut text: String = raw
+        .chars()
+        .filter(|ch| !ch.is_control() || matches!(ch, '\n' | '\t'))
+        .collect();
+    truncate_to_char_boundary(&mut text, MAX_EXTRA_INSTRUCTIONS_LEN);
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Section appended after the generated instructions, so the requirements and
+/// scope above it keep precedence.
+pub(crate) fn extra_instructions_section(extra: &str) -> String {
+    format!(
+        "\nAdditional guidance from the parent for this task (it does not override the requirements above):\n{extra}\n"
+    )
+}
+
+/// Model of the last turn recorded in a rollout, if any turn context was saved.
+fn recorded_model(history: &InitialHistory) -> Option<String> {
+    history
+        .get_rollout_items()
+        .into_iter()
+        .rev()
+        .find_map(|item| match item {
+            RolloutItem::TurnContext(ctx) => Some(ctx.model),
+            _ => None,
+        })
+}
+
+/// Applies `[subagents].resume_model_policy` to a resumed rollout. Returns a
+/// warning to record, or an error when the resume must be refused. Rollouts
+/// without a recorded model are accepted.
+fn check_resume_model(
+    policy: ResumeModelPolicy,
+    recorded: Option<&str>,
+    model: &str,
+) -> Result<Option<String>, String> {
+    let Some(recorded) = recorded.filter(|recorded| *recorded != model) else {
+        return Ok(None);
+    };
+    match policy {
+        ResumeModelPolicy::Ignore => Ok(None),
+        ResumeModelPolicy::Warn => Ok(Some(format!(
+            "resumed rollout was recorded with model {recorded}; continuing with {model}"
+        ))),
+        ResumeModelPolicy::Strict => Err(format!(
+            "refusing to resume: rollout was recorded with model {recorded} but this subagent would run {model} ([subagents].resume_model_policy = \"strict\")"
+        )),
+    }
+}
+
+fn millis_between(start: Instant, end: Instant) -> u64 {
+    u64::try_from(end.saturating_duration_since(start).as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Parses a comma-separated status filter such as `running,queued`.
+pub(crate) fn parse_status_filter(filter: &str) -> Result<Vec<SubagentStatus>, String> {
+    let mut statuses = Vec::new();
+    for part in filter
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let status = SubagentStatus::from_str(part).ok_or_else(|| {
+            format!(
+                "unknown status `{part}` (expected queued, running, complete, aborted, or error)"
+            )
+        })?;
+        if !statuses.contains(&status) {
+            statuses.push(status);
+        }
+    }
+    if statuses.is_empty() {
+        return Err("status filter must name at least one status".to_string());
+    }
+    Ok(statuses)
+}
+
+/// Event text for an inherited sandbox that allows writes, or `None` when it
+/// is read-only.
+fn inherited_sandbox_notice(policy: &SandboxPolicy) -> Option<String> {
+    match policy {
+        SandboxPolicy::ReadOnly => None,
+        SandboxPolicy::DangerFullAccess => {
+            Some("sandbox: danger-full-access (inherited, no restrictions)".to_string())
+        }
+        SandboxPolicy::WorkspaceWrite { network_access, .. } => {
+            let network = if *network_access {
+                ", network access"
+            } else {
+                ""
+            };
+            Some(format!("sandbox: workspace-write (inherited{network})"))
+        }
+    }
+}
+
+/// Parses a `reasoning_effort` argument (case-insensitive); `None` and blank
+/// values inherit the parent's effort.
+pub(crate) fn parse_reasoning_effort(raw: Option<&str>) -> Result<Option<ReasoningEffort>, String> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(None);
+    };
+    const EFFORTS: [ReasoningEffort; 6] = [
+        ReasoningEffort::None,
+        ReasoningEffort::Minimal,
+        ReasoningEffort::Low,
+        ReasoningEffort::Medium,
+        ReasoningEffort::High,
+        ReasoningEffort::XHigh,
+    ];
+    EFFORTS
+        .into_iter()
+        .find(|effort| effort.to_string().eq_ignore_ascii_case(raw))
+        .map(Some)
+        .ok_or_else(|| {
+            let allowed = EFFORTS.map(|effort| effort.to_string()).join(", ");
+            format!("`reasoning_effort` must be one of: {allowed}")
+        })
+}
+
+/// Sandbox name reported by polls, e.g. `read-only` or
+/// `workspace-write (network access)`.
+fn sandbox_label(policy: &SandboxPolicy) -> String {
+    match policy {
+        SandboxPolicy::WorkspaceWrite {
+            network_access: true,
+            ..
+        } => format!("{policy} (network access)"),
+        _ => policy.to_string(),
+    }
+}
+
+/// Hash of what makes two spawn requests the same task for `dedupe_spawns`.
+fn spawn_fingerprint(req: &SubagentSpawnRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (
+        req.label.as_str(),
+        req.mode.as_str(),
+        req.prompt.trim(),
+        &req.skills,
+    )
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Rejects a `model` override the provider does not offer. Other providers
+/// serve their own model names, so only the OpenAI provider's model list is
+/// authoritative.
+async fn check_requested_model(
+    req: &SubagentSpawnRequest,
+    models_manager: &ModelsManager,
+    parent_config: &crate::config::Config,
+) -> Result<(), String> {
+    if let Some(model) = req.model.as_deref()
+        && parent_config.model_provider.is_openai()
+    {
+        let available: Vec<String> = models_manager
+            .list_models(parent_config)
+            .await
+            .into_iter()
+            .map(|preset| preset.model)
+            .collect();
+        check_model_override(model, &available)?;
+    }
+    Ok(())
+}
+
+/// Rejects a model override the provider does not offer.
+fn check_model_override(model: &str, available: &[String]) -> Result<(), String> {
+    if available.iter().any(|name| name == model) {
+        return Ok(());
+    }
+    Err(format!(
+        "unknown model `{model}` (available: {})",
+        available.join(", ")
+    ))
+}
+
+/// Canonicalizes `path` and checks that it is a directory inside the
+/// repository containing `base` (or inside `base` when it is not in a repo).
+async fn resolve_subagent_cwd(path: &Path, base: &Path) -> Result<PathBuf, String> {
+    let cwd = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|e| format!("cannot use {} as cwd: {e}", path.display()))?;
+    if !cwd.is_dir() {
+        return Err(format!("{} is not a directory", cwd.display()));
+    }
+    let root = get_git_repo_root(base).unwrap_or_else(|| base.to_path_buf());
+    let root = tokio::fs::canonicalize(&root).await.unwrap_or(root);
+    if !cwd.starts_with(&root) {
+        return Err(format!(
+            "{} is outside the repository root {}",
+            cwd.display(),
+            root.display()
+        ));
+    }
+    Ok(cwd)
+}
+
+/// Suggested wait before the next poll of an agent spawned `elapsed_ms` ago:
+/// a quarter of its age rounded up to a power of two, so the hint backs off
+/// exponentially the longer the agent runs.
+fn suggested_next_poll_ms(elapsed_ms: u64) -> u64 {
+    (elapsed_ms / 4)
+        .next_power_of_two()
+        .clamp(MIN_SUGGESTED_POLL_MS, MAX_SUGGESTED_POLL_MS)
+}
+
+/// Time left until `deadline_unix_ms`, or `None` once it has passed.
+fn time_until_deadline(deadline_unix_ms: u64, now: DateTime<Utc>) -> Option<Duration> {
+    let now_ms = u64::try_from(now.timestamp_millis()).unwrap_or(0);
+    deadline_unix_ms
+        .checked_sub(now_ms)
+        .filter(|left| *left > 0)
+        .map(Duration::from_millis)
+}
+
+/// Checks that a `resume_rollout_path` is a file inside the rollout
+/// directory (`$CODEX_HOME/sessions`) and, when `known` is given, that it is
+/// one of those paths. Paths are compared after resolving symlinks and `..`.
+async fn check_resume_rollout_path(
+    path: &Path,
+    codex_home: &Path,
+    known: Option<&[PathBuf]>,
+) -> Result<(), String> {
+    let shown = path.display();
+    let resolved = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|err| format!("rollout_path `{shown}` cannot be read: {err}"))?;
+    let root = codex_home.join(SESSIONS_SUBDIR);
+    let inside_root = tokio::fs::canonicalize(&root)
+        .await
+        .is_ok_and(|root| resolved.starts_with(root));
+    if !inside_root || !resolved.is_file() {
+        return Err(format!(
+            "rollout_path `{shown}` is not a rollout file under {}",
+            root.display()
+        ));
+    }
+    if let Some(known) = known {
+        let mut found = false;
+        for candidate in known {
+            if tokio::fs::canonicalize(candidate).await.ok().as_ref() == Some(&resolved) {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            return Err(format!(
+                "rollout_path `{shown}` was not reported by a subagent of this session"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Sums the on-disk size of the requested skills and refuses the spawn when the
+/// total exceeds `max_skill_bytes`. Unknown skills are reported later by the
+/// regular skill resolution step.
+async fn check_skill_budget(
+    skills_manager: &SkillsManager,
+    cwd: &Path,
+    requested: &[String],
+    max_skill_bytes: u64,
+) -> Result<(), String> {
+    let outcome = skills_manager.skills_for_cwd(cwd);
+    let mut sizes: Vec<(String, u64)> = Vec::new();
+    for skill in resolve_requested_skills(requested, &outcome.skills).matched {
+        let bytes = tokio::fs::metadata(&skill.path)
+            .await
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+        sizes.push((skill.name.clone(), bytes));
+    }
+
+    let total: u64 = sizes.iter().map(|(_, bytes)| bytes).sum();
+    if total <= max_skill_bytes {
+        return Ok(());
+    }
+    let breakdown = sizes
+        .iter()
+        .map(|(name, bytes)| format!("{name}: {bytes} bytes"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(format!(
+        "requested skills total {total} bytes, exceeding [subagents].max_skill_bytes ({max_skill_bytes}): {breakdown}"
+    ))
+}
+
+/// Returns the skills available in `cwd`, re-scanning the skill roots until
+/// every `requested` skill is found (or every pattern matches at least one
+/// skill), `wait` elapses, or `cancel` fires.
+async fn wait_for_skills(
+    skills_manager: &SkillsManager,
+    cwd: &Path,
+    requested: &[String],
+    wait: Duration,
+    cancel: &CancellationToken,
+) -> SkillLoadOutcome {
+    let all_found = |outcome: &SkillLoadOutcome| {
+        resolve_requested_skills(requested, &outcome.skills)
+            .unmatched
+            .is_empty()
+    };
+    let mut outcome = skills_manager.skills_for_cwd(cwd);
+    let deadline = Instant::now() + wait;
+    while !all_found(&outcome) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(left.min(SKILL_RESCAN_INTERVAL)) => {}
+        }
+        outcome = skills_manager.reload_for_cwd(cwd);
+    }
+    outcome
+}
+
+/// Applies the sandbox, approval, and tool feature defaults of `mode`.
+fn apply_mode_profile(config: &mut crate::config::Config, mode: SubagentMode) {
+    match mode {
+        SubagentMode::Explore => {
+            config.sandbox_policy = SandboxPolicy::new_read_only_policy();
+            config.approval_policy = AskForApproval::OnRequest;
+            config
+                .features
+                .disable(Feature::ApplyPatchFreeform)
+                .disable(Feature::UnifiedExec)
+                .disable(Feature::ShellTool)
+                .disable(Feature::ShellSnapshot)
+                .disable(Feature::ViewImageTool)
+                .disable(Feature::WebSearchRequest);
+        }
+        SubagentMode::General => {
+            // Inherit parent sandbox/approval policy by default.
+        }
+        SubagentMode::Review => {
+            config.sandbox_policy = SandboxPolicy::new_read_only_policy();
+            config.approval_policy = AskForApproval::OnRequest;
+            config
+                .features
+                .disable(Feature::ApplyPatchFreeform)
+                .disable(Feature::UnifiedExec)
+                .disable(Feature::ShellTool)
+                .disable(Feature::ShellSnapshot)
+                .enable(Feature::WebSearchRequest);
+        }
+    }
+}
+
+/// Resolves the tool names a subagent with `config` will be offered, after its
+/// mode profile and tool allow/deny lists are applied. Pass the subagent's MCP
+/// tools once its session reports them; until then only built-ins are listed.
+fn resolved_tool_names(
+    model_family: &ModelFamily,
+    config: &crate::config::Config,
+    mcp_tools: Option<HashMap<String, McpTool>>,
+) -> Vec<String> {
+    let tools_config = ToolsConfig::new(&ToolsConfigParams {
+        model_family,
+        features: &config.features,
+        tool_name_allowlist: config.tool_name_allowlist.as_deref(),
+        tool_name_denylist: config.tool_name_denylist.as_deref(),
+    });
+    let mut names: Vec<String> = ToolRouter::from_config(&tools_config, mcp_tools)
+        .specs()
+        .iter()
+        .map(|spec| spec.name().to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Records that a terminal agent's output has been handed to the caller. A
+/// completed lazy agent's marker only counts once the output is materialized.
+fn mark_consumed(handle: &SubagentHandle, state: &mut SubagentState, materialized: bool) {
+    let terminal = !matches!(
+        state.status,
+        SubagentStatus::Queued | SubagentStatus::Running
+    );
+    let lazy_marker =
+        handle.lazy_output && state.status == SubagentStatus::Complete && !materialized;
+    if terminal && state.final_output.is_some() && !lazy_marker {
+        state.consumed = true;
+    }
+}
+
+fn truncate_to_char_boundary(s: &mut String, max_bytes: usize) {
+    if s.len() <= max_bytes {
+        return;
+    }
+    let mut idx = max_bytes;
+    while idx > 0 && !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    s.truncate(idx);
+}
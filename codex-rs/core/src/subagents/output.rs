@@ -0,0 +1,306 @@
+//! Final-output handling: markers the agent can emit, post-processing,
+//! slicing, and reading the answer back from a rollout.
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use codex_protocol::models::LocalShellAction;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::RolloutItem;
+
+use super::ABORT_SIBLINGS_MARKER;
+use super::CONFIDENCE_MARKER;
+use super::ChecklistItem;
+use super::SubagentHandle;
+use super::SubagentSpawnRequest;
+use super::SubagentState;
+use super::TRANSCRIPT_TOOL_TEXT_BYTES;
+use super::truncate_to_char_boundary;
+use crate::config::types::OutputPostprocess;
+use crate::rollout::RolloutRecorder;
+
+/// Removes the first `<<abort-siblings: reason>>` marker from `text` and
+/// returns its reason.
+pub(super) fn take_abort_siblings_marker(text: &mut String) -> Option<String> {
+    let start = text.find(ABORT_SIBLINGS_MARKER)?;
+    let end = start + text[start..].find(">>")?;
+    let reason = text[start + ABORT_SIBLINGS_MARKER.len()..end].trim();
+    let reason = if reason.is_empty() {
+        "fatal finding".to_string()
+    } else {
+        reason.to_string()
+    };
+    remove_marker(text, start..end + ">>".len());
+    Some(reason)
+}
+
+/// Removes the last `<<confidence: X>>` marker from `text` and returns its
+/// score, clamped to `0.0..=1.0`. Unparseable or non-finite scores yield
+/// `None`; the marker is removed either way.
+pub(super) fn take_confidence_marker(text: &mut String) -> Option<f32> {
+    let start = text.rfind(CONFIDENCE_MARKER)?;
+    let end = start + text[start..].find(">>")?;
+    let score = text[start + CONFIDENCE_MARKER.len()..end]
+        .trim()
+        .parse::<f32>()
+        .ok();
+    remove_marker(text, start..end + ">>".len());
+    score
+        .filter(|score| score.is_finite())
+        .map(|score| score.clamp(0.0, 1.0))
+}
+
+/// What one agent message carried in the markers its request enables.
+#[derive(Debug, Default)]
+pub(super) struct MessageMarkers {
+    pub(super) confidence: Option<f32>,
+    pub(super) abort_siblings: Option<String>,
+}
+
+/// Strips the markers `req` lets the agent emit from one of its messages, so
+/// they never reach events or output, and returns what they carried.
+pub(super) fn strip_markers(req: &SubagentSpawnRequest, message: &mut String) -> MessageMarkers {
+    MessageMarkers {
+        confidence: req
+            .report_confidence
+            .then(|| take_confidence_marker(message))
+            .flatten(),
+        abort_siblings: req
+            .allow_sibling_abort
+            .then(|| take_abort_siblings_marker(message))
+            .flatten(),
+    }
+}
+
+/// Cuts a marker out of `text`, dropping the whitespace it leaves at the end.
+fn remove_marker(text: &mut String, range: Range<usize>) {
+    text.replace_range(range, "");
+    text.truncate(text.trim_end().len());
+}
+
+/// Parses the first fenced `checklist` block in `output`. Items use Markdown
+/// task syntax (`- [ ] step`, `- [x] step`); plain bullets count as not done.
+pub(super) fn parse_checklist(output: &str) -> Vec<ChecklistItem> {
+    let mut items = Vec::new();
+    let mut in_block = false;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if !in_block {
+            in_block = trimmed
+                .strip_prefix("```")
+                .is_some_and(|info| info.trim().eq_ignore_ascii_case("checklist"));
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            break;
+        }
+        let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        else {
+            continue;
+        };
+        let (done, text) = if let Some(text) = item.strip_prefix("[ ]") {
+            (false, text)
+        } else if let Some(text) = item
+            .strip_prefix("[x]")
+            .or_else(|| item.strip_prefix("[X]"))
+        {
+            (true, text)
+        } else {
+            (false, item)
+        };
+        let text = text.trim();
+        if !text.is_empty() {
+            items.push(ChecklistItem {
+                text: text.to_string(),
+                done,
+            });
+        }
+    }
+    items
+}
+
+/// Reads the final answer of the last turn from a rollout: the last agent
+/// message, or every agent message after the last user message when
+/// `join_messages` is set (mirroring the live driver). The agent's output
+/// post-processor is applied, as it would have been when storing it.
+pub(super) async fn materialize_output(
+    path: &Path,
+    handle: &SubagentHandle,
+) -> Result<Option<String>, String> {
+    let history = RolloutRecorder::get_rollout_history(path)
+        .await
+        .map_err(|e| format!("failed to read subagent rollout: {e}"))?;
+    let mut turn_messages: Vec<String> = Vec::new();
+    for item in history.get_rollout_items() {
+        match item {
+            RolloutItem::EventMsg(EventMsg::UserMessage(_)) => turn_messages.clear(),
+            RolloutItem::EventMsg(EventMsg::AgentMessage(ev)) => {
+                let mut message = ev.message;
+                if let Some(req) = &handle.request {
+                    strip_markers(req, &mut message);
+                }
+                turn_messages.push(message);
+            }
+            _ => {}
+        }
+    }
+    let output = if handle.join_messages && !turn_messages.is_empty() {
+        Some(turn_messages.join("\n\n"))
+    } else {
+        turn_messages.pop()
+    };
+    Ok(output.map(|text| postprocess_output(handle.output_postprocess, text)))
+}
+
+/// Renders rollout items as one `[kind] text` entry per message, reasoning
+/// summary, or tool call, separated by blank lines. Long tool arguments and
+/// outputs are cut to [`TRANSCRIPT_TOOL_TEXT_BYTES`].
+pub(super) fn render_transcript(items: &[RolloutItem]) -> String {
+    let tool_text = |text: &str| {
+        let mut text = text.trim().to_string();
+        if text.len() > TRANSCRIPT_TOOL_TEXT_BYTES {
+            truncate_to_char_boundary(&mut text, TRANSCRIPT_TOOL_TEXT_BYTES);
+            text.push_str(" [truncated]");
+        }
+        text
+    };
+    let mut entries: Vec<String> = Vec::new();
+    for item in items {
+        let entry = match item {
+            RolloutItem::EventMsg(EventMsg::UserMessage(ev)) => format!("[user] {}", ev.message),
+            RolloutItem::EventMsg(EventMsg::AgentMessage(ev)) => {
+                format!("[assistant] {}", ev.message)
+            }
+            RolloutItem::EventMsg(EventMsg::AgentReasoning(ev)) => {
+                format!("[reasoning] {}", ev.text)
+            }
+            RolloutItem::EventMsg(EventMsg::TurnAborted(_)) => "[turn aborted]".to_string(),
+            RolloutItem::EventMsg(EventMsg::ContextCompacted(_)) => {
+                "[context compacted]".to_string()
+            }
+            RolloutItem::ResponseItem(ResponseItem::FunctionCall {
+                name, arguments, ..
+            }) => format!("[tool call] {name} {}", tool_text(arguments)),
+            RolloutItem::ResponseItem(ResponseItem::CustomToolCall { name, input, .. }) => {
+                format!("[tool call] {name} {}", tool_text(input))
+            }
+            RolloutItem::ResponseItem(ResponseItem::LocalShellCall {
+                action: LocalShellAction::Exec(exec),
+                ..
+            }) => format!("[tool call] shell {}", tool_text(&exec.command.join(" "))),
+            RolloutItem::ResponseItem(ResponseItem::FunctionCallOutput { output, .. }) => {
+                format!("[tool output] {}", tool_text(&output.content))
+            }
+            RolloutItem::ResponseItem(ResponseItem::CustomToolCallOutput { output, .. }) => {
+                format!("[tool output] {}", tool_text(output))
+            }
+            _ => continue,
+        };
+        entries.push(entry);
+    }
+    entries.join("\n\n")
+}
+
+/// Applies the configured `[subagents].output_postprocess` to a final output.
+pub(super) fn postprocess_output(postprocess: OutputPostprocess, text: String) -> String {
+    match postprocess {
+        OutputPostprocess::None => text,
+        OutputPostprocess::Trim => normalize_whitespace(&text),
+        OutputPostprocess::StripMarkdown => normalize_whitespace(&strip_markdown(&text)),
+    }
+}
+
+/// Trims the text and each line's trailing whitespace, and collapses runs of
+/// blank lines into one.
+fn normalize_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_run = false;
+    for line in text.trim().lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank_run = true;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank_run { "\n\n" } else { "\n" });
+        }
+        blank_run = false;
+        out.push_str(line);
+    }
+    out
+}
+
+/// Best-effort removal of markdown syntax: code fences, heading and quote
+/// markers, bold markers, inline code ticks, and link/image wrappers (kept
+/// as `text (url)`). List markers and single `*`/`_` are left alone because
+/// they are ambiguous in code-heavy answers.
+fn strip_markdown(text: &str) -> String {
+    static LINK_RE: OnceLock<regex_lite::Regex> = OnceLock::new();
+    #[expect(clippy::unwrap_used)]
+    let link_re =
+        LINK_RE.get_or_init(|| regex_lite::Regex::new(r"!?\[([^\]]*)\]\(([^)\s]*)\)").unwrap());
+
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let body = line.trim_start();
+        if body.starts_with("```") || body.starts_with("~~~") {
+            continue;
+        }
+        let indent = &line[..line.len() - body.len()];
+        let mut body = body.strip_prefix('>').map_or(body, str::trim_start);
+        let hashes = body.len() - body.trim_start_matches('#').len();
+        if (1..=6).contains(&hashes) && body[hashes..].starts_with(' ') {
+            body = body[hashes..].trim_start();
+        }
+        let body = link_re.replace_all(body, |caps: &regex_lite::Captures<'_>| {
+            let label = caps.get(1).map_or("", |m| m.as_str());
+            let url = caps.get(2).map_or("", |m| m.as_str());
+            if label.is_empty() || label == url {
+                url.to_string()
+            } else {
+                format!("{label} ({url})")
+            }
+        });
+        lines.push(format!(
+            "{indent}{}",
+            body.replace("**", "").replace('`', "")
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Cuts `len` bytes out of `text` at `offset`, moving both ends back to char
+/// boundaries (but always making progress). Returns the effective offset, the
+/// slice, and the offset of the next slice if any text remains.
+pub(super) fn slice_output(text: &str, offset: usize, len: usize) -> (usize, &str, Option<usize>) {
+    let floor = |mut idx: usize| {
+        idx = idx.min(text.len());
+        while !text.is_char_boundary(idx) {
+            idx -= 1;
+        }
+        idx
+    };
+    let start = floor(offset);
+    let mut end = floor(start.saturating_add(len));
+    if end == start && start < text.len() {
+        end = start + text[start..].chars().next().map_or(0, char::len_utf8);
+    }
+    let next = (end < text.len()).then_some(end);
+    (start, &text[start..end], next)
+}
+
+pub(super) fn cap_output(
+    handle: &SubagentHandle,
+    state: &mut SubagentState,
+    mut message: String,
+) -> String {
+    if message.len() > handle.max_output_chars {
+        truncate_to_char_boundary(&mut message, handle.max_output_chars);
+        state.truncated = true;
+        state.output_truncated = true;
+    }
+    message
+}
@@ -0,0 +1,303 @@
+//! Admission control: the priority queue in front of the concurrency pools,
+//! mutex groups, and `depends_on` ordering.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+use super::ConcurrencySnapshot;
+use super::ManagerEventKind;
+use super::SetPriorityOutcome;
+use super::SubagentEventKind;
+use super::SubagentHandle;
+use super::SubagentManager;
+use super::SubagentStatus;
+use super::events::push_event;
+use super::global_subagent_limiter;
+use super::resolve_max_concurrency;
+use super::sanitize_agent_id;
+
+/// A subagent waiting for a concurrency permit. Higher priorities go first;
+/// equal priorities are served in spawn order.
+pub(super) struct QueuedAgent {
+    pub(super) agent_id: String,
+    pub(super) priority: i32,
+    pub(super) seq: u64,
+}
+
+/// The agent first in line: highest priority, then earliest spawn.
+fn queue_head(queue: &[QueuedAgent]) -> Option<&str> {
+    queue
+        .iter()
+        .max_by(|a, b| a.priority.cmp(&b.priority).then_with(|| b.seq.cmp(&a.seq)))
+        .map(|entry| entry.agent_id.as_str())
+}
+
+/// Per-session concurrency cap, taken in addition to the global limiter so
+/// one session cannot starve the others.
+pub(super) struct SessionLimiter {
+    pub(super) semaphore: Arc<Semaphore>,
+    pub(super) limit: usize,
+}
+
+impl SessionLimiter {
+    pub(super) fn new(max_concurrency: Option<usize>) -> Self {
+        let limit = resolve_max_concurrency(max_concurrency);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+}
+
+/// Held by a running agent: one slot of its session and one of the process.
+pub(super) struct ConcurrencyPermits {
+    _session: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+impl SubagentManager {
+    /// Free slots in this session's pool and in the process-wide pool. A new
+    /// agent starts right away only when both are non-zero.
+    pub(crate) fn concurrency(&self) -> ConcurrencySnapshot {
+        let session = self.session_limiter();
+        ConcurrencySnapshot {
+            session_free: session.semaphore.available_permits(),
+            session_limit: session.limit,
+            global_free: global_subagent_limiter().available_permits(),
+        }
+    }
+
+    pub(super) fn session_limiter(&self) -> &SessionLimiter {
+        self.session_limiter
+            .get_or_init(|| SessionLimiter::new(None))
+    }
+
+    /// Takes a session slot, then a global one. Dropping the future before
+    /// both are held releases the session slot again.
+    pub(super) async fn acquire_slots(&self) -> Option<ConcurrencyPermits> {
+        let session = Arc::clone(&self.session_limiter().semaphore)
+            .acquire_owned()
+            .await
+            .ok()?;
+        let global = global_subagent_limiter().acquire_owned().await.ok()?;
+        Some(ConcurrencyPermits {
+            _session: session,
+            _global: global,
+        })
+    }
+
+    /// Changes the priority of a queued agent. Agents that already started
+    /// running keep their permit and are left untouched.
+    pub(crate) async fn set_priority(
+        &self,
+        agent_id: &str,
+        priority: i32,
+    ) -> Option<SetPriorityOutcome> {
+        let handle = self.get_handle(agent_id).await?;
+        let mut queue = self.permit_queue.lock().await;
+        let head_before = queue_head(&queue).map(str::to_string);
+        let Some(entry) = queue.iter_mut().find(|entry| entry.agent_id == handle.id) else {
+            return Some(SetPriorityOutcome::NotQueued);
+        };
+        entry.priority = priority;
+        let head_changed = queue_head(&queue) != head_before.as_deref();
+        drop(queue);
+        if head_changed {
+            self.permit_queue_changed.notify_waiters();
+        }
+        self.record_audit(
+            ManagerEventKind::Reprioritized,
+            Some(&handle.id),
+            format!("priority={priority}"),
+        )
+        .await;
+        Some(SetPriorityOutcome::Updated)
+    }
+
+    /// Waits until `handle` is the highest-priority queued agent and both a
+    /// session and a global concurrency slot are available. Returns `None` if
+    /// the agent is cancelled while waiting.
+    pub(super) async fn acquire_permit(
+        &self,
+        handle: &SubagentHandle,
+        priority: i32,
+    ) -> Option<ConcurrencyPermits> {
+        let seq = self.permit_queue_seq.fetch_add(1, Ordering::Relaxed);
+        let outranks_head = {
+            let mut queue = self.permit_queue.lock().await;
+            queue.push(QueuedAgent {
+                agent_id: handle.id.clone(),
+                priority,
+                seq,
+            });
+            queue.len() > 1 && queue_head(&queue) == Some(handle.id.as_str())
+        };
+        // Waking the head would cancel its pending `acquire_slots` and send it
+        // to the back of the semaphores' queues, so only do it when the
+        // newcomer takes its place.
+        if outranks_head {
+            self.permit_queue_changed.notify_waiters();
+        }
+
+        let permit = loop {
+            let changed = self.permit_queue_changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let is_next = queue_head(&self.permit_queue.lock().await) == Some(handle.id.as_str());
+
+            if is_next {
+                tokio::select! {
+                    permits = self.acquire_slots() => break permits,
+                    _ = &mut changed => {}
+                    _ = handle.cancel.cancelled() => break None,
+                }
+            } else {
+                tokio::select! {
+                    _ = &mut changed => {}
+                    _ = handle.cancel.cancelled() => break None,
+                }
+            }
+        };
+
+        let was_head = {
+            let mut queue = self.permit_queue.lock().await;
+            let was_head = queue_head(&queue) == Some(handle.id.as_str());
+            queue.retain(|entry| entry.agent_id != handle.id);
+            was_head && !queue.is_empty()
+        };
+        if was_head {
+            self.permit_queue_changed.notify_waiters();
+        }
+        permit
+    }
+
+    /// Waits until no other agent in `group` is running. Returns `None` if the
+    /// agent is cancelled while waiting.
+    pub(super) async fn acquire_mutex_group(
+        &self,
+        handle: &SubagentHandle,
+        group: &str,
+    ) -> Option<OwnedSemaphorePermit> {
+        let semaphore = Arc::clone(
+            self.mutex_groups
+                .lock()
+                .await
+                .entry(group.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(1))),
+        );
+        if let Ok(guard) = Arc::clone(&semaphore).try_acquire_owned() {
+            return Some(guard);
+        }
+        {
+            let mut state = handle.state.lock().await;
+            push_event(
+                handle,
+                &mut state,
+                SubagentEventKind::Status,
+                format!("waiting for mutex group {group}"),
+            );
+        }
+        handle.notify.notify_waiters();
+        tokio::select! {
+            _ = handle.cancel.cancelled() => None,
+            guard = semaphore.acquire_owned() => guard.ok(),
+        }
+    }
+
+    /// Resolves `depends_on` to the stored (sanitized) agent ids, so callers
+    /// may pass either form. Rejects unknown agents and dependencies that would
+    /// close a cycle back to `agent_id`.
+    pub(super) async fn check_dependencies(
+        &self,
+        agent_id: &str,
+        depends_on: &[String],
+    ) -> Result<Vec<String>, String> {
+        if depends_on.is_empty() {
+            return Ok(Vec::new());
+        }
+        let agents = self.agents.read().await.clone();
+        let mut resolved = Vec::with_capacity(depends_on.len());
+        for dep in depends_on {
+            let id = sanitize_agent_id(dep);
+            if id.as_deref() == Some(agent_id) {
+                return Err("an agent cannot depend on itself".to_string());
+            }
+            match id {
+                Some(id) if agents.contains_key(&id) => resolved.push(id),
+                _ => return Err(format!("unknown dependency `{dep}`")),
+            }
+        }
+        let mut stack = resolved.clone();
+        let mut visited: HashSet<String> = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if id == agent_id {
+                return Err(format!(
+                    "depends_on would create a cycle through `{agent_id}`"
+                ));
+            }
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+            if let Some(handle) = agents.get(&id) {
+                stack.extend(handle.state.lock().await.depends_on.iter().cloned());
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Waits until every agent in `depends_on` has completed. Returns the
+    /// reason when one of them errored, was aborted, or is gone, and `None`
+    /// if `handle` is cancelled while waiting.
+    pub(super) async fn await_dependencies(
+        &self,
+        handle: &SubagentHandle,
+        depends_on: &[String],
+    ) -> Option<Result<(), String>> {
+        let mut announced = false;
+        for dep_id in depends_on {
+            let Some(dep) = self.agents.read().await.get(dep_id).cloned() else {
+                return Some(Err(format!("dependency `{dep_id}` is no longer tracked")));
+            };
+            loop {
+                // Register before reading the status so a completion between
+                // the two is not missed.
+                let notified = dep.notify.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                let status = dep.state.lock().await.status;
+                match status {
+                    SubagentStatus::Complete => break,
+                    SubagentStatus::Error | SubagentStatus::Aborted => {
+                        return Some(Err(format!(
+                            "dependency `{dep_id}` ended with status {}",
+                            status.as_str()
+                        )));
+                    }
+                    SubagentStatus::Queued | SubagentStatus::Running => {}
+                }
+                if !announced {
+                    announced = true;
+                    let mut state = handle.state.lock().await;
+                    push_event(
+                        handle,
+                        &mut state,
+                        SubagentEventKind::Status,
+                        format!("waiting for dependencies: {}", depends_on.join(", ")),
+                    );
+                    drop(state);
+                    handle.notify.notify_waiters();
+                }
+                tokio::select! {
+                    _ = handle.cancel.cancelled() => return None,
+                    _ = &mut notified => {}
+                }
+            }
+        }
+        Some(Ok(()))
+    }
+}
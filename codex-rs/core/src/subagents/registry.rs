@@ -0,0 +1,128 @@
+//! Persisting the agent registry into the parent rollout and restoring it
+//! when the session resumes.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use codex_protocol::protocol::RolloutItem;
+use codex_protocol::protocol::SubagentRegistryEntry;
+use codex_protocol::protocol::SubagentRegistryItem;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use super::LAZY_OUTPUT_MARKER;
+use super::ManagerEventKind;
+use super::SubagentEventKind;
+use super::SubagentHandle;
+use super::SubagentManager;
+use super::SubagentState;
+use super::SubagentStatus;
+use super::events::push_event;
+use crate::codex::Session;
+use crate::config::types::SubagentsConfig;
+
+pub(super) const RESTORED_INTERRUPTED_MESSAGE: &str = "interrupted: the parent session restarted while this agent was active; continue it with subagent_spawn and its rollout_path as resume_rollout_path";
+
+impl SubagentManager {
+    /// Registry of known agents, oldest first, for persisting into the
+    /// parent rollout.
+    pub(crate) async fn snapshot(&self) -> SubagentRegistryItem {
+        let mut handles: Vec<Arc<SubagentHandle>> =
+            self.agents.read().await.values().cloned().collect();
+        handles.sort_by_key(|handle| handle.created_at);
+        let mut agents = Vec::with_capacity(handles.len());
+        for handle in handles {
+            let state = handle.state.lock().await;
+            agents.push(SubagentRegistryEntry {
+                agent_id: handle.id.clone(),
+                label: handle.label.clone(),
+                mode: handle.mode.into(),
+                rollout_path: state.rollout_path.clone(),
+                status: state.status.into(),
+            });
+        }
+        SubagentRegistryItem { agents }
+    }
+
+    /// Writes the current registry into the parent session's rollout.
+    pub(crate) async fn persist_registry(&self, parent_session: &Session) {
+        let registry = self.snapshot().await;
+        parent_session
+            .persist_rollout_items(&[RolloutItem::SubagentRegistry(registry)])
+            .await;
+    }
+
+    /// Rehydrates agents from a registry persisted by an earlier run of this
+    /// session. Restored agents have no live conversation: completed ones read
+    /// their output back from the rollout, and agents that were still queued
+    /// or running are marked as errors that can be picked up by spawning with
+    /// their rollout as `resume_rollout_path`. Ids that are already registered
+    /// are skipped.
+    pub(crate) async fn restore(&self, registry: &SubagentRegistryItem, config: &SubagentsConfig) {
+        let mut restored = 0;
+        for entry in &registry.agents {
+            if self.agents.read().await.contains_key(&entry.agent_id) {
+                continue;
+            }
+            let status = SubagentStatus::from(entry.status);
+            let has_rollout = entry.rollout_path.is_some();
+            let (status, final_output) = match status {
+                SubagentStatus::Queued | SubagentStatus::Running => (
+                    SubagentStatus::Error,
+                    Some(RESTORED_INTERRUPTED_MESSAGE.to_string()),
+                ),
+                SubagentStatus::Complete if has_rollout => {
+                    (status, Some(LAZY_OUTPUT_MARKER.to_string()))
+                }
+                _ => (status, None),
+            };
+            let handle = Arc::new(SubagentHandle {
+                id: entry.agent_id.clone(),
+                label: entry.label.clone(),
+                mode: entry.mode.into(),
+                cancel: CancellationToken::new(),
+                notify: Notify::new(),
+                state: Mutex::new(SubagentState {
+                    status,
+                    rollout_path: entry.rollout_path.clone(),
+                    final_output,
+                    last_update: Some(Instant::now()),
+                    ..Default::default()
+                }),
+                created_at: Instant::now(),
+                max_events: config.max_events,
+                max_event_chars: config.max_event_chars,
+                max_output_chars: config.max_output_chars,
+                // Outputs are never held in memory after a restart.
+                lazy_output: true,
+                join_messages: config.join_messages,
+                ephemeral: false,
+                output_postprocess: config.output_postprocess,
+                request: None,
+            });
+            {
+                let mut state = handle.state.lock().await;
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Status,
+                    "restored from the parent rollout".to_string(),
+                );
+            }
+            self.agents
+                .write()
+                .await
+                .insert(entry.agent_id.clone(), handle);
+            restored += 1;
+        }
+        if restored > 0 {
+            self.record_audit(
+                ManagerEventKind::Restored,
+                None,
+                format!("restored {restored} agent(s) from the parent rollout"),
+            )
+            .await;
+        }
+    }
+}
@@ -0,0 +1,1269 @@
+//! The background driver: runs one subagent session from spawn to its
+//! final status, with retries, deadlines, and shutdown.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::Utc;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::protocol::CodexErrorInfo;
+use codex_protocol::protocol::Event;
+use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::InitialHistory;
+use codex_protocol::protocol::Op;
+use codex_protocol::protocol::ReviewDecision;
+use codex_protocol::protocol::SessionSource;
+use codex_protocol::protocol::SubAgentSource;
+use codex_protocol::user_input::UserInput;
+use futures::FutureExt;
+use futures::StreamExt;
+use reqwest::StatusCode;
+use tempfile::TempDir;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::warn;
+
+use super::ABORT_SIBLINGS_INSTRUCTIONS;
+use super::CONFIDENCE_INSTRUCTIONS;
+use super::LAZY_OUTPUT_MARKER;
+use super::ManagerEventKind;
+use super::OVERFLOW_SUMMARY_TIMEOUT;
+use super::PROPOSE_ONLY_INSTRUCTIONS;
+use super::SESSION_CONFIGURED_TIMEOUT;
+use super::SPAWN_RETRY_BASE_BACKOFF;
+use super::SUBAGENT_SHUTDOWN_TIMEOUT;
+use super::SUBAGENT_TRACE_ID_HEADER;
+use super::SubagentErrorKind;
+use super::SubagentEventKind;
+use super::SubagentHandle;
+use super::SubagentManager;
+use super::SubagentMode;
+use super::SubagentOutputFormat;
+use super::SubagentSpawnRequest;
+use super::SubagentState;
+use super::SubagentStatus;
+use super::apply_mode_profile;
+use super::approvals::auto_deny_approval;
+use super::approvals::handle_exec_approval_request;
+use super::approvals::handle_patch_approval_request;
+use super::approvals::record_proposed_patch;
+use super::check_resume_model;
+use super::events::push_event;
+use super::events::push_summary_event;
+use super::events::tool_call_kind;
+use super::extra_instructions_section;
+use super::inherited_sandbox_notice;
+use super::output::cap_output;
+use super::output::parse_checklist;
+use super::output::postprocess_output;
+use super::output::strip_markers;
+use super::recorded_model;
+use super::resolved_tool_names;
+use super::sandbox_label;
+use super::subagent_base_instructions;
+use super::time_until_deadline;
+use super::wait_for_skills;
+use crate::AuthManager;
+use crate::Prompt;
+use crate::client_common::ResponseEvent;
+use crate::codex::Codex;
+use crate::codex::CodexSpawnOk;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::config::types::OutputOverflow;
+use crate::error::CodexErr;
+use crate::features::Feature;
+use crate::openai_models::models_manager::ModelsManager;
+use crate::protocol::AskForApproval;
+use crate::protocol::SandboxPolicy;
+use crate::rollout::RolloutRecorder;
+use crate::skills::SkillsManager;
+use crate::skills::describe_unmatched_skill;
+use crate::skills::resolve_requested_skills;
+use crate::stream_events_utils::last_assistant_message_from_item;
+
+/// Creates the scratch directory a general-mode agent runs in when
+/// `[subagents].isolated_workspace` is set.
+pub(super) fn create_isolated_workspace(
+    config: &crate::config::Config,
+    mode: SubagentMode,
+) -> Result<Option<TempDir>, String> {
+    if !config.subagents.isolated_workspace || mode != SubagentMode::General {
+        return Ok(None);
+    }
+    tempfile::Builder::new()
+        .prefix("codex-subagent-")
+        .tempdir()
+        .map(Some)
+        .map_err(|e| format!("failed to create isolated workspace: {e}"))
+}
+
+/// Runs the subagent (with retries) and then removes its isolated workspace.
+/// The directory is owned by this task, so it is also removed if the run
+/// unwinds.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn run_subagent_in_workspace(
+    workspace: Option<TempDir>,
+    manager: Arc<SubagentManager>,
+    handle: Arc<SubagentHandle>,
+    req: SubagentSpawnRequest,
+    parent_session: Arc<Session>,
+    parent_turn: Arc<TurnContext>,
+    auth_manager: Arc<AuthManager>,
+    models_manager: Arc<ModelsManager>,
+    skills_manager: Arc<SkillsManager>,
+    parent_config: crate::config::Config,
+) {
+    run_subagent_with_retries(
+        manager,
+        Arc::clone(&handle),
+        req,
+        parent_session,
+        parent_turn,
+        auth_manager,
+        models_manager,
+        skills_manager,
+        parent_config,
+    )
+    .await;
+
+    let Some(workspace) = workspace else {
+        return;
+    };
+    let path = workspace.path().display().to_string();
+    let message = match workspace.close() {
+        Ok(()) => format!("isolated workspace {path} removed"),
+        Err(e) => format!("failed to remove isolated workspace {path}: {e}"),
+    };
+    let mut state = handle.state.lock().await;
+    state.workspace = None;
+    push_event(&handle, &mut state, SubagentEventKind::Status, message);
+    drop(state);
+    handle.notify.notify_waiters();
+}
+
+/// Runs the subagent, restarting it up to `req.max_retries` times when a run
+/// ends in `Error`. Cancellation and timeouts are never retried.
+#[allow(clippy::too_many_arguments)]
+async fn run_subagent_with_retries(
+    manager: Arc<SubagentManager>,
+    handle: Arc<SubagentHandle>,
+    req: SubagentSpawnRequest,
+    parent_session: Arc<Session>,
+    parent_turn: Arc<TurnContext>,
+    auth_manager: Arc<AuthManager>,
+    models_manager: Arc<ModelsManager>,
+    skills_manager: Arc<SkillsManager>,
+    parent_config: crate::config::Config,
+) {
+    let max_retries = req.max_retries;
+    let fixed_backoff = Duration::from_millis(req.retry_backoff_ms);
+    let mut attempt = 0;
+    loop {
+        run_subagent_one_shot(
+            Arc::clone(&manager),
+            Arc::clone(&handle),
+            req.clone(),
+            Arc::clone(&parent_session),
+            Arc::clone(&parent_turn),
+            Arc::clone(&auth_manager),
+            Arc::clone(&models_manager),
+            Arc::clone(&skills_manager),
+            parent_config.clone(),
+        )
+        .await;
+        manager.persist_registry(&parent_session).await;
+
+        if attempt >= max_retries || handle.cancel.is_cancelled() {
+            return;
+        }
+        let backoff = {
+            let mut state = handle.state.lock().await;
+            if state.status != SubagentStatus::Error {
+                return;
+            }
+            // Honor the provider's retry-after for rate limits instead of the
+            // fixed backoff, so large fan-outs back off together.
+            let (backoff, source) = match (state.error_kind, state.retry_after) {
+                (Some(SubagentErrorKind::RateLimited), Some(retry_after)) => {
+                    (retry_after, " (provider retry-after)")
+                }
+                _ => (fixed_backoff, ""),
+            };
+            attempt += 1;
+            state.status = SubagentStatus::Queued;
+            state.final_output = None;
+            state.checklist.clear();
+            state.proposed_patches.clear();
+            state.error_kind = None;
+            state.running_since = None;
+            state.submitted_at = None;
+            state.first_token_at = None;
+            state.completed_at = None;
+            state.tool_calls.clear();
+            state.earlier_runs_tokens = state.token_usage();
+            state.tokens_used = 0;
+            state.input_tokens = 0;
+            state.output_tokens = 0;
+            state.partial_output.clear();
+            state.partial_committed_len = 0;
+            state.truncated = false;
+            state.output_truncated = false;
+            state.consumed = false;
+            state.confidence = None;
+            state.retry_after = None;
+            state.last_update = Some(Instant::now());
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                format!(
+                    "retrying ({attempt}/{max_retries}) in {}ms{source}",
+                    backoff.as_millis()
+                ),
+            );
+            backoff
+        };
+        handle.notify.notify_waiters();
+        manager
+            .record_audit(
+                ManagerEventKind::Retried,
+                Some(&handle.id),
+                format!("attempt {attempt}/{max_retries}"),
+            )
+            .await;
+
+        tokio::select! {
+            _ = handle.cancel.cancelled() => {
+                let mut state = handle.state.lock().await;
+                state.status = SubagentStatus::Aborted;
+                push_event(&handle, &mut state, SubagentEventKind::Status, "cancelled".to_string());
+                handle.notify.notify_waiters();
+                return;
+            }
+            _ = tokio::time::sleep(backoff) => {}
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_subagent_one_shot(
+    manager: Arc<SubagentManager>,
+    handle: Arc<SubagentHandle>,
+    req: SubagentSpawnRequest,
+    parent_session: Arc<Session>,
+    parent_turn: Arc<TurnContext>,
+    auth_manager: Arc<AuthManager>,
+    models_manager: Arc<ModelsManager>,
+    skills_manager: Arc<SkillsManager>,
+    parent_config: crate::config::Config,
+) {
+    let timeout_duration = req
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(parent_config.subagents.default_timeout);
+    if let Some(deadline) = req.deadline_unix_ms
+        && time_until_deadline(deadline, Utc::now()).is_none()
+    {
+        abort_past_deadline(&handle).await;
+        return;
+    }
+    match manager.await_dependencies(&handle, &req.depends_on).await {
+        Some(Ok(())) => {}
+        Some(Err(reason)) => {
+            let mut state = handle.state.lock().await;
+            state.status = SubagentStatus::Aborted;
+            state.note_abort_reason(reason.clone());
+            state.last_update = Some(Instant::now());
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                format!("aborted: {reason}"),
+            );
+            handle.notify.notify_waiters();
+            return;
+        }
+        None => {
+            let mut state = handle.state.lock().await;
+            state.status = SubagentStatus::Aborted;
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                "cancelled".to_string(),
+            );
+            handle.notify.notify_waiters();
+            return;
+        }
+    }
+    let cooperative_budget = parent_config.subagents.cooperative_budget;
+    let join_messages = parent_config.subagents.join_messages;
+    let share_approvals = parent_config.subagents.share_approvals;
+    let idle_timeout = parent_config.subagents.idle_timeout;
+    let explore_auto_deny =
+        parent_config.subagents.explore_auto_deny && req.mode == SubagentMode::Explore;
+    let total_event_memory_bytes = parent_config.subagents.total_event_memory_bytes;
+    let overflow = parent_config.subagents.overflow;
+
+    let permit = manager.acquire_permit(&handle, req.priority).await;
+    let Some(permit) = permit else {
+        let mut state = handle.state.lock().await;
+        state.status = SubagentStatus::Aborted;
+        handle.notify.notify_waiters();
+        return;
+    };
+
+    let group_guard = match &req.mutex_group {
+        Some(group) => match manager.acquire_mutex_group(&handle, group).await {
+            Some(guard) => Some(guard),
+            None => {
+                let mut state = handle.state.lock().await;
+                state.status = SubagentStatus::Aborted;
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Status,
+                    "cancelled".to_string(),
+                );
+                handle.notify.notify_waiters();
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // The deadline keeps running while the agent waits for a slot, so the
+    // effective timeout is computed only once it can start.
+    let (timeout_duration, deadline_bound) = match req.deadline_unix_ms {
+        Some(deadline) => match time_until_deadline(deadline, Utc::now()) {
+            Some(left) if left < timeout_duration => (left, true),
+            Some(_) => (timeout_duration, false),
+            None => {
+                abort_past_deadline(&handle).await;
+                return;
+            }
+        },
+        None => (timeout_duration, false),
+    };
+
+    let run_started = Instant::now();
+    {
+        let mut state = handle.state.lock().await;
+        state.status = SubagentStatus::Running;
+        state.running_since = Some(Instant::now());
+        state.summary_pending = true;
+        state.last_update = Some(Instant::now());
+        push_event(
+            &handle,
+            &mut state,
+            SubagentEventKind::Status,
+            "running".to_string(),
+        );
+    }
+    handle.notify.notify_waiters();
+
+    // Catch panics in the driver (including session startup) so they surface
+    // as a diagnosable error instead of a silently dead task.
+    let run = AssertUnwindSafe(timeout(timeout_duration, async {
+        // Prepare per-subagent config.
+        let mut config = parent_config;
+        config.features.disable(Feature::Subagents);
+        config.features.disable(Feature::GhostCommit);
+
+        // Subagents are intentionally lightweight by default.
+        config.project_doc_max_bytes = 0;
+        config.record_rollout = req.record_rollout;
+        let (cwd_override, workspace) = {
+            let state = handle.state.lock().await;
+            (state.cwd.clone(), state.workspace.clone())
+        };
+        if let Some(cwd) = &cwd_override {
+            config.cwd = cwd.clone();
+        }
+        if let Some(trace_id) = &req.trace_id {
+            config
+                .model_provider
+                .http_headers
+                .get_or_insert_with(HashMap::new)
+                .insert(SUBAGENT_TRACE_ID_HEADER.to_string(), trace_id.clone());
+        }
+
+        let mut instructions = subagent_base_instructions(&req.label, req.mode, req.output_format);
+        if req.allow_sibling_abort {
+            instructions.push_str(ABORT_SIBLINGS_INSTRUCTIONS);
+        }
+        // A trailing marker would break a JSON-only answer.
+        if req.report_confidence && req.output_format == SubagentOutputFormat::Text {
+            instructions.push_str(CONFIDENCE_INSTRUCTIONS);
+        }
+        if req.propose_only {
+            instructions.push_str(PROPOSE_ONLY_INSTRUCTIONS);
+        }
+        if let Some(citations) = req.citation_style.instructions() {
+            instructions.push_str(citations);
+        }
+        if let Some(workspace) = &workspace {
+            instructions.push_str(&format!(
+                "- Isolated workspace: your working directory {} is a scratch directory that is deleted when you finish. Create and edit files only there; treat the project at {} as read-only. Put anything the parent needs in your final answer.\n",
+                workspace.display(),
+                config.cwd.display()
+            ));
+        }
+        if let Some(extra) = &req.extra_instructions {
+            instructions.push_str(&extra_instructions_section(extra));
+        }
+        config.developer_instructions = Some(match config.developer_instructions.take() {
+            Some(existing) => format!("{existing}\n\n{instructions}"),
+            None => instructions,
+        });
+
+        apply_mode_profile(&mut config, req.mode);
+        // Filter the registry before spawning so disallowed tools are never
+        // offered and calls to them fail as unsupported.
+        if let Some(allowlist) = req.tools_policy.to_allowlist() {
+            config.tool_name_allowlist = Some(allowlist);
+        }
+        if let Some(denylist) = req.tools_policy.to_denylist() {
+            config.tool_name_denylist = Some(denylist);
+        }
+        if let Some(model) = &req.model {
+            config.model = Some(model.clone());
+        }
+        if let Some(effort) = req.reasoning_effort {
+            config.model_reasoning_effort = Some(effort);
+        }
+        if let Some(workspace) = &workspace {
+            // Writes are confined to the scratch directory (and temp dirs).
+            config.cwd = workspace.clone();
+            config.sandbox_policy = SandboxPolicy::new_workspace_write_policy();
+        }
+        if req.propose_only {
+            // Route every patch through an approval request (which is then
+            // recorded and denied) and keep shell commands from writing.
+            config.sandbox_policy = SandboxPolicy::new_read_only_policy();
+            config.approval_policy = AskForApproval::UnlessTrusted;
+        }
+        {
+            let mut state = handle.state.lock().await;
+            state.sandbox = Some(sandbox_label(&config.sandbox_policy));
+            state.approval = Some(config.approval_policy.to_string());
+            state.reasoning_effort = config.model_reasoning_effort;
+        }
+        // General agents inherit the parent's sandbox; make a writable one
+        // visible to whoever watches this background agent.
+        if req.mode == SubagentMode::General
+            && workspace.is_none()
+            && !req.propose_only
+            && let Some(notice) = inherited_sandbox_notice(&config.sandbox_policy)
+        {
+            let mut state = handle.state.lock().await;
+            push_event(&handle, &mut state, SubagentEventKind::Status, notice);
+        }
+
+        // Seed history if resuming.
+        let initial_history = if let Some(path) = &req.resume_rollout_path {
+            match RolloutRecorder::get_rollout_history(path).await {
+                Ok(history) => Some(history),
+                Err(e) => {
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Error;
+                    state.error_kind = Some(SubagentErrorKind::ResumeHistory);
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Error,
+                        format!("failed to resume subagent history: {e}"),
+                    );
+                    handle.notify.notify_waiters();
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        // Resolve skills (if provided).
+        if !req.skills.is_empty() {
+            config.features.enable(Feature::Skills);
+        }
+
+        let model = models_manager.get_model(&config.model, &config).await;
+        if let Some(history) = &initial_history {
+            let check = check_resume_model(
+                config.subagents.resume_model_policy,
+                recorded_model(history).as_deref(),
+                &model,
+            );
+            let mut state = handle.state.lock().await;
+            match check {
+                Ok(None) => {}
+                Ok(Some(warning)) => {
+                    push_event(&handle, &mut state, SubagentEventKind::Control, warning);
+                }
+                Err(err) => {
+                    state.status = SubagentStatus::Error;
+                    state.error_kind = Some(SubagentErrorKind::ResumeHistory);
+                    push_event(&handle, &mut state, SubagentEventKind::Error, err);
+                    handle.notify.notify_waiters();
+                    return;
+                }
+            }
+        }
+        let model_family = models_manager.construct_model_family(&model, &config).await;
+        handle.state.lock().await.tools = resolved_tool_names(&model_family, &config, None);
+
+        let spawn_retries = config.subagents.spawn_retries;
+        let initial_history = initial_history.unwrap_or(InitialHistory::New);
+        let session_source = SessionSource::SubAgent(SubAgentSource::Other(
+            req.source_category
+                .clone()
+                .unwrap_or_else(|| req.label.clone()),
+        ));
+        let mut spawn_attempt = 0;
+        let CodexSpawnOk { codex, .. } = loop {
+            let err = match Codex::spawn(
+                config.clone(),
+                Arc::clone(&auth_manager),
+                Arc::clone(&models_manager),
+                Arc::clone(&skills_manager),
+                initial_history.clone(),
+                session_source.clone(),
+            )
+            .await
+            {
+                Ok(ok) => break ok,
+                Err(err) => err,
+            };
+            let mut state = handle.state.lock().await;
+            if spawn_attempt >= spawn_retries || !is_transient_spawn_error(&err) {
+                state.status = SubagentStatus::Error;
+                state.error_kind = Some(SubagentErrorKind::Spawn);
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Error,
+                    format!("failed to spawn subagent: {err}"),
+                );
+                handle.notify.notify_waiters();
+                return;
+            }
+            spawn_attempt += 1;
+            let backoff = spawn_retry_backoff(spawn_attempt);
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                format!(
+                    "retrying (attempt {spawn_attempt}) in {}ms: failed to spawn subagent: {err}",
+                    backoff.as_millis()
+                ),
+            );
+            drop(state);
+            handle.notify.notify_waiters();
+            tokio::select! {
+                _ = handle.cancel.cancelled() => {
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Aborted;
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Status,
+                        "cancelled".to_string(),
+                    );
+                    handle.notify.notify_waiters();
+                    return;
+                }
+                _ = tokio::time::sleep(backoff) => {}
+            }
+        };
+
+        // Wait for SessionConfigured so we can capture rollout_path for resume/polling.
+        let codex = Arc::new(codex);
+        if let Ok(Some(path)) = timeout(
+            SESSION_CONFIGURED_TIMEOUT,
+            wait_for_session_configured(&codex),
+        )
+        .await
+            && req.record_rollout
+        {
+            {
+                let mut state = handle.state.lock().await;
+                state.rollout_path = Some(path);
+                state.last_update = Some(Instant::now());
+            }
+            // Record the rollout path right away so a restart mid-run can
+            // still resume this agent.
+            manager.persist_registry(&parent_session).await;
+        }
+        handle.state.lock().await.codex = Some(Arc::clone(&codex));
+        handle.notify.notify_waiters();
+        // The response adds the session's MCP tools to `tools`; a failed submit
+        // leaves the built-ins and surfaces through `next_event`.
+        let _ = codex.submit(Op::ListMcpTools).await;
+
+        let mut inputs: Vec<UserInput> = vec![UserInput::Text {
+            text: req.prompt.clone(),
+        }];
+
+        if !req.skills.is_empty() {
+            let outcome = wait_for_skills(
+                &skills_manager,
+                cwd_override.as_deref().unwrap_or(&parent_turn.cwd),
+                &req.skills,
+                Duration::from_millis(req.await_skills_ms),
+                &handle.cancel,
+            )
+            .await;
+            let resolved = resolve_requested_skills(&req.skills, &outcome.skills);
+            if let Some(entry) = resolved.unmatched.first() {
+                let mut state = handle.state.lock().await;
+                state.status = SubagentStatus::Error;
+                state.error_kind = Some(SubagentErrorKind::SkillResolution);
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Error,
+                    describe_unmatched_skill(entry),
+                );
+                handle.notify.notify_waiters();
+                return;
+            }
+            for skill in resolved.matched {
+                inputs.push(UserInput::Skill {
+                    name: skill.name.clone(),
+                    path: skill.path.clone(),
+                });
+            }
+        }
+
+        let submitted_at = Instant::now();
+        if let Err(e) = codex.submit(Op::UserInput { items: inputs }).await {
+            let mut state = handle.state.lock().await;
+            state.status = SubagentStatus::Error;
+            state.error_kind = Some(SubagentErrorKind::Spawn);
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Error,
+                format!("failed to start subagent: {e}"),
+            );
+            handle.notify.notify_waiters();
+            return;
+        }
+        let queued_messages = {
+            let mut state = handle.state.lock().await;
+            state.submitted_at = Some(submitted_at);
+            std::mem::take(&mut state.queued_messages)
+        };
+        for text in queued_messages {
+            // A failed submit means the session is gone; `next_event` reports it.
+            let _ = codex
+                .submit(Op::UserInput {
+                    items: vec![UserInput::Text { text }],
+                })
+                .await;
+        }
+
+        // Drive until completion or cancellation, forwarding approvals through the parent.
+        let mut siblings_aborted = false;
+        // Agent messages of the current turn, kept only when `join_messages` is set.
+        let mut turn_messages: Vec<String> = Vec::new();
+        loop {
+            let event: Event = tokio::select! {
+                _ = handle.cancel.cancelled() => {
+                    shutdown_subagent(&handle, &codex).await;
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Aborted;
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Status,
+                        "cancelled".to_string(),
+                    );
+                    handle.notify.notify_waiters();
+                    return;
+                }
+                // Recreated on every iteration, so each event restarts the window.
+                _ = async {
+                    match idle_timeout {
+                        Some(idle) => tokio::time::sleep(idle).await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    shutdown_subagent(&handle, &codex).await;
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Error;
+                    state.error_kind = Some(SubagentErrorKind::IdleTimeout);
+                    let idle_ms = idle_timeout.unwrap_or_default().as_millis();
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Error,
+                        format!("no events for {idle_ms}ms; stopped as idle"),
+                    );
+                    handle.notify.notify_waiters();
+                    return;
+                }
+                event = codex.next_event() => match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        record_session_death(&handle, &codex, e).await;
+                        return;
+                    }
+                }
+            };
+
+            if let Some(kind) = tool_call_kind(&event.msg) {
+                let mut state = handle.state.lock().await;
+                *state.tool_calls.entry(kind).or_default() += 1;
+            }
+            match event.msg {
+                EventMsg::SessionConfigured(ev) => {
+                    let mut state = handle.state.lock().await;
+                    if req.record_rollout {
+                        state.rollout_path = Some(ev.rollout_path.clone());
+                    }
+                    state.last_update = Some(Instant::now());
+                    handle.notify.notify_waiters();
+                }
+                EventMsg::McpListToolsResponse(ev) => {
+                    handle.state.lock().await.tools =
+                        resolved_tool_names(&model_family, &config, Some(ev.tools));
+                }
+                EventMsg::ExecApprovalRequest(ev) if explore_auto_deny => {
+                    auto_deny_approval(
+                        &handle,
+                        &codex,
+                        Op::ExecApproval {
+                            id: event.id.clone(),
+                            decision: ReviewDecision::Denied,
+                        },
+                        format!("exec: {}", ev.command.join(" ")),
+                    )
+                    .await;
+                }
+                EventMsg::ExecApprovalRequest(ev) => {
+                    handle_exec_approval_request(
+                        &handle,
+                        &codex,
+                        &parent_session,
+                        &event.id,
+                        ev,
+                        share_approvals,
+                    )
+                    .await;
+                }
+                EventMsg::ApplyPatchApprovalRequest(ev) if req.propose_only => {
+                    record_proposed_patch(&handle, &codex, &event.id, ev).await;
+                }
+                EventMsg::ApplyPatchApprovalRequest(ev) if explore_auto_deny => {
+                    auto_deny_approval(
+                        &handle,
+                        &codex,
+                        Op::PatchApproval {
+                            id: event.id.clone(),
+                            decision: ReviewDecision::Denied,
+                        },
+                        format!("patch ({} file(s))", ev.changes.len()),
+                    )
+                    .await;
+                }
+                EventMsg::ApplyPatchApprovalRequest(ev) => {
+                    handle_patch_approval_request(&handle, &codex, &parent_session, &event.id, ev)
+                        .await;
+                }
+                EventMsg::Error(ev) => {
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Error;
+                    state.final_output = Some(cap_output(&handle, &mut state, ev.message.clone()));
+                    state.last_update = Some(Instant::now());
+                    // Refined to `RateLimited` by `classify_error` when it applies.
+                    state.error_kind = Some(SubagentErrorKind::Model);
+                    let prefix =
+                        classify_error(&mut state, &ev.message, ev.codex_error_info.as_ref())
+                            .unwrap_or_else(|| "error".to_string());
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Error,
+                        format!("{prefix}: {}", ev.message),
+                    );
+                    handle.notify.notify_waiters();
+                }
+                EventMsg::StreamError(ev) => {
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Error;
+                    state.final_output = Some(cap_output(&handle, &mut state, ev.message.clone()));
+                    state.last_update = Some(Instant::now());
+                    // Refined to `RateLimited` by `classify_error` when it applies.
+                    state.error_kind = Some(SubagentErrorKind::Stream);
+                    let prefix =
+                        classify_error(&mut state, &ev.message, ev.codex_error_info.as_ref())
+                            .unwrap_or_else(|| "stream error".to_string());
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Error,
+                        format!("{prefix}: {}", ev.message),
+                    );
+                    handle.notify.notify_waiters();
+                }
+                EventMsg::TaskStarted(_) => {
+                    turn_messages.clear();
+                }
+                EventMsg::AgentMessageDelta(ev) => {
+                    let mut state = handle.state.lock().await;
+                    state.first_token_at.get_or_insert_with(Instant::now);
+                    state.push_partial_delta(&ev.delta, handle.max_output_chars);
+                }
+                EventMsg::AgentMessage(ev) => {
+                    let mut message = ev.message;
+                    let markers = strip_markers(&req, &mut message);
+                    {
+                        let mut state = handle.state.lock().await;
+                        state.first_token_at.get_or_insert_with(Instant::now);
+                        state.commit_partial_message(&message, handle.max_output_chars);
+                    }
+                    if join_messages {
+                        turn_messages.push(message.clone());
+                    }
+                    let abort_reason = markers.abort_siblings.filter(|_| !siblings_aborted);
+                    {
+                        let mut state = handle.state.lock().await;
+                        state.last_update = Some(Instant::now());
+                        if let Some(confidence) = markers.confidence {
+                            state.confidence = Some(confidence);
+                        }
+                        push_event(&handle, &mut state, SubagentEventKind::Message, message);
+                    }
+                    if let Some(reason) = abort_reason {
+                        siblings_aborted = true;
+                        let cancelled = manager.abort_siblings(&handle, &reason).await;
+                        let mut state = handle.state.lock().await;
+                        push_event(
+                            &handle,
+                            &mut state,
+                            SubagentEventKind::Control,
+                            format!("aborted {cancelled} sibling agent(s): {reason}"),
+                        );
+                    }
+                    handle.notify.notify_waiters();
+                }
+                EventMsg::TokenCount(ev) => {
+                    if let Some(info) = ev.info {
+                        // Cumulative for the session, so follow-up turns
+                        // from `subagent_send` are included.
+                        let usage = &info.total_token_usage;
+                        let mut state = handle.state.lock().await;
+                        state.tokens_used = u64::try_from(usage.total_tokens).unwrap_or(0);
+                        state.input_tokens = u64::try_from(usage.input_tokens).unwrap_or(0);
+                        state.output_tokens = u64::try_from(usage.output_tokens).unwrap_or(0);
+                    }
+                    if let Some(budget) = cooperative_budget {
+                        manager.maybe_send_budget_warning(budget).await;
+                    }
+                }
+                EventMsg::TaskComplete(tc) => {
+                    let final_message = if join_messages && !turn_messages.is_empty() {
+                        Some(std::mem::take(&mut turn_messages).join("\n\n"))
+                    } else {
+                        tc.last_agent_message.map(|mut message| {
+                            strip_markers(&req, &mut message);
+                            message
+                        })
+                    };
+                    let mut output = final_message
+                        .clone()
+                        .map(|text| postprocess_output(handle.output_postprocess, text));
+                    // Summarize before taking the state lock; the call can take a while.
+                    let mut summarized_from = None;
+                    if overflow == OutputOverflow::Summarize
+                        && !req.lazy_output
+                        && let Some(text) = output.as_deref()
+                        && text.len() > handle.max_output_chars
+                    {
+                        let len = text.len();
+                        match summarize_overflow(&parent_turn, text, handle.max_output_chars).await
+                        {
+                            Some(summary) => {
+                                summarized_from = Some(len);
+                                output = Some(summary);
+                            }
+                            None => warn!(
+                                "subagent {}: summarizing oversized output failed; truncating",
+                                handle.id
+                            ),
+                        }
+                    }
+                    let mut state = handle.state.lock().await;
+                    state.completed_at = Some(Instant::now());
+                    if !req.lazy_output {
+                        state.checklist = final_message
+                            .as_deref()
+                            .map(parse_checklist)
+                            .unwrap_or_default();
+                    }
+                    if state.status != SubagentStatus::Error {
+                        state.status = SubagentStatus::Complete;
+                        state.final_output = if req.lazy_output {
+                            Some(LAZY_OUTPUT_MARKER.to_string())
+                        } else {
+                            output.map(|text| cap_output(&handle, &mut state, text))
+                        };
+                    } else if state.final_output.is_none() {
+                        state.final_output =
+                            output.map(|text| cap_output(&handle, &mut state, text));
+                    }
+                    if let Some(len) = summarized_from {
+                        push_event(
+                            &handle,
+                            &mut state,
+                            SubagentEventKind::Status,
+                            format!(
+                                "final output was {len} bytes (max_output_chars {}); replaced with a summary",
+                                handle.max_output_chars
+                            ),
+                        );
+                    }
+                    state.last_update = Some(Instant::now());
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Status,
+                        "complete".to_string(),
+                    );
+                    handle.notify.notify_waiters();
+                    drop(state);
+                    shutdown_subagent(&handle, &codex).await;
+                    break;
+                }
+                EventMsg::TurnAborted(_) => {
+                    let mut state = handle.state.lock().await;
+                    state.status = SubagentStatus::Aborted;
+                    state.note_abort_reason("turn aborted");
+                    state.last_update = Some(Instant::now());
+                    push_event(
+                        &handle,
+                        &mut state,
+                        SubagentEventKind::Status,
+                        "aborted".to_string(),
+                    );
+                    handle.notify.notify_waiters();
+                    drop(state);
+                    shutdown_subagent(&handle, &codex).await;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }))
+    .catch_unwind()
+    .await;
+
+    drop(group_guard);
+    drop(permit);
+    let codex = handle.state.lock().await.codex.take();
+
+    match run {
+        Ok(Ok(())) => {}
+        Ok(Err(_elapsed)) if deadline_bound => {
+            handle.cancel.cancel();
+            let mut state = handle.state.lock().await;
+            if state.status == SubagentStatus::Running {
+                state.status = SubagentStatus::Aborted;
+                state.note_abort_reason("deadline reached");
+            }
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Status,
+                "deadline reached".to_string(),
+            );
+        }
+        Ok(Err(_elapsed)) => {
+            handle.cancel.cancel();
+            let mut state = handle.state.lock().await;
+            if state.status == SubagentStatus::Running {
+                state.status = SubagentStatus::Error;
+                state.error_kind = Some(SubagentErrorKind::Timeout);
+            }
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Error,
+                format!("timed out after {}ms", timeout_duration.as_millis()),
+            );
+        }
+        Err(panic) => {
+            if let Some(codex) = codex {
+                shutdown_subagent(&handle, &codex).await;
+            }
+            let message = panic_message(panic.as_ref());
+            let mut state = handle.state.lock().await;
+            state.status = SubagentStatus::Error;
+            state.error_kind = Some(SubagentErrorKind::Panic);
+            state.final_output = Some(cap_output(
+                &handle,
+                &mut state,
+                format!("subagent panicked: {message}"),
+            ));
+            state.last_update = Some(Instant::now());
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Error,
+                format!("panicked: {message}"),
+            );
+        }
+    }
+
+    let run_ms = u64::try_from(run_started.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let mut state = handle.state.lock().await;
+    push_summary_event(&handle, &mut state, run_ms);
+    drop(state);
+    handle.notify.notify_waiters();
+
+    manager.run_maintenance().await;
+    if let Some(budget) = total_event_memory_bytes {
+        manager.trim_event_memory(budget).await;
+    }
+}
+
+/// Asks the parent turn's model to condense an oversized final output to at
+/// most `max_chars` bytes. `None` when the call fails, times out, or returns
+/// no text; the caller then truncates as usual.
+async fn summarize_overflow(turn: &TurnContext, text: &str, max_chars: usize) -> Option<String> {
+    let prompt = Prompt {
+        input: vec![ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: overflow_summary_prompt(text, max_chars),
+            }],
+        }],
+        base_instructions_override: Some(
+            "You condense reports written by other agents. Reply with the condensed report only."
+                .to_string(),
+        ),
+        ..Default::default()
+    };
+    let summarize = async {
+        let mut stream = turn.client.clone().stream(&prompt).await.ok()?;
+        let mut summary = None;
+        while let Some(event) = stream.next().await {
+            match event.ok()? {
+                ResponseEvent::OutputItemDone(item) => {
+                    if let Some(text) = last_assistant_message_from_item(&item) {
+                        summary = Some(text);
+                    }
+                }
+                ResponseEvent::Completed { .. } => return summary,
+                _ => {}
+            }
+        }
+        None
+    };
+    let summary = timeout(OVERFLOW_SUMMARY_TIMEOUT, summarize).await.ok()??;
+    let summary = summary.trim();
+    (!summary.is_empty()).then(|| summary.to_string())
+}
+
+fn overflow_summary_prompt(text: &str, max_chars: usize) -> String {
+    format!(
+        "The report below is {} bytes, over the {max_chars}-byte limit. Rewrite it in at most {max_chars} bytes. Keep the conclusion, findings, file paths, and any requested answer; drop repetition and narration.\n\n<report>\n{text}\n</report>",
+        text.len()
+    )
+}
+
+async fn abort_past_deadline(handle: &SubagentHandle) {
+    let mut state = handle.state.lock().await;
+    state.status = SubagentStatus::Aborted;
+    state.note_abort_reason("deadline passed before the agent started");
+    state.last_update = Some(Instant::now());
+    push_event(
+        handle,
+        &mut state,
+        SubagentEventKind::Status,
+        "aborted: deadline passed before the agent started".to_string(),
+    );
+    handle.notify.notify_waiters();
+}
+
+/// Errors worth another `Codex::spawn` attempt: network trouble, timeouts, and
+/// server-side failures. Auth, quota, and config errors are final.
+pub(super) fn is_transient_spawn_error(err: &CodexErr) -> bool {
+    match err {
+        CodexErr::Stream(..)
+        | CodexErr::Timeout
+        | CodexErr::ConnectionFailed(_)
+        | CodexErr::ResponseStreamFailed(_)
+        | CodexErr::InternalServerError
+        | CodexErr::SessionConfiguredNotFirstEvent => true,
+        CodexErr::UnexpectedStatus(err) => {
+            err.status.is_server_error() || err.status == StatusCode::TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+/// Exponential backoff before spawn retry `attempt` (1-based).
+pub(super) fn spawn_retry_backoff(attempt: u32) -> Duration {
+    SPAWN_RETRY_BASE_BACKOFF.saturating_mul(1 << attempt.saturating_sub(1).min(5))
+}
+
+/// Records `error_kind` (and the retry-after delay) for classified failures.
+/// Returns the event prefix to use instead of the generic one.
+pub(super) fn classify_error(
+    state: &mut SubagentState,
+    message: &str,
+    info: Option<&CodexErrorInfo>,
+) -> Option<String> {
+    if !is_rate_limit_error(message, info) {
+        return None;
+    }
+    state.error_kind = Some(SubagentErrorKind::RateLimited);
+    state.retry_after = parse_retry_after(message);
+    Some(match state.retry_after {
+        Some(delay) => format!("rate limited (retry after {}ms)", delay.as_millis()),
+        None => "rate limited".to_string(),
+    })
+}
+
+fn is_rate_limit_error(message: &str, info: Option<&CodexErrorInfo>) -> bool {
+    let status = match info {
+        Some(
+            CodexErrorInfo::HttpConnectionFailed { http_status_code }
+            | CodexErrorInfo::ResponseStreamConnectionFailed { http_status_code }
+            | CodexErrorInfo::ResponseStreamDisconnected { http_status_code }
+            | CodexErrorInfo::ResponseTooManyFailedAttempts { http_status_code },
+        ) => *http_status_code,
+        _ => None,
+    };
+    if status == Some(429) {
+        return true;
+    }
+    let message = message.to_ascii_lowercase();
+    message.contains("rate limit") || message.contains("429 too many requests")
+}
+
+/// Parses the provider's "try again in 11.054s" / "try again in 500ms" hint.
+pub(super) fn parse_retry_after(message: &str) -> Option<Duration> {
+    static RE: OnceLock<regex_lite::Regex> = OnceLock::new();
+    #[expect(clippy::unwrap_used)]
+    let re = RE.get_or_init(|| {
+        regex_lite::Regex::new(r"(?i)try again in\s*(\d+(?:\.\d+)?)\s*(ms|s|seconds?)\b").unwrap()
+    });
+    let captures = re.captures(message)?;
+    let value: f64 = captures.get(1)?.as_str().parse().ok()?;
+    if captures.get(2)?.as_str().eq_ignore_ascii_case("ms") {
+        Some(Duration::from_secs_f64(value / 1000.0))
+    } else {
+        Some(Duration::from_secs_f64(value))
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+async fn wait_for_session_configured(codex: &Codex) -> Option<PathBuf> {
+    loop {
+        let event = codex.next_event().await.ok()?;
+        // Ignore other startup chatter.
+        if let EventMsg::SessionConfigured(ev) = event.msg {
+            return Some(ev.rollout_path);
+        }
+    }
+}
+
+/// Records that the agent's session stopped sending events. A panic in the
+/// session task becomes a [`SubagentErrorKind::Panic`] error carrying the
+/// panic message; any other end is a stream error.
+pub(super) async fn record_session_death(handle: &SubagentHandle, codex: &Codex, err: CodexErr) {
+    let panic = timeout(SUBAGENT_SHUTDOWN_TIMEOUT, codex.session_panic())
+        .await
+        .ok()
+        .flatten();
+    let mut state = handle.state.lock().await;
+    state.status = SubagentStatus::Error;
+    if let Some(panic) = panic {
+        let message = panic_message(panic.as_ref());
+        state.error_kind = Some(SubagentErrorKind::Panic);
+        state.final_output = Some(cap_output(
+            handle,
+            &mut state,
+            format!("subagent session panicked: {message}"),
+        ));
+        state.last_update = Some(Instant::now());
+        push_event(
+            handle,
+            &mut state,
+            SubagentEventKind::Error,
+            format!("session panicked: {message}"),
+        );
+    } else {
+        state.error_kind = Some(SubagentErrorKind::Stream);
+        push_event(
+            handle,
+            &mut state,
+            SubagentEventKind::Error,
+            format!("subagent died: {err}"),
+        );
+    }
+    handle.notify.notify_waiters();
+}
+
+/// Interrupts and shuts down the agent's session. If the submits do not go
+/// through within [`SUBAGENT_SHUTDOWN_TIMEOUT`] (a wedged session whose
+/// submission queue is full), the session task is aborted and the handle's
+/// reference to the session is dropped, so the run does not keep its
+/// concurrency slot waiting on it. Must be called without holding the state
+/// lock.
+pub(super) async fn shutdown_subagent(handle: &SubagentHandle, codex: &Codex) {
+    let graceful = async {
+        let _ = codex.submit(Op::Interrupt).await;
+        let _ = codex.submit(Op::Shutdown {}).await;
+    };
+    if timeout(SUBAGENT_SHUTDOWN_TIMEOUT, graceful).await.is_ok() {
+        debug!(agent_id = %handle.id, "subagent session shut down");
+        return;
+    }
+    warn!(
+        agent_id = %handle.id,
+        "subagent session did not accept shutdown within {}ms; aborting it",
+        SUBAGENT_SHUTDOWN_TIMEOUT.as_millis()
+    );
+    // A submission loop stuck inside a tool call never sees its channel
+    // close, so stop the task itself.
+    let session_task = codex
+        .session_task
+        .lock()
+        .ok()
+        .and_then(|mut task| task.take());
+    if let Some(task) = session_task {
+        task.abort();
+        warn!(agent_id = %handle.id, "aborted the subagent session task");
+    }
+    let mut state = handle.state.lock().await;
+    state.codex = None;
+    push_event(
+        handle,
+        &mut state,
+        SubagentEventKind::Status,
+        "shutdown timed out; session aborted".to_string(),
+    );
+}
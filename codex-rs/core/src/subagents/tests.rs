@@ -0,0 +1,2568 @@
+use super::approvals::ParentExecApprovalKey;
+use super::approvals::auto_deny_approval;
+use super::approvals::handle_exec_approval_request;
+use super::approvals::parent_approved_for_session;
+use super::approvals::push_approval_event;
+use super::approvals::review_decision_str;
+use super::events::push_summary_event;
+use super::output::parse_checklist;
+use super::output::postprocess_output;
+use super::output::take_abort_siblings_marker;
+use super::output::take_confidence_marker;
+use super::registry::RESTORED_INTERRUPTED_MESSAGE;
+use super::run::classify_error;
+use super::run::is_transient_spawn_error;
+use super::run::parse_retry_after;
+use super::run::record_session_death;
+use super::run::shutdown_subagent;
+use super::run::spawn_retry_backoff;
+use super::*;
+use crate::error::CodexErr;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::models::SandboxPermissions;
+use codex_protocol::protocol::CodexErrorInfo;
+use codex_protocol::protocol::Event;
+use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::ExecApprovalRequestEvent;
+use codex_protocol::protocol::ReviewDecision;
+use futures::FutureExt;
+use pretty_assertions::assert_eq;
+
+fn test_handle(id: &str, state: SubagentState) -> SubagentHandle {
+    SubagentHandle {
+        id: id.to_string(),
+        label: "test".to_string(),
+        mode: SubagentMode::General,
+        cancel: CancellationToken::new(),
+        notify: Notify::new(),
+        state: Mutex::new(state),
+        created_at: Instant::now(),
+        max_events: 8,
+        max_event_chars: 64,
+        max_output_chars: 64,
+        lazy_output: false,
+        join_messages: false,
+        ephemeral: false,
+        output_postprocess: OutputPostprocess::None,
+        request: None,
+    }
+}
+
+#[test]
+fn deadline_leaves_remaining_time_until_it_passes() {
+    let now = DateTime::from_timestamp_millis(10_000).expect("valid timestamp");
+    assert_eq!(
+        time_until_deadline(12_500, now),
+        Some(Duration::from_millis(2_500))
+    );
+    assert_eq!(time_until_deadline(10_000, now), None);
+    assert_eq!(time_until_deadline(1, now), None);
+}
+
+#[test]
+fn spawn_retries_only_transient_errors() {
+    assert!(is_transient_spawn_error(&CodexErr::Timeout));
+    assert!(is_transient_spawn_error(&CodexErr::InternalServerError));
+    assert!(is_transient_spawn_error(&CodexErr::Stream(
+        "connection reset".to_string(),
+        None
+    )));
+    assert!(!is_transient_spawn_error(&CodexErr::QuotaExceeded));
+    assert!(!is_transient_spawn_error(&CodexErr::Fatal(
+        "failed to load execpolicy".to_string()
+    )));
+
+    assert_eq!(
+        (1..=3).map(spawn_retry_backoff).collect::<Vec<_>>(),
+        vec![
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+        ]
+    );
+}
+
+#[test]
+fn partial_output_tracks_streamed_messages() {
+    let mut state = SubagentState::default();
+    state.push_partial_delta("Look", 64);
+    state.push_partial_delta("ing", 64);
+    assert_eq!(state.partial_output(), Some("Looking".to_string()));
+
+    state.commit_partial_message("Looking around.", 64);
+    state.push_partial_delta("Found", 64);
+    assert_eq!(
+        state.partial_output(),
+        Some("Looking around.\n\nFound".to_string())
+    );
+
+    state.commit_partial_message("Found it.", 20);
+    assert_eq!(
+        state.partial_output(),
+        Some("Looking around.\n\nFou".to_string())
+    );
+    state.push_partial_delta("more", 20);
+    assert_eq!(state.partial_output.len(), 20);
+
+    state.final_output = Some("done".to_string());
+    assert_eq!(state.partial_output(), None);
+}
+
+#[tokio::test]
+async fn dependencies_reject_unknown_ids_and_cycles() {
+    let manager = SubagentManager::default();
+    let a = test_handle("a", SubagentState::default());
+    let b = test_handle(
+        "b",
+        SubagentState {
+            depends_on: vec!["a".to_string()],
+            ..Default::default()
+        },
+    );
+    // `a` was re-registered after a prune and now depends on `c`.
+    a.state.lock().await.depends_on = vec!["c".to_string()];
+    {
+        let mut agents = manager.agents.write().await;
+        agents.insert("a".to_string(), Arc::new(a));
+        agents.insert("b".to_string(), Arc::new(b));
+    }
+
+    assert_eq!(
+        manager.check_dependencies("d", &["b".to_string()]).await,
+        Ok(vec!["b".to_string()])
+    );
+    // Raw ids resolve to the stored form, as they do for `subagent_poll`.
+    assert_eq!(
+        manager.check_dependencies("d", &[" B ".to_string()]).await,
+        Ok(vec!["b".to_string()])
+    );
+    assert_eq!(
+        manager.check_dependencies("d", &["x".to_string()]).await,
+        Err("unknown dependency `x`".to_string())
+    );
+    assert_eq!(
+        manager.check_dependencies("d", &["D".to_string()]).await,
+        Err("an agent cannot depend on itself".to_string())
+    );
+    assert_eq!(
+        manager
+            .check_dependencies("c", &["Scan B".to_string()])
+            .await,
+        Err("unknown dependency `Scan B`".to_string())
+    );
+    assert_eq!(
+        manager.check_dependencies("c", &["B".to_string()]).await,
+        Err("depends_on would create a cycle through `c`".to_string())
+    );
+}
+
+#[tokio::test]
+async fn dependent_waits_and_aborts_when_dependency_fails() {
+    let manager = Arc::new(SubagentManager::default());
+    let dep = Arc::new(test_handle("dep", SubagentState::default()));
+    manager
+        .agents
+        .write()
+        .await
+        .insert("dep".to_string(), Arc::clone(&dep));
+    let waiter = Arc::new(test_handle("waiter", SubagentState::default()));
+
+    let task = tokio::spawn({
+        let manager = Arc::clone(&manager);
+        let waiter = Arc::clone(&waiter);
+        async move {
+            manager
+                .await_dependencies(&waiter, &["dep".to_string()])
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!task.is_finished());
+
+    dep.state.lock().await.status = SubagentStatus::Error;
+    dep.notify.notify_waiters();
+    assert_eq!(
+        task.await.expect("join"),
+        Some(Err("dependency `dep` ended with status error".to_string()))
+    );
+    let events: Vec<String> = waiter
+        .state
+        .lock()
+        .await
+        .recent_events
+        .iter()
+        .map(|event| event.message.clone())
+        .collect();
+    assert_eq!(events, vec!["waiting for dependencies: dep".to_string()]);
+}
+
+#[tokio::test]
+async fn delegate_progress_is_kept_per_call() {
+    let manager = SubagentManager::default();
+    manager
+        .record_delegate_progress("call-1", "scan", "first".to_string())
+        .await;
+    manager
+        .record_delegate_progress("call-2", "scan", "second".to_string())
+        .await;
+
+    assert_eq!(
+        manager.delegate_progress("scan", None).await,
+        Err(
+            "2 delegate calls labelled `scan` are running (call-1, call-2); pass call_id to pick one"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        manager.delegate_progress("scan", Some("call-2")).await,
+        Ok(Some(("call-2".to_string(), "second".to_string())))
+    );
+
+    // One call finishing leaves the other's progress in place.
+    manager.clear_delegate_progress("call-1").await;
+    assert_eq!(
+        manager.delegate_progress("scan", None).await,
+        Ok(Some(("call-2".to_string(), "second".to_string())))
+    );
+    manager.clear_delegate_progress("call-2").await;
+    assert_eq!(manager.delegate_progress("scan", None).await, Ok(None));
+}
+
+#[tokio::test]
+async fn session_limiter_caps_agents_per_manager() {
+    let manager = SubagentManager::new(Some(1));
+    let other_session = SubagentManager::new(Some(1));
+
+    let permits = manager.acquire_slots().await.expect("first slot");
+    let concurrency = manager.concurrency();
+    assert_eq!(
+        (concurrency.session_free, concurrency.session_limit),
+        (0, 1)
+    );
+    assert!(
+        tokio::time::timeout(Duration::from_millis(20), manager.acquire_slots())
+            .await
+            .is_err()
+    );
+    assert_eq!(manager.concurrency().session_free, 0);
+
+    // The other session's own slot is still free; only the process-wide
+    // pool (sized by the host's cores) is shared.
+    let other = other_session.session_limiter().semaphore.try_acquire();
+    assert!(other.is_ok());
+    drop(other);
+
+    drop(permits);
+    assert_eq!(manager.concurrency().session_free, 1);
+}
+
+#[test]
+fn delegate_limiter_is_separate_from_the_subagent_pool() {
+    let subagents = global_subagent_limiter();
+    let delegates = global_delegate_limiter();
+    assert!(!Arc::ptr_eq(&subagents, &delegates));
+
+    // A delegate holding every delegate slot leaves the background pool
+    // untouched, so `delegate` calls never queue behind subagents.
+    let subagents_free = subagents.available_permits();
+    let held = delegates
+        .try_acquire_many(u32::try_from(delegates.available_permits()).expect("permits"))
+        .expect("delegate slots");
+    assert_eq!(delegates.available_permits(), 0);
+    assert_eq!(subagents.available_permits(), subagents_free);
+    drop(held);
+}
+
+#[tokio::test]
+async fn shutdown_aborts_a_wedged_session() {
+    // A full submission queue that nobody drains, as in a stuck session.
+    let (tx_sub, _rx_sub) = async_channel::bounded(1);
+    let (_tx_event, rx_event) = async_channel::unbounded();
+    tx_sub
+        .try_send(crate::protocol::Submission {
+            id: "0".to_string(),
+            op: Op::Interrupt,
+        })
+        .expect("fill queue");
+    // Stands in for a submission loop stuck inside a tool call; `alive` is
+    // dropped only when the task is aborted.
+    let (alive, stopped) = tokio::sync::oneshot::channel::<()>();
+    let session_task = tokio::spawn(async move {
+        let _alive = alive;
+        std::future::pending::<()>().await;
+    });
+    let codex = Arc::new(Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(Some(session_task)),
+    });
+    let handle = test_handle(
+        "a",
+        SubagentState {
+            codex: Some(Arc::clone(&codex)),
+            ..Default::default()
+        },
+    );
+
+    shutdown_subagent(&handle, &codex).await;
+
+    assert!(
+        tokio::time::timeout(Duration::from_secs(1), stopped)
+            .await
+            .expect("session task aborted")
+            .is_err()
+    );
+    assert!(codex.session_task.lock().expect("session task").is_none());
+    let state = handle.state.lock().await;
+    assert!(state.codex.is_none());
+    assert_eq!(
+        state
+            .recent_events
+            .back()
+            .map(|event| event.message.as_str()),
+        Some("shutdown timed out; session aborted")
+    );
+}
+
+#[tokio::test]
+async fn session_task_panic_is_reported_as_panic() {
+    let (tx_sub, _rx_sub) = async_channel::unbounded();
+    let (tx_event, rx_event) = async_channel::unbounded::<Event>();
+    // Stands in for a submission loop that panics while holding the
+    // session's event sender.
+    let session_task = tokio::spawn(async move {
+        let _tx_event = tx_event;
+        panic!("session exploded");
+    });
+    let codex = Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(Some(session_task)),
+    };
+    let handle = test_handle(
+        "a",
+        SubagentState {
+            status: SubagentStatus::Running,
+            ..Default::default()
+        },
+    );
+
+    let err = codex.next_event().await.expect_err("event channel closed");
+    record_session_death(&handle, &codex, err).await;
+
+    let state = handle.state.lock().await;
+    assert_eq!(state.status, SubagentStatus::Error);
+    assert_eq!(state.error_kind, Some(SubagentErrorKind::Panic));
+    assert_eq!(
+        state.final_output.as_deref(),
+        Some("subagent session panicked: session exploded")
+    );
+    drop(state);
+
+    // A session that simply went away stays a stream error.
+    let (tx_sub, _rx_sub) = async_channel::unbounded();
+    let (_, rx_event) = async_channel::unbounded::<Event>();
+    let codex = Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(Some(tokio::spawn(async {}))),
+    };
+    let handle = test_handle("b", SubagentState::default());
+    let err = codex.next_event().await.expect_err("event channel closed");
+    record_session_death(&handle, &codex, err).await;
+    let state = handle.state.lock().await;
+    assert_eq!(state.error_kind, Some(SubagentErrorKind::Stream));
+    assert_eq!(state.final_output, None);
+}
+
+#[tokio::test]
+async fn raw_agent_ids_resolve_to_their_sanitized_form() {
+    let manager = SubagentManager::default();
+    let agent_id = sanitize_agent_id("My Agent").expect("valid id");
+    assert_eq!(agent_id, "myagent");
+    manager.agents.write().await.insert(
+        agent_id.clone(),
+        Arc::new(test_handle(
+            &agent_id,
+            SubagentState {
+                status: SubagentStatus::Running,
+                ..Default::default()
+            },
+        )),
+    );
+
+    let poll = manager
+        .poll("My Agent", None)
+        .await
+        .expect("raw id resolves");
+    assert_eq!(poll.agent_id, "myagent");
+    assert!(manager.poll("myagent", None).await.is_some());
+    assert!(manager.poll("Other Agent", None).await.is_none());
+    assert_eq!(manager.cancel("My Agent", None).await, Some(()));
+    let audit = manager.audit_log(None).await;
+    assert_eq!(
+        audit.last().and_then(|event| event.agent_id.as_deref()),
+        Some("myagent")
+    );
+}
+
+#[tokio::test]
+async fn poll_reports_why_an_agent_was_aborted() {
+    let manager = SubagentManager::default();
+    for id in ["a", "b"] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(test_handle(
+                id,
+                SubagentState {
+                    status: SubagentStatus::Running,
+                    ..Default::default()
+                },
+            )),
+        );
+    }
+
+    manager.cancel("a", Some("  superseded by b  ")).await;
+    manager.cancel("b", Some(" ")).await;
+    // A later cancel does not overwrite the first reason.
+    manager.cancel("a", Some("again")).await;
+    // Still running until the driver observes the cancellation.
+    let poll = manager.poll("a", None).await.expect("poll");
+    assert_eq!(poll.abort_reason, None);
+
+    for id in ["a", "b"] {
+        let handle = manager.get_handle(id).await.expect("agent");
+        handle.state.lock().await.status = SubagentStatus::Aborted;
+    }
+    let poll = manager.poll("a", None).await.expect("poll");
+    assert_eq!(poll.abort_reason.as_deref(), Some("superseded by b"));
+    let poll = manager.poll("b", None).await.expect("poll");
+    assert_eq!(poll.abort_reason.as_deref(), Some("cancelled"));
+}
+
+#[tokio::test]
+async fn status_counts_cover_every_agent() {
+    let manager = SubagentManager::default();
+    for (id, status) in [
+        ("a", SubagentStatus::Queued),
+        ("b", SubagentStatus::Running),
+        ("c", SubagentStatus::Running),
+        ("d", SubagentStatus::Complete),
+        ("e", SubagentStatus::Error),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(test_handle(
+                id,
+                SubagentState {
+                    status,
+                    ..Default::default()
+                },
+            )),
+        );
+    }
+
+    let counts = manager.status_counts().await;
+    assert_eq!(
+        counts,
+        SubagentStatusCounts {
+            queued: 1,
+            running: 2,
+            complete: 1,
+            aborted: 0,
+            error: 1,
+        }
+    );
+    assert_eq!(counts.total(), 5);
+}
+
+#[tokio::test]
+async fn poll_until_status_returns_once_the_agent_starts() {
+    let manager = SubagentManager::default();
+    for (id, status) in [
+        ("queued", SubagentStatus::Queued),
+        ("done", SubagentStatus::Complete),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(test_handle(
+                id,
+                SubagentState {
+                    status,
+                    ..Default::default()
+                },
+            )),
+        );
+    }
+
+    // A terminal agent ends the wait right away.
+    let poll = manager
+        .poll_until("done", Some(60_000), Some(SubagentStatus::Running))
+        .await
+        .expect("poll");
+    assert_eq!(poll.status, SubagentStatus::Complete);
+
+    let handle = manager.get_handle("queued").await.expect("agent");
+    let poll = manager.poll_until("queued", Some(60_000), Some(SubagentStatus::Running));
+    tokio::pin!(poll);
+    assert!(poll.as_mut().now_or_never().is_none());
+    handle.state.lock().await.status = SubagentStatus::Running;
+    handle.notify.notify_waiters();
+    let poll = poll.await.expect("poll");
+    assert_eq!(poll.status, SubagentStatus::Running);
+}
+
+#[tokio::test]
+async fn poll_reports_elapsed_and_running_time() {
+    let manager = SubagentManager::default();
+    let now = Instant::now();
+    for (id, status, running_since) in [
+        ("queued", SubagentStatus::Queued, None),
+        (
+            "running",
+            SubagentStatus::Running,
+            Some(now - Duration::from_secs(2)),
+        ),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(SubagentHandle {
+                created_at: now - Duration::from_secs(5),
+                ..test_handle(
+                    id,
+                    SubagentState {
+                        status,
+                        running_since,
+                        ..Default::default()
+                    },
+                )
+            }),
+        );
+    }
+
+    // A queued agent has aged but not started running.
+    let queued = manager.poll("queued", None).await.expect("poll");
+    assert!(queued.elapsed_ms >= 5_000);
+    assert_eq!(queued.running_ms, None);
+
+    // Running time excludes the queue wait before the run started.
+    let running = manager.poll("running", None).await.expect("poll");
+    let running_ms = running.running_ms.expect("running_ms");
+    assert!(running_ms >= 2_000);
+    assert!(running.elapsed_ms >= running_ms + 3_000);
+}
+
+#[tokio::test]
+async fn awaited_poll_waits_for_the_run_summary() {
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "done".to_string(),
+        Arc::new(test_handle(
+            "done",
+            SubagentState {
+                status: SubagentStatus::Complete,
+                summary_pending: true,
+                ..Default::default()
+            },
+        )),
+    );
+    let handle = manager.get_handle("done").await.expect("agent");
+
+    // The driver is still shutting the session down.
+    let poll = manager.poll("done", Some(60_000));
+    tokio::pin!(poll);
+    assert!(poll.as_mut().now_or_never().is_none());
+    let ids = ["done".to_string()];
+    let waiting = manager.wait_any(&ids, Some(60_000));
+    tokio::pin!(waiting);
+    assert!(waiting.as_mut().now_or_never().is_none());
+
+    push_summary_event(&handle, &mut *handle.state.lock().await, 10);
+    handle.notify.notify_waiters();
+    for poll in [poll.await, waiting.await.expect("wait")] {
+        let poll = poll.expect("poll");
+        assert_eq!(
+            poll.recent_events.last().map(|event| event.kind),
+            Some(SubagentEventKind::Summary)
+        );
+    }
+}
+
+#[test]
+fn inherited_sandbox_notice_names_writable_policies() {
+    assert_eq!(
+        inherited_sandbox_notice(&SandboxPolicy::new_read_only_policy()),
+        None
+    );
+    assert_eq!(
+        inherited_sandbox_notice(&SandboxPolicy::new_workspace_write_policy()).as_deref(),
+        Some("sandbox: workspace-write (inherited)")
+    );
+    assert_eq!(
+        inherited_sandbox_notice(&SandboxPolicy::WorkspaceWrite {
+            writable_roots: Vec::new(),
+            network_access: true,
+            exclude_tmpdir_env_var: false,
+            exclude_slash_tmp: false,
+        })
+        .as_deref(),
+        Some("sandbox: workspace-write (inherited, network access)")
+    );
+    assert_eq!(
+        inherited_sandbox_notice(&SandboxPolicy::DangerFullAccess).as_deref(),
+        Some("sandbox: danger-full-access (inherited, no restrictions)")
+    );
+}
+
+#[tokio::test]
+async fn duplicate_spawns_match_active_identical_agents() {
+    let req = SubagentSpawnRequest {
+        label: "scan".to_string(),
+        prompt: "find the bug".to_string(),
+        skills: vec!["db/migrate".to_string()],
+        ..Default::default()
+    };
+    let manager = SubagentManager::default();
+    for (id, status) in [
+        ("done", SubagentStatus::Complete),
+        ("live", SubagentStatus::Running),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(SubagentHandle {
+                request: Some(req.clone()),
+                ..test_handle(
+                    id,
+                    SubagentState {
+                        status,
+                        ..Default::default()
+                    },
+                )
+            }),
+        );
+    }
+
+    let manager_ref = &manager;
+    let found = move |req: SubagentSpawnRequest, agent_id: Option<&'static str>| async move {
+        manager_ref
+            .find_duplicate_spawn(&req, agent_id)
+            .await
+            .map(|handle| handle.id.clone())
+    };
+    assert_eq!(found(req.clone(), None).await, Some("live".to_string()));
+    assert_eq!(found(req.clone(), Some("done")).await, None);
+    assert_eq!(
+        found(
+            SubagentSpawnRequest {
+                prompt: "find another bug".to_string(),
+                ..req.clone()
+            },
+            None
+        )
+        .await,
+        None
+    );
+    assert_eq!(
+        found(
+            SubagentSpawnRequest {
+                mode: SubagentMode::Explore,
+                ..req
+            },
+            None
+        )
+        .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn poll_reports_approval_counts() {
+    let approvals = SubagentApprovalCounts {
+        exec: 3,
+        patch: 1,
+        aborted: 1,
+    };
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "a".to_string(),
+        Arc::new(test_handle(
+            "a",
+            SubagentState {
+                approvals,
+                ..Default::default()
+            },
+        )),
+    );
+
+    let poll = manager.poll("a", None).await.expect("poll");
+    assert_eq!(poll.approvals, approvals);
+}
+
+#[tokio::test]
+async fn poll_reports_the_spawning_call() {
+    let spawned_by = SubagentSpawnedBy {
+        turn_id: "turn-1".to_string(),
+        call_id: "call-7".to_string(),
+    };
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "a".to_string(),
+        Arc::new(SubagentHandle {
+            request: Some(SubagentSpawnRequest {
+                spawned_by: Some(spawned_by.clone()),
+                ..Default::default()
+            }),
+            ..test_handle("a", SubagentState::default())
+        }),
+    );
+    manager.agents.write().await.insert(
+        "b".to_string(),
+        Arc::new(test_handle("b", SubagentState::default())),
+    );
+
+    assert_eq!(
+        manager.poll("a", None).await.expect("poll").spawned_by,
+        Some(spawned_by)
+    );
+    assert_eq!(
+        manager.poll("b", None).await.expect("poll").spawned_by,
+        None
+    );
+}
+
+#[tokio::test]
+async fn higher_priority_agents_take_the_next_free_slot() {
+    let manager = Arc::new(SubagentManager::new(Some(1)));
+    let held = manager.acquire_slots().await.expect("first slot");
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    for (id, priority) in [("low", 0), ("high", 5)] {
+        let task_manager = Arc::clone(&manager);
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let handle = test_handle(id, SubagentState::default());
+            let permit = task_manager.acquire_permit(&handle, priority).await;
+            let _ = tx.send((id, permit.is_some()));
+        });
+        while !manager
+            .permit_queue
+            .lock()
+            .await
+            .iter()
+            .any(|entry| entry.agent_id == id)
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    drop(held);
+    assert_eq!(rx.recv().await, Some(("high", true)));
+    assert_eq!(rx.recv().await, Some(("low", true)));
+}
+
+#[tokio::test]
+async fn queue_head_is_woken_only_when_it_changes() {
+    let manager = Arc::new(SubagentManager::new(Some(1)));
+    let held = manager.acquire_slots().await.expect("first slot");
+
+    let enqueue = |id: &'static str, priority: i32| {
+        let task_manager = Arc::clone(&manager);
+        tokio::spawn(async move {
+            let handle = test_handle(id, SubagentState::default());
+            task_manager
+                .acquire_permit(&handle, priority)
+                .await
+                .is_some()
+        })
+    };
+    let queued = |id: &'static str| {
+        let manager = Arc::clone(&manager);
+        async move {
+            while !manager
+                .permit_queue
+                .lock()
+                .await
+                .iter()
+                .any(|entry| entry.agent_id == id)
+            {
+                tokio::task::yield_now().await;
+            }
+        }
+    };
+
+    let head = enqueue("head", 5);
+    queued("head").await;
+
+    // A lower-priority newcomer leaves the head waiting on the semaphore.
+    let changed = manager.permit_queue_changed.notified();
+    tokio::pin!(changed);
+    changed.as_mut().enable();
+    let low = enqueue("low", 0);
+    queued("low").await;
+    assert!(changed.as_mut().now_or_never().is_none());
+
+    // One that outranks the head takes its place and wakes it.
+    let changed = manager.permit_queue_changed.notified();
+    tokio::pin!(changed);
+    changed.as_mut().enable();
+    let top = enqueue("top", 9);
+    queued("top").await;
+    assert!(changed.as_mut().now_or_never().is_some());
+
+    drop(held);
+    assert!(top.await.expect("top task"));
+    assert!(head.await.expect("head task"));
+    assert!(low.await.expect("low task"));
+}
+
+#[tokio::test]
+async fn mutex_group_admits_one_agent_at_a_time() {
+    let manager = SubagentManager::default();
+    let first = test_handle("first", SubagentState::default());
+    let second = test_handle("second", SubagentState::default());
+
+    let guard = manager
+        .acquire_mutex_group(&first, "build")
+        .await
+        .expect("first agent gets the group");
+    let other_group = manager.acquire_mutex_group(&second, "docs").await;
+    assert!(other_group.is_some());
+    drop(other_group);
+
+    second.cancel.cancel();
+    assert!(
+        manager
+            .acquire_mutex_group(&second, "build")
+            .await
+            .is_none()
+    );
+    let events: Vec<String> = second
+        .state
+        .lock()
+        .await
+        .recent_events
+        .iter()
+        .map(|event| event.message.clone())
+        .collect();
+    assert_eq!(events, vec!["waiting for mutex group build".to_string()]);
+
+    drop(guard);
+    let third = test_handle("third", SubagentState::default());
+    assert!(manager.acquire_mutex_group(&third, "build").await.is_some());
+}
+
+#[tokio::test]
+async fn poll_reports_next_event_index_past_evicted_events() {
+    let manager = SubagentManager::default();
+    let handle = test_handle("a", SubagentState::default());
+    {
+        let mut state = handle.state.lock().await;
+        for i in 0..10 {
+            push_event(
+                &handle,
+                &mut state,
+                SubagentEventKind::Message,
+                format!("m{i}"),
+            );
+        }
+    }
+    manager
+        .agents
+        .write()
+        .await
+        .insert("a".to_string(), Arc::new(handle));
+
+    let poll = manager.poll("a", None).await.expect("poll");
+    assert_eq!(poll.next_event_index, 10);
+    let seqs: Vec<u64> = poll.recent_events.iter().map(|event| event.seq).collect();
+    assert_eq!(seqs, (2..10).collect::<Vec<u64>>());
+}
+
+#[tokio::test]
+async fn wait_any_returns_first_agent_to_finish() {
+    let manager = Arc::new(SubagentManager::default());
+    for id in ["slow", "fast"] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(test_handle(id, SubagentState::default())),
+        );
+    }
+    let ids = vec!["slow".to_string(), "fast".to_string()];
+
+    assert_eq!(
+        manager
+            .wait_any(&ids, Some(10))
+            .await
+            .map(|poll| poll.is_none()),
+        Ok(true)
+    );
+    assert_eq!(
+        manager
+            .wait_any(&["missing".to_string()], None)
+            .await
+            .map(|poll| poll.is_none()),
+        Err("unknown agent_id `missing`".to_string())
+    );
+
+    let task = tokio::spawn({
+        let manager = Arc::clone(&manager);
+        let ids = ids.clone();
+        async move { manager.wait_any(&ids, Some(5_000)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let fast = manager
+        .agents
+        .read()
+        .await
+        .get("fast")
+        .cloned()
+        .expect("fast");
+    fast.state.lock().await.status = SubagentStatus::Complete;
+    fast.notify.notify_waiters();
+
+    let poll = task
+        .await
+        .expect("join")
+        .expect("known agents")
+        .expect("an agent finished");
+    assert_eq!(
+        (poll.agent_id.as_str(), poll.status),
+        ("fast", SubagentStatus::Complete)
+    );
+}
+
+#[tokio::test]
+async fn restart_replaces_finished_agents_only() {
+    let manager = SubagentManager::default();
+    let request = SubagentSpawnRequest {
+        prompt: "count files".to_string(),
+        depends_on: vec!["setup".to_string()],
+        ..Default::default()
+    };
+    for (id, status, request) in [
+        ("running", SubagentStatus::Running, Some(request.clone())),
+        ("restored", SubagentStatus::Error, None),
+        ("failed", SubagentStatus::Error, Some(request)),
+    ] {
+        let handle = SubagentHandle {
+            request,
+            ..test_handle(
+                id,
+                SubagentState {
+                    status,
+                    tokens_used: 40,
+                    ..Default::default()
+                },
+            )
+        };
+        manager
+            .agents
+            .write()
+            .await
+            .insert(id.to_string(), Arc::new(handle));
+    }
+
+    let err = manager
+        .replace_for_restart("running")
+        .await
+        .err()
+        .expect("running agent");
+    assert!(err.contains("is still running"), "{err}");
+    let err = manager
+        .replace_for_restart("restored")
+        .await
+        .err()
+        .expect("restored agent");
+    assert!(err.contains("cannot be restarted"), "{err}");
+
+    let (handle, req) = manager
+        .replace_for_restart("failed")
+        .await
+        .expect("restart failed agent");
+    assert_eq!(req.prompt, "count files");
+    assert!(req.depends_on.is_empty());
+    let current = manager.agents.read().await.get("failed").cloned();
+    assert!(current.is_some_and(|current| Arc::ptr_eq(&current, &handle)));
+    let state = handle.state.lock().await;
+    assert_eq!(state.status, SubagentStatus::Queued);
+    assert_eq!(state.token_usage().total, 40);
+}
+
+#[tokio::test]
+async fn prune_terminated_keeps_newest_finished_agents() {
+    let manager = SubagentManager::default();
+    let now = Instant::now();
+    for (id, status, age_secs) in [
+        ("running", SubagentStatus::Running, 500),
+        ("old", SubagentStatus::Error, 300),
+        ("older", SubagentStatus::Complete, 400),
+        ("recent", SubagentStatus::Complete, 10),
+    ] {
+        let handle = test_handle(
+            id,
+            SubagentState {
+                status,
+                last_update: now.checked_sub(Duration::from_secs(age_secs)),
+                ..Default::default()
+            },
+        );
+        manager
+            .agents
+            .write()
+            .await
+            .insert(id.to_string(), Arc::new(handle));
+    }
+
+    assert!(manager.prune_terminated(None, None).await.is_empty());
+    assert_eq!(
+        manager.prune_terminated(Some(2), None).await,
+        vec!["older".to_string()]
+    );
+    assert_eq!(
+        manager
+            .prune_terminated(None, Some(Duration::from_secs(60)))
+            .await,
+        vec!["old".to_string()]
+    );
+    let mut remaining: Vec<String> = manager.agents.read().await.keys().cloned().collect();
+    remaining.sort();
+    assert_eq!(remaining, vec!["recent".to_string(), "running".to_string()]);
+}
+
+#[tokio::test]
+async fn maintenance_forgets_consumed_ephemeral_agents() {
+    let manager = SubagentManager::default();
+    for (id, ephemeral) in [("ephemeral", true), ("kept", false)] {
+        let handle = SubagentHandle {
+            ephemeral,
+            ..test_handle(
+                id,
+                SubagentState {
+                    status: SubagentStatus::Complete,
+                    final_output: Some("done".to_string()),
+                    ..Default::default()
+                },
+            )
+        };
+        manager
+            .agents
+            .write()
+            .await
+            .insert(id.to_string(), Arc::new(handle));
+    }
+
+    // Nothing has been consumed yet.
+    manager.run_maintenance().await;
+    assert_eq!(manager.agents.read().await.len(), 2);
+
+    for id in ["ephemeral", "kept"] {
+        manager.poll(id, None).await.expect("poll");
+    }
+    manager.run_maintenance().await;
+
+    let remaining: Vec<String> = manager.agents.read().await.keys().cloned().collect();
+    assert_eq!(remaining, vec!["kept".to_string()]);
+    let audit = manager.audit_log(None).await;
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].kind, ManagerEventKind::Forgotten);
+}
+
+#[tokio::test]
+async fn audit_log_is_bounded_and_limited() {
+    let manager = SubagentManager::default();
+    for i in 0..MAX_AUDIT_EVENTS + 3 {
+        manager
+            .record_audit(
+                ManagerEventKind::Spawned,
+                Some(&format!("a{i}")),
+                String::new(),
+            )
+            .await;
+    }
+    let all = manager.audit_log(None).await;
+    assert_eq!(all.len(), MAX_AUDIT_EVENTS);
+    assert_eq!(all[0].agent_id.as_deref(), Some("a3"));
+
+    let recent: Vec<Option<String>> = manager
+        .audit_log(Some(2))
+        .await
+        .into_iter()
+        .map(|event| event.agent_id)
+        .collect();
+    let last = MAX_AUDIT_EVENTS + 2;
+    assert_eq!(
+        recent,
+        vec![Some(format!("a{}", last - 1)), Some(format!("a{last}"))]
+    );
+}
+
+#[tokio::test]
+async fn trim_event_memory_trims_oldest_finished_agents_first() {
+    let manager = SubagentManager::default();
+    let now = Instant::now();
+    for (id, status, age_secs) in [
+        ("old", SubagentStatus::Complete, 20),
+        ("newer", SubagentStatus::Complete, 10),
+        ("running", SubagentStatus::Running, 30),
+    ] {
+        let handle = Arc::new(test_handle(
+            id,
+            SubagentState {
+                status,
+                last_update: now.checked_sub(Duration::from_secs(age_secs)),
+                ..Default::default()
+            },
+        ));
+        {
+            let mut state = handle.state.lock().await;
+            for _ in 0..3 {
+                push_event(
+                    &handle,
+                    &mut state,
+                    SubagentEventKind::Message,
+                    "0123456789".to_string(),
+                );
+            }
+        }
+        manager.agents.write().await.insert(id.to_string(), handle);
+    }
+
+    // 90 bytes retained; trimming "old" down to its last event frees 20.
+    manager.trim_event_memory(70).await;
+
+    let mut remaining = Vec::new();
+    for id in ["old", "newer", "running"] {
+        let handle = manager.agents.read().await.get(id).cloned().expect("agent");
+        remaining.push(handle.state.lock().await.recent_events.len());
+    }
+    assert_eq!(remaining, vec![1, 3, 3]);
+
+    let audit = manager.audit_log(None).await;
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].kind, ManagerEventKind::EventsTrimmed);
+    assert_eq!(audit[0].agent_id.as_deref(), Some("old"));
+}
+
+#[test]
+fn token_usage_adds_earlier_runs() {
+    let state = SubagentState {
+        tokens_used: 150,
+        input_tokens: 100,
+        output_tokens: 50,
+        earlier_runs_tokens: SubagentTokenUsage {
+            input: 10,
+            output: 5,
+            total: 15,
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        state.token_usage(),
+        SubagentTokenUsage {
+            input: 110,
+            output: 55,
+            total: 165,
+        }
+    );
+}
+
+#[test]
+fn resolved_tool_names_follow_mode_lists_and_mcp_tools() {
+    let mcp_tools = || {
+        ["mcp__docs__search", "mcp__docs__delete"]
+            .into_iter()
+            .map(|name| {
+                let tool = McpTool {
+                    annotations: None,
+                    description: None,
+                    input_schema: mcp_types::ToolInputSchema {
+                        properties: None,
+                        required: None,
+                        r#type: "object".to_string(),
+                    },
+                    name: name.to_string(),
+                    output_schema: None,
+                    title: None,
+                };
+                (name.to_string(), tool)
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    let general = crate::config::test_config();
+    let model_family =
+        crate::openai_models::models_manager::ModelsManager::construct_model_family_offline(
+            "gpt-5-codex",
+            &general,
+        );
+    let mut explore = general.clone();
+    apply_mode_profile(&mut explore, SubagentMode::Explore);
+
+    let built_ins = resolved_tool_names(&model_family, &general, None);
+    assert!(built_ins.contains(&"view_image".to_string()));
+    assert!(!built_ins.contains(&"mcp__docs__search".to_string()));
+    let explore_tools = resolved_tool_names(&model_family, &explore, Some(mcp_tools()));
+    assert!(!explore_tools.contains(&"view_image".to_string()));
+    assert!(explore_tools.contains(&"mcp__docs__search".to_string()));
+    assert!(explore_tools.contains(&"mcp__docs__delete".to_string()));
+
+    explore.tool_name_allowlist = Some(vec!["mcp__docs__*".to_string(), "update_plan".to_string()]);
+    explore.tool_name_denylist = Some(vec!["mcp__docs__delete".to_string()]);
+    assert_eq!(
+        resolved_tool_names(&model_family, &explore, Some(mcp_tools())),
+        vec!["mcp__docs__search".to_string(), "update_plan".to_string()]
+    );
+}
+
+#[test]
+fn summary_event_records_run_outcome() {
+    let handle = SubagentHandle {
+        max_event_chars: 4,
+        ..test_handle("a1", SubagentState::default())
+    };
+    let mut state = SubagentState {
+        status: SubagentStatus::Complete,
+        tokens_used: 1200,
+        ..Default::default()
+    };
+    *state.tool_calls.entry("exec").or_default() += 2;
+    push_event(
+        &handle,
+        &mut state,
+        SubagentEventKind::Message,
+        "longer than four".to_string(),
+    );
+
+    push_summary_event(&handle, &mut state, 1500);
+
+    let event = state.recent_events.back().expect("summary event");
+    assert_eq!(event.kind, SubagentEventKind::Summary);
+    assert_eq!(
+        event.message,
+        "summary: complete in 1500ms, 1200 tokens, 2 tool call(s), truncated"
+    );
+    assert_eq!(
+        event.summary,
+        Some(SubagentRunSummary {
+            status: SubagentStatus::Complete,
+            error_kind: None,
+            run_ms: 1500,
+            tokens: 1200,
+            tool_calls: BTreeMap::from([("exec", 2)]),
+            truncated: true,
+        })
+    );
+}
+
+#[test]
+fn parse_checklist_reads_task_items() {
+    let output = "Plan ready.\n\n```checklist\n- [x] read config\n- [ ] add flag\n* update docs\n\n```\n- [ ] not part of the block\n";
+    assert_eq!(
+        parse_checklist(output),
+        vec![
+            ChecklistItem {
+                text: "read config".to_string(),
+                done: true,
+            },
+            ChecklistItem {
+                text: "add flag".to_string(),
+                done: false,
+            },
+            ChecklistItem {
+                text: "update docs".to_string(),
+                done: false,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn resolve_subagent_cwd_stays_inside_root() {
+    let tmp = tempfile::TempDir::new().expect("TempDir");
+    let base = tmp.path().join("repo");
+    std::fs::create_dir_all(base.join("crates/core")).expect("create dirs");
+    std::fs::create_dir_all(tmp.path().join("elsewhere")).expect("create dirs");
+
+    let inside = resolve_subagent_cwd(&base.join("crates/core"), &base)
+        .await
+        .expect("inside root");
+    assert_eq!(
+        inside,
+        std::fs::canonicalize(base.join("crates/core")).expect("canonicalize")
+    );
+
+    let escaped = resolve_subagent_cwd(&base.join("crates/../../elsewhere"), &base).await;
+    assert!(escaped.is_err_and(|e| e.contains("outside the repository root")));
+    assert!(
+        resolve_subagent_cwd(&base.join("missing"), &base)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn spawn_cwd_must_stay_inside_root() {
+    let tmp = tempfile::TempDir::new().expect("TempDir");
+    let base = tmp.path().join("repo");
+    std::fs::create_dir_all(base.join("crates")).expect("create dirs");
+    std::fs::create_dir_all(tmp.path().join("elsewhere")).expect("create dirs");
+    let skills_manager = SkillsManager::new(tmp.path().join("home"));
+    let config = crate::config::test_config();
+
+    let item = |cwd: PathBuf| SubagentSpawnRequest {
+        prompt: "look around".to_string(),
+        cwd: Some(cwd),
+        ..Default::default()
+    };
+    let results = SubagentManager::default()
+        .validate_batch(
+            vec![
+                Ok(item(PathBuf::from("crates"))),
+                Ok(item(tmp.path().join("elsewhere"))),
+            ],
+            &skills_manager,
+            &base,
+            &config,
+        )
+        .await;
+
+    assert_eq!(results[0], Ok(()));
+    assert!(
+        results[1]
+            .as_ref()
+            .is_err_and(|e| e.contains("outside the repository root"))
+    );
+}
+
+#[tokio::test]
+async fn isolated_workspace_is_general_only_and_pins_cwd() {
+    let mut config = crate::config::test_config();
+    assert!(
+        create_isolated_workspace(&config, SubagentMode::General)
+            .expect("workspace")
+            .is_none()
+    );
+    config.subagents.isolated_workspace = true;
+    assert!(
+        create_isolated_workspace(&config, SubagentMode::Explore)
+            .expect("workspace")
+            .is_none()
+    );
+    let workspace = create_isolated_workspace(&config, SubagentMode::General)
+        .expect("workspace")
+        .expect("general agents get a workspace");
+    assert!(workspace.path().is_dir());
+
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "iso".to_string(),
+        Arc::new(test_handle(
+            "iso",
+            SubagentState {
+                status: SubagentStatus::Running,
+                workspace: Some(workspace.path().to_path_buf()),
+                ..Default::default()
+            },
+        )),
+    );
+    let result = manager
+        .set_cwd("iso", Path::new("."), workspace.path())
+        .await
+        .expect("known agent");
+    assert_eq!(
+        result,
+        Err("agent runs in an isolated workspace; its cwd cannot be changed".to_string())
+    );
+    assert_eq!(
+        manager.poll("iso", None).await.expect("poll").workspace,
+        Some(workspace.path().to_path_buf())
+    );
+
+    let path = workspace.path().to_path_buf();
+    workspace.close().expect("remove workspace");
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn validate_batch_reports_each_item_without_spawning() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let cwd = tempfile::TempDir::new().expect("TempDir");
+    let skills_manager = SkillsManager::new(codex_home.path().to_path_buf());
+    let mut config = crate::config::test_config();
+    config.subagents.max_agents = 3;
+
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "busy".to_string(),
+        Arc::new(test_handle(
+            "busy",
+            SubagentState {
+                status: SubagentStatus::Running,
+                ..Default::default()
+            },
+        )),
+    );
+
+    let item = |agent_id: &str| SubagentSpawnRequest {
+        agent_id: Some(agent_id.to_string()),
+        label: "scan".to_string(),
+        prompt: "look around".to_string(),
+        ..Default::default()
+    };
+    let results = manager
+        .validate_batch(
+            vec![
+                Ok(item("a")),
+                Err("unknown subagent mode".to_string()),
+                Ok(item("a")),
+                Ok(item("busy")),
+                Ok(SubagentSpawnRequest {
+                    skills: vec!["missing".to_string()],
+                    ..item("b")
+                }),
+                Ok(SubagentSpawnRequest {
+                    max_per_label: Some(1),
+                    ..item("c")
+                }),
+                Ok(item("d")),
+                Ok(item("e")),
+            ],
+            &skills_manager,
+            cwd.path(),
+            &config,
+        )
+        .await;
+
+    assert_eq!(
+        results,
+        vec![
+            Ok(()),
+            Err("unknown subagent mode".to_string()),
+            Err("agent_id `a` is repeated in this batch".to_string()),
+            Err("agent_id already exists".to_string()),
+            Err("unknown skill requested: missing".to_string()),
+            Err(
+                "too many active subagents labelled `scan` (1, max_per_label 1); wait for some to finish"
+                    .to_string()
+            ),
+            Ok(()),
+            Err(
+                "too many subagents in this session (max 3) once earlier batch items are spawned"
+                    .to_string()
+            ),
+        ]
+    );
+    assert_eq!(manager.agents.read().await.len(), 1);
+    assert!(manager.audit_log(None).await.is_empty());
+}
+
+#[tokio::test]
+async fn max_per_label_counts_active_agents_sharing_the_label() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let cwd = tempfile::TempDir::new().expect("TempDir");
+    let skills_manager = SkillsManager::new(codex_home.path().to_path_buf());
+    let config = crate::config::test_config();
+
+    let manager = SubagentManager::default();
+    for (id, label, status) in [
+        ("queued", "scan", SubagentStatus::Queued),
+        ("running", "scan", SubagentStatus::Running),
+        ("done", "scan", SubagentStatus::Complete),
+        ("other", "fix", SubagentStatus::Running),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(SubagentHandle {
+                label: label.to_string(),
+                ..test_handle(
+                    id,
+                    SubagentState {
+                        status,
+                        ..Default::default()
+                    },
+                )
+            }),
+        );
+    }
+
+    let check = async |label: &str, max_per_label: usize, batch_same_label: usize| {
+        let req = SubagentSpawnRequest {
+            label: label.to_string(),
+            prompt: "look around".to_string(),
+            max_per_label: Some(max_per_label),
+            ..Default::default()
+        };
+        manager
+            .check_spawn_request(
+                &req,
+                None,
+                &skills_manager,
+                cwd.path(),
+                &config,
+                batch_same_label,
+            )
+            .await
+    };
+
+    // Finished agents and other labels do not count.
+    assert_eq!(
+        check("scan", 2, 0).await,
+        Err(
+            "too many active subagents labelled `scan` (2, max_per_label 2); wait for some to finish"
+                .to_string()
+        )
+    );
+    assert_eq!(check("scan", 3, 0).await, Ok(()));
+    assert_eq!(check("fix", 2, 0).await, Ok(()));
+    // Earlier items of the same batch count as active.
+    assert_eq!(
+        check("scan", 3, 1).await,
+        Err(
+            "too many active subagents labelled `scan` (3, max_per_label 3); wait for some to finish"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn reasoning_effort_parses_known_levels() {
+    assert_eq!(parse_reasoning_effort(None), Ok(None));
+    assert_eq!(parse_reasoning_effort(Some("  ")), Ok(None));
+    assert_eq!(
+        parse_reasoning_effort(Some(" High ")),
+        Ok(Some(ReasoningEffort::High))
+    );
+    assert_eq!(
+        parse_reasoning_effort(Some("xhigh")),
+        Ok(Some(ReasoningEffort::XHigh))
+    );
+    assert_eq!(
+        parse_reasoning_effort(Some("max")),
+        Err(
+            "`reasoning_effort` must be one of: none, minimal, low, medium, high, xhigh"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn sandbox_label_names_policy_and_network() {
+    assert_eq!(
+        sandbox_label(&SandboxPolicy::new_read_only_policy()),
+        "read-only"
+    );
+    assert_eq!(
+        sandbox_label(&SandboxPolicy::new_workspace_write_policy()),
+        "workspace-write"
+    );
+    assert_eq!(
+        sandbox_label(&SandboxPolicy::WorkspaceWrite {
+            writable_roots: Vec::new(),
+            network_access: true,
+            exclude_tmpdir_env_var: false,
+            exclude_slash_tmp: false,
+        }),
+        "workspace-write (network access)"
+    );
+    assert_eq!(
+        sandbox_label(&SandboxPolicy::DangerFullAccess),
+        "danger-full-access"
+    );
+    assert_eq!(AskForApproval::OnRequest.to_string(), "on-request");
+}
+
+#[tokio::test]
+async fn require_unique_labels_rejects_live_and_batch_duplicates() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let cwd = tempfile::TempDir::new().expect("TempDir");
+    let skills_manager = SkillsManager::new(codex_home.path().to_path_buf());
+    let mut config = crate::config::test_config();
+    config.subagents.require_unique_labels = true;
+
+    let manager = SubagentManager::default();
+    for (id, status) in [
+        ("live", SubagentStatus::Running),
+        ("done", SubagentStatus::Complete),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(SubagentHandle {
+                label: id.to_string(),
+                ..test_handle(
+                    id,
+                    SubagentState {
+                        status,
+                        ..Default::default()
+                    },
+                )
+            }),
+        );
+    }
+
+    let item = |label: &str| SubagentSpawnRequest {
+        label: label.to_string(),
+        prompt: "look around".to_string(),
+        ..Default::default()
+    };
+    let results = manager
+        .validate_batch(
+            vec![
+                Ok(item("live")),
+                Ok(item("done")),
+                Ok(item("new")),
+                Ok(item("new")),
+            ],
+            &skills_manager,
+            cwd.path(),
+            &config,
+        )
+        .await;
+    assert_eq!(
+        results,
+        vec![
+            Err(
+                "a queued or running subagent is already labelled `live`; pick a distinct label (require_unique_labels)"
+                    .to_string()
+            ),
+            Ok(()),
+            Ok(()),
+            Err(
+                "label `new` is used by an earlier item in this batch; labels must be unique (require_unique_labels)"
+                    .to_string()
+            ),
+        ]
+    );
+
+    config.subagents.require_unique_labels = false;
+    let results = manager
+        .validate_batch(vec![Ok(item("live"))], &skills_manager, cwd.path(), &config)
+        .await;
+    assert_eq!(results, vec![Ok(())]);
+}
+
+#[tokio::test]
+async fn dry_run_spawn_validates_without_registering() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let cwd = tempfile::TempDir::new().expect("TempDir");
+    let skills_manager = SkillsManager::new(codex_home.path().to_path_buf());
+    let models_manager = ModelsManager::new(crate::AuthManager::from_auth_for_testing(
+        crate::CodexAuth::from_api_key("Test API Key"),
+    ));
+    let config = crate::config::test_config();
+
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "busy".to_string(),
+        Arc::new(test_handle("busy", SubagentState::default())),
+    );
+    let req = SubagentSpawnRequest {
+        agent_id: Some("Scan-One".to_string()),
+        label: "scan".to_string(),
+        mode: SubagentMode::Explore,
+        prompt: "look around".to_string(),
+        ..Default::default()
+    };
+    let (manager_ref, cwd) = (&manager, cwd.path());
+    let (skills_manager, models_manager, config) = (&skills_manager, &models_manager, &config);
+    let dry_run = move |req: SubagentSpawnRequest| async move {
+        manager_ref
+            .dry_run_spawn(&req, skills_manager, models_manager, cwd, config)
+            .await
+    };
+
+    assert_eq!(
+        dry_run(req.clone()).await,
+        Ok(SubagentDryRun {
+            agent_id: Some("scan-one".to_string()),
+            label: "scan".to_string(),
+            mode: SubagentMode::Explore,
+            skills: Vec::new(),
+        })
+    );
+    assert_eq!(
+        dry_run(SubagentSpawnRequest {
+            agent_id: Some("busy".to_string()),
+            ..req.clone()
+        })
+        .await,
+        Err("agent_id already exists".to_string())
+    );
+    assert_eq!(
+        dry_run(SubagentSpawnRequest {
+            skills: vec!["missing".to_string()],
+            ..req.clone()
+        })
+        .await,
+        Err("unknown skill requested: missing".to_string())
+    );
+    assert_eq!(
+        dry_run(SubagentSpawnRequest {
+            depends_on: vec!["nope".to_string()],
+            ..req
+        })
+        .await,
+        Err("unknown dependency `nope`".to_string())
+    );
+    assert_eq!(manager.agents.read().await.len(), 1);
+    assert!(manager.audit_log(None).await.is_empty());
+}
+
+#[test]
+fn output_postprocess_cleans_final_output() {
+    let raw = "## Summary  \n\n\n\nUse **`cargo test`** and see [the docs](https://example.com).\n> quoted\n```rust\n    let x = 1;\n```\n- item\n";
+    assert_eq!(
+        postprocess_output(OutputPostprocess::None, raw.to_string()),
+        raw
+    );
+    assert_eq!(
+        postprocess_output(OutputPostprocess::Trim, raw.to_string()),
+        "## Summary\n\nUse **`cargo test`** and see [the docs](https://example.com).\n> quoted\n```rust\n    let x = 1;\n```\n- item"
+    );
+    assert_eq!(
+        postprocess_output(OutputPostprocess::StripMarkdown, raw.to_string()),
+        "Summary\n\nUse cargo test and see the docs (https://example.com).\nquoted\n    let x = 1;\n- item"
+    );
+}
+
+#[test]
+fn slice_output_respects_char_boundaries() {
+    let text = "ab\u{e9}cd";
+    assert_eq!(slice_output(text, 0, 2), (0, "ab", Some(2)));
+    // The é is two bytes; a one-byte slice still makes progress.
+    assert_eq!(slice_output(text, 2, 1), (2, "\u{e9}", Some(4)));
+    assert_eq!(slice_output(text, 3, 10), (2, "\u{e9}cd", None));
+    assert_eq!(slice_output(text, 99, 4), (6, "", None));
+}
+
+#[test]
+fn extra_instructions_are_sanitized_and_capped() {
+    assert_eq!(
+        sanitize_extra_instructions("  only consider\u{7}\n\tsrc/net\r  "),
+        Some("only consider\n\tsrc/net".to_string())
+    );
+    assert_eq!(sanitize_extra_instructions(" \u{1b} "), None);
+    let long = sanitize_extra_instructions(&"\u{e9}".repeat(MAX_EXTRA_INSTRUCTIONS_LEN))
+        .expect("non-empty");
+    assert_eq!(long.len(), MAX_EXTRA_INSTRUCTIONS_LEN);
+
+    let instructions = format!(
+        "{}{}",
+        subagent_base_instructions("a", SubagentMode::Explore, SubagentOutputFormat::Text),
+        extra_instructions_section("ignore the scope above")
+    );
+    assert!(
+        instructions.find("read-only exploration") < instructions.find("ignore the scope above")
+    );
+}
+
+#[test]
+fn suggested_poll_interval_backs_off_with_age() {
+    let hints: Vec<u64> = [0, 3_000, 8_000, 20_000, 60_000, 600_000]
+        .into_iter()
+        .map(suggested_next_poll_ms)
+        .collect();
+    assert_eq!(hints, vec![1_000, 1_024, 2_048, 8_192, 16_384, 30_000]);
+}
+
+#[test]
+fn transcript_renders_messages_and_tool_calls() {
+    let items = vec![
+        RolloutItem::EventMsg(EventMsg::UserMessage(
+            codex_protocol::protocol::UserMessageEvent {
+                message: "find the bug".to_string(),
+                images: None,
+            },
+        )),
+        RolloutItem::ResponseItem(ResponseItem::Message {
+            id: None,
+            role: "developer".to_string(),
+            content: Vec::new(),
+        }),
+        RolloutItem::EventMsg(EventMsg::AgentReasoning(
+            codex_protocol::protocol::AgentReasoningEvent {
+                text: "check the parser".to_string(),
+            },
+        )),
+        RolloutItem::ResponseItem(ResponseItem::FunctionCall {
+            id: None,
+            name: "shell".to_string(),
+            arguments: "{\"command\":[\"rg\",\"parse\"]}".to_string(),
+            call_id: "c1".to_string(),
+        }),
+        RolloutItem::ResponseItem(ResponseItem::FunctionCallOutput {
+            call_id: "c1".to_string(),
+            output: codex_protocol::models::FunctionCallOutputPayload {
+                content: "x".repeat(TRANSCRIPT_TOOL_TEXT_BYTES + 1),
+                ..Default::default()
+            },
+        }),
+        RolloutItem::EventMsg(EventMsg::AgentMessage(
+            codex_protocol::protocol::AgentMessageEvent {
+                message: "off by one in parse()".to_string(),
+            },
+        )),
+    ];
+
+    assert_eq!(
+        render_transcript(&items),
+        format!(
+            "[user] find the bug\n\n[reasoning] check the parser\n\n[tool call] shell {{\"command\":[\"rg\",\"parse\"]}}\n\n[tool output] {} [truncated]\n\n[assistant] off by one in parse()",
+            "x".repeat(TRANSCRIPT_TOOL_TEXT_BYTES)
+        )
+    );
+}
+
+#[tokio::test]
+async fn result_chunk_pages_through_stored_output() {
+    let manager = SubagentManager::default();
+    let handle = Arc::new(SubagentHandle {
+        ephemeral: true,
+        ..test_handle(
+            "a",
+            SubagentState {
+                status: SubagentStatus::Complete,
+                final_output: Some("hello world".to_string()),
+                ..Default::default()
+            },
+        )
+    });
+    manager
+        .agents
+        .write()
+        .await
+        .insert("a".to_string(), Arc::clone(&handle));
+
+    let mut offset = 0;
+    let mut chunks = Vec::new();
+    loop {
+        let chunk = manager
+            .result_chunk("a", offset, 4)
+            .await
+            .expect("known agent")
+            .expect("chunk");
+        assert_eq!(chunk.total_len, 11);
+        assert!(!chunk.from_rollout);
+        chunks.push(chunk.chunk);
+        match chunk.next_offset {
+            Some(next) => {
+                assert!(!handle.state.lock().await.consumed);
+                offset = next;
+            }
+            None => break,
+        }
+    }
+    assert_eq!(chunks, vec!["hell", "o wo", "rld"]);
+    assert!(handle.state.lock().await.consumed);
+    assert!(manager.result_chunk("missing", 0, 4).await.is_none());
+}
+
+#[tokio::test]
+async fn approval_events_are_tagged_and_timed() {
+    let handle = test_handle("a", SubagentState::default());
+    push_approval_event(
+        &handle,
+        format!(
+            "exec {}: cargo test",
+            review_decision_str(&ReviewDecision::Denied)
+        ),
+    )
+    .await;
+
+    let state = handle.state.lock().await;
+    let event = state.recent_events.back().expect("event");
+    assert_eq!(event.kind, SubagentEventKind::Approval);
+    assert_eq!(event.message, "exec denied: cargo test");
+    assert!(event.at_ms <= millis_between(handle.created_at, Instant::now()));
+}
+
+#[tokio::test]
+async fn registry_snapshot_restores_as_placeholders() {
+    let manager = SubagentManager::default();
+    for (id, status, rollout_path) in [
+        (
+            "done",
+            SubagentStatus::Complete,
+            Some(PathBuf::from("/tmp/done.jsonl")),
+        ),
+        (
+            "live",
+            SubagentStatus::Running,
+            Some(PathBuf::from("/tmp/live.jsonl")),
+        ),
+    ] {
+        manager.agents.write().await.insert(
+            id.to_string(),
+            Arc::new(test_handle(
+                id,
+                SubagentState {
+                    status,
+                    rollout_path,
+                    ..Default::default()
+                },
+            )),
+        );
+    }
+
+    let registry = manager.snapshot().await;
+    let line =
+        serde_json::to_string(&RolloutItem::SubagentRegistry(registry.clone())).expect("serialize");
+    let RolloutItem::SubagentRegistry(parsed) =
+        serde_json::from_str::<RolloutItem>(&line).expect("deserialize")
+    else {
+        panic!("expected a subagent registry item");
+    };
+    assert_eq!(parsed, registry);
+
+    let restored = SubagentManager::default();
+    let config = crate::config::test_config().subagents;
+    restored.restore(&parsed, &config).await;
+    restored.restore(&parsed, &config).await;
+
+    let done = restored.poll("done", None).await.expect("done agent");
+    assert_eq!(done.status, SubagentStatus::Complete);
+    assert_eq!(done.final_output.as_deref(), Some(LAZY_OUTPUT_MARKER));
+    assert_eq!(done.rollout_path, Some(PathBuf::from("/tmp/done.jsonl")));
+
+    let live = restored.poll("live", None).await.expect("live agent");
+    assert_eq!(live.status, SubagentStatus::Error);
+    assert_eq!(
+        live.final_output.as_deref(),
+        Some(RESTORED_INTERRUPTED_MESSAGE)
+    );
+    assert_eq!(restored.agents.read().await.len(), 2);
+}
+
+#[tokio::test]
+async fn cancel_all_skips_finished_agents() {
+    let manager = SubagentManager::default();
+    let running = Arc::new(test_handle(
+        "running",
+        SubagentState {
+            status: SubagentStatus::Running,
+            ..Default::default()
+        },
+    ));
+    let queued = Arc::new(test_handle("queued", SubagentState::default()));
+    let done = Arc::new(test_handle(
+        "done",
+        SubagentState {
+            status: SubagentStatus::Complete,
+            ..Default::default()
+        },
+    ));
+    for handle in [&running, &queued, &done] {
+        manager
+            .agents
+            .write()
+            .await
+            .insert(handle.id.clone(), Arc::clone(handle));
+    }
+
+    assert_eq!(manager.cancel_all().await, 2);
+    assert!(running.cancel.is_cancelled());
+    assert!(queued.cancel.is_cancelled());
+    assert!(!done.cancel.is_cancelled());
+}
+
+#[tokio::test]
+async fn share_approvals_reuses_the_parents_session_approvals() {
+    let (session, _turn) = crate::codex::make_session_and_context();
+    let command = vec!["cargo".to_string(), "test".to_string()];
+    let cwd = PathBuf::from("/repo");
+    let approve = async |sandbox_permissions| {
+        session.services.tool_approvals.lock().await.put(
+            ParentExecApprovalKey {
+                command: &command,
+                cwd: &cwd,
+                sandbox_permissions,
+            },
+            ReviewDecision::ApprovedForSession,
+        );
+    };
+    assert!(!parent_approved_for_session(&session, &command, &cwd).await);
+    // The subagent may escalate, so only an escalated approval covers it.
+    approve(SandboxPermissions::UseDefault).await;
+    assert!(!parent_approved_for_session(&session, &command, &cwd).await);
+    approve(SandboxPermissions::RequireEscalated).await;
+    assert!(parent_approved_for_session(&session, &command, &cwd).await);
+
+    // The cached approval answers the subagent without asking the parent.
+    let (tx_sub, rx_sub) = async_channel::unbounded();
+    let (_tx_event, rx_event) = async_channel::unbounded();
+    let codex = Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(None),
+    };
+    let handle = test_handle("child", SubagentState::default());
+    let request = ExecApprovalRequestEvent {
+        call_id: "call-1".to_string(),
+        turn_id: "turn-1".to_string(),
+        command: command.clone(),
+        cwd: cwd.clone(),
+        reason: None,
+        proposed_execpolicy_amendment: None,
+        parsed_cmd: Vec::new(),
+    };
+    handle_exec_approval_request(&handle, &codex, &session, "turn-1", request, true).await;
+    let submission = rx_sub.try_recv().expect("approval submitted");
+    let Op::ExecApproval { id, decision } = submission.op else {
+        panic!("expected exec approval, got {:?}", submission.op);
+    };
+    assert_eq!(
+        (id.as_str(), decision),
+        ("turn-1", ReviewDecision::ApprovedForSession)
+    );
+    let state = handle.state.lock().await;
+    assert_eq!(state.approvals.exec, 1);
+    assert_eq!(
+        state
+            .recent_events
+            .back()
+            .map(|event| event.message.as_str()),
+        Some("exec approved from parent session cache: cargo test")
+    );
+}
+
+#[tokio::test]
+async fn explore_auto_deny_denies_without_asking_the_parent() {
+    let (tx_sub, rx_sub) = async_channel::unbounded();
+    let (_tx_event, rx_event) = async_channel::unbounded();
+    let codex = Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(None),
+    };
+    let handle = test_handle("explorer", SubagentState::default());
+
+    auto_deny_approval(
+        &handle,
+        &codex,
+        Op::ExecApproval {
+            id: "turn-1".to_string(),
+            decision: ReviewDecision::Denied,
+        },
+        "exec: touch notes.md".to_string(),
+    )
+    .await;
+    let submission = rx_sub.try_recv().expect("denial submitted");
+    let Op::ExecApproval { id, decision } = submission.op else {
+        panic!("expected exec approval, got {:?}", submission.op);
+    };
+    assert_eq!((id.as_str(), decision), ("turn-1", ReviewDecision::Denied));
+    assert!(rx_sub.is_empty());
+
+    let event = handle
+        .state
+        .lock()
+        .await
+        .recent_events
+        .back()
+        .cloned()
+        .expect("approval event");
+    assert_eq!(event.kind, SubagentEventKind::Approval);
+    assert_eq!(
+        event.message,
+        "exec: touch notes.md auto-denied: explore agents are read-only"
+    );
+}
+
+#[tokio::test]
+async fn budget_warning_counts_every_agent_and_reaches_running_ones_once() {
+    let manager = SubagentManager::default();
+    let (tx_sub, rx_sub) = async_channel::unbounded();
+    let (_tx_event, rx_event) = async_channel::unbounded();
+    let codex = Arc::new(Codex {
+        next_id: std::sync::atomic::AtomicU64::new(1),
+        tx_sub,
+        rx_event,
+        session_task: std::sync::Mutex::new(None),
+    });
+    let running = Arc::new(test_handle(
+        "running",
+        SubagentState {
+            status: SubagentStatus::Running,
+            codex: Some(Arc::clone(&codex)),
+            tokens_used: 40,
+            ..Default::default()
+        },
+    ));
+    // Queued agents are counted but not warned, even with a live session.
+    let queued = Arc::new(test_handle(
+        "queued",
+        SubagentState {
+            codex: Some(Arc::clone(&codex)),
+            tokens_used: 5,
+            ..Default::default()
+        },
+    ));
+    // A restarted agent carries the tokens of its earlier runs.
+    let restarted = Arc::new(test_handle(
+        "restarted",
+        SubagentState {
+            status: SubagentStatus::Complete,
+            tokens_used: 20,
+            earlier_runs_tokens: SubagentTokenUsage {
+                total: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+    let pruned = Arc::new(test_handle(
+        "pruned",
+        SubagentState {
+            status: SubagentStatus::Complete,
+            tokens_used: 30,
+            last_update: Some(Instant::now() - Duration::from_secs(60)),
+            ..Default::default()
+        },
+    ));
+    for handle in [&running, &queued, &restarted, &pruned] {
+        manager
+            .agents
+            .write()
+            .await
+            .insert(handle.id.clone(), Arc::clone(handle));
+    }
+    assert_eq!(
+        manager
+            .prune_terminated(None, Some(Duration::from_secs(30)))
+            .await,
+        vec!["pruned".to_string()]
+    );
+
+    // 40 + 5 + 30 (restarted) + 30 (pruned) = 105 tokens.
+    manager.maybe_send_budget_warning(106).await;
+    assert!(rx_sub.is_empty());
+
+    manager.maybe_send_budget_warning(105).await;
+    manager.maybe_send_budget_warning(105).await;
+    let submission = rx_sub.try_recv().expect("warning submitted");
+    let Op::UserInput { items } = submission.op else {
+        panic!("expected user input, got {:?}", submission.op);
+    };
+    assert_eq!(
+        items,
+        vec![UserInput::Text {
+            text: COOPERATIVE_BUDGET_MESSAGE.to_string(),
+        }]
+    );
+    assert!(rx_sub.is_empty());
+
+    let event = running
+        .state
+        .lock()
+        .await
+        .recent_events
+        .back()
+        .cloned()
+        .expect("control event");
+    assert_eq!(event.kind, SubagentEventKind::Control);
+    assert_eq!(event.message, "budget warning sent (105/105 tokens used)");
+    assert!(queued.state.lock().await.recent_events.is_empty());
+    let warnings = manager
+        .audit_log(None)
+        .await
+        .into_iter()
+        .filter(|event| event.kind == ManagerEventKind::BudgetWarning)
+        .count();
+    assert_eq!(warnings, 1);
+}
+
+#[tokio::test]
+async fn send_queues_before_start_and_rejects_finished_agents() {
+    let manager = SubagentManager::default();
+    let queued = Arc::new(test_handle("queued", SubagentState::default()));
+    manager
+        .agents
+        .write()
+        .await
+        .insert("queued".to_string(), Arc::clone(&queued));
+    manager.agents.write().await.insert(
+        "done".to_string(),
+        Arc::new(test_handle(
+            "done",
+            SubagentState {
+                status: SubagentStatus::Complete,
+                ..Default::default()
+            },
+        )),
+    );
+
+    assert_eq!(
+        manager.send("queued", "also check tests".to_string()).await,
+        Some(Ok(SendOutcome::Queued))
+    );
+    assert_eq!(
+        queued.state.lock().await.queued_messages,
+        vec!["also check tests".to_string()]
+    );
+    assert_eq!(
+        manager.send("done", "too late".to_string()).await,
+        Some(Err("agent is already complete".to_string()))
+    );
+    assert_eq!(manager.send("missing", "hi".to_string()).await, None);
+}
+
+#[tokio::test]
+async fn result_chunk_needs_rollout_for_truncated_output() {
+    let manager = SubagentManager::default();
+    manager.agents.write().await.insert(
+        "a".to_string(),
+        Arc::new(test_handle(
+            "a",
+            SubagentState {
+                status: SubagentStatus::Complete,
+                final_output: Some("cut".to_string()),
+                output_truncated: true,
+                ..Default::default()
+            },
+        )),
+    );
+    let result = manager.result_chunk("a", 0, 4).await.expect("known agent");
+    assert!(result.is_err());
+}
+
+#[test]
+fn resume_model_policy_handles_mismatches() {
+    assert_eq!(
+        check_resume_model(ResumeModelPolicy::Strict, Some("gpt-a"), "gpt-a"),
+        Ok(None)
+    );
+    assert_eq!(
+        check_resume_model(ResumeModelPolicy::Strict, None, "gpt-b"),
+        Ok(None)
+    );
+    assert_eq!(
+        check_resume_model(ResumeModelPolicy::Ignore, Some("gpt-a"), "gpt-b"),
+        Ok(None)
+    );
+    assert_eq!(
+        check_resume_model(ResumeModelPolicy::Warn, Some("gpt-a"), "gpt-b"),
+        Ok(Some(
+            "resumed rollout was recorded with model gpt-a; continuing with gpt-b".to_string()
+        ))
+    );
+    assert!(check_resume_model(ResumeModelPolicy::Strict, Some("gpt-a"), "gpt-b").is_err());
+}
+
+#[test]
+fn status_filter_accepts_comma_separated_values() {
+    assert_eq!(
+        parse_status_filter("running, Queued,running"),
+        Ok(vec![SubagentStatus::Running, SubagentStatus::Queued])
+    );
+    assert_eq!(
+        parse_status_filter("running,done"),
+        Err(
+            "unknown status `done` (expected queued, running, complete, aborted, or error)"
+                .to_string()
+        )
+    );
+    assert!(parse_status_filter(" , ").is_err());
+}
+
+#[test]
+fn model_override_must_be_available() {
+    let available = vec!["gpt-a".to_string(), "gpt-b".to_string()];
+    assert_eq!(check_model_override("gpt-b", &available), Ok(()));
+    assert_eq!(
+        check_model_override("gpt-c", &available),
+        Err("unknown model `gpt-c` (available: gpt-a, gpt-b)".to_string())
+    );
+}
+
+#[tokio::test]
+async fn wait_for_skills_picks_up_late_skills() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let cwd = tempfile::TempDir::new().expect("TempDir");
+    let skills_manager = SkillsManager::new(codex_home.path().to_path_buf());
+    let requested = vec!["late-skill".to_string()];
+    let cancel = CancellationToken::new();
+
+    let outcome = wait_for_skills(
+        &skills_manager,
+        cwd.path(),
+        &requested,
+        Duration::ZERO,
+        &cancel,
+    )
+    .await;
+    assert!(outcome.skills.is_empty());
+
+    let skill_dir = codex_home.path().join("skills/late");
+    let writer = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        std::fs::create_dir_all(&skill_dir).expect("create skill dir");
+        std::fs::write(
+            skill_dir.join("SKILL.md"),
+            "---\nname: late-skill\ndescription: shows up late\n---\n\n# Body\n",
+        )
+        .expect("write skill");
+    });
+    let outcome = wait_for_skills(
+        &skills_manager,
+        cwd.path(),
+        &requested,
+        Duration::from_secs(5),
+        &cancel,
+    )
+    .await;
+    writer.await.expect("writer");
+    let names: Vec<String> = outcome.skills.into_iter().map(|skill| skill.name).collect();
+    assert_eq!(names, requested);
+}
+
+#[tokio::test]
+async fn resume_rollout_path_must_be_under_sessions_dir() {
+    let codex_home = tempfile::TempDir::new().expect("TempDir");
+    let day_dir = codex_home.path().join("sessions/2026/01/02");
+    std::fs::create_dir_all(&day_dir).expect("create sessions dir");
+    let rollout = day_dir.join("rollout-a.jsonl");
+    std::fs::write(&rollout, "").expect("write rollout");
+    let outside = codex_home.path().join("notes.jsonl");
+    std::fs::write(&outside, "").expect("write outside file");
+
+    assert_eq!(
+        check_resume_rollout_path(&rollout, codex_home.path(), None).await,
+        Ok(())
+    );
+    let escaped = day_dir.join("../../../../notes.jsonl");
+    let err = check_resume_rollout_path(&escaped, codex_home.path(), None)
+        .await
+        .expect_err("path outside sessions");
+    assert!(err.contains("is not a rollout file under"), "{err}");
+    let err = check_resume_rollout_path(&day_dir, codex_home.path(), None)
+        .await
+        .expect_err("directory");
+    assert!(err.contains("is not a rollout file under"), "{err}");
+
+    let err = check_resume_rollout_path(&rollout, codex_home.path(), Some(&[]))
+        .await
+        .expect_err("unknown rollout");
+    assert!(err.contains("was not reported by a subagent"), "{err}");
+    let known = std::slice::from_ref(&rollout);
+    assert_eq!(
+        check_resume_rollout_path(&rollout, codex_home.path(), Some(known)).await,
+        Ok(())
+    );
+}
+
+#[test]
+fn skill_patterns_expand_to_all_matching_skills() {
+    let skills: Vec<crate::skills::SkillMetadata> = ["db/migrate", "db/seed", "lint"]
+        .into_iter()
+        .map(|name| crate::skills::SkillMetadata {
+            name: name.to_string(),
+            description: String::new(),
+            path: PathBuf::from(format!("/skills/{name}/SKILL.md")),
+            scope: codex_protocol::protocol::SkillScope::User,
+        })
+        .collect();
+    let requested = vec![
+        "db/*".to_string(),
+        "db/seed".to_string(),
+        "lint".to_string(),
+        "docs/*".to_string(),
+    ];
+
+    let resolved = resolve_requested_skills(&requested, &skills);
+    let names: Vec<&str> = resolved
+        .matched
+        .iter()
+        .map(|skill| skill.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["db/migrate", "db/seed", "lint"]);
+    assert_eq!(resolved.unmatched, vec!["docs/*".to_string()]);
+    assert_eq!(
+        describe_unmatched_skill("docs/*"),
+        "no skills match pattern `docs/*`"
+    );
+    assert_eq!(
+        describe_unmatched_skill("lnt"),
+        "unknown skill requested: lnt"
+    );
+}
+
+#[test]
+fn citation_style_parses_known_styles_only() {
+    assert_eq!(
+        ["none", "Inline-URLs", "numbered", "footnotes"].map(SubagentCitationStyle::from_str),
+        [
+            Some(SubagentCitationStyle::None),
+            Some(SubagentCitationStyle::InlineUrls),
+            Some(SubagentCitationStyle::Numbered),
+            None,
+        ]
+    );
+    assert_eq!(SubagentCitationStyle::None.instructions(), None);
+    assert!(
+        SubagentCitationStyle::Numbered
+            .instructions()
+            .is_some_and(|text| text.contains("`Sources` list"))
+    );
+}
+
+#[test]
+fn review_mode_parses_and_focuses_on_defects() {
+    assert_eq!(
+        SubagentMode::from_str("Reviewer"),
+        Some(SubagentMode::Review)
+    );
+    assert_eq!(SubagentMode::Review.as_str(), "review");
+
+    let text = subagent_base_instructions("a", SubagentMode::Review, SubagentOutputFormat::Text);
+    assert!(text.contains("do not modify files or run commands"));
+    assert!(text.contains("missing or weak tests"));
+}
+
+#[test]
+fn json_output_format_drops_prose_guidance() {
+    let text = subagent_base_instructions("a", SubagentMode::Explore, SubagentOutputFormat::Text);
+    assert!(text.contains("prefer checklists"));
+
+    let json = subagent_base_instructions("a", SubagentMode::Explore, SubagentOutputFormat::Json);
+    assert!(!json.contains("checklist"));
+    assert!(json.contains("single valid JSON value"));
+}
+
+#[test]
+fn take_abort_siblings_marker_extracts_reason_and_strips_it() {
+    let take = |text: &str| {
+        let mut text = text.to_string();
+        let reason = take_abort_siblings_marker(&mut text);
+        (reason, text)
+    };
+    assert_eq!(
+        take("found it <<abort-siblings: RCE in parser >> done"),
+        (
+            Some("RCE in parser".to_string()),
+            "found it  done".to_string()
+        )
+    );
+    assert_eq!(
+        take("Stop.\n<<abort-siblings:>>"),
+        (Some("fatal finding".to_string()), "Stop.".to_string())
+    );
+    assert_eq!(take("nothing to see"), (None, "nothing to see".to_string()));
+}
+
+#[test]
+fn take_confidence_marker_clamps_and_strips_the_last_marker() {
+    let take = |text: &str| {
+        let mut text = text.to_string();
+        let score = take_confidence_marker(&mut text);
+        (score, text)
+    };
+    assert_eq!(
+        take("<<confidence: 0.3>> then <<confidence: 0.82 >>"),
+        (Some(0.82), "<<confidence: 0.3>> then".to_string())
+    );
+    assert_eq!(
+        take("Done.\n\n<<confidence: 7>>"),
+        (Some(1.0), "Done.".to_string())
+    );
+    assert_eq!(take("<<confidence: -2>>"), (Some(0.0), String::new()));
+    assert_eq!(take("ok <<confidence: NaN>>"), (None, "ok".to_string()));
+    assert_eq!(take("ok <<confidence: high>>"), (None, "ok".to_string()));
+    assert_eq!(take("no marker"), (None, "no marker".to_string()));
+}
+
+#[test]
+fn rate_limit_errors_record_retry_after() {
+    let mut state = SubagentState::default();
+    let message =
+        "Rate limit reached for gpt-5.1 on tokens per min (TPM). Please try again in 11.054s.";
+    assert_eq!(
+        classify_error(&mut state, message, None),
+        Some("rate limited (retry after 11054ms)".to_string())
+    );
+    assert_eq!(state.error_kind, Some(SubagentErrorKind::RateLimited));
+    assert_eq!(state.retry_after, Some(Duration::from_millis(11_054)));
+
+    let mut state = SubagentState::default();
+    let info = CodexErrorInfo::ResponseTooManyFailedAttempts {
+        http_status_code: Some(429),
+    };
+    assert_eq!(
+        classify_error(&mut state, "exceeded retry limit", Some(&info)),
+        Some("rate limited".to_string())
+    );
+    assert_eq!(state.retry_after, None);
+
+    let mut state = SubagentState::default();
+    assert_eq!(classify_error(&mut state, "boom", None), None);
+    assert_eq!(state.error_kind, None);
+    assert_eq!(
+        parse_retry_after("try again in 250ms"),
+        Some(Duration::from_millis(250))
+    );
+}
+
+#[test]
+fn result_format_extracts_code_blocks_and_json() {
+    let text = "Here you go:\n```rust\nfn main() {}\n```\nand ```two```";
+    assert_eq!(SubagentResultFormat::Raw.apply(text), Ok(text.to_string()));
+    assert_eq!(
+        SubagentResultFormat::FirstCodeBlock.apply(text),
+        Ok("fn main() {}".to_string())
+    );
+    assert_eq!(
+        SubagentResultFormat::FirstCodeBlock.apply("no fences"),
+        Err("output has no fenced code block".to_string())
+    );
+
+    let text = "Result {not json} then:\n```json\n{ \"n\": [1, 2], \"ok\": true }\n```";
+    assert_eq!(
+        SubagentResultFormat::Json.apply(text),
+        Ok("{\"n\":[1,2],\"ok\":true}".to_string())
+    );
+    assert_eq!(
+        SubagentResultFormat::Json.apply("[1, 2]"),
+        Err("output has no JSON object".to_string())
+    );
+    assert_eq!(
+        SubagentResultFormat::from_str(" First_Code_Block "),
+        Some(SubagentResultFormat::FirstCodeBlock)
+    );
+    assert_eq!(SubagentResultFormat::from_str("yaml"), None);
+}
+
+#[test]
+fn error_kinds_have_stable_names() {
+    let kinds = [
+        SubagentErrorKind::Panic,
+        SubagentErrorKind::RateLimited,
+        SubagentErrorKind::Spawn,
+        SubagentErrorKind::Stream,
+        SubagentErrorKind::Model,
+        SubagentErrorKind::Timeout,
+        SubagentErrorKind::IdleTimeout,
+        SubagentErrorKind::SkillResolution,
+        SubagentErrorKind::ResumeHistory,
+    ];
+    assert_eq!(
+        kinds.map(SubagentErrorKind::as_str),
+        [
+            "panic",
+            "rate_limited",
+            "spawn",
+            "stream",
+            "model",
+            "timeout",
+            "idle_timeout",
+            "skill_resolution",
+            "resume_history",
+        ]
+    );
+}
+
+#[test]
+fn parse_checklist_without_block_is_empty() {
+    assert_eq!(parse_checklist("- [ ] loose item"), Vec::new());
+}
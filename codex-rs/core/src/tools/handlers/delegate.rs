@@ -1,22 +1,34 @@
-use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use codex_protocol::user_input::UserInput;
 use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 
+use crate::codex::Session;
+use crate::codex::TurnContext;
 use crate::codex_delegate::run_codex_conversation_one_shot;
+use crate::config::Config;
 use crate::features::Feature;
 use crate::function_tool::FunctionCallError;
 use crate::protocol::EventMsg;
 use crate::protocol::SandboxPolicy;
 use crate::protocol::SubAgentSource;
+use crate::skills::is_skill_pattern;
+use crate::skills::resolve_requested_skills;
+use crate::subagents::SubagentResultFormat;
+use crate::subagents::extra_instructions_section;
+use crate::subagents::parse_reasoning_effort;
+use crate::subagents::sanitize_extra_instructions;
 use crate::tools::context::ToolInvocation;
 use crate::tools::context::ToolOutput;
 use crate::tools::context::ToolPayload;
+use crate::tools::handlers::subagent::result_format_from_args;
 use crate::tools::registry::ToolHandler;
 use crate::tools::registry::ToolKind;
 
@@ -24,6 +36,7 @@ pub struct DelegateHandler;
 
 const DEFAULT_SUBAGENT_LABEL: &str = "delegate";
 const MAX_LABEL_LEN: usize = 48;
+const MIN_PROGRESS_INTERVAL_MS: u64 = 250;
 
 #[derive(Debug, Deserialize)]
 struct DelegateArgs {
@@ -43,9 +56,82 @@ struct DelegateArgs {
     /// Optional deadline for the subagent run.
     #[serde(default)]
     timeout_ms: Option<u64>,
+
+    /// When set, publish the accumulated partial output at this interval so the
+    /// parent session can read it (keyed by label) while the delegate runs.
+    #[serde(default)]
+    progress_interval_ms: Option<u64>,
+
+    /// When true, forward the delegate's message text to the parent session
+    /// as background events, one per completed line, while it runs.
+    #[serde(default)]
+    stream: bool,
+
+    /// Task-specific guidance appended after the delegate's instructions.
+    #[serde(default)]
+    extra_instructions: Option<String>,
+
+    /// JSON schema the final output must match. When set, the result is a
+    /// JSON object carrying the parsed output and whether it matched.
+    #[serde(default)]
+    response_schema: Option<serde_json::Value>,
+
+    /// When true, keep the parent's user instructions and project docs
+    /// (AGENTS.md) instead of starting from a lightweight session.
+    #[serde(default)]
+    inherit_context: bool,
+
+    /// Post-processing for the output: "raw" (default), "first_code_block",
+    /// or "json".
+    #[serde(default)]
+    format: Option<String>,
+
+    /// Reasoning effort override ("low", "high", ...); unset inherits the
+    /// parent's.
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+}
+
+/// Result of a `delegate` call that declared a `response_schema`.
+#[derive(Debug, Serialize)]
+struct StructuredDelegateOutput {
+    /// The parsed JSON output, or the raw text when it never matched.
+    output: serde_json::Value,
+    schema_valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_error: Option<String>,
 }
 
-fn sanitize_subagent_label(label: &str) -> String {
+/// Splits streamed message deltas into completed, non-empty lines.
+#[derive(Default)]
+struct DeltaLines {
+    pending: String,
+}
+
+impl DeltaLines {
+    fn push(&mut self, delta: &str) -> Vec<String> {
+        self.pending.push_str(delta);
+        let Some(end) = self.pending.rfind('\n') else {
+            return Vec::new();
+        };
+        let rest = self.pending.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.pending, rest);
+        complete
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.pending);
+        let rest = rest.trim_end();
+        (!rest.trim().is_empty()).then(|| rest.to_string())
+    }
+}
+
+pub(super) fn sanitize_subagent_label(label: &str) -> String {
     let trimmed = label.trim();
     if trimmed.is_empty() {
         return DEFAULT_SUBAGENT_LABEL.to_string();
@@ -90,11 +176,165 @@ Requirements:\n\
     )
 }
 
-struct CancelOnDrop(CancellationToken);
+fn response_schema_section(schema: &serde_json::Value) -> String {
+    let schema = serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string());
+    format!(
+        "\nStructured output:\n\
+- Respond with a single JSON value that matches this JSON schema, with no code fences or surrounding text:\n\
+{schema}\n"
+    )
+}
+
+/// Follow-up prompt for the single retry after the output did not match the schema.
+fn schema_correction_prompt(previous: &str, problem: &str) -> String {
+    format!(
+        "Your previous answer did not match the required JSON schema: {problem}.\n\
+Previous answer:\n{previous}\n\n\
+Answer again with only a JSON value that matches the schema."
+    )
+}
+
+/// Parses the delegate's final text as JSON (tolerating a surrounding code
+/// fence) and checks it against `schema`.
+fn check_response(
+    schema: &serde_json::Value,
+    text: &str,
+    max_output_chars: usize,
+) -> Result<serde_json::Value, String> {
+    if text.len() > max_output_chars {
+        return Err(format!("output is longer than {max_output_chars} bytes"));
+    }
+    let trimmed = text.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    let value: serde_json::Value = serde_json::from_str(unfenced.trim())
+        .map_err(|e| format!("output is not valid JSON ({e})"))?;
+    check_response_value("$", schema, &value)?;
+    Ok(value)
+}
+
+/// Checks `value` against the subset of JSON Schema that `response_schema`
+/// honors: `type`, `enum`, `const`, `properties`, `required`,
+/// `additionalProperties: false`, and `items`. Other keywords are ignored.
+fn check_response_value(
+    path: &str,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+    let types: Vec<&str> = match schema.get("type") {
+        Some(serde_json::Value::String(ty)) => vec![ty.as_str()],
+        Some(serde_json::Value::Array(types)) => {
+            types.iter().filter_map(serde_json::Value::as_str).collect()
+        }
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|ty| json_type_matches(ty, value)) {
+        return Err(format!("`{path}` must be of type {}", types.join(" or ")));
+    }
+    if let Some(serde_json::Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        let allowed = allowed
+            .iter()
+            .map(serde_json::Value::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!("`{path}` must be one of: {allowed}"));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        return Err(format!("`{path}` must be {expected}"));
+    }
+    match value {
+        serde_json::Value::Object(map) => {
+            let required = schema
+                .get("required")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(serde_json::Value::as_str);
+            for name in required {
+                if !map.contains_key(name) {
+                    return Err(format!("`{path}.{name}` is required"));
+                }
+            }
+            let properties = schema
+                .get("properties")
+                .and_then(serde_json::Value::as_object);
+            let closed =
+                schema.get("additionalProperties") == Some(&serde_json::Value::Bool(false));
+            for (name, item) in map {
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(item_schema) => {
+                        check_response_value(&format!("{path}.{name}"), item_schema, item)?;
+                    }
+                    None if closed => {
+                        return Err(format!("`{path}.{name}` is not allowed by the schema"));
+                    }
+                    None => {}
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check_response_value(&format!("{path}[{index}]"), item_schema, item)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn json_type_matches(ty: &str, value: &serde_json::Value) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// A delegate's concurrency slot. Dropping it (on return, error, or when the
+/// parent turn drops the handler future) cancels the delegate's session
+/// before the permit is released, so the next delegate never overlaps a run
+/// that has not been told to stop.
+struct DelegateSlot {
+    cancel_token: CancellationToken,
+    _permit: OwnedSemaphorePermit,
+}
 
-impl Drop for CancelOnDrop {
+impl DelegateSlot {
+    async fn acquire(
+        limiter: Arc<Semaphore>,
+        cancel_token: CancellationToken,
+    ) -> Result<Self, FunctionCallError> {
+        let permit = limiter.acquire_owned().await.map_err(|_| {
+            FunctionCallError::Fatal("delegate concurrency limiter closed unexpectedly".to_string())
+        })?;
+        Ok(Self {
+            cancel_token,
+            _permit: permit,
+        })
+    }
+}
+
+impl Drop for DelegateSlot {
     fn drop(&mut self) {
-        self.0.cancel();
+        // Runs before the fields are dropped, so the permit is released last.
+        self.cancel_token.cancel();
     }
 }
 
@@ -108,6 +348,7 @@ impl ToolHandler for DelegateHandler {
         let ToolInvocation {
             session,
             turn,
+            call_id,
             payload,
             ..
         } = invocation;
@@ -128,18 +369,37 @@ impl ToolHandler for DelegateHandler {
                 "delegate.prompt must be non-empty".to_string(),
             ));
         }
+        let response_schema = args.response_schema.filter(|schema| !schema.is_null());
+        if response_schema
+            .as_ref()
+            .is_some_and(|schema| !schema.is_object())
+        {
+            return Err(FunctionCallError::RespondToModel(
+                "delegate.response_schema must be a JSON schema object".to_string(),
+            ));
+        }
+        let format = result_format_from_args(args.format.as_deref())?;
+        let reasoning_effort = parse_reasoning_effort(args.reasoning_effort.as_deref())
+            .map_err(FunctionCallError::RespondToModel)?;
+        if response_schema.is_some() && format != SubagentResultFormat::Raw {
+            return Err(FunctionCallError::RespondToModel(
+                "delegate.format cannot be combined with response_schema".to_string(),
+            ));
+        }
 
         let label =
             sanitize_subagent_label(args.label.as_deref().unwrap_or(DEFAULT_SUBAGENT_LABEL));
+        let progress_interval = args
+            .progress_interval_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| Duration::from_millis(ms.max(MIN_PROGRESS_INTERVAL_MS)));
 
-        let _permit = crate::subagents::global_subagent_limiter()
-            .acquire_owned()
-            .await
-            .map_err(|_| {
-                FunctionCallError::Fatal(
-                    "delegate concurrency limiter closed unexpectedly".to_string(),
-                )
-            })?;
+        let cancel_token = CancellationToken::new();
+        let _slot = DelegateSlot::acquire(
+            crate::subagents::global_delegate_limiter(),
+            cancel_token.clone(),
+        )
+        .await?;
 
         let timeout_duration = args
             .timeout_ms
@@ -155,10 +415,26 @@ impl ToolHandler for DelegateHandler {
         sub_agent_config.features.disable(Feature::GhostCommit);
 
         // By default we keep subagents lightweight: skip project docs and the parent AGENTS.md.
-        sub_agent_config.user_instructions = None;
-        sub_agent_config.developer_instructions =
-            Some(delegate_base_instructions(&label, args.allow_tools));
-        sub_agent_config.project_doc_max_bytes = 0;
+        if !args.inherit_context {
+            sub_agent_config.user_instructions = None;
+            sub_agent_config.project_doc_max_bytes = 0;
+        }
+        let mut instructions = delegate_base_instructions(&label, args.allow_tools);
+        if let Some(schema) = response_schema.as_ref() {
+            instructions.push_str(&response_schema_section(schema));
+        }
+        if let Some(extra) = args
+            .extra_instructions
+            .as_deref()
+            .and_then(sanitize_extra_instructions)
+        {
+            instructions.push_str(&extra_instructions_section(&extra));
+        }
+        sub_agent_config.developer_instructions = Some(instructions);
+
+        if let Some(effort) = reasoning_effort {
+            sub_agent_config.model_reasoning_effort = Some(effort);
+        }
 
         // Default to a safe sandbox even when tools are enabled (if the user opts in).
         sub_agent_config.sandbox_policy = SandboxPolicy::new_read_only_policy();
@@ -185,100 +461,248 @@ impl ToolHandler for DelegateHandler {
 
         if !args.skills.is_empty() {
             let outcome = session.services.skills_manager.skills_for_cwd(&turn.cwd);
-            let mut missing: Vec<String> = Vec::new();
-            let mut seen: HashSet<String> = HashSet::new();
-
-            for name in args.skills {
-                if !seen.insert(name.clone()) {
-                    continue;
+            let resolved = resolve_requested_skills(&args.skills, &outcome.skills);
+
+            if !resolved.unmatched.is_empty() {
+                let (patterns, missing): (Vec<String>, Vec<String>) = resolved
+                    .unmatched
+                    .into_iter()
+                    .partition(|entry| is_skill_pattern(entry));
+                let mut problems: Vec<String> = Vec::new();
+                if !missing.is_empty() {
+                    problems.push(format!("unknown skills requested: {}", missing.join(", ")));
                 }
-                if let Some(skill) = outcome.skills.iter().find(|s| s.name == name) {
-                    inputs.push(UserInput::Skill {
-                        name: skill.name.clone(),
-                        path: skill.path.clone(),
-                    });
-                } else {
-                    missing.push(name);
+                if !patterns.is_empty() {
+                    problems.push(format!(
+                        "no skills match pattern {}",
+                        patterns
+                            .iter()
+                            .map(|pattern| format!("`{pattern}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
                 }
-            }
-
-            if !missing.is_empty() {
                 return Err(FunctionCallError::RespondToModel(format!(
-                    "unknown skills requested: {}; check the available skills list",
-                    missing.join(", ")
+                    "{}; check the available skills list",
+                    problems.join("; ")
                 )));
             }
+
+            for skill in resolved.matched {
+                inputs.push(UserInput::Skill {
+                    name: skill.name.clone(),
+                    path: skill.path.clone(),
+                });
+            }
         }
 
-        let cancel_token = CancellationToken::new();
-        let _cancel_on_drop = CancelOnDrop(cancel_token.clone());
+        let run = DelegateRun {
+            session: &session,
+            turn: &turn,
+            call_id: &call_id,
+            label: &label,
+            cancel_token: &cancel_token,
+            progress_interval,
+            stream: args.stream,
+            max_output_chars,
+        };
+        let output = timeout(timeout_duration, async {
+            let text = run.run(sub_agent_config.clone(), inputs.clone()).await?;
+            let Some(schema) = response_schema.as_ref() else {
+                return Ok(text);
+            };
+            let (text, checked) = match check_response(schema, &text, max_output_chars) {
+                Ok(value) => (text, Ok(value)),
+                Err(problem) => {
+                    // One corrective attempt; the one-shot session is gone, so
+                    // replay the original input with the problem appended.
+                    let mut retry_inputs = inputs;
+                    retry_inputs.push(UserInput::Text {
+                        text: schema_correction_prompt(
+                            codex_utils_string::take_bytes_at_char_boundary(
+                                &text,
+                                max_output_chars,
+                            ),
+                            &problem,
+                        ),
+                    });
+                    let text = run.run(sub_agent_config, retry_inputs).await?;
+                    let checked = check_response(schema, &text, max_output_chars);
+                    (text, checked)
+                }
+            };
+            let structured = match checked {
+                Ok(output) => StructuredDelegateOutput {
+                    output,
+                    schema_valid: true,
+                    schema_error: None,
+                },
+                Err(problem) => StructuredDelegateOutput {
+                    output: serde_json::Value::String(
+                        codex_utils_string::take_bytes_at_char_boundary(&text, max_output_chars)
+                            .to_string(),
+                    ),
+                    schema_valid: false,
+                    schema_error: Some(problem),
+                },
+            };
+            Ok::<_, FunctionCallError>(
+                serde_json::to_string(&structured)
+                    .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+            )
+        })
+        .await;
+        if progress_interval.is_some() {
+            session
+                .services
+                .subagent_manager
+                .clear_delegate_progress(&call_id)
+                .await;
+        }
+        let output = output.map_err(|_| {
+            cancel_token.cancel();
+            FunctionCallError::RespondToModel(format!(
+                "delegate timed out after {}ms",
+                timeout_duration.as_millis()
+            ))
+        })??;
 
+        // Structured results are already bounded; truncating them would break the JSON.
+        let content = if response_schema.is_some() {
+            output
+        } else {
+            // Output without the requested shape is returned as-is.
+            let output = format.apply(&output).unwrap_or(output);
+            codex_utils_string::take_bytes_at_char_boundary(&output, max_output_chars).to_string()
+        };
+        Ok(ToolOutput::Function {
+            content,
+            content_items: None,
+            success: Some(true),
+        })
+    }
+}
+
+/// Everything one delegate run needs besides its config and input, so the
+/// schema retry can run the delegate a second time.
+struct DelegateRun<'a> {
+    session: &'a Arc<Session>,
+    turn: &'a Arc<TurnContext>,
+    /// Key under which partial output is published for `delegate_progress`.
+    call_id: &'a str,
+    label: &'a str,
+    cancel_token: &'a CancellationToken,
+    progress_interval: Option<Duration>,
+    stream: bool,
+    max_output_chars: usize,
+}
+
+impl DelegateRun<'_> {
+    /// Runs the delegate to completion and returns its final message.
+    async fn run(
+        &self,
+        config: Config,
+        inputs: Vec<UserInput>,
+    ) -> Result<String, FunctionCallError> {
+        let Self {
+            session,
+            turn,
+            call_id,
+            label,
+            cancel_token,
+            progress_interval,
+            stream,
+            max_output_chars,
+        } = *self;
         let subagent = run_codex_conversation_one_shot(
-            sub_agent_config,
+            config,
             Arc::clone(&session.services.auth_manager),
             Arc::clone(&session.services.models_manager),
             inputs,
-            Arc::clone(&session),
-            Arc::clone(&turn),
+            Arc::clone(session),
+            Arc::clone(turn),
             cancel_token.clone(),
             None,
-            SubAgentSource::Other(label),
+            SubAgentSource::Other(label.to_string()),
         )
         .await
         .map_err(|e| FunctionCallError::RespondToModel(format!("delegate failed to start: {e}")))?;
 
-        let output = timeout(timeout_duration, async {
-            let mut last_error: Option<String> = None;
-            loop {
-                let event = subagent.next_event().await.map_err(|e| {
-                    FunctionCallError::RespondToModel(format!(
-                        "delegate subagent failed while waiting for output: {e}"
-                    ))
-                })?;
-                match event.msg {
-                    EventMsg::Error(ev) => {
-                        last_error = Some(ev.message);
+        let subagent_manager = &session.services.subagent_manager;
+        let mut last_error: Option<String> = None;
+        let mut partial = String::new();
+        let mut progress_ticker = progress_interval.map(tokio::time::interval);
+        let mut stream_lines = stream.then(DeltaLines::default);
+        loop {
+            let event = match progress_ticker.as_mut() {
+                Some(ticker) => tokio::select! {
+                    _ = ticker.tick() => {
+                        subagent_manager
+                            .record_delegate_progress(call_id, label, partial.clone())
+                            .await;
+                        continue;
+                    }
+                    event = subagent.next_event() => event,
+                },
+                None => subagent.next_event().await,
+            }
+            .map_err(|e| {
+                FunctionCallError::RespondToModel(format!(
+                    "delegate subagent failed while waiting for output: {e}"
+                ))
+            })?;
+            match event.msg {
+                // The delegate conversation only forwards item-based deltas,
+                // not the legacy `AgentMessageDelta` events.
+                EventMsg::AgentMessageContentDelta(ev) => {
+                    if progress_ticker.is_some() && partial.len() < max_output_chars {
+                        partial.push_str(&ev.delta);
+                        partial = codex_utils_string::take_bytes_at_char_boundary(
+                            &partial,
+                            max_output_chars,
+                        )
+                        .to_string();
                     }
-                    EventMsg::StreamError(ev) => {
-                        last_error = Some(ev.message);
+                    if let Some(lines) = stream_lines.as_mut() {
+                        for line in lines.push(&ev.delta) {
+                            session
+                                .notify_background_event(turn, format!("{label}: {line}"))
+                                .await;
+                        }
                     }
-                    EventMsg::TaskComplete(task_complete) => {
-                        let Some(text) = task_complete.last_agent_message else {
-                            if let Some(err) = last_error {
-                                return Err(FunctionCallError::RespondToModel(format!(
-                                    "delegate subagent failed: {err}"
-                                )));
-                            }
-                            return Err(FunctionCallError::RespondToModel(
-                                "delegate subagent produced no final output".to_string(),
-                            ));
-                        };
-                        return Ok(text);
+                }
+                EventMsg::Error(ev) => {
+                    last_error = Some(ev.message);
+                }
+                EventMsg::StreamError(ev) => {
+                    last_error = Some(ev.message);
+                }
+                EventMsg::TaskComplete(task_complete) => {
+                    if let Some(line) = stream_lines.as_mut().and_then(DeltaLines::finish) {
+                        session
+                            .notify_background_event(turn, format!("{label}: {line}"))
+                            .await;
                     }
-                    EventMsg::TurnAborted(_) => {
+                    let Some(text) = task_complete.last_agent_message else {
+                        if let Some(err) = last_error {
+                            return Err(FunctionCallError::RespondToModel(format!(
+                                "delegate subagent failed: {err}"
+                            )));
+                        }
                         return Err(FunctionCallError::RespondToModel(
-                            "delegate subagent was aborted".to_string(),
+                            "delegate subagent produced no final output".to_string(),
                         ));
-                    }
-                    _ => {}
+                    };
+                    return Ok(text);
                 }
+                EventMsg::TurnAborted(_) => {
+                    return Err(FunctionCallError::RespondToModel(
+                        "delegate subagent was aborted".to_string(),
+                    ));
+                }
+                _ => {}
             }
-        })
-        .await
-        .map_err(|_| {
-            cancel_token.cancel();
-            FunctionCallError::RespondToModel(format!(
-                "delegate timed out after {}ms",
-                timeout_duration.as_millis()
-            ))
-        })??;
-
-        Ok(ToolOutput::Function {
-            content: codex_utils_string::take_bytes_at_char_boundary(&output, max_output_chars)
-                .to_string(),
-            content_items: None,
-            success: Some(true),
-        })
+        }
     }
 }
 
@@ -287,6 +711,50 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    #[tokio::test]
+    async fn cancelled_delegate_releases_its_slot_after_cancelling() {
+        let limiter = Arc::new(Semaphore::new(1));
+        let cancel_token = CancellationToken::new();
+        let run = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            let cancel_token = cancel_token.clone();
+            async move {
+                let _slot = DelegateSlot::acquire(limiter, cancel_token)
+                    .await
+                    .expect("acquire slot");
+                std::future::pending::<()>().await;
+            }
+        });
+        while limiter.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The next delegate waits for the slot, then checks that the first
+        // run was told to stop before the slot was handed over.
+        let next = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            let cancel_token = cancel_token.clone();
+            async move {
+                let _slot = DelegateSlot::acquire(limiter, CancellationToken::new())
+                    .await
+                    .expect("acquire slot");
+                cancel_token.is_cancelled()
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!next.is_finished());
+
+        // Cancelling the parent turn drops the mid-run handler future.
+        run.abort();
+        assert!(run.await.expect_err("aborted").is_cancelled());
+        let cancelled_first = timeout(Duration::from_secs(5), next)
+            .await
+            .expect("slot released")
+            .expect("next delegate");
+        assert!(cancelled_first);
+        assert_eq!(limiter.available_permits(), 1);
+    }
+
     #[test]
     fn sanitize_label_defaults_and_truncates() {
         assert_eq!(sanitize_subagent_label(""), DEFAULT_SUBAGENT_LABEL);
@@ -299,4 +767,55 @@ mod tests {
             "a".repeat(MAX_LABEL_LEN)
         );
     }
+
+    #[test]
+    fn delta_lines_emit_completed_lines() {
+        let mut lines = DeltaLines::default();
+        assert_eq!(lines.push("Look"), Vec::<String>::new());
+        assert_eq!(lines.push("ing at src\n\nFound 3 "), vec!["Looking at src"]);
+        assert_eq!(lines.push("files\nDone"), vec!["Found 3 files"]);
+        assert_eq!(lines.finish(), Some("Done".to_string()));
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn check_response_validates_against_the_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "verdict": { "type": "string", "enum": ["pass", "fail"] },
+                "files": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["verdict"],
+            "additionalProperties": false,
+        });
+        assert_eq!(
+            check_response(&schema, "```json\n{\"verdict\": \"pass\"}\n```", 1024),
+            Ok(serde_json::json!({ "verdict": "pass" }))
+        );
+        assert_eq!(
+            check_response(&schema, "{\"files\": []}", 1024),
+            Err("`$.verdict` is required".to_string())
+        );
+        assert_eq!(
+            check_response(&schema, "{\"verdict\": \"maybe\"}", 1024),
+            Err("`$.verdict` must be one of: \"pass\", \"fail\"".to_string())
+        );
+        assert_eq!(
+            check_response(&schema, "{\"verdict\": \"fail\", \"files\": [1]}", 1024),
+            Err("`$.files[0]` must be of type string".to_string())
+        );
+        assert_eq!(
+            check_response(&schema, "{\"verdict\": \"fail\", \"extra\": 1}", 1024),
+            Err("`$.extra` is not allowed by the schema".to_string())
+        );
+        assert_eq!(
+            check_response(&schema, "{\"verdict\": \"pass\"}", 8),
+            Err("output is longer than 8 bytes".to_string())
+        );
+        assert!(
+            check_response(&schema, "looks good", 1024)
+                .is_err_and(|problem| problem.starts_with("output is not valid JSON"))
+        );
+    }
 }
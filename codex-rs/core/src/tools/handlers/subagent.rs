@@ -1,21 +1,55 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use async_trait::async_trait;
+use codex_protocol::protocol::FileChange;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
 
+use super::delegate::sanitize_subagent_label;
+use crate::config::types::MAX_SUBAGENTS_MAX_EVENTS;
+use crate::config::types::MAX_SUBAGENTS_MAX_OUTPUT_CHARS;
+use crate::config::types::MIN_SUBAGENTS_MAX_EVENTS;
+use crate::config::types::MIN_SUBAGENTS_MAX_OUTPUT_CHARS;
+use crate::custom_agents::AgentToolsPolicy;
+use crate::custom_agents::CustomAgent;
+use crate::custom_agents::discover_agents;
+use crate::custom_agents::find_agent;
+use crate::custom_agents::sanitize_agent_name;
+use crate::custom_agents::validate_agent_content;
+use crate::custom_agents::validate_agent_path;
 use crate::function_tool::FunctionCallError;
+use crate::subagents::MAX_TRACE_ID_LEN;
+use crate::subagents::ProposedPatch;
+use crate::subagents::SetPriorityOutcome;
+use crate::subagents::SubagentApprovalCounts;
+use crate::subagents::SubagentCitationStyle;
 use crate::subagents::SubagentMode;
+use crate::subagents::SubagentOutputFormat;
+use crate::subagents::SubagentPollResponse;
+use crate::subagents::SubagentResultFormat;
+use crate::subagents::SubagentRunSummary;
 use crate::subagents::SubagentSpawnRequest;
+use crate::subagents::SubagentSpawnResponse;
+use crate::subagents::SubagentSpawnedBy;
 use crate::subagents::SubagentStatus;
+use crate::subagents::parse_reasoning_effort;
+use crate::subagents::parse_status_filter;
+use crate::subagents::sanitize_extra_instructions;
 use crate::tools::context::ToolInvocation;
 use crate::tools::context::ToolOutput;
 use crate::tools::context::ToolPayload;
 use crate::tools::registry::ToolHandler;
 use crate::tools::registry::ToolKind;
+use crate::tools::spec::subagent_tool_parameters;
 
 pub struct SubagentHandler;
 
 const DEFAULT_SUBAGENT_LABEL: &str = "subagent";
 const MAX_LABEL_LEN: usize = 48;
+const DEFAULT_LOGS_MAX_BYTES: usize = 16 * 1024;
+const MAX_LOGS_MAX_BYTES: usize = 64 * 1024;
 
 #[derive(Debug, Deserialize)]
 struct SubagentSpawnArgs {
@@ -24,42 +58,232 @@ struct SubagentSpawnArgs {
     prompt: String,
     #[serde(default)]
     label: Option<String>,
-    /// Built-in profile name ("general" or "explore").
+    /// Built-in profile name ("general", "explore", or "review").
     #[serde(default)]
     mode: Option<String>,
     #[serde(default)]
     skills: Vec<String>,
     #[serde(default)]
     timeout_ms: Option<u64>,
+    /// Absolute stop time in Unix epoch milliseconds.
+    #[serde(default)]
+    deadline_unix_ms: Option<u64>,
+    /// Expected final answer format ("text" or "json").
+    #[serde(default)]
+    output_format: Option<String>,
+    /// Citation guidance ("none", "inline-urls" or "numbered").
+    #[serde(default)]
+    citation_style: Option<String>,
+    #[serde(default)]
+    allow_sibling_abort: bool,
+    #[serde(default)]
+    report_confidence: bool,
+    #[serde(default)]
+    propose_only: bool,
+    #[serde(default = "default_record_rollout")]
+    record_rollout: bool,
+    #[serde(default)]
+    max_per_label: Option<usize>,
+    /// Telemetry category sent instead of the label (e.g. "security-scan").
+    #[serde(default)]
+    source_category: Option<String>,
+    #[serde(default)]
+    lazy_output: bool,
+    #[serde(default)]
+    ephemeral: bool,
+    #[serde(default)]
+    await_skills_ms: u64,
+    #[serde(default)]
+    mutex_group: Option<String>,
+    /// Correlation id forwarded as a request header by the subagent.
+    #[serde(default)]
+    trace_id: Option<String>,
+    /// Working directory for the agent, inside the repository.
+    #[serde(default)]
+    cwd: Option<String>,
+    /// Agent ids that must complete before this agent starts.
+    #[serde(default)]
+    depends_on: Vec<String>,
+    /// Higher values acquire a concurrency slot first.
+    #[serde(default)]
+    priority: i32,
+    /// Per-agent overrides of `[subagents].max_events` / `max_output_chars`.
+    #[serde(default)]
+    max_events: Option<usize>,
+    #[serde(default)]
+    max_output_chars: Option<usize>,
+    /// Task-specific guidance appended to the agent's instructions.
+    #[serde(default)]
+    extra_instructions: Option<String>,
+    /// Validate and report what would be spawned without starting anything.
+    #[serde(default)]
+    dry_run: bool,
+    /// Rollout file seeded as the agent's initial history. `subagent_resume`,
+    /// the deprecated alias of this tool, passes it as `rollout_path`.
+    #[serde(default, alias = "rollout_path")]
+    resume_rollout_path: Option<String>,
+    /// Reasoning effort override ("low", "high", ...); unset inherits.
+    #[serde(default)]
+    reasoning_effort: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-struct SubagentResumeArgs {
-    #[serde(default)]
-    agent_id: Option<String>,
-    rollout_path: String,
+struct SubagentSpawnNamedArgs {
+    name: String,
     prompt: String,
     #[serde(default)]
-    label: Option<String>,
-    #[serde(default)]
-    mode: Option<String>,
+    agent_id: Option<String>,
     #[serde(default)]
     skills: Vec<String>,
     #[serde(default)]
     timeout_ms: Option<u64>,
 }
 
+#[derive(Debug, Deserialize)]
+struct SubagentValidateBatchArgs {
+    /// Spawn arguments, parsed one by one so a malformed item is reported in
+    /// place instead of failing the whole batch.
+    items: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentSpawnBatchArgs {
+    /// `subagent_spawn` arguments, checked one by one as they are spawned.
+    items: Vec<serde_json::Value>,
+}
+
 #[derive(Debug, Deserialize)]
 struct SubagentIdArgs {
     agent_id: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct SubagentPruneArgs {
+    /// Keep at most this many finished agents (newest first).
+    #[serde(default)]
+    keep: Option<usize>,
+    /// Remove finished agents last updated longer ago than this.
+    #[serde(default)]
+    older_than_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentResultArgs {
+    agent_id: String,
+    #[serde(default)]
+    materialize: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentResultChunkArgs {
+    agent_id: String,
+    #[serde(default)]
+    offset: usize,
+    /// Defaults to (and is capped at) `[subagents].max_output_chars`.
+    #[serde(default)]
+    length: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentCancelArgs {
+    agent_id: String,
+    /// Reported as the agent's `abort_reason`; defaults to "cancelled".
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentLogsArgs {
+    agent_id: String,
+    #[serde(default)]
+    offset: usize,
+    /// Defaults to `DEFAULT_LOGS_MAX_BYTES`, capped at `MAX_LOGS_MAX_BYTES`.
+    #[serde(default)]
+    max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentChdirArgs {
+    agent_id: String,
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentWaitAnyArgs {
+    agent_ids: Vec<String>,
+    #[serde(default)]
+    await_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentListArgs {
+    #[serde(default)]
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentSendArgs {
+    agent_id: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentReprioritizeArgs {
+    agent_id: String,
+    priority: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DelegateProgressArgs {
+    label: String,
+    /// Picks one of several running delegates that share `label`.
+    #[serde(default)]
+    call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentResultsRankedArgs {
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubagentAuditArgs {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateAgentArgs {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    path: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct SubagentPollArgs {
     agent_id: String,
     /// Optional time to wait for status changes (milliseconds).
     #[serde(default)]
     await_ms: Option<u64>,
+    /// Return `recent_events` as `{kind, message, ts, at_ms, seq}` objects.
+    #[serde(default)]
+    structured_events: bool,
+    /// Only return events whose `seq` is at least this (a previous poll's
+    /// `next_event_index`).
+    #[serde(default)]
+    since_event_index: Option<u64>,
+    /// Status that ends the `await_ms` wait early (e.g. "running").
+    #[serde(default)]
+    until_status: Option<String>,
+    /// Post-processing for `final_output`: "raw", "first_code_block", "json".
+    #[serde(default)]
+    format: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -68,6 +292,50 @@ struct SpawnResponse {
     status: String,
     label: String,
     mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deduplicated: Option<bool>,
+}
+
+impl From<SubagentSpawnResponse> for SpawnResponse {
+    fn from(resp: SubagentSpawnResponse) -> Self {
+        Self {
+            agent_id: resp.agent_id,
+            status: resp.status.as_str().to_string(),
+            label: resp.label,
+            mode: resp.mode.as_str().to_string(),
+            deduplicated: resp.deduplicated.then_some(true),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnDryRunResponse {
+    dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent_id: Option<String>,
+    label: String,
+    mode: String,
+    skills: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnBatchResponse {
+    /// True when every item was spawned.
+    ok: bool,
+    /// Spawned agents, in item order.
+    agents: Vec<SpawnResponse>,
+    /// The first invalid item, or the item whose spawn failed; later items
+    /// were not spawned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<SpawnBatchErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnBatchErrorResponse {
+    index: usize,
+    error: String,
+    /// Other items that were not spawned because of this one.
+    skipped: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -80,190 +348,1560 @@ struct PollResponse {
     rollout_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     final_output: Option<String>,
-    recent_events: Vec<String>,
+    /// Why `format` could not be applied; `final_output` is then raw.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial_output: Option<String>,
+    recent_events: RecentEvents,
+    next_event_index: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checklist: Vec<ChecklistItemResponse>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    proposed_patches: Vec<ProposedPatchResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttft_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_ms: Option<u64>,
+    elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    running_ms: Option<u64>,
+    tokens: TokensResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    approvals: Option<ApprovalsResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sandbox: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    approval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spawned_by: Option<SpawnedByResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    abort_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_next_poll_ms: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
-struct ListResponse {
-    agents: Vec<PollResponse>,
+struct SpawnedByResponse {
+    turn_id: String,
+    call_id: String,
 }
 
-fn sanitize_label(label: &str) -> String {
-    let trimmed = label.trim();
-    if trimmed.is_empty() {
-        return DEFAULT_SUBAGENT_LABEL.to_string();
-    }
+#[derive(Debug, Serialize)]
+struct TokensResponse {
+    input: u64,
+    output: u64,
+    total: u64,
+}
 
-    let mut out = String::new();
-    for ch in trimmed.chars() {
-        if out.len() >= MAX_LABEL_LEN {
-            break;
-        }
-        match ch {
-            'a'..='z' | '0'..='9' | '-' | '_' | '.' => out.push(ch),
-            'A'..='Z' => out.push(ch.to_ascii_lowercase()),
-            ' ' | '/' | ':' => out.push('-'),
-            _ => {}
-        }
-    }
+#[derive(Debug, Serialize)]
+struct ApprovalsResponse {
+    exec: u64,
+    patch: u64,
+    aborted: u64,
+}
 
-    if out.is_empty() {
-        DEFAULT_SUBAGENT_LABEL.to_string()
-    } else {
-        out
-    }
+#[derive(Debug, Serialize)]
+struct ProposedPatchResponse {
+    call_id: String,
+    files: Vec<ProposedFileResponse>,
 }
 
-fn status_str(status: SubagentStatus) -> &'static str {
-    match status {
-        SubagentStatus::Queued => "queued",
-        SubagentStatus::Running => "running",
-        SubagentStatus::Complete => "complete",
-        SubagentStatus::Aborted => "aborted",
-        SubagentStatus::Error => "error",
-    }
+#[derive(Debug, Serialize)]
+struct ProposedFileResponse {
+    path: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    move_path: Option<String>,
+    /// Unified diff for updates, full content for adds/deletes.
+    diff: String,
 }
 
-fn mode_from_args(mode: Option<String>) -> Result<SubagentMode, String> {
-    let mode = mode.unwrap_or_else(|| "general".to_string());
-    SubagentMode::from_str(&mode)
-        .ok_or_else(|| "unknown subagent mode; expected one of: general, explore".to_string())
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum RecentEvents {
+    Text(Vec<String>),
+    Structured(Vec<EventResponse>),
 }
 
-fn cap_output(text: Option<String>, max_output_chars: usize) -> Option<String> {
-    let mut text = text?;
-    if text.len() > max_output_chars {
-        text = codex_utils_string::take_bytes_at_char_boundary(&text, max_output_chars).to_string();
-    }
-    Some(text)
+#[derive(Debug, Serialize)]
+struct EventResponse {
+    kind: String,
+    message: String,
+    ts: String,
+    at_ms: u64,
+    seq: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<RunSummaryResponse>,
 }
 
-#[async_trait]
-impl ToolHandler for SubagentHandler {
-    fn kind(&self) -> ToolKind {
-        ToolKind::Function
+#[derive(Debug, Serialize)]
+struct RunSummaryResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<String>,
+    run_ms: u64,
+    tokens: u64,
+    tool_calls: BTreeMap<String, u64>,
+    truncated: bool,
+}
+
+impl From<SubagentRunSummary> for RunSummaryResponse {
+    fn from(summary: SubagentRunSummary) -> Self {
+        Self {
+            status: summary.status.as_str().to_string(),
+            error_kind: summary.error_kind.map(|kind| kind.as_str().to_string()),
+            run_ms: summary.run_ms,
+            tokens: summary.tokens,
+            tool_calls: summary
+                .tool_calls
+                .into_iter()
+                .map(|(kind, count)| (kind.to_string(), count))
+                .collect(),
+            truncated: summary.truncated,
+        }
     }
+}
 
-    async fn handle(&self, invocation: ToolInvocation) -> Result<ToolOutput, FunctionCallError> {
-        let ToolInvocation {
-            session,
-            turn,
-            tool_name,
-            payload,
-            ..
-        } = invocation;
+#[derive(Debug, Serialize)]
+struct ChecklistItemResponse {
+    text: String,
+    done: bool,
+}
 
-        let ToolPayload::Function { arguments } = payload else {
-            return Err(FunctionCallError::RespondToModel(
-                "subagent tools expect a function payload".to_string(),
-            ));
-        };
+#[derive(Debug, Serialize)]
+struct ListResponse {
+    agents: Vec<PollResponse>,
+    concurrency: ConcurrencyResponse,
+}
 
-        match tool_name.as_str() {
-            "subagent_spawn" => {
-                let args: SubagentSpawnArgs = serde_json::from_str(&arguments).map_err(|e| {
-                    FunctionCallError::RespondToModel(format!(
-                        "failed to parse function arguments: {e:?}"
-                    ))
-                })?;
-                let prompt = args.prompt.trim();
-                if prompt.is_empty() {
-                    return Err(FunctionCallError::RespondToModel(
-                        "subagent_spawn.prompt must be non-empty".to_string(),
-                    ));
-                }
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    queued: usize,
+    running: usize,
+    complete: usize,
+    aborted: usize,
+    error: usize,
+    total: usize,
+    concurrency: StatsConcurrencyResponse,
+}
 
-                let mode = mode_from_args(args.mode).map_err(FunctionCallError::RespondToModel)?;
-                let label = sanitize_label(args.label.as_deref().unwrap_or(DEFAULT_SUBAGENT_LABEL));
+/// Session-scoped slots: the per-session limit and how many are free.
+#[derive(Debug, Serialize)]
+struct StatsConcurrencyResponse {
+    limit: usize,
+    available: usize,
+}
 
-                let parent_config = turn.client.config().as_ref().clone();
-                let resp = session
-                    .services
-                    .subagent_manager
-                    .spawn_one_shot(
-                        SubagentSpawnRequest {
-                            agent_id: args.agent_id,
-                            mode,
-                            label: label.clone(),
-                            prompt: prompt.to_string(),
-                            skills: args.skills,
-                            timeout_ms: args.timeout_ms,
-                            resume_rollout_path: None,
-                        },
-                        session.clone(),
-                        turn.clone(),
-                        session.services.auth_manager.clone(),
-                        session.services.models_manager.clone(),
-                        session.services.skills_manager.clone(),
-                        parent_config,
-                    )
-                    .await;
+#[derive(Debug, Serialize)]
+struct ConcurrencyResponse {
+    session_free: usize,
+    session_limit: usize,
+    global_free: usize,
+}
 
-                let resp = resp.map_err(FunctionCallError::RespondToModel)?;
-                let out = SpawnResponse {
-                    agent_id: resp.agent_id,
-                    status: status_str(resp.status).to_string(),
-                    label: resp.label,
-                    mode: resp.mode.as_str().to_string(),
-                };
-                Ok(ToolOutput::Function {
-                    content: serde_json::to_string(&out)
-                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
-                    content_items: None,
-                    success: Some(true),
+#[derive(Debug, Serialize)]
+struct RankedResultsResponse {
+    results: Vec<RankedResultResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct RankedResultResponse {
+    agent_id: String,
+    label: String,
+    status: String,
+    /// `null` when the agent reported no confidence marker.
+    confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_output: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReprioritizeResponse {
+    agent_id: String,
+    priority: i32,
+    updated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DelegateProgressResponse {
+    label: String,
+    /// Call id of the delegate the output belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    call_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial_output: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditResponse {
+    events: Vec<AuditEventResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEventResponse {
+    at: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent_id: Option<String>,
+    detail: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheInfoResponse {
+    tables: Vec<CacheTableResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheTableResponse {
+    name: String,
+    size: usize,
+    entries: Vec<CacheEntryResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheEntryResponse {
+    key: String,
+    agent_id: String,
+    hits: u64,
+    age_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheClearResponse {
+    cleared: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateAgentResponse {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent: Option<ValidatedAgentResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidatedAgentResponse {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<String>,
+    tools_policy: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allowed_tools: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    denied_tools: Vec<String>,
+    max_retries: u32,
+    retry_backoff_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_concurrency: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skills: Vec<String>,
+    prompt_bytes: usize,
+}
+
+impl From<CustomAgent> for ValidatedAgentResponse {
+    fn from(agent: CustomAgent) -> Self {
+        let (tools_policy, allowed_tools, denied_tools) = match agent.tools {
+            AgentToolsPolicy::Inherit => ("inherit", Vec::new(), Vec::new()),
+            AgentToolsPolicy::None => ("none", Vec::new(), Vec::new()),
+            AgentToolsPolicy::Allowlist(names) => ("allowlist", names, Vec::new()),
+            AgentToolsPolicy::Denylist(names) => ("denylist", Vec::new(), names),
+        };
+        Self {
+            name: agent.name,
+            description: agent.description,
+            model: agent.model,
+            mode: agent.mode.map(|mode| mode.as_str().to_string()),
+            tools_policy: tools_policy.to_string(),
+            allowed_tools,
+            denied_tools,
+            max_retries: agent.max_retries,
+            retry_backoff_ms: agent.retry_backoff_ms,
+            timeout_ms: agent.timeout_ms,
+            max_concurrency: agent.max_concurrency,
+            color: agent.color,
+            icon: agent.icon,
+            skills: agent.skills,
+            prompt_bytes: agent.prompt.len(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CustomAgentsListResponse {
+    agents: Vec<CustomAgentEntryResponse>,
+    errors: Vec<AgentLoadErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct CustomAgentEntryResponse {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// `user`, `extra`, or `repo`; later scopes override earlier ones.
+    scope: String,
+    mode: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AgentLoadErrorResponse {
+    path: String,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ResultResponse {
+    agent_id: String,
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_output: Option<String>,
+    /// True when `final_output` was read back from a lazy agent's rollout.
+    materialized: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ResultChunkResponse {
+    agent_id: String,
+    status: String,
+    offset: usize,
+    chunk: String,
+    total_len: usize,
+    next_offset: Option<usize>,
+    from_rollout: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LogsResponse {
+    agent_id: String,
+    status: String,
+    offset: usize,
+    transcript: String,
+    total_len: usize,
+    next_offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChdirResponse {
+    agent_id: String,
+    cwd: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CancelAllResponse {
+    cancelled: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct PruneResponse {
+    pruned: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WaitAnyResponse {
+    timed_out: bool,
+    /// Poll response of the agent that finished first.
+    #[serde(flatten)]
+    agent: Option<PollResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct SendResponse {
+    agent_id: String,
+    /// `delivered` or `queued` (the agent has not started yet).
+    delivery: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateBatchResponse {
+    ok: bool,
+    items: Vec<ValidateBatchItemResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateBatchItemResponse {
+    index: usize,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolsResponse {
+    agent_id: String,
+    tools: Vec<String>,
+}
+
+fn default_record_rollout() -> bool {
+    true
+}
+
+/// Builds the request `subagent_spawn` would submit, rejecting the arguments
+/// the handler validates before reaching the manager.
+fn spawn_request_from_args(args: SubagentSpawnArgs) -> Result<SubagentSpawnRequest, String> {
+    let prompt = args.prompt.trim();
+    if prompt.is_empty() {
+        return Err("subagent_spawn.prompt must be non-empty".to_string());
+    }
+    let resume_rollout_path = match args.resume_rollout_path.as_deref().map(str::trim) {
+        Some("") => {
+            return Err("subagent_spawn.resume_rollout_path must be non-empty".to_string());
+        }
+        path => path.map(std::path::PathBuf::from),
+    };
+    Ok(SubagentSpawnRequest {
+        agent_id: args.agent_id,
+        mode: mode_from_args(args.mode)?,
+        label: sanitize_label(args.label.as_deref().unwrap_or(DEFAULT_SUBAGENT_LABEL)),
+        prompt: prompt.to_string(),
+        skills: args.skills,
+        timeout_ms: args.timeout_ms,
+        deadline_unix_ms: args.deadline_unix_ms,
+        resume_rollout_path,
+        output_format: output_format_from_args(args.output_format)?,
+        citation_style: citation_style_from_args(args.citation_style)?,
+        allow_sibling_abort: args.allow_sibling_abort,
+        report_confidence: args.report_confidence,
+        propose_only: args.propose_only,
+        max_retries: 0,
+        retry_backoff_ms: 0,
+        record_rollout: args.record_rollout,
+        max_per_label: args.max_per_label,
+        source_category: source_category_from_args(args.source_category.as_deref()),
+        lazy_output: args.lazy_output,
+        ephemeral: args.ephemeral,
+        await_skills_ms: args.await_skills_ms,
+        mutex_group: mutex_group_from_args(args.mutex_group.as_deref()),
+        trace_id: trace_id_from_args(args.trace_id.as_deref())?,
+        tools_policy: AgentToolsPolicy::Inherit,
+        model: None,
+        cwd: args
+            .cwd
+            .filter(|cwd| !cwd.trim().is_empty())
+            .map(std::path::PathBuf::from),
+        depends_on: args.depends_on,
+        priority: args.priority,
+        spawned_by: None,
+        max_events: limit_from_args(
+            "max_events",
+            args.max_events,
+            MIN_SUBAGENTS_MAX_EVENTS,
+            MAX_SUBAGENTS_MAX_EVENTS,
+        )?,
+        max_output_chars: limit_from_args(
+            "max_output_chars",
+            args.max_output_chars,
+            MIN_SUBAGENTS_MAX_OUTPUT_CHARS,
+            MAX_SUBAGENTS_MAX_OUTPUT_CHARS,
+        )?,
+        extra_instructions: args
+            .extra_instructions
+            .as_deref()
+            .and_then(sanitize_extra_instructions),
+        reasoning_effort: parse_reasoning_effort(args.reasoning_effort.as_deref())?,
+    })
+}
+
+/// Parses one `subagent_validate_batch` / `subagent_spawn_batch` item the
+/// way `subagent_spawn` parses its arguments, schema check included.
+fn batch_item_request(item: serde_json::Value) -> Result<SubagentSpawnRequest, String> {
+    let args: SubagentSpawnArgs = parse_checked_value("subagent_spawn", item)?;
+    if args.dry_run {
+        return Err(
+            "dry_run is not supported in batch items; use subagent_validate_batch".to_string(),
+        );
+    }
+    spawn_request_from_args(args)
+}
+
+/// Validates a per-agent memory limit: values below `min` are rejected and
+/// values above `max` are clamped to it.
+fn limit_from_args(
+    name: &str,
+    value: Option<usize>,
+    min: usize,
+    max: usize,
+) -> Result<Option<usize>, String> {
+    match value {
+        Some(value) if value < min => Err(format!("`{name}` must be at least {min}")),
+        value => Ok(value.map(|value| value.min(max))),
+    }
+}
+
+fn mutex_group_from_args(raw: Option<&str>) -> Option<String> {
+    let group = raw?.trim();
+    (!group.is_empty()).then(|| group.to_string())
+}
+
+/// Validates an optional trace id; blank values are dropped. The id ends up in
+/// a request header, so control characters and non-ASCII are rejected.
+fn trace_id_from_args(raw: Option<&str>) -> Result<Option<String>, String> {
+    let Some(trace_id) = raw.map(str::trim).filter(|id| !id.is_empty()) else {
+        return Ok(None);
+    };
+    if trace_id.len() > MAX_TRACE_ID_LEN {
+        return Err(format!(
+            "trace_id must be at most {MAX_TRACE_ID_LEN} characters"
+        ));
+    }
+    if !trace_id
+        .chars()
+        .all(|ch| ch.is_ascii() && !ch.is_ascii_control())
+    {
+        return Err(
+            "trace_id must be printable ASCII (no CR/LF or control characters)".to_string(),
+        );
+    }
+    Ok(Some(trace_id.to_string()))
+}
+
+/// Sanitizes an optional telemetry category; blank values fall back to the label.
+fn source_category_from_args(raw: Option<&str>) -> Option<String> {
+    raw.filter(|category| !category.trim().is_empty())
+        .map(sanitize_label)
+}
+
+fn sanitize_label(label: &str) -> String {
+    let trimmed = label.trim();
+    if trimmed.is_empty() {
+        return DEFAULT_SUBAGENT_LABEL.to_string();
+    }
+
+    let mut out = String::new();
+    for ch in trimmed.chars() {
+        if out.len() >= MAX_LABEL_LEN {
+            break;
+        }
+        match ch {
+            'a'..='z' | '0'..='9' | '-' | '_' | '.' => out.push(ch),
+            'A'..='Z' => out.push(ch.to_ascii_lowercase()),
+            ' ' | '/' | ':' => out.push('-'),
+            _ => {}
+        }
+    }
+
+    if out.is_empty() {
+        DEFAULT_SUBAGENT_LABEL.to_string()
+    } else {
+        out
+    }
+}
+
+/// Parses the arguments of a subagent tool, first checking them against the
+/// tool's schema so a malformed call gets a message naming the argument to fix.
+fn parse_checked_args<T: DeserializeOwned>(
+    tool_name: &str,
+    arguments: &str,
+) -> Result<T, FunctionCallError> {
+    let invalid = |message: String| {
+        FunctionCallError::RespondToModel(format!("invalid {tool_name} arguments: {message}"))
+    };
+    let value: serde_json::Value = serde_json::from_str(arguments)
+        .map_err(|e| invalid(format!("arguments are not valid JSON ({e})")))?;
+    parse_checked_value(tool_name, value).map_err(invalid)
+}
+
+/// Checks already-parsed arguments against `tool_name`'s schema, then
+/// deserializes them.
+fn parse_checked_value<T: DeserializeOwned>(
+    tool_name: &str,
+    value: serde_json::Value,
+) -> Result<T, String> {
+    if let Some(schema) = subagent_tool_parameters(tool_name) {
+        schema.validate_arguments(&value)?;
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+fn mode_from_args(mode: Option<String>) -> Result<SubagentMode, String> {
+    let mode = mode.unwrap_or_else(|| "general".to_string());
+    SubagentMode::from_str(&mode)
+        .ok_or_else(|| "`mode` must be one of: general, explore, review".to_string())
+}
+
+fn output_format_from_args(format: Option<String>) -> Result<SubagentOutputFormat, String> {
+    let Some(format) = format else {
+        return Ok(SubagentOutputFormat::default());
+    };
+    SubagentOutputFormat::from_str(&format)
+        .ok_or_else(|| "`output_format` must be one of: text, json".to_string())
+}
+
+pub(crate) fn result_format_from_args(
+    format: Option<&str>,
+) -> Result<SubagentResultFormat, FunctionCallError> {
+    let Some(format) = format else {
+        return Ok(SubagentResultFormat::default());
+    };
+    SubagentResultFormat::from_str(format).ok_or_else(|| {
+        FunctionCallError::RespondToModel(
+            "`format` must be one of: raw, first_code_block, json".to_string(),
+        )
+    })
+}
+
+fn citation_style_from_args(style: Option<String>) -> Result<SubagentCitationStyle, String> {
+    let Some(style) = style else {
+        return Ok(SubagentCitationStyle::default());
+    };
+    SubagentCitationStyle::from_str(&style)
+        .ok_or_else(|| "`citation_style` must be one of: none, inline-urls, numbered".to_string())
+}
+
+fn cap_output(text: Option<String>, max_output_chars: usize) -> Option<String> {
+    let mut text = text?;
+    if text.len() > max_output_chars {
+        text = codex_utils_string::take_bytes_at_char_boundary(&text, max_output_chars).to_string();
+    }
+    Some(text)
+}
+
+fn poll_response(poll: SubagentPollResponse, structured_events: bool) -> PollResponse {
+    let recent_events = if structured_events {
+        RecentEvents::Structured(
+            poll.recent_events
+                .into_iter()
+                .map(|event| EventResponse {
+                    kind: event.kind.as_str().to_string(),
+                    message: event.message,
+                    ts: event.ts.to_rfc3339(),
+                    at_ms: event.at_ms,
+                    seq: event.seq,
+                    summary: event.summary.map(RunSummaryResponse::from),
+                })
+                .collect(),
+        )
+    } else {
+        RecentEvents::Text(
+            poll.recent_events
+                .into_iter()
+                .map(|event| event.message)
+                .collect(),
+        )
+    };
+    PollResponse {
+        agent_id: poll.agent_id,
+        status: poll.status.as_str().to_string(),
+        label: poll.label,
+        mode: poll.mode.as_str().to_string(),
+        rollout_path: poll.rollout_path.as_ref().map(|p| p.display().to_string()),
+        final_output: cap_output(poll.final_output, poll.max_output_chars),
+        format_error: None,
+        partial_output: cap_output(poll.partial_output, poll.max_output_chars),
+        recent_events,
+        next_event_index: poll.next_event_index,
+        checklist: poll
+            .checklist
+            .into_iter()
+            .map(|item| ChecklistItemResponse {
+                text: item.text,
+                done: item.done,
+            })
+            .collect(),
+        proposed_patches: poll
+            .proposed_patches
+            .into_iter()
+            .map(proposed_patch_response)
+            .collect(),
+        error_kind: poll.error_kind.map(|kind| kind.as_str().to_string()),
+        confidence: poll.confidence,
+        ttft_ms: poll.ttft_ms,
+        total_ms: poll.total_ms,
+        elapsed_ms: poll.elapsed_ms,
+        running_ms: poll.running_ms,
+        tokens: TokensResponse {
+            input: poll.tokens.input,
+            output: poll.tokens.output,
+            total: poll.tokens.total,
+        },
+        approvals: (poll.approvals != SubagentApprovalCounts::default()).then_some(
+            ApprovalsResponse {
+                exec: poll.approvals.exec,
+                patch: poll.approvals.patch,
+                aborted: poll.approvals.aborted,
+            },
+        ),
+        workspace: poll.workspace.map(|path| path.display().to_string()),
+        sandbox: poll.sandbox,
+        approval: poll.approval,
+        reasoning_effort: poll.reasoning_effort.map(|effort| effort.to_string()),
+        spawned_by: poll.spawned_by.map(|by| SpawnedByResponse {
+            turn_id: by.turn_id,
+            call_id: by.call_id,
+        }),
+        abort_reason: poll.abort_reason,
+        suggested_next_poll_ms: poll.suggested_next_poll_ms,
+    }
+}
+
+fn proposed_patch_response(patch: ProposedPatch) -> ProposedPatchResponse {
+    let mut files: Vec<ProposedFileResponse> = patch
+        .changes
+        .into_iter()
+        .map(|(path, change)| {
+            let (kind, move_path, diff) = match change {
+                FileChange::Add { content } => ("add", None, content),
+                FileChange::Delete { content } => ("delete", None, content),
+                FileChange::Update {
+                    unified_diff,
+                    move_path,
+                } => ("update", move_path, unified_diff),
+            };
+            ProposedFileResponse {
+                path: path.display().to_string(),
+                kind: kind.to_string(),
+                move_path: move_path.map(|p| p.display().to_string()),
+                diff,
+            }
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    ProposedPatchResponse {
+        call_id: patch.call_id,
+        files,
+    }
+}
+
+#[async_trait]
+impl ToolHandler for SubagentHandler {
+    fn kind(&self) -> ToolKind {
+        ToolKind::Function
+    }
+
+    async fn handle(&self, invocation: ToolInvocation) -> Result<ToolOutput, FunctionCallError> {
+        let ToolInvocation {
+            session,
+            turn,
+            call_id,
+            tool_name,
+            payload,
+            ..
+        } = invocation;
+        let spawned_by = SubagentSpawnedBy {
+            turn_id: turn.sub_id.clone(),
+            call_id,
+        };
+
+        let ToolPayload::Function { arguments } = payload else {
+            return Err(FunctionCallError::RespondToModel(
+                "subagent tools expect a function payload".to_string(),
+            ));
+        };
+
+        match tool_name.as_str() {
+            "subagent_spawn" | "subagent_resume" => {
+                let args: SubagentSpawnArgs = parse_checked_args(&tool_name, &arguments)?;
+                let dry_run = args.dry_run;
+                let request = SubagentSpawnRequest {
+                    spawned_by: Some(spawned_by),
+                    ..spawn_request_from_args(args).map_err(FunctionCallError::RespondToModel)?
+                };
+
+                let parent_config = turn.client.config().as_ref().clone();
+                if dry_run {
+                    let plan = session
+                        .services
+                        .subagent_manager
+                        .dry_run_spawn(
+                            &request,
+                            &session.services.skills_manager,
+                            &session.services.models_manager,
+                            &turn.cwd,
+                            &parent_config,
+                        )
+                        .await
+                        .map_err(FunctionCallError::RespondToModel)?;
+                    let out = SpawnDryRunResponse {
+                        dry_run: true,
+                        agent_id: plan.agent_id,
+                        label: plan.label,
+                        mode: plan.mode.as_str().to_string(),
+                        skills: plan.skills,
+                    };
+                    return Ok(ToolOutput::Function {
+                        content: serde_json::to_string(&out)
+                            .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                        content_items: None,
+                        success: Some(true),
+                    });
+                }
+                let resp = session
+                    .services
+                    .subagent_manager
+                    .spawn_one_shot(
+                        request,
+                        session.clone(),
+                        turn.clone(),
+                        session.services.auth_manager.clone(),
+                        session.services.models_manager.clone(),
+                        session.services.skills_manager.clone(),
+                        parent_config,
+                    )
+                    .await;
+
+                let resp = resp.map_err(FunctionCallError::RespondToModel)?;
+                let out = SpawnResponse::from(resp);
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_spawn_batch" => {
+                let args: SubagentSpawnBatchArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                if args.items.is_empty() {
+                    return Err(FunctionCallError::RespondToModel(
+                        "items must not be empty".to_string(),
+                    ));
+                }
+
+                let requests: Vec<Result<SubagentSpawnRequest, String>> = args
+                    .items
+                    .into_iter()
+                    .map(|item| {
+                        batch_item_request(item).map(|request| SubagentSpawnRequest {
+                            spawned_by: Some(spawned_by.clone()),
+                            ..request
+                        })
+                    })
+                    .collect();
+                let total = requests.len();
+
+                // Check every item before launching any, so an invalid item
+                // cannot leave the batch half spawned.
+                let parent_config = turn.client.config().as_ref().clone();
+                let invalid = session
+                    .services
+                    .subagent_manager
+                    .validate_batch(
+                        requests.clone(),
+                        &session.services.skills_manager,
+                        &turn.cwd,
+                        &parent_config,
+                    )
+                    .await
+                    .into_iter()
+                    .enumerate()
+                    .find_map(|(index, result)| result.err().map(|error| (index, error)));
+                let mut agents = Vec::with_capacity(total);
+                let mut error = invalid.map(|(index, error)| SpawnBatchErrorResponse {
+                    index,
+                    error,
+                    skipped: total - 1,
+                });
+                if error.is_none() {
+                    for (index, request) in requests.into_iter().flatten().enumerate() {
+                        let resp = session
+                            .services
+                            .subagent_manager
+                            .spawn_one_shot(
+                                request,
+                                session.clone(),
+                                turn.clone(),
+                                session.services.auth_manager.clone(),
+                                session.services.models_manager.clone(),
+                                session.services.skills_manager.clone(),
+                                parent_config.clone(),
+                            )
+                            .await;
+                        match resp {
+                            Ok(resp) => agents.push(SpawnResponse::from(resp)),
+                            Err(message) => {
+                                error = Some(SpawnBatchErrorResponse {
+                                    index,
+                                    error: message,
+                                    skipped: total - index - 1,
+                                });
+                                break;
+                            }
+                        }
+                    }
+                }
+                let out = SpawnBatchResponse {
+                    ok: error.is_none(),
+                    agents,
+                    error,
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_spawn_named" => {
+                let args: SubagentSpawnNamedArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                let Some(name) = sanitize_agent_name(&args.name) else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "subagent_spawn_named.name must be non-empty".to_string(),
+                    ));
+                };
+                let prompt = args.prompt.trim();
+                if prompt.is_empty() {
+                    return Err(FunctionCallError::RespondToModel(
+                        "subagent_spawn_named.prompt must be non-empty".to_string(),
+                    ));
+                }
+
+                let parent_config = turn.client.config().as_ref().clone();
+                let agent = find_agent(&parent_config, &name)
+                    .await
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let mut subagent_config = parent_config;
+                agent.apply_to_config(&mut subagent_config);
+                let available = session.services.skills_manager.skills_for_cwd(&turn.cwd);
+                agent
+                    .check_skills(&available.skills)
+                    .map_err(FunctionCallError::RespondToModel)?;
+
+                let resp = session
+                    .services
+                    .subagent_manager
+                    .spawn_one_shot(
+                        SubagentSpawnRequest {
+                            agent_id: args.agent_id,
+                            mode: agent.mode_or_default(),
+                            label: agent.name.clone(),
+                            prompt: prompt.to_string(),
+                            skills: agent.merged_skills(args.skills),
+                            timeout_ms: args.timeout_ms.or(agent.timeout_ms),
+                            max_retries: agent.max_retries,
+                            retry_backoff_ms: agent.retry_backoff_ms,
+                            max_per_label: agent.max_concurrency,
+                            tools_policy: agent.tools.clone(),
+                            model: agent.model.clone(),
+                            spawned_by: Some(spawned_by),
+                            ..Default::default()
+                        },
+                        session.clone(),
+                        turn.clone(),
+                        session.services.auth_manager.clone(),
+                        session.services.models_manager.clone(),
+                        session.services.skills_manager.clone(),
+                        subagent_config,
+                    )
+                    .await
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let out = SpawnResponse::from(resp);
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_validate_batch" => {
+                let args: SubagentValidateBatchArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                let requests = args.items.into_iter().map(batch_item_request).collect();
+
+                let parent_config = turn.client.config();
+                let results = session
+                    .services
+                    .subagent_manager
+                    .validate_batch(
+                        requests,
+                        &session.services.skills_manager,
+                        &turn.cwd,
+                        &parent_config,
+                    )
+                    .await;
+                let out = ValidateBatchResponse {
+                    ok: results.iter().all(Result::is_ok),
+                    items: results
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, result)| ValidateBatchItemResponse {
+                            index,
+                            ok: result.is_ok(),
+                            error: result.err(),
+                        })
+                        .collect(),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_poll" => {
+                let args: SubagentPollArgs = parse_checked_args("subagent_poll", &arguments)?;
+                let until_status = args
+                    .until_status
+                    .as_deref()
+                    .map(|status| {
+                        SubagentStatus::from_str(status.trim()).ok_or_else(|| {
+                            FunctionCallError::RespondToModel(
+                                "`until_status` must be one of: queued, running, complete, aborted, error"
+                                    .to_string(),
+                            )
+                        })
+                    })
+                    .transpose()?;
+                let format = result_format_from_args(args.format.as_deref())?;
+                let Some(mut poll) = session
+                    .services
+                    .subagent_manager
+                    .poll_until(&args.agent_id, args.await_ms, until_status)
+                    .await
+                else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                };
+                let config = turn.client.config();
+                session
+                    .services
+                    .subagent_manager
+                    .prune_terminated(
+                        config.subagents.retain_terminated,
+                        config.subagents.retain_ttl,
+                    )
+                    .await;
+                if let Some(since) = args.since_event_index {
+                    poll.recent_events.retain(|event| event.seq >= since);
+                }
+                // Format before capping so truncation cannot split the extracted part.
+                let mut format_error = None;
+                if poll.status == SubagentStatus::Complete
+                    && let Some(text) = poll.final_output.as_deref()
+                {
+                    match format.apply(text) {
+                        Ok(formatted) => poll.final_output = Some(formatted),
+                        Err(problem) => format_error = Some(problem),
+                    }
+                }
+
+                let mut out = poll_response(poll, args.structured_events);
+                out.format_error = format_error;
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_cancel" => {
+                let args: SubagentCancelArgs = parse_checked_args("subagent_cancel", &arguments)?;
+                if session
+                    .services
+                    .subagent_manager
+                    .cancel(&args.agent_id, args.reason.as_deref())
+                    .await
+                    .is_none()
+                {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                }
+                Ok(ToolOutput::Function {
+                    content: "{\"status\":\"cancelled\"}".to_string(),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_restart" => {
+                let args: SubagentIdArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let parent_config = turn.client.config().as_ref().clone();
+                let resp = session
+                    .services
+                    .subagent_manager
+                    .restart(
+                        &args.agent_id,
+                        session.clone(),
+                        turn.clone(),
+                        session.services.auth_manager.clone(),
+                        session.services.models_manager.clone(),
+                        session.services.skills_manager.clone(),
+                        parent_config,
+                    )
+                    .await
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let out = SpawnResponse::from(resp);
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_prune" => {
+                let args: SubagentPruneArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                // Without arguments, apply the configured retention, or drop
+                // every finished agent when none is configured.
+                let (keep, older_than) = if args.keep.is_none() && args.older_than_ms.is_none() {
+                    let config = turn.client.config();
+                    let retain = config.subagents.retain_terminated;
+                    let ttl = config.subagents.retain_ttl;
+                    if retain.is_none() && ttl.is_none() {
+                        (Some(0), None)
+                    } else {
+                        (retain, ttl)
+                    }
+                } else {
+                    (args.keep, args.older_than_ms.map(Duration::from_millis))
+                };
+                let pruned = session
+                    .services
+                    .subagent_manager
+                    .prune_terminated(keep, older_than)
+                    .await;
+                let out = PruneResponse { pruned };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_cancel_all" => {
+                let cancelled = session.services.subagent_manager.cancel_all().await;
+                let out = CancelAllResponse { cancelled };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_stats" => {
+                let manager = &session.services.subagent_manager;
+                let counts = manager.status_counts().await;
+                let concurrency = manager.concurrency();
+                let out = StatsResponse {
+                    queued: counts.queued,
+                    running: counts.running,
+                    complete: counts.complete,
+                    aborted: counts.aborted,
+                    error: counts.error,
+                    total: counts.total(),
+                    concurrency: StatsConcurrencyResponse {
+                        limit: concurrency.session_limit,
+                        available: concurrency.session_free,
+                    },
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
                 })
             }
-            "subagent_resume" => {
-                let args: SubagentResumeArgs = serde_json::from_str(&arguments).map_err(|e| {
+            "subagent_wait_any" => {
+                let args: SubagentWaitAnyArgs = serde_json::from_str(&arguments).map_err(|e| {
                     FunctionCallError::RespondToModel(format!(
                         "failed to parse function arguments: {e:?}"
                     ))
                 })?;
-                let prompt = args.prompt.trim();
-                if prompt.is_empty() {
+                let finished = session
+                    .services
+                    .subagent_manager
+                    .wait_any(&args.agent_ids, args.await_ms)
+                    .await
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let out = WaitAnyResponse {
+                    timed_out: finished.is_none(),
+                    agent: finished.map(|poll| poll_response(poll, false)),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_cache_info" => {
+                let tables = session.services.subagent_manager.cache_info().await;
+                let out = CacheInfoResponse {
+                    tables: tables
+                        .into_iter()
+                        .map(|table| CacheTableResponse {
+                            name: table.name.to_string(),
+                            size: table.entries.len(),
+                            entries: table
+                                .entries
+                                .into_iter()
+                                .map(|(key, entry)| CacheEntryResponse {
+                                    key,
+                                    agent_id: entry.agent_id,
+                                    hits: entry.hits,
+                                    age_ms: u64::try_from(entry.inserted_at.elapsed().as_millis())
+                                        .unwrap_or(u64::MAX),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_cache_clear" => {
+                let cleared = session.services.subagent_manager.clear_caches().await;
+                let out = CacheClearResponse { cleared };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_validate_agent" => {
+                let args: ValidateAgentArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let result = match (args.content, args.path) {
+                    (Some(content), None) => {
+                        let name = args.name.as_deref().map(str::trim).unwrap_or_default();
+                        let name = if name.is_empty() { "agent" } else { name };
+                        validate_agent_content(&content, name)
+                    }
+                    (None, Some(path)) => {
+                        let config = turn.client.config();
+                        validate_agent_path(&config, std::path::Path::new(path.trim())).await
+                    }
+                    _ => {
+                        return Err(FunctionCallError::RespondToModel(
+                            "provide exactly one of `content` or `path`".to_string(),
+                        ));
+                    }
+                };
+                let out = match result {
+                    Ok(agent) => ValidateAgentResponse {
+                        valid: true,
+                        error: None,
+                        agent: Some(agent.into()),
+                    },
+                    Err(error) => ValidateAgentResponse {
+                        valid: false,
+                        error: Some(error),
+                        agent: None,
+                    },
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "custom_agents_list" => {
+                let config = turn.client.config();
+                let outcome = discover_agents(&config).await;
+                let out = CustomAgentsListResponse {
+                    agents: outcome
+                        .agents
+                        .into_iter()
+                        .map(|agent| CustomAgentEntryResponse {
+                            mode: agent.mode_or_default().as_str().to_string(),
+                            scope: agent.scope.as_str().to_string(),
+                            path: agent.path.display().to_string(),
+                            name: agent.name,
+                            description: agent.description,
+                            color: agent.color,
+                            icon: agent.icon,
+                        })
+                        .collect(),
+                    errors: outcome
+                        .errors
+                        .into_iter()
+                        .map(|error| AgentLoadErrorResponse {
+                            path: error.path.display().to_string(),
+                            message: error.message,
+                        })
+                        .collect(),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_list" => {
+                let args: SubagentListArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let statuses = args
+                    .status
+                    .as_deref()
+                    .map(parse_status_filter)
+                    .transpose()
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let agents = session
+                    .services
+                    .subagent_manager
+                    .list(statuses.as_deref())
+                    .await;
+                let concurrency = session.services.subagent_manager.concurrency();
+                let out = ListResponse {
+                    agents: agents
+                        .into_iter()
+                        .map(|poll| poll_response(poll, false))
+                        .collect(),
+                    concurrency: ConcurrencyResponse {
+                        session_free: concurrency.session_free,
+                        session_limit: concurrency.session_limit,
+                        global_free: concurrency.global_free,
+                    },
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_results_ranked" => {
+                let args: SubagentResultsRankedArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                let label = args.label.as_deref().map(sanitize_label);
+                let results = session
+                    .services
+                    .subagent_manager
+                    .ranked_results(label.as_deref())
+                    .await;
+                let max_output_chars = turn.client.config().subagents.max_output_chars;
+                let out = RankedResultsResponse {
+                    results: results
+                        .into_iter()
+                        .take(args.limit.unwrap_or(usize::MAX))
+                        .map(|poll| RankedResultResponse {
+                            agent_id: poll.agent_id,
+                            label: poll.label,
+                            status: poll.status.as_str().to_string(),
+                            confidence: poll.confidence,
+                            final_output: cap_output(poll.final_output, max_output_chars),
+                        })
+                        .collect(),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_audit" => {
+                let args: SubagentAuditArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let events = session
+                    .services
+                    .subagent_manager
+                    .audit_log(args.limit)
+                    .await;
+                let out = AuditResponse {
+                    events: events
+                        .into_iter()
+                        .map(|event| AuditEventResponse {
+                            at: event.at.to_rfc3339(),
+                            kind: event.kind.as_str().to_string(),
+                            agent_id: event.agent_id,
+                            detail: event.detail,
+                        })
+                        .collect(),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_result" => {
+                let args: SubagentResultArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let Some(result) = session
+                    .services
+                    .subagent_manager
+                    .result(&args.agent_id, args.materialize)
+                    .await
+                else {
                     return Err(FunctionCallError::RespondToModel(
-                        "subagent_resume.prompt must be non-empty".to_string(),
+                        "unknown agent_id".to_string(),
                     ));
-                }
-
-                let rollout_path = args.rollout_path.trim();
-                if rollout_path.is_empty() {
+                };
+                let result = result.map_err(FunctionCallError::RespondToModel)?;
+                let out = ResultResponse {
+                    agent_id: args.agent_id,
+                    status: result.status.as_str().to_string(),
+                    final_output: result.final_output,
+                    materialized: result.materialized,
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_result_chunk" => {
+                let args: SubagentResultChunkArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                let max_output_chars = turn.client.config().subagents.max_output_chars;
+                let length = args
+                    .length
+                    .unwrap_or(max_output_chars)
+                    .clamp(1, max_output_chars.max(1));
+                let Some(chunk) = session
+                    .services
+                    .subagent_manager
+                    .result_chunk(&args.agent_id, args.offset, length)
+                    .await
+                else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                };
+                let chunk = chunk.map_err(FunctionCallError::RespondToModel)?;
+                let out = ResultChunkResponse {
+                    agent_id: args.agent_id,
+                    status: chunk.status.as_str().to_string(),
+                    offset: chunk.offset,
+                    chunk: chunk.chunk,
+                    total_len: chunk.total_len,
+                    next_offset: chunk.next_offset,
+                    from_rollout: chunk.from_rollout,
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_logs" => {
+                let args: SubagentLogsArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let max_bytes = args
+                    .max_bytes
+                    .unwrap_or(DEFAULT_LOGS_MAX_BYTES)
+                    .clamp(1, MAX_LOGS_MAX_BYTES);
+                let Some(chunk) = session
+                    .services
+                    .subagent_manager
+                    .transcript(&args.agent_id, args.offset, max_bytes)
+                    .await
+                else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                };
+                let chunk = chunk.map_err(FunctionCallError::RespondToModel)?;
+                let out = LogsResponse {
+                    agent_id: args.agent_id,
+                    status: chunk.status.as_str().to_string(),
+                    offset: chunk.offset,
+                    transcript: chunk.chunk,
+                    total_len: chunk.total_len,
+                    next_offset: chunk.next_offset,
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "subagent_chdir" => {
+                let args: SubagentChdirArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let path = args.path.trim();
+                if path.is_empty() {
                     return Err(FunctionCallError::RespondToModel(
-                        "subagent_resume.rollout_path must be non-empty".to_string(),
+                        "subagent_chdir.path must be non-empty".to_string(),
                     ));
                 }
-
-                let mode = mode_from_args(args.mode).map_err(FunctionCallError::RespondToModel)?;
-                let label = sanitize_label(args.label.as_deref().unwrap_or(DEFAULT_SUBAGENT_LABEL));
-                let parent_config = turn.client.config().as_ref().clone();
-                let resp = session
+                let Some(result) = session
                     .services
                     .subagent_manager
-                    .spawn_one_shot(
-                        SubagentSpawnRequest {
-                            agent_id: args.agent_id,
-                            mode,
-                            label: label.clone(),
-                            prompt: prompt.to_string(),
-                            skills: args.skills,
-                            timeout_ms: args.timeout_ms,
-                            resume_rollout_path: Some(std::path::PathBuf::from(rollout_path)),
-                        },
-                        session.clone(),
-                        turn.clone(),
-                        session.services.auth_manager.clone(),
-                        session.services.models_manager.clone(),
-                        session.services.skills_manager.clone(),
-                        parent_config,
-                    )
-                    .await;
-
-                let resp = resp.map_err(FunctionCallError::RespondToModel)?;
-                let out = SpawnResponse {
-                    agent_id: resp.agent_id,
-                    status: status_str(resp.status).to_string(),
-                    label: resp.label,
-                    mode: resp.mode.as_str().to_string(),
+                    .set_cwd(&args.agent_id, std::path::Path::new(path), &turn.cwd)
+                    .await
+                else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                };
+                let cwd = result.map_err(FunctionCallError::RespondToModel)?;
+                let out = ChdirResponse {
+                    agent_id: args.agent_id,
+                    cwd: cwd.display().to_string(),
                 };
                 Ok(ToolOutput::Function {
                     content: serde_json::to_string(&out)
@@ -272,32 +1910,32 @@ impl ToolHandler for SubagentHandler {
                     success: Some(true),
                 })
             }
-            "subagent_poll" => {
-                let args: SubagentPollArgs = serde_json::from_str(&arguments).map_err(|e| {
+            "subagent_send" => {
+                let args: SubagentSendArgs = serde_json::from_str(&arguments).map_err(|e| {
                     FunctionCallError::RespondToModel(format!(
                         "failed to parse function arguments: {e:?}"
                     ))
                 })?;
-                let Some(poll) = session
+                let message = args.message.trim();
+                if message.is_empty() {
+                    return Err(FunctionCallError::RespondToModel(
+                        "subagent_send.message must be non-empty".to_string(),
+                    ));
+                }
+                let Some(result) = session
                     .services
                     .subagent_manager
-                    .poll(&args.agent_id, args.await_ms)
+                    .send(&args.agent_id, message.to_string())
                     .await
                 else {
                     return Err(FunctionCallError::RespondToModel(
                         "unknown agent_id".to_string(),
                     ));
                 };
-
-                let max_output_chars = turn.client.config().subagents.max_output_chars;
-                let out = PollResponse {
-                    agent_id: poll.agent_id,
-                    status: status_str(poll.status).to_string(),
-                    label: poll.label,
-                    mode: poll.mode.as_str().to_string(),
-                    rollout_path: poll.rollout_path.as_ref().map(|p| p.display().to_string()),
-                    final_output: cap_output(poll.final_output, max_output_chars),
-                    recent_events: poll.recent_events,
+                let outcome = result.map_err(FunctionCallError::RespondToModel)?;
+                let out = SendResponse {
+                    agent_id: args.agent_id,
+                    delivery: outcome.as_str().to_string(),
                 };
                 Ok(ToolOutput::Function {
                     content: serde_json::to_string(&out)
@@ -306,48 +1944,84 @@ impl ToolHandler for SubagentHandler {
                     success: Some(true),
                 })
             }
-            "subagent_cancel" => {
+            "subagent_tools" => {
                 let args: SubagentIdArgs = serde_json::from_str(&arguments).map_err(|e| {
                     FunctionCallError::RespondToModel(format!(
                         "failed to parse function arguments: {e:?}"
                     ))
                 })?;
-                if session
+                let Some(tools) = session
                     .services
                     .subagent_manager
-                    .cancel(&args.agent_id)
+                    .tools(&args.agent_id)
                     .await
-                    .is_none()
-                {
+                else {
                     return Err(FunctionCallError::RespondToModel(
                         "unknown agent_id".to_string(),
                     ));
-                }
+                };
+                let out = ToolsResponse {
+                    agent_id: args.agent_id,
+                    tools,
+                };
                 Ok(ToolOutput::Function {
-                    content: "{\"status\":\"cancelled\"}".to_string(),
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
                     content_items: None,
                     success: Some(true),
                 })
             }
-            "subagent_list" => {
-                let agents = session.services.subagent_manager.list().await;
-                let max_output_chars = turn.client.config().subagents.max_output_chars;
-                let out = ListResponse {
-                    agents: agents
-                        .into_iter()
-                        .map(|poll| PollResponse {
-                            agent_id: poll.agent_id,
-                            status: status_str(poll.status).to_string(),
-                            label: poll.label,
-                            mode: poll.mode.as_str().to_string(),
-                            rollout_path: poll
-                                .rollout_path
-                                .as_ref()
-                                .map(|p| p.display().to_string()),
-                            final_output: cap_output(poll.final_output, max_output_chars),
-                            recent_events: poll.recent_events,
-                        })
-                        .collect(),
+            "subagent_reprioritize" => {
+                let args: SubagentReprioritizeArgs =
+                    serde_json::from_str(&arguments).map_err(|e| {
+                        FunctionCallError::RespondToModel(format!(
+                            "failed to parse function arguments: {e:?}"
+                        ))
+                    })?;
+                let Some(outcome) = session
+                    .services
+                    .subagent_manager
+                    .set_priority(&args.agent_id, args.priority)
+                    .await
+                else {
+                    return Err(FunctionCallError::RespondToModel(
+                        "unknown agent_id".to_string(),
+                    ));
+                };
+                let out = ReprioritizeResponse {
+                    agent_id: args.agent_id,
+                    priority: args.priority,
+                    updated: outcome == SetPriorityOutcome::Updated,
+                    note: (outcome == SetPriorityOutcome::NotQueued).then(|| {
+                        "agent is not queued; priority only affects agents waiting to start"
+                            .to_string()
+                    }),
+                };
+                Ok(ToolOutput::Function {
+                    content: serde_json::to_string(&out)
+                        .unwrap_or_else(|_| "{\"error\":\"failed to serialize\"}".to_string()),
+                    content_items: None,
+                    success: Some(true),
+                })
+            }
+            "delegate_progress" => {
+                let args: DelegateProgressArgs = serde_json::from_str(&arguments).map_err(|e| {
+                    FunctionCallError::RespondToModel(format!(
+                        "failed to parse function arguments: {e:?}"
+                    ))
+                })?;
+                let label = sanitize_subagent_label(&args.label);
+                let progress = session
+                    .services
+                    .subagent_manager
+                    .delegate_progress(&label, args.call_id.as_deref())
+                    .await
+                    .map_err(FunctionCallError::RespondToModel)?;
+                let (call_id, partial_output) = progress.unzip();
+                let out = DelegateProgressResponse {
+                    label,
+                    call_id,
+                    partial_output,
                 };
                 Ok(ToolOutput::Function {
                     content: serde_json::to_string(&out)
@@ -173,15 +173,23 @@ impl ToolRegistryBuilder {
 
     /// Restrict the tool registry to an explicit allowlist of tool names.
     ///
+    /// Entries containing `*` are glob patterns where `*` matches any run of
+    /// characters (e.g. `read_*`); all other entries must match exactly.
+    ///
     /// This trims both the serialized tool specs sent to the model and the
     /// runtime dispatch handlers.
     pub fn restrict_to_tool_names(&mut self, allowlist: &[String]) {
-        use std::collections::HashSet;
+        let allowed = tool_name_matcher(allowlist);
+        self.specs.retain(|spec| allowed(spec.spec.name()));
+        self.handlers.retain(|name, _| allowed(name.as_str()));
+    }
 
-        let allowed: HashSet<&str> = allowlist.iter().map(String::as_str).collect();
-        self.specs.retain(|spec| allowed.contains(spec.spec.name()));
-        self.handlers
-            .retain(|name, _| allowed.contains(name.as_str()));
+    /// Remove the tools matching a denylist, with the same matching rules as
+    /// [`Self::restrict_to_tool_names`].
+    pub fn exclude_tool_names(&mut self, denylist: &[String]) {
+        let denied = tool_name_matcher(denylist);
+        self.specs.retain(|spec| !denied(spec.spec.name()));
+        self.handlers.retain(|name, _| !denied(name.as_str()));
     }
 
     pub fn push_spec(&mut self, spec: ToolSpec) {
@@ -238,3 +246,19 @@ fn unsupported_tool_call_message(payload: &ToolPayload, tool_name: &str) -> Stri
         _ => format!("unsupported call: {tool_name}"),
     }
 }
+
+/// Matches tool names against `entries`: exact names, or glob patterns when
+/// an entry contains `*`.
+fn tool_name_matcher(entries: &[String]) -> impl Fn(&str) -> bool {
+    use std::collections::HashSet;
+    use wildmatch::WildMatchPattern;
+
+    let (patterns, exact): (Vec<&String>, Vec<&String>) =
+        entries.iter().partition(|entry| entry.contains('*'));
+    let exact: HashSet<String> = exact.into_iter().cloned().collect();
+    let patterns: Vec<WildMatchPattern<'*', '\0'>> = patterns
+        .into_iter()
+        .map(|pattern| WildMatchPattern::new(pattern.as_str()))
+        .collect();
+    move |name: &str| exact.contains(name) || patterns.iter().any(|pattern| pattern.matches(name))
+}
@@ -39,6 +39,9 @@ impl ToolRouter {
         if let Some(allowlist) = &config.tool_name_allowlist {
             builder.restrict_to_tool_names(allowlist);
         }
+        if let Some(denylist) = &config.tool_name_denylist {
+            builder.exclude_tool_names(denylist);
+        }
         let (specs, registry) = builder.build();
 
         Self { registry, specs }
@@ -24,6 +24,7 @@ pub(crate) struct ToolsConfig {
     pub include_view_image_tool: bool,
     pub include_subagent_tools: bool,
     pub tool_name_allowlist: Option<Vec<String>>,
+    pub tool_name_denylist: Option<Vec<String>>,
     pub experimental_supported_tools: Vec<String>,
 }
 
@@ -31,6 +32,7 @@ pub(crate) struct ToolsConfigParams<'a> {
     pub(crate) model_family: &'a ModelFamily,
     pub(crate) features: &'a Features,
     pub(crate) tool_name_allowlist: Option<&'a [String]>,
+    pub(crate) tool_name_denylist: Option<&'a [String]>,
 }
 
 impl ToolsConfig {
@@ -39,6 +41,7 @@ impl ToolsConfig {
             model_family,
             features,
             tool_name_allowlist,
+            tool_name_denylist,
         } = params;
         let include_apply_patch_tool = features.enabled(Feature::ApplyPatchFreeform);
         let include_web_search_request = features.enabled(Feature::WebSearchRequest);
@@ -72,6 +75,7 @@ impl ToolsConfig {
             include_view_image_tool,
             include_subagent_tools,
             tool_name_allowlist: tool_name_allowlist.map(<[String]>::to_vec),
+            tool_name_denylist: tool_name_denylist.map(<[String]>::to_vec),
             experimental_supported_tools: model_family.experimental_supported_tools.clone(),
         }
     }
@@ -133,6 +137,122 @@ impl From<JsonSchema> for AdditionalProperties {
     }
 }
 
+impl JsonSchema {
+    /// Checks tool call arguments against this (object) schema and describes
+    /// the first problem in terms of the offending argument, e.g. "`timeout_ms`
+    /// must be a number". Required strings must be non-empty; `null` counts as
+    /// absent.
+    pub(crate) fn validate_arguments(&self, arguments: &JsonValue) -> Result<(), String> {
+        match (self, arguments) {
+            (Self::Object { .. }, JsonValue::Object(_)) => {
+                check_argument_value(None, self, arguments)
+            }
+            (Self::Object { .. }, _) => Err("arguments must be a JSON object".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn expected(&self) -> &'static str {
+        match self {
+            Self::Boolean { .. } => "a boolean",
+            Self::String { .. } => "a string",
+            Self::Number { .. } => "a number",
+            Self::Array { .. } => "an array",
+            Self::Object { .. } => "an object",
+        }
+    }
+}
+
+fn check_argument_value(
+    path: Option<&str>,
+    schema: &JsonSchema,
+    value: &JsonValue,
+) -> Result<(), String> {
+    match (schema, value) {
+        (JsonSchema::Boolean { .. }, JsonValue::Bool(_))
+        | (JsonSchema::String { .. }, JsonValue::String(_))
+        | (JsonSchema::Number { .. }, JsonValue::Number(_)) => Ok(()),
+        (JsonSchema::Array { items, .. }, JsonValue::Array(values)) => {
+            let path = path.unwrap_or_default();
+            for (index, item) in values.iter().enumerate() {
+                check_argument_value(Some(&format!("{path}[{index}]")), items, item)?;
+            }
+            Ok(())
+        }
+        (
+            JsonSchema::Object {
+                properties,
+                required,
+                additional_properties,
+            },
+            JsonValue::Object(map),
+        ) => {
+            let field = |name: &str| match path {
+                Some(path) => format!("{path}.{name}"),
+                None => name.to_string(),
+            };
+            for name in required.iter().flatten() {
+                let value = map.get(name).filter(|value| !value.is_null());
+                match (properties.get(name), value) {
+                    (Some(JsonSchema::String { .. }), None) => {
+                        return Err(format!(
+                            "`{}` is required and must be a non-empty string",
+                            field(name)
+                        ));
+                    }
+                    (Some(JsonSchema::String { .. }), Some(JsonValue::String(text)))
+                        if text.trim().is_empty() =>
+                    {
+                        return Err(format!(
+                            "`{}` is required and must be a non-empty string",
+                            field(name)
+                        ));
+                    }
+                    (Some(schema), None) => {
+                        return Err(format!(
+                            "`{}` is required and must be {}",
+                            field(name),
+                            schema.expected()
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            for (name, value) in map {
+                if value.is_null() {
+                    continue;
+                }
+                match (properties.get(name), additional_properties) {
+                    (Some(schema), _) => {
+                        check_argument_value(Some(&field(name)), schema, value)?;
+                    }
+                    (None, Some(AdditionalProperties::Schema(schema))) => {
+                        check_argument_value(Some(&field(name)), schema, value)?;
+                    }
+                    (None, Some(AdditionalProperties::Boolean(false))) => {
+                        let expected = properties
+                            .keys()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        return Err(format!(
+                            "unknown argument `{}`; expected one of: {expected}",
+                            field(name)
+                        ));
+                    }
+                    (None, _) => {}
+                }
+            }
+            Ok(())
+        }
+        _ => Err(format!(
+            "`{}` must be {}",
+            path.unwrap_or("arguments"),
+            schema.expected()
+        )),
+    }
+}
+
 fn create_exec_command_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
@@ -999,7 +1119,7 @@ fn create_delegate_tool() -> ToolSpec {
         "skills".to_string(),
         JsonSchema::Array {
             items: Box::new(JsonSchema::String {
-                description: Some("Skill name.".to_string()),
+                description: Some("Skill name, or a glob pattern such as `db/*`.".to_string()),
             }),
             description: Some("Optional list of skills to inject into the subagent.".to_string()),
         },
@@ -1020,10 +1140,73 @@ fn create_delegate_tool() -> ToolSpec {
             ),
         },
     );
+    properties.insert(
+        "progress_interval_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Optional interval (milliseconds) at which partial output is published for `delegate_progress`."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "stream".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Show the delegate's message text to the user line by line while it runs. The returned output is unchanged."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "extra_instructions".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Task-specific guidance appended after the delegate's built-in instructions (e.g. \"only consider the src/net module\"). Cannot override them; capped at 4096 bytes."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "response_schema".to_string(),
+        JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: None,
+        },
+    );
+    properties.insert(
+        "inherit_context".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Give the delegate the parent's user instructions and project docs (AGENTS.md). Defaults to false."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "reasoning_effort".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Reasoning effort for the delegate: `none`, `minimal`, `low`, `medium`, `high`, or `xhigh`. Defaults to the parent's."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "format".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Post-process the output: `raw` (default), `first_code_block` (only the contents of the first fenced code block), or `json` (the first JSON object, re-serialized). Output without such a part is returned raw. Not allowed with `response_schema`."
+                    .to_string(),
+            ),
+        },
+    );
 
     ToolSpec::Function(ResponsesApiTool {
         name: "delegate".to_string(),
-        description: "Runs a focused one-shot subagent and returns its output.".to_string(),
+        description: "Runs a focused one-shot subagent and returns its output. With `response_schema` (a JSON schema), the output must be JSON matching it and the result is `{output, schema_valid, schema_error?}`. By default the delegate starts without the parent's user instructions and project docs, which keeps it fast and cheap; set `inherit_context` when the task depends on project conventions, at the cost of a larger prompt on every delegate turn."
+            .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
@@ -1033,7 +1216,39 @@ fn create_delegate_tool() -> ToolSpec {
     })
 }
 
-fn create_subagent_spawn_tool() -> ToolSpec {
+fn create_delegate_progress_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "label".to_string(),
+        JsonSchema::String {
+            description: Some("Label of the running delegate call.".to_string()),
+        },
+    );
+    properties.insert(
+        "call_id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Call id of the delegate to read when several running delegates share the label; the error for an ambiguous label lists them."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "delegate_progress".to_string(),
+        description: "Read the latest partial output of a running `delegate` call that set `progress_interval_ms`. Returns the output and the call id it belongs to."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["label".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+/// Arguments accepted by `subagent_spawn`; `subagent_resume` takes the same
+/// ones under its own name for the rollout.
+fn subagent_spawn_properties() -> BTreeMap<String, JsonSchema> {
     let mut properties = BTreeMap::new();
     properties.insert(
         "agent_id".to_string(),
@@ -1051,25 +1266,628 @@ fn create_subagent_spawn_tool() -> ToolSpec {
         },
     );
     properties.insert(
-        "label".to_string(),
+        "label".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional label used for telemetry/tagging (sent as `x-openai-subagent`)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "mode".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Subagent profile: `general` (default), `explore`, or `review`.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "skills".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String {
+                description: Some("Skill name, or a glob pattern such as `db/*`.".to_string()),
+            }),
+            description: Some("Optional list of skills to inject into the subagent.".to_string()),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Optional deadline for the subagent run (milliseconds). Defaults to a generous value."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "deadline_unix_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Optional absolute stop time (Unix epoch milliseconds). The run stops at this moment or after timeout_ms, whichever comes first; use the same value to stop a fan-out together."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "output_format".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Expected final answer format: `text` (default) or `json` (a single JSON value)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "citation_style".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "How the subagent should cite sources: `none` (default), `inline-urls`, or `numbered`."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "allow_sibling_abort".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Allow this agent to cancel live agents sharing its label by emitting `<<abort-siblings: reason>>` (defaults to false)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "report_confidence".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Ask the agent to rate its answer with a `<<confidence: X>>` marker, reported as `confidence` and used by `subagent_results_ranked` (defaults to false)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "propose_only".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Record patches for review instead of applying them: every patch is auto-denied and returned in `proposed_patches` (defaults to false)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "record_rollout".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Write a rollout file for this agent (defaults to true). Set false for throwaway queries; the agent then has no rollout_path and cannot be resumed."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "max_per_label".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Reject the spawn if this many queued or running agents already share its label."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "source_category".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Stable telemetry category sent instead of the label (e.g. \"security-scan\"); defaults to the label."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "lazy_output".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Run to completion but store only a short \"ready\" marker as final_output; fetch the full answer with subagent_result (materialize: true)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "ephemeral".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Forget the agent automatically once a poll has returned its final output."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "await_skills_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Keep re-checking for requested skills that are not discovered yet for up to this many milliseconds before failing (default 0)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "mutex_group".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Run at most one agent from this named group at a time (e.g. \"build-lock\"); others wait their turn."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "trace_id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional correlation id sent as the `x-codex-trace-id` header on every request the subagent makes (printable ASCII, max 128 chars)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "cwd".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional working directory for the subagent, absolute or relative to the current cwd. Must be inside the repository root."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "depends_on".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Optional agent ids that must complete before this agent starts. If one errors or is aborted, this agent is aborted too. Dependencies must already exist; cycles are rejected."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "priority".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Queue priority while waiting for a concurrency slot; higher values start first (default 0). Change it later with subagent_reprioritize."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "max_events".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Recent events kept for this agent, overriding [subagents].max_events (1-1024)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "max_output_chars".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Characters kept of this agent's output, overriding [subagents].max_output_chars (at least 1024; capped at 1048576)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "extra_instructions".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Task-specific guidance appended after the agent's built-in instructions (e.g. \"only consider the src/net module\"). Cannot override them; capped at 4096 bytes."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "dry_run".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Validate the request (agent_id, mode, skills, capacity, resume path, ...) and report the resolved label, mode, and skills without starting the agent."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "resume_rollout_path".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Path to a Codex rollout (.jsonl) file under the sessions directory (e.g. a previous agent's rollout_path). The agent starts with that conversation as history and then runs `prompt`."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "reasoning_effort".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Reasoning effort for the agent: `none`, `minimal`, `low`, `medium`, `high`, or `xhigh`. Defaults to the parent's. Use `low` for cheap scans and `high` for reviews. Polls report the effort the agent runs with."
+                    .to_string(),
+            ),
+        },
+    );
+    properties
+}
+
+fn create_subagent_spawn_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_spawn".to_string(),
+        description: "Spawns a background one-shot subagent and returns an agent_id to poll."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: subagent_spawn_properties(),
+            required: Some(vec!["prompt".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_poll_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
+        },
+    );
+    properties.insert(
+        "await_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Optional time to wait for the subagent to make progress (milliseconds)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "structured_events".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Return recent_events as {kind, message, ts, at_ms, seq} objects instead of strings (defaults to false)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "since_event_index".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Only return events whose seq is at least this value. Pass the next_event_index from the previous poll to fetch just the new events."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "until_status".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "With await_ms, stop waiting once the agent reaches this status (`queued`, `running`, `complete`, `aborted`, or `error`) instead of a terminal one. A terminal status always ends the wait."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "format".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Post-process `final_output` of a completed agent: `raw` (default), `first_code_block` (only the contents of the first fenced code block), or `json` (the first JSON object, re-serialized). If the output has no such part, `final_output` stays raw and `format_error` says why."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_poll".to_string(),
+        description: "Poll a background subagent for status and output.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_cancel_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
+        },
+    );
+    properties.insert(
+        "reason".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Why the agent is being stopped; reported as abort_reason by subagent_poll (default \"cancelled\")."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_cancel".to_string(),
+        description: "Cancel a background subagent.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_list_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "status".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Only list agents with these statuses, comma-separated (e.g. \"running,queued\"). One of queued, running, complete, aborted, error."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_list".to_string(),
+        description: "List background subagents spawned in this session.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(Vec::new()),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_cache_info_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_cache_info".to_string(),
+        description: "Show the subagent manager's lookup tables (sizes and entries with hashed keys, hit counts, and ages)."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: Some(Vec::new()),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_cache_clear_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_cache_clear".to_string(),
+        description: "Empty the subagent manager's lookup tables so later spawns start fresh."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: Some(Vec::new()),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_result_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
+        },
+    );
+    properties.insert(
+        "materialize".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "For lazy_output agents, read the full final answer back from the rollout."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_result".to_string(),
+        description: "Return a background subagent's final output. Lazy agents only store a \"ready\" marker until materialize is true."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_chdir_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
+        },
+    );
+    properties.insert(
+        "path".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "New working directory, absolute or relative to the agent's current cwd. Must be inside the repository."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_chdir".to_string(),
+        description: "Move a queued or running background subagent to a new working directory without respawning it."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string(), "path".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_results_ranked_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "label".to_string(),
+        JsonSchema::String {
+            description: Some("Only rank agents with this label.".to_string()),
+        },
+    );
+    properties.insert(
+        "limit".to_string(),
+        JsonSchema::Number {
+            description: Some("Return at most this many results.".to_string()),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_results_ranked".to_string(),
+        description: "List finished background subagents by self-reported confidence (highest first; agents without a score last). Spawn the candidates with `report_confidence` so they report one."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_validate_batch_tool() -> ToolSpec {
+    let item = match create_subagent_spawn_tool() {
+        ToolSpec::Function(tool) => tool.parameters,
+        _ => JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: None,
+        },
+    };
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "items".to_string(),
+        JsonSchema::Array {
+            items: Box::new(item),
+            description: Some(
+                "Spawn specs, each taking the same arguments as subagent_spawn.".to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_validate_batch".to_string(),
+        description: "Check a batch of subagent_spawn specs (mode, skills, prompt, max_agents, ...) without launching any. Returns per-item ok/error."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["items".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_spawn_batch_tool() -> ToolSpec {
+    let item = match create_subagent_spawn_tool() {
+        ToolSpec::Function(tool) => tool.parameters,
+        _ => JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: None,
+        },
+    };
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "items".to_string(),
+        JsonSchema::Array {
+            items: Box::new(item),
+            description: Some(
+                "Spawn specs, each taking the same arguments as subagent_spawn.".to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_spawn_batch".to_string(),
+        description: "Spawn several background subagents in one call, in order. Returns the spawned agents (agent_id, status, label, mode). Every spec is checked before any agent starts: if one is invalid (e.g. duplicate agent_id or bad mode), nothing is spawned and the first invalid spec is reported with its index."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["items".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_result_chunk_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
+        },
+    );
+    properties.insert(
+        "offset".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Byte offset to start from (defaults to 0; pass the previous next_offset to continue)."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "length".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Maximum bytes to return (defaults to and is capped at the max_output_chars budget)."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_result_chunk".to_string(),
+        description: "Page through a subagent's full, untruncated final output. Returns chunk, total_len, and next_offset (null at the end)."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_spawn_named_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "name".to_string(),
         JsonSchema::String {
             description: Some(
-                "Optional label used for telemetry/tagging (sent as `x-openai-subagent`)."
-                    .to_string(),
+                "Name of a custom agent from .codex/agents or $CODEX_HOME/agents.".to_string(),
             ),
         },
     );
     properties.insert(
-        "mode".to_string(),
+        "prompt".to_string(),
+        JsonSchema::String {
+            description: Some("Task for the agent.".to_string()),
+        },
+    );
+    properties.insert(
+        "agent_id".to_string(),
         JsonSchema::String {
-            description: Some("Subagent profile: `general` (default) or `explore`.".to_string()),
+            description: Some("Optional explicit id for the agent.".to_string()),
         },
     );
     properties.insert(
         "skills".to_string(),
         JsonSchema::Array {
             items: Box::new(JsonSchema::String {
-                description: Some("Skill name.".to_string()),
+                description: Some("Skill name, or a glob pattern such as `db/*`.".to_string()),
             }),
             description: Some("Optional list of skills to inject into the subagent.".to_string()),
         },
@@ -1078,26 +1896,25 @@ fn create_subagent_spawn_tool() -> ToolSpec {
         "timeout_ms".to_string(),
         JsonSchema::Number {
             description: Some(
-                "Optional deadline for the subagent run (milliseconds). Defaults to a generous value."
+                "Optional deadline for the run (milliseconds); defaults to the agent's `timeout_ms`."
                     .to_string(),
             ),
         },
     );
-
     ToolSpec::Function(ResponsesApiTool {
-        name: "subagent_spawn".to_string(),
-        description: "Spawns a background one-shot subagent and returns an agent_id to poll."
+        name: "subagent_spawn_named".to_string(),
+        description: "Spawn a background subagent from a custom agent definition (its prompt, mode, model, and tools policy). Poll it like subagent_spawn."
             .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["prompt".to_string()]),
+            required: Some(vec!["name".to_string(), "prompt".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_subagent_poll_tool() -> ToolSpec {
+fn create_subagent_send_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
         "agent_id".to_string(),
@@ -1105,38 +1922,96 @@ fn create_subagent_poll_tool() -> ToolSpec {
             description: Some("Agent id returned by subagent_spawn.".to_string()),
         },
     );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some("Follow-up instruction or clarification for the agent.".to_string()),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_send".to_string(),
+        description: "Send a follow-up message to a queued or running background subagent. It keeps its context and sees the message at its next step; finished agents reject it."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string(), "message".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_cancel_all_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_cancel_all".to_string(),
+        description: "Cancel every queued or running background subagent at once. Returns how many were cancelled."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: Some(Vec::new()),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_wait_any_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agent_ids".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some("Agents to wait for.".to_string()),
+        },
+    );
     properties.insert(
         "await_ms".to_string(),
         JsonSchema::Number {
             description: Some(
-                "Optional time to wait for the subagent to make progress (milliseconds)."
+                "Maximum time to wait (milliseconds). Without it the agents are checked once."
                     .to_string(),
             ),
         },
     );
+
     ToolSpec::Function(ResponsesApiTool {
-        name: "subagent_poll".to_string(),
-        description: "Poll a background subagent for status and output.".to_string(),
+        name: "subagent_wait_any".to_string(),
+        description: "Wait until the first of several background subagents finishes and return its poll response. An agent that already finished is returned right away, so leave it out of later calls. On timeout returns timed_out: true."
+            .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["agent_id".to_string()]),
+            required: Some(vec!["agent_ids".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_subagent_cancel_tool() -> ToolSpec {
+fn create_custom_agents_list_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "custom_agents_list".to_string(),
+        description: "List the custom agents available to subagent_spawn_named (name, description, scope, mode, path) plus the agent files that failed to load, with their error messages. Scopes are user, extra, and repo; a later scope overrides an agent of the same name from an earlier one."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: Some(Vec::new()),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_restart_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
         "agent_id".to_string(),
         JsonSchema::String {
-            description: Some("Agent id returned by subagent_spawn.".to_string()),
+            description: Some("Id of a finished (complete, aborted, or error) agent.".to_string()),
         },
     );
     ToolSpec::Function(ResponsesApiTool {
-        name: "subagent_cancel".to_string(),
-        description: "Cancel a background subagent.".to_string(),
+        name: "subagent_restart".to_string(),
+        description: "Re-run a finished subagent with the same prompt, label, mode, skills, and options, keeping its agent_id. Fails while the agent is queued or running.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
@@ -1146,78 +2021,234 @@ fn create_subagent_cancel_tool() -> ToolSpec {
     })
 }
 
-fn create_subagent_list_tool() -> ToolSpec {
+fn create_subagent_prune_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "keep".to_string(),
+        JsonSchema::Number {
+            description: Some("Keep at most this many finished agents, newest first.".to_string()),
+        },
+    );
+    properties.insert(
+        "older_than_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Remove finished agents last updated longer ago than this (milliseconds)."
+                    .to_string(),
+            ),
+        },
+    );
     ToolSpec::Function(ResponsesApiTool {
-        name: "subagent_list".to_string(),
-        description: "List background subagents spawned in this session.".to_string(),
+        name: "subagent_prune".to_string(),
+        description: "Remove finished (complete, aborted, or error) subagents from the session. Without arguments, applies the configured retention, or removes every finished agent when none is configured. Queued and running agents are never removed.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_stats_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_stats".to_string(),
+        description: "Count background subagents by status (queued, running, complete, aborted, error, total) and report the session's concurrency limit and free slots. Cheaper than subagent_list for checking whether any agent is still running.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties: BTreeMap::new(),
-            required: Some(Vec::new()),
+            required: None,
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_subagent_resume_tool() -> ToolSpec {
+fn create_subagent_logs_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
         "agent_id".to_string(),
         JsonSchema::String {
+            description: Some("Agent whose transcript to read.".to_string()),
+        },
+    );
+    properties.insert(
+        "offset".to_string(),
+        JsonSchema::Number {
             description: Some(
-                "Optional explicit id for the agent (useful for deterministic orchestration)."
+                "Byte offset to start from; pass the previous next_offset to continue (default 0)."
                     .to_string(),
             ),
         },
     );
     properties.insert(
-        "rollout_path".to_string(),
+        "max_bytes".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Maximum bytes of transcript to return (default 16384, max 65536).".to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_logs".to_string(),
+        description: "Read a subagent's full transcript from its rollout: user and assistant messages, reasoning summaries, and tool calls with their (shortened) outputs. Use it to see how the agent reached its answer. Paginate with offset/next_offset.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+/// Parameter schema of the subagent tools whose arguments are validated
+/// before they are parsed.
+pub(crate) fn subagent_tool_parameters(name: &str) -> Option<JsonSchema> {
+    let spec = match name {
+        "subagent_spawn" => create_subagent_spawn_tool(),
+        "subagent_resume" => create_subagent_resume_tool(),
+        "subagent_poll" => create_subagent_poll_tool(),
+        "subagent_cancel" => create_subagent_cancel_tool(),
+        _ => return None,
+    };
+    match spec {
+        ToolSpec::Function(tool) => Some(tool.parameters),
+        _ => None,
+    }
+}
+
+fn create_subagent_validate_agent_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "content".to_string(),
         JsonSchema::String {
             description: Some(
-                "Path to a Codex rollout (.jsonl) file to resume as initial history.".to_string(),
+                "Raw agent markdown (YAML frontmatter + prompt body) to validate before saving."
+                    .to_string(),
             ),
         },
     );
     properties.insert(
-        "prompt".to_string(),
+        "name".to_string(),
         JsonSchema::String {
-            description: Some("Prompt to run in the resumed subagent.".to_string()),
+            description: Some(
+                "Filename stem to assume for `content` when the frontmatter has no name (defaults to \"agent\")."
+                    .to_string(),
+            ),
         },
     );
     properties.insert(
-        "label".to_string(),
+        "path".to_string(),
         JsonSchema::String {
             description: Some(
-                "Optional label used for telemetry/tagging (sent as `x-openai-subagent`)."
+                "Path to an existing agent file under .codex/agents or $CODEX_HOME/agents. Use instead of `content`."
                     .to_string(),
             ),
         },
     );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_validate_agent".to_string(),
+        description: "Check a custom agent definition the way discovery would. Returns the resolved fields or the specific parse error."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_tools_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
     properties.insert(
-        "mode".to_string(),
+        "agent_id".to_string(),
         JsonSchema::String {
-            description: Some("Subagent profile: `general` (default) or `explore`.".to_string()),
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
         },
     );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_tools".to_string(),
+        description: "List the tools a background subagent was given, including its MCP tools, after mode and tools-policy overrides."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_reprioritize_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
     properties.insert(
-        "skills".to_string(),
-        JsonSchema::Array {
-            items: Box::new(JsonSchema::String {
-                description: Some("Skill name.".to_string()),
-            }),
-            description: Some("Optional list of skills to inject into the subagent.".to_string()),
+        "agent_id".to_string(),
+        JsonSchema::String {
+            description: Some("Agent id returned by subagent_spawn.".to_string()),
         },
     );
     properties.insert(
-        "timeout_ms".to_string(),
+        "priority".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "New priority; higher values acquire a concurrency slot first (default 0)."
+                    .to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_reprioritize".to_string(),
+        description: "Change the priority of a queued background subagent. No-op for agents that already started."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agent_id".to_string(), "priority".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_audit_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "limit".to_string(),
         JsonSchema::Number {
-            description: Some("Optional deadline for the subagent run (milliseconds).".to_string()),
+            description: Some(
+                "Return only the most recent N entries (defaults to the whole log).".to_string(),
+            ),
+        },
+    );
+    ToolSpec::Function(ResponsesApiTool {
+        name: "subagent_audit".to_string(),
+        description: "Read the session-wide audit log of subagent manager actions (spawns, prunes, cancellations, budget warnings, sibling aborts, reprioritizations, retries), oldest first."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_subagent_resume_tool() -> ToolSpec {
+    let mut properties = subagent_spawn_properties();
+    properties.remove("resume_rollout_path");
+    properties.insert(
+        "rollout_path".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Path to a Codex rollout (.jsonl) file under the sessions directory to resume as initial history."
+                    .to_string(),
+            ),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
         name: "subagent_resume".to_string(),
-        description: "Resumes a previous subagent rollout and runs a new prompt.".to_string(),
+        description: "Deprecated: use subagent_spawn with resume_rollout_path. Resumes a previous subagent rollout and runs a new prompt."
+            .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
@@ -1300,6 +2331,28 @@ pub(crate) fn build_specs(
             create_subagent_cancel_tool(),
             create_subagent_list_tool(),
             create_subagent_resume_tool(),
+            create_subagent_tools_tool(),
+            create_delegate_progress_tool(),
+            create_subagent_reprioritize_tool(),
+            create_subagent_audit_tool(),
+            create_subagent_cache_info_tool(),
+            create_subagent_cache_clear_tool(),
+            create_subagent_validate_agent_tool(),
+            create_subagent_result_tool(),
+            create_subagent_chdir_tool(),
+            create_subagent_results_ranked_tool(),
+            create_subagent_validate_batch_tool(),
+            create_subagent_result_chunk_tool(),
+            create_subagent_spawn_named_tool(),
+            create_subagent_send_tool(),
+            create_subagent_cancel_all_tool(),
+            create_subagent_wait_any_tool(),
+            create_custom_agents_list_tool(),
+            create_subagent_restart_tool(),
+            create_subagent_prune_tool(),
+            create_subagent_stats_tool(),
+            create_subagent_logs_tool(),
+            create_subagent_spawn_batch_tool(),
         ] {
             builder.push_spec_with_parallel_support(spec, true);
         }
@@ -1309,6 +2362,28 @@ pub(crate) fn build_specs(
             "subagent_cancel",
             "subagent_list",
             "subagent_resume",
+            "subagent_tools",
+            "delegate_progress",
+            "subagent_reprioritize",
+            "subagent_audit",
+            "subagent_cache_info",
+            "subagent_cache_clear",
+            "subagent_validate_agent",
+            "subagent_result",
+            "subagent_chdir",
+            "subagent_results_ranked",
+            "subagent_validate_batch",
+            "subagent_result_chunk",
+            "subagent_spawn_named",
+            "subagent_send",
+            "subagent_cancel_all",
+            "subagent_wait_any",
+            "custom_agents_list",
+            "subagent_restart",
+            "subagent_prune",
+            "subagent_stats",
+            "subagent_logs",
+            "subagent_spawn_batch",
         ] {
             builder.register_handler(name, subagent_handler.clone());
         }
@@ -1511,6 +2586,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&config, None).build();
 
@@ -1561,6 +2637,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tool_name_allowlist_accepts_wildcards() {
+        let config = test_config();
+        let model_family = ModelsManager::construct_model_family_offline("gpt-5-codex", &config);
+        let features = Features::with_defaults();
+        let allowlist = vec!["*_mcp_resource*".to_string(), "update_plan".to_string()];
+        let tools_config = ToolsConfig::new(&ToolsConfigParams {
+            model_family: &model_family,
+            features: &features,
+            tool_name_allowlist: Some(allowlist.as_slice()),
+            tool_name_denylist: None,
+        });
+        let mut builder = build_specs(&tools_config, Some(HashMap::new()));
+        builder.restrict_to_tool_names(&allowlist);
+        let (tools, _) = builder.build();
+        let tool_names = tools.iter().map(|t| t.spec.name()).collect::<Vec<_>>();
+        assert_eq!(
+            tool_names,
+            vec![
+                "list_mcp_resources",
+                "list_mcp_resource_templates",
+                "read_mcp_resource",
+                "update_plan",
+            ]
+        );
+    }
+
+    #[test]
+    fn tool_name_denylist_removes_matching_tools() {
+        let config = test_config();
+        let model_family = ModelsManager::construct_model_family_offline("gpt-5-codex", &config);
+        let features = Features::with_defaults();
+        let tools_config = ToolsConfig::new(&ToolsConfigParams {
+            model_family: &model_family,
+            features: &features,
+            tool_name_allowlist: None,
+            tool_name_denylist: None,
+        });
+        let (all_tools, _) = build_specs(&tools_config, Some(HashMap::new())).build();
+
+        let denylist = vec!["*_mcp_resource*".to_string(), "update_plan".to_string()];
+        let mut builder = build_specs(&tools_config, Some(HashMap::new()));
+        builder.exclude_tool_names(&denylist);
+        let (tools, _) = builder.build();
+        let tool_names = tools.iter().map(|t| t.spec.name()).collect::<Vec<_>>();
+        let expected = all_tools
+            .iter()
+            .map(|t| t.spec.name())
+            .filter(|name| {
+                !matches!(
+                    *name,
+                    "list_mcp_resources"
+                        | "list_mcp_resource_templates"
+                        | "read_mcp_resource"
+                        | "update_plan"
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tool_names, expected);
+    }
+
     fn assert_model_tools(model_slug: &str, features: &Features, expected_tools: &[&str]) {
         let config = test_config();
         let model_family = ModelsManager::construct_model_family_offline(model_slug, &config);
@@ -1568,6 +2705,7 @@ mod tests {
             model_family: &model_family,
             features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&tools_config, Some(HashMap::new())).build();
         let tool_names = tools.iter().map(|t| t.spec.name()).collect::<Vec<_>>();
@@ -1606,6 +2744,28 @@ mod tests {
                 "subagent_cancel",
                 "subagent_list",
                 "subagent_resume",
+                "subagent_tools",
+                "delegate_progress",
+                "subagent_reprioritize",
+                "subagent_audit",
+                "subagent_cache_info",
+                "subagent_cache_clear",
+                "subagent_validate_agent",
+                "subagent_result",
+                "subagent_chdir",
+                "subagent_results_ranked",
+                "subagent_validate_batch",
+                "subagent_result_chunk",
+                "subagent_spawn_named",
+                "subagent_send",
+                "subagent_cancel_all",
+                "subagent_wait_any",
+                "custom_agents_list",
+                "subagent_restart",
+                "subagent_prune",
+                "subagent_stats",
+                "subagent_logs",
+                "subagent_spawn_batch",
                 "list_mcp_resources",
                 "list_mcp_resource_templates",
                 "read_mcp_resource",
@@ -1621,6 +2781,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&tools_config, None).build();
         let delegate = tools
@@ -1635,6 +2796,28 @@ mod tests {
             "subagent_cancel",
             "subagent_list",
             "subagent_resume",
+            "subagent_tools",
+            "delegate_progress",
+            "subagent_reprioritize",
+            "subagent_audit",
+            "subagent_cache_info",
+            "subagent_cache_clear",
+            "subagent_validate_agent",
+            "subagent_result",
+            "subagent_chdir",
+            "subagent_results_ranked",
+            "subagent_validate_batch",
+            "subagent_result_chunk",
+            "subagent_spawn_named",
+            "subagent_send",
+            "subagent_cancel_all",
+            "subagent_wait_any",
+            "custom_agents_list",
+            "subagent_restart",
+            "subagent_prune",
+            "subagent_stats",
+            "subagent_logs",
+            "subagent_spawn_batch",
         ] {
             let tool = tools
                 .iter()
@@ -1821,6 +3004,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&tools_config, Some(HashMap::new())).build();
 
@@ -1844,6 +3028,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&tools_config, None).build();
 
@@ -1865,6 +3050,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(&tools_config, None).build();
 
@@ -1897,6 +3083,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(
             &tools_config,
@@ -1992,6 +3179,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
 
         // Intentionally construct a map with keys that would sort alphabetically.
@@ -2070,6 +3258,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
 
         let (tools, _) = build_specs(
@@ -2128,6 +3317,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
 
         let (tools, _) = build_specs(
@@ -2183,6 +3373,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
 
         let (tools, _) = build_specs(
@@ -2240,6 +3431,7 @@ mod tests {
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
 
         let (tools, _) = build_specs(
@@ -2353,6 +3545,7 @@ Examples of valid command strings:
             model_family: &model_family,
             features: &features,
             tool_name_allowlist: None,
+            tool_name_denylist: None,
         });
         let (tools, _) = build_specs(
             &tools_config,
@@ -2510,4 +3703,40 @@ Examples of valid command strings:
             })]
         );
     }
+
+    #[test]
+    fn subagent_arguments_are_checked_against_the_tool_schema() {
+        let spawn = subagent_tool_parameters("subagent_spawn").expect("spawn schema");
+        let check = |arguments: JsonValue| spawn.validate_arguments(&arguments);
+
+        assert_eq!(check(json!({"prompt": "scan", "label": null})), Ok(()));
+        assert_eq!(
+            check(json!("scan")),
+            Err("arguments must be a JSON object".to_string())
+        );
+        assert_eq!(
+            check(json!({"prompt": "  "})),
+            Err("`prompt` is required and must be a non-empty string".to_string())
+        );
+        assert_eq!(
+            check(json!({"prompt": "scan", "timeout_ms": "5s"})),
+            Err("`timeout_ms` must be a number".to_string())
+        );
+        assert_eq!(
+            check(json!({"prompt": "scan", "skills": ["db/*", 3]})),
+            Err("`skills[1]` must be a string".to_string())
+        );
+        let err = check(json!({"prompt": "scan", "modes": "explore"})).expect_err("unknown");
+        assert!(
+            err.starts_with("unknown argument `modes`; expected one of: "),
+            "{err}"
+        );
+
+        let poll = subagent_tool_parameters("subagent_poll").expect("poll schema");
+        assert_eq!(
+            poll.validate_arguments(&json!({})),
+            Err("`agent_id` is required and must be a non-empty string".to_string())
+        );
+        assert_eq!(subagent_tool_parameters("subagent_list"), None);
+    }
 }
@@ -1,19 +1,29 @@
+use codex_core::config::Config;
+use codex_core::config::types::OutputOverflow;
 use codex_core::features::Feature;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::Op;
 use core_test_support::responses::ResponseMock;
+use core_test_support::responses::ResponsesRequest;
+use core_test_support::responses::ev_apply_patch_custom_tool_call;
 use core_test_support::responses::ev_assistant_message;
 use core_test_support::responses::ev_completed;
+use core_test_support::responses::ev_completed_with_tokens;
 use core_test_support::responses::ev_function_call;
 use core_test_support::responses::ev_response_created;
+use core_test_support::responses::mount_response_once_match;
 use core_test_support::responses::mount_sse_once_match;
 use core_test_support::responses::sse;
+use core_test_support::responses::sse_failed;
+use core_test_support::responses::sse_response;
 use core_test_support::responses::start_mock_server;
 use core_test_support::skip_if_no_network;
 use core_test_support::skip_if_sandbox;
+use core_test_support::test_codex::TestCodex;
 use core_test_support::test_codex::test_codex;
 use core_test_support::wait_for_event;
 use pretty_assertions::assert_eq;
+use wiremock::MockServer;
 use wiremock::matchers::body_string_contains;
 use wiremock::matchers::header;
 
@@ -24,6 +34,92 @@ fn parse_tool_output_json(mock: &ResponseMock, call_id: &str) -> serde_json::Val
     serde_json::from_str(&text).unwrap_or_else(|_| panic!("invalid JSON tool output for {call_id}"))
 }
 
+/// Drives one main turn in which the model makes `calls` (call id, tool name,
+/// arguments) in order, one per response, and then finishes. Subagent
+/// responses must be mounted by the caller. Returns the session and each
+/// call's output text.
+#[expect(clippy::expect_used)]
+async fn run_tool_calls<F>(
+    server: &MockServer,
+    calls: &[(&str, &str, serde_json::Value)],
+    configure: F,
+) -> (TestCodex, Vec<String>)
+where
+    F: FnOnce(&mut Config) + Send + 'static,
+{
+    let trigger = "trigger-tool-calls";
+    let mut previous = trigger;
+    let mut mocks = Vec::new();
+    for (index, (call_id, tool, args)) in calls.iter().enumerate() {
+        let response_id = format!("resp-main-{index}");
+        mocks.push(
+            mount_sse_once_match(
+                server,
+                body_string_contains(previous),
+                sse(vec![
+                    ev_response_created(&response_id),
+                    ev_function_call(call_id, tool, &args.to_string()),
+                    ev_completed(&response_id),
+                ]),
+            )
+            .await,
+        );
+        previous = call_id;
+    }
+    mocks.push(
+        mount_sse_once_match(
+            server,
+            body_string_contains(previous),
+            sse(vec![
+                ev_response_created("resp-main-done"),
+                ev_assistant_message("msg-main-done", "done"),
+                ev_completed("resp-main-done"),
+            ]),
+        )
+        .await,
+    );
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+            configure(config);
+        });
+    let test = builder.build(server).await.expect("build test codex");
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: trigger.to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let outputs = calls
+        .iter()
+        .zip(&mocks[1..])
+        .map(|((call_id, _, _), mock)| {
+            mock.function_call_output_text(call_id)
+                .unwrap_or_else(|| panic!("missing tool output for {call_id}"))
+        })
+        .collect();
+    (test, outputs)
+}
+
+fn parse_json(text: &str) -> serde_json::Value {
+    serde_json::from_str(text).unwrap_or_else(|_| panic!("invalid JSON tool output: {text}"))
+}
+
+/// The request a subagent labelled `label` sent through `mock`. Mocks also
+/// capture main-turn requests that reach them before their own match.
+fn subagent_request(mock: &ResponseMock, label: &str) -> ResponsesRequest {
+    mock.requests()
+        .into_iter()
+        .find(|req| req.header("x-openai-subagent").as_deref() == Some(label))
+        .unwrap_or_else(|| panic!("missing request from subagent {label}"))
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn subagent_spawn_then_poll_waits_until_complete() {
     skip_if_no_network!();
@@ -60,7 +156,7 @@ async fn subagent_spawn_then_poll_waits_until_complete() {
     let sse_subagent = sse(vec![
         ev_response_created("resp-sub-1"),
         ev_assistant_message("msg-sub-1", "Subagent output"),
-        ev_completed("resp-sub-1"),
+        ev_completed_with_tokens("resp-sub-1", 42),
     ]);
     let subagent_mock =
         mount_sse_once_match(&server, header("x-openai-subagent", label), sse_subagent).await;
@@ -128,4 +224,1775 @@ async fn subagent_spawn_then_poll_waits_until_complete() {
     assert_eq!(poll_output["agent_id"], agent_id);
     assert_eq!(poll_output["status"], "complete");
     assert_eq!(poll_output["final_output"], "Subagent output");
+    let elapsed_ms = poll_output["elapsed_ms"].as_u64().expect("elapsed_ms");
+    let running_ms = poll_output["running_ms"].as_u64().expect("running_ms");
+    assert!(running_ms <= elapsed_ms, "{poll_output}");
+    assert_eq!(
+        poll_output["tokens"],
+        serde_json::json!({ "input": 42, "output": 0, "total": 42 })
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn custom_agent_tools_allowlist_rejects_other_tools() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+
+    // Main request 1: spawn the custom agent by name.
+    let spawn_call_id = "call-spawn-named-1";
+    let poll_call_id = "call-poll-named-1";
+    let spawn_args = serde_json::json!({
+        "name": "reader",
+        "prompt": "Read the README.",
+        "agent_id": "reader-1",
+    })
+    .to_string();
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-allowlist-test"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn_named", &spawn_args),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+
+    // Subagent request 1: try a tool outside the allowlist.
+    let shell_call_id = "call-sub-shell-1";
+    let shell_args = serde_json::json!({ "command": "echo hi" }).to_string();
+    let subagent_1 = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "reader"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_function_call(shell_call_id, "shell_command", &shell_args),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    // Subagent request 2: finish after seeing the rejection.
+    let subagent_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(shell_call_id),
+        sse(vec![
+            ev_response_created("resp-sub-2"),
+            ev_assistant_message("msg-sub-2", "done reading"),
+            ev_completed("resp-sub-2"),
+        ]),
+    )
+    .await;
+
+    // Main request 2: wait for the agent to finish.
+    let poll_args = serde_json::json!({ "agent_id": "reader-1", "await_ms": 5000 }).to_string();
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(spawn_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(poll_call_id, "subagent_poll", &poll_args),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+
+    // Main request 3: finish the turn.
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(poll_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_assistant_message("msg-main-3", "done"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+            let agents_dir = config.codex_home.join("agents");
+            std::fs::create_dir_all(&agents_dir).expect("create agents dir");
+            std::fs::write(
+                agents_dir.join("reader.md"),
+                "---\nmode: general\ntools:\n  - read_file\n---\nOnly read files.",
+            )
+            .expect("write agent");
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-allowlist-test".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    // Only allowlisted tools are offered to the subagent.
+    let first = subagent_request(&subagent_1, "reader");
+    let tools: Vec<String> = first.body_json()["tools"]
+        .as_array()
+        .expect("tools array")
+        .iter()
+        .filter_map(|tool| tool.get("name").and_then(|name| name.as_str()))
+        .map(str::to_string)
+        .collect();
+    assert!(
+        !tools.iter().any(|name| name == "shell_command"),
+        "{tools:?}"
+    );
+
+    // A call to a tool outside the allowlist is rejected.
+    let shell_output = subagent_2
+        .function_call_output_text(shell_call_id)
+        .expect("shell call output");
+    assert_eq!(shell_output, "unsupported call: shell_command");
+
+    let poll_output = parse_tool_output_json(&main_3, poll_call_id);
+    assert_eq!(poll_output["status"], "complete");
+    assert_eq!(poll_output["final_output"], "done reading");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn custom_agent_model_override_is_sent_to_provider() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+
+    // Main request 1: spawn the custom agent by name.
+    let spawn_call_id = "call-spawn-model-1";
+    let poll_call_id = "call-poll-model-1";
+    let spawn_args = serde_json::json!({
+        "name": "scout",
+        "prompt": "Map the repo.",
+        "agent_id": "scout-1",
+    })
+    .to_string();
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-model-override-test"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn_named", &spawn_args),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+
+    // Subagent request: runs on the agent's model.
+    let subagent = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "scout"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "mapped"),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    // Main request 2: wait for the agent to finish.
+    let poll_args = serde_json::json!({ "agent_id": "scout-1", "await_ms": 5000 }).to_string();
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(spawn_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(poll_call_id, "subagent_poll", &poll_args),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+
+    // Main request 3: finish the turn.
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(poll_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_assistant_message("msg-main-3", "done"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+            let agents_dir = config.codex_home.join("agents");
+            std::fs::create_dir_all(&agents_dir).expect("create agents dir");
+            std::fs::write(
+                agents_dir.join("scout.md"),
+                "---\nmodel: gpt-5.1-codex-mini\n---\nMap the repo.",
+            )
+            .expect("write agent");
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-model-override-test".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    assert_eq!(
+        subagent_request(&subagent, "scout").body_json()["model"],
+        "gpt-5.1-codex-mini"
+    );
+    let poll_output = parse_tool_output_json(&main_3, poll_call_id);
+    assert_eq!(poll_output["status"], "complete");
+    assert_eq!(poll_output["final_output"], "mapped");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subagent_with_past_deadline_never_calls_the_model() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let spawn_call_id = "call-spawn-deadline";
+    let poll_call_id = "call-poll-deadline";
+    let agent_id = "agent-deadline";
+    let label = "deadline-test";
+
+    let spawn_args = serde_json::json!({
+        "agent_id": agent_id,
+        "label": label,
+        "prompt": "This should never reach the model.",
+        "deadline_unix_ms": 1_000,
+    })
+    .to_string();
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-deadline-test"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn", &spawn_args),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+
+    let subagent_mock = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", label),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "too late"),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    let poll_args = serde_json::json!({
+        "agent_id": agent_id,
+        "await_ms": 5000,
+    })
+    .to_string();
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(spawn_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(poll_call_id, "subagent_poll", &poll_args),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(poll_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_assistant_message("msg-main-3", "done"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-deadline-test".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let poll_output = parse_tool_output_json(&main_3, poll_call_id);
+    assert_eq!(poll_output["status"], "aborted");
+    assert_eq!(
+        poll_output["recent_events"],
+        serde_json::json!(["aborted: deadline passed before the agent started"])
+    );
+    assert!(
+        !subagent_mock
+            .requests()
+            .iter()
+            .any(|req| req.header("x-openai-subagent").as_deref() == Some(label))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subagent_resume_accepts_the_subagent_spawn_arguments() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    let day_dir = test.home.path().join("sessions/2025/01/01");
+    std::fs::create_dir_all(&day_dir).expect("create sessions dir");
+    let rollout = day_dir.join("rollout-2025-01-01T00-00-00-resume.jsonl");
+    std::fs::write(&rollout, "").expect("write rollout");
+    let rollout = rollout.display().to_string();
+
+    // The same spawn-only arguments (dry_run, reasoning_effort, priority,
+    // depends_on) through both entry points; only the rollout key differs.
+    let spawn_call_id = "call-spawn-resume";
+    let resume_call_id = "call-resume-alias";
+    let shared = serde_json::json!({
+        "agent_id": "resumer-1",
+        "label": "resumer",
+        "mode": "explore",
+        "prompt": "Pick up where you left off.",
+        "dry_run": true,
+        "reasoning_effort": "low",
+        "priority": 2,
+        "depends_on": [],
+    });
+    let mut spawn_args = shared.clone();
+    spawn_args["resume_rollout_path"] = serde_json::json!(rollout);
+    let mut resume_args = shared;
+    resume_args["rollout_path"] = serde_json::json!(rollout);
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-resume-alias-test"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn", &spawn_args.to_string()),
+            ev_function_call(resume_call_id, "subagent_resume", &resume_args.to_string()),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+    let main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(resume_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_assistant_message("msg-main-2", "done"),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-resume-alias-test".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let expected = serde_json::json!({
+        "dry_run": true,
+        "agent_id": "resumer-1",
+        "label": "resumer",
+        "mode": "explore",
+        "skills": [],
+    });
+    assert_eq!(parse_tool_output_json(&main_2, spawn_call_id), expected);
+    assert_eq!(parse_tool_output_json(&main_2, resume_call_id), expected);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subagent_spawn_batch_spawns_nothing_when_an_item_is_invalid() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let label = "batch-test";
+
+    // Main request 1: one batch fails in the middle, the other repeats an id.
+    let bad_mode_call_id = "call-batch-bad-mode";
+    let duplicate_call_id = "call-batch-duplicate";
+    let bad_mode_args = serde_json::json!({
+        "items": [
+            { "agent_id": "first", "label": label, "prompt": "one" },
+            { "agent_id": "second", "label": label, "prompt": "two", "mode": "bogus" },
+            { "agent_id": "third", "label": label, "prompt": "three" },
+        ],
+    })
+    .to_string();
+    let duplicate_args = serde_json::json!({
+        "items": [
+            { "agent_id": "dup", "label": label, "prompt": "one" },
+            { "agent_id": "dup", "label": label, "prompt": "two" },
+        ],
+    })
+    .to_string();
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-batch-test"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(bad_mode_call_id, "subagent_spawn_batch", &bad_mode_args),
+            ev_function_call(duplicate_call_id, "subagent_spawn_batch", &duplicate_args),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+
+    let subagent_mock = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", label),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "should not run"),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    // Main request 2: list the session's agents.
+    let list_call_id = "call-list-after-batch";
+    let main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(duplicate_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(list_call_id, "subagent_list", "{}"),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+
+    // Main request 3: finish the turn.
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(list_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_assistant_message("msg-main-3", "done"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-batch-test".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    assert_eq!(
+        parse_tool_output_json(&main_2, bad_mode_call_id),
+        serde_json::json!({
+            "ok": false,
+            "agents": [],
+            "error": {
+                "index": 1,
+                "error": "`mode` must be one of: general, explore, review",
+                "skipped": 2,
+            },
+        })
+    );
+    assert_eq!(
+        parse_tool_output_json(&main_2, duplicate_call_id),
+        serde_json::json!({
+            "ok": false,
+            "agents": [],
+            "error": {
+                "index": 1,
+                "error": "agent_id `dup` is repeated in this batch",
+                "skipped": 1,
+            },
+        })
+    );
+    let list_output = parse_tool_output_json(&main_3, list_call_id);
+    assert_eq!(list_output["agents"], serde_json::json!([]));
+    assert!(
+        !subagent_mock
+            .requests()
+            .iter()
+            .any(|req| req.header("x-openai-subagent").as_deref() == Some(label))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subagent_without_rollout_reports_no_rollout_path() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+
+    let spawn_call_id = "call-spawn-no-rollout";
+    let poll_call_id = "call-poll-no-rollout";
+    let list_call_id = "call-list-no-rollout";
+    let agent_id = "norollout-1";
+    let label = "norollout";
+
+    let spawn_args = serde_json::json!({
+        "agent_id": agent_id,
+        "label": label,
+        "mode": "explore",
+        "prompt": "Answer without tools.",
+        "record_rollout": false,
+    })
+    .to_string();
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-no-rollout"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn", &spawn_args),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+    let _subagent = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", label),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "Subagent output"),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+    let poll_args = serde_json::json!({ "agent_id": agent_id, "await_ms": 5000 }).to_string();
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(spawn_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(poll_call_id, "subagent_poll", &poll_args),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(poll_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_function_call(list_call_id, "subagent_list", "{}"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+    let main_4 = mount_sse_once_match(
+        &server,
+        body_string_contains(list_call_id),
+        sse(vec![
+            ev_response_created("resp-main-4"),
+            ev_assistant_message("msg-main-4", "done"),
+            ev_completed("resp-main-4"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-no-rollout".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let poll_output = parse_tool_output_json(&main_3, poll_call_id);
+    assert_eq!(poll_output["status"], "complete");
+    assert_eq!(poll_output["final_output"], "Subagent output");
+    assert_eq!(poll_output.get("rollout_path"), None, "{poll_output}");
+
+    let list_output = parse_tool_output_json(&main_4, list_call_id);
+    let agents = list_output["agents"].as_array().expect("agents");
+    assert_eq!(agents.len(), 1, "{list_output}");
+    assert_eq!(agents[0]["agent_id"], agent_id);
+    assert_eq!(agents[0].get("rollout_path"), None, "{list_output}");
+
+    // Only the parent session wrote a rollout file.
+    let mut rollouts = Vec::new();
+    let mut dirs = vec![test.home.path().join("sessions")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).expect("read sessions dir") {
+            let path = entry.expect("dir entry").path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                rollouts.push(path);
+            }
+        }
+    }
+    assert_eq!(rollouts.len(), 1, "{rollouts:?}");
+}
+
+const OVERFLOW_MAX_OUTPUT_CHARS: usize = 64;
+const OVERFLOW_SUMMARY_PROMPT: &str = "-byte limit. Rewrite it";
+
+/// Runs one subagent whose answer is over `max_output_chars` with
+/// `overflow = "summarize"`, answering the summary call with `summary_body`.
+/// Returns the agent's final output, the report it wrote, and how many
+/// summary calls were made.
+#[expect(clippy::expect_used)]
+async fn run_overflow_subagent(
+    spawn_extra: serde_json::Value,
+    summary_body: String,
+) -> (serde_json::Value, String, usize) {
+    let server = start_mock_server().await;
+
+    let spawn_call_id = "call-spawn-overflow";
+    let poll_call_id = "call-poll-overflow";
+    let agent_id = "overflow-1";
+    let label = "overflow";
+    let report = "Finding: the cache is never invalidated. ".repeat(5);
+
+    let mut spawn_args = serde_json::json!({
+        "agent_id": agent_id,
+        "label": label,
+        "mode": "explore",
+        "prompt": "Write a long report.",
+    });
+    if let (Some(args), Some(extra)) = (spawn_args.as_object_mut(), spawn_extra.as_object()) {
+        args.extend(extra.clone());
+    }
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-overflow"),
+        sse(vec![
+            ev_response_created("resp-main-1"),
+            ev_function_call(spawn_call_id, "subagent_spawn", &spawn_args.to_string()),
+            ev_completed("resp-main-1"),
+        ]),
+    )
+    .await;
+    let _subagent = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", label),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", &report),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+    let summary = mount_sse_once_match(
+        &server,
+        body_string_contains(OVERFLOW_SUMMARY_PROMPT),
+        summary_body,
+    )
+    .await;
+    let poll_args = serde_json::json!({ "agent_id": agent_id, "await_ms": 5000 }).to_string();
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains(spawn_call_id),
+        sse(vec![
+            ev_response_created("resp-main-2"),
+            ev_function_call(poll_call_id, "subagent_poll", &poll_args),
+            ev_completed("resp-main-2"),
+        ]),
+    )
+    .await;
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains(poll_call_id),
+        sse(vec![
+            ev_response_created("resp-main-3"),
+            ev_assistant_message("msg-main-3", "done"),
+            ev_completed("resp-main-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+            config.subagents.overflow = OutputOverflow::Summarize;
+            config.subagents.max_output_chars = OVERFLOW_MAX_OUTPUT_CHARS;
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-overflow".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let poll_output = parse_tool_output_json(&main_3, poll_call_id);
+    assert_eq!(poll_output["status"], "complete", "{poll_output}");
+    // The mock also sees requests that fall through to later mocks.
+    let summary_calls = summary
+        .requests()
+        .iter()
+        .filter(|req| {
+            req.message_input_texts("user")
+                .iter()
+                .any(|text| text.contains(OVERFLOW_SUMMARY_PROMPT))
+        })
+        .count();
+    (poll_output["final_output"].clone(), report, summary_calls)
+}
+
+fn summary_sse(text: &str) -> String {
+    sse(vec![
+        ev_response_created("resp-summary"),
+        ev_assistant_message("msg-summary", text),
+        ev_completed("resp-summary"),
+    ])
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn oversized_subagent_output_is_replaced_by_a_summary() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let (final_output, _report, summary_calls) = run_overflow_subagent(
+        serde_json::json!({}),
+        summary_sse("Cache is never invalidated."),
+    )
+    .await;
+
+    assert_eq!(final_output, "Cache is never invalidated.");
+    assert_eq!(summary_calls, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn failed_or_empty_summary_falls_back_to_truncation() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    for summary_body in [
+        sse_failed("resp-summary", "server_error", "summary failed"),
+        summary_sse("   "),
+    ] {
+        let (final_output, report, summary_calls) =
+            run_overflow_subagent(serde_json::json!({}), summary_body).await;
+
+        assert_eq!(final_output, report[..OVERFLOW_MAX_OUTPUT_CHARS]);
+        assert_eq!(summary_calls, 1);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn lazy_output_skips_the_overflow_summary() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let (final_output, _report, summary_calls) = run_overflow_subagent(
+        serde_json::json!({ "lazy_output": true }),
+        summary_sse("unused"),
+    )
+    .await;
+
+    let final_output = final_output.as_str().expect("final_output");
+    assert!(
+        final_output.starts_with("ready (lazy_output"),
+        "{final_output}"
+    );
+    assert_eq!(summary_calls, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn overflow_summary_is_capped_at_max_output_chars() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let long_summary = "Summary: the cache is never invalidated after writes. ".repeat(3);
+    let (final_output, _report, summary_calls) =
+        run_overflow_subagent(serde_json::json!({}), summary_sse(&long_summary)).await;
+
+    assert_eq!(final_output, long_summary[..OVERFLOW_MAX_OUTPUT_CHARS]);
+    assert_eq!(summary_calls, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subagent_without_events_is_stopped_as_idle() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    // The model never answers within the idle window.
+    let _subagent = mount_response_once_match(
+        &server,
+        header("x-openai-subagent", "idler"),
+        sse_response(sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "too late"),
+            ev_completed("resp-sub-1"),
+        ]))
+        .set_delay(std::time::Duration::from_secs(3)),
+    )
+    .await;
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-idle",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "idler-1",
+                    "label": "idler",
+                    "mode": "explore",
+                    "prompt": "Think slowly.",
+                }),
+            ),
+            (
+                "call-poll-idle",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "idler-1", "await_ms": 5000 }),
+            ),
+        ],
+        |config| {
+            config.subagents.idle_timeout = Some(std::time::Duration::from_millis(300));
+        },
+    )
+    .await;
+
+    let poll = parse_json(&outputs[1]);
+    assert_eq!(poll["status"], "error", "{poll}");
+    assert_eq!(poll["error_kind"], "idle_timeout", "{poll}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn delegate_inherits_user_instructions_only_when_asked() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let mut delegates = Vec::new();
+    for label in ["lean", "rich"] {
+        delegates.push(
+            mount_sse_once_match(
+                &server,
+                header("x-openai-subagent", label),
+                sse(vec![
+                    ev_response_created(&format!("resp-{label}")),
+                    ev_assistant_message(&format!("msg-{label}"), "noted"),
+                    ev_completed(&format!("resp-{label}")),
+                ]),
+            )
+            .await,
+        );
+    }
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-delegate-lean",
+                "delegate",
+                serde_json::json!({ "label": "lean", "prompt": "Summarize." }),
+            ),
+            (
+                "call-delegate-rich",
+                "delegate",
+                serde_json::json!({
+                    "label": "rich",
+                    "prompt": "Summarize.",
+                    "inherit_context": true,
+                }),
+            ),
+        ],
+        |config| {
+            config.user_instructions = Some("house rule: tabs only".to_string());
+        },
+    )
+    .await;
+    assert_eq!(outputs, vec!["noted".to_string(), "noted".to_string()]);
+
+    let saw_house_rule = |mock: &ResponseMock, label: &str| {
+        subagent_request(mock, label)
+            .message_input_texts("user")
+            .iter()
+            .any(|text| text.contains("house rule: tabs only"))
+    };
+    assert!(!saw_house_rule(&delegates[0], "lean"));
+    assert!(saw_house_rule(&delegates[1], "rich"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn propose_only_agent_records_patches_without_applying_them() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let patch = "*** Begin Patch\n*** Add File: proposed.txt\n+hello\n*** End Patch";
+    mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "proposer"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_apply_patch_custom_tool_call("call-sub-patch", patch),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+    mount_sse_once_match(
+        &server,
+        body_string_contains("call-sub-patch"),
+        sse(vec![
+            ev_response_created("resp-sub-2"),
+            ev_assistant_message("msg-sub-2", "Proposed adding proposed.txt."),
+            ev_completed("resp-sub-2"),
+        ]),
+    )
+    .await;
+
+    let (test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-propose",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "proposer-1",
+                    "label": "proposer",
+                    "mode": "general",
+                    "propose_only": true,
+                    "prompt": "Add proposed.txt.",
+                }),
+            ),
+            (
+                "call-poll-propose",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "proposer-1", "await_ms": 5000 }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    let poll = parse_json(&outputs[1]);
+    assert_eq!(poll["status"], "complete", "{poll}");
+    assert_eq!(poll["proposed_patches"][0]["call_id"], "call-sub-patch");
+    let file = &poll["proposed_patches"][0]["files"][0];
+    assert_eq!(file["kind"], "add", "{poll}");
+    assert_eq!(file["diff"], "hello\n", "{poll}");
+    assert!(
+        file["path"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("proposed.txt")),
+        "{poll}"
+    );
+    assert!(!test.cwd_path().join("proposed.txt").exists());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn poll_returns_structured_events_only_when_asked() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "narrator"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "All done."),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-narrator",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "narrator-1",
+                    "label": "narrator",
+                    "mode": "explore",
+                    "prompt": "Report back.",
+                }),
+            ),
+            (
+                "call-poll-structured",
+                "subagent_poll",
+                serde_json::json!({
+                    "agent_id": "narrator-1",
+                    "await_ms": 5000,
+                    "structured_events": true,
+                }),
+            ),
+            (
+                "call-poll-text",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "narrator-1" }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    let structured = parse_json(&outputs[1]);
+    let events = structured["recent_events"].as_array().expect("events");
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["kind"].as_str().expect("kind"))
+        .collect();
+    assert_eq!(kinds, vec!["status", "message", "status", "summary"]);
+    for (seq, event) in events.iter().enumerate() {
+        assert_eq!(event["seq"], seq as u64, "{event}");
+        assert!(
+            event["ts"].is_string() && event["at_ms"].is_u64(),
+            "{event}"
+        );
+    }
+    assert_eq!(events[1]["message"], "All done.");
+    assert_eq!(events[3]["summary"]["status"], "complete");
+
+    // Without the flag the same events come back as their messages.
+    let text = parse_json(&outputs[2]);
+    let messages: Vec<&serde_json::Value> = events.iter().map(|event| &event["message"]).collect();
+    assert_eq!(
+        text["recent_events"]
+            .as_array()
+            .expect("events")
+            .iter()
+            .collect::<Vec<_>>(),
+        messages
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn cache_info_lists_deduplicated_spawns_until_cleared() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    // Keep the first agent running while the duplicate spawn arrives.
+    let _subagent = mount_response_once_match(
+        &server,
+        header("x-openai-subagent", "scanner"),
+        sse_response(sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "scanned"),
+            ev_completed("resp-sub-1"),
+        ]))
+        .set_delay(std::time::Duration::from_secs(2)),
+    )
+    .await;
+
+    let spawn = serde_json::json!({
+        "label": "scanner",
+        "mode": "explore",
+        "prompt": "Scan the repo.",
+    });
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            ("call-spawn-first", "subagent_spawn", spawn.clone()),
+            ("call-spawn-again", "subagent_spawn", spawn),
+            (
+                "call-cache-info",
+                "subagent_cache_info",
+                serde_json::json!({}),
+            ),
+            (
+                "call-cache-clear",
+                "subagent_cache_clear",
+                serde_json::json!({}),
+            ),
+            (
+                "call-cache-empty",
+                "subagent_cache_info",
+                serde_json::json!({}),
+            ),
+        ],
+        |config| {
+            config.subagents.dedupe_spawns = true;
+        },
+    )
+    .await;
+
+    let first = parse_json(&outputs[0]);
+    let again = parse_json(&outputs[1]);
+    assert_eq!(again["agent_id"], first["agent_id"], "{again}");
+    assert_eq!(again["deduplicated"], true, "{again}");
+
+    let info = parse_json(&outputs[2]);
+    let table = &info["tables"][0];
+    assert_eq!(table["name"], "dedup", "{info}");
+    assert_eq!(table["size"], 1, "{info}");
+    assert_eq!(table["entries"][0]["agent_id"], first["agent_id"], "{info}");
+    assert_eq!(table["entries"][0]["hits"], 1, "{info}");
+
+    assert_eq!(parse_json(&outputs[3])["cleared"], 1);
+    let empty = parse_json(&outputs[4]);
+    assert_eq!(empty["tables"][0]["size"], 0, "{empty}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn join_messages_keeps_every_message_of_the_final_turn() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    for (join_messages, expected) in [
+        (false, "Second part."),
+        (true, "First part.\n\nSecond part."),
+    ] {
+        let server = start_mock_server().await;
+        mount_sse_once_match(
+            &server,
+            header("x-openai-subagent", "writer"),
+            sse(vec![
+                ev_response_created("resp-sub-1"),
+                ev_assistant_message("msg-sub-1", "First part."),
+                ev_assistant_message("msg-sub-2", "Second part."),
+                ev_completed("resp-sub-1"),
+            ]),
+        )
+        .await;
+
+        let (_test, outputs) = run_tool_calls(
+            &server,
+            &[
+                (
+                    "call-spawn-writer",
+                    "subagent_spawn",
+                    serde_json::json!({
+                        "agent_id": "writer-1",
+                        "label": "writer",
+                        "mode": "explore",
+                        "prompt": "Answer in two parts.",
+                    }),
+                ),
+                (
+                    "call-poll-writer",
+                    "subagent_poll",
+                    serde_json::json!({ "agent_id": "writer-1", "await_ms": 5000 }),
+                ),
+            ],
+            move |config| {
+                config.subagents.join_messages = join_messages;
+            },
+        )
+        .await;
+
+        let poll = parse_json(&outputs[1]);
+        assert_eq!(poll["final_output"], expected, "{poll}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn confidence_marker_is_requested_only_when_asked_and_stripped() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    for report_confidence in [false, true] {
+        let server = start_mock_server().await;
+        let subagent = mount_sse_once_match(
+            &server,
+            header("x-openai-subagent", "ranker"),
+            sse(vec![
+                ev_response_created("resp-sub-1"),
+                ev_assistant_message("msg-sub-1", "Use the cache.\n\n<<confidence: 0.8>>"),
+                ev_completed("resp-sub-1"),
+            ]),
+        )
+        .await;
+
+        let (_test, outputs) = run_tool_calls(
+            &server,
+            &[
+                (
+                    "call-spawn-ranker",
+                    "subagent_spawn",
+                    serde_json::json!({
+                        "agent_id": "ranker-1",
+                        "label": "ranker",
+                        "mode": "explore",
+                        "prompt": "Pick an approach.",
+                        "report_confidence": report_confidence,
+                    }),
+                ),
+                (
+                    "call-poll-ranker",
+                    "subagent_poll",
+                    serde_json::json!({ "agent_id": "ranker-1", "await_ms": 5000 }),
+                ),
+                (
+                    "call-ranked",
+                    "subagent_results_ranked",
+                    serde_json::json!({}),
+                ),
+            ],
+            |_| {},
+        )
+        .await;
+
+        let instructions = subagent_request(&subagent, "ranker")
+            .body_json()
+            .to_string();
+        assert_eq!(
+            instructions.contains("end your final answer with `<<confidence: X>>`"),
+            report_confidence
+        );
+        let poll = parse_json(&outputs[1]);
+        let ranked = parse_json(&outputs[2]);
+        if report_confidence {
+            assert_eq!(poll["final_output"], "Use the cache.", "{poll}");
+            assert_eq!(poll["confidence"], 0.8, "{poll}");
+            assert!(!poll.to_string().contains("<<confidence"), "{poll}");
+            assert_eq!(ranked["results"][0]["final_output"], "Use the cache.");
+            assert_eq!(ranked["results"][0]["confidence"], 0.8);
+        } else {
+            assert_eq!(poll["confidence"], serde_json::Value::Null, "{poll}");
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn depends_on_accepts_raw_agent_ids() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    for (label, answer) in [("scan", "Scanned."), ("report", "Reported.")] {
+        mount_sse_once_match(
+            &server,
+            header("x-openai-subagent", label),
+            sse(vec![
+                ev_response_created(&format!("resp-{label}")),
+                ev_assistant_message(&format!("msg-{label}"), answer),
+                ev_completed(&format!("resp-{label}")),
+            ]),
+        )
+        .await;
+    }
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-scan",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "scan-a",
+                    "label": "scan",
+                    "mode": "explore",
+                    "prompt": "Scan the tree.",
+                }),
+            ),
+            (
+                "call-spawn-report",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "report-a",
+                    "label": "report",
+                    "mode": "explore",
+                    "prompt": "Report on the scan.",
+                    "depends_on": ["Scan-A"],
+                }),
+            ),
+            (
+                "call-poll-report",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "report-a", "await_ms": 5000 }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    assert_eq!(
+        parse_json(&outputs[1])["status"],
+        "queued",
+        "{}",
+        outputs[1]
+    );
+    let poll = parse_json(&outputs[2]);
+    assert_eq!(poll["status"], "complete", "{poll}");
+    assert_eq!(poll["final_output"], "Reported.", "{poll}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn source_category_replaces_the_label_in_the_subagent_header() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let mut subagents = Vec::new();
+    for source in ["security-scan", "plain"] {
+        subagents.push(
+            mount_sse_once_match(
+                &server,
+                header("x-openai-subagent", source),
+                sse(vec![
+                    ev_response_created(&format!("resp-{source}")),
+                    ev_assistant_message(&format!("msg-{source}"), "ok"),
+                    ev_completed(&format!("resp-{source}")),
+                ]),
+            )
+            .await,
+        );
+    }
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-categorized",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "categorized",
+                    "label": "scan-auth",
+                    "source_category": "security-scan",
+                    "mode": "explore",
+                    "prompt": "Scan auth.",
+                }),
+            ),
+            (
+                "call-spawn-plain",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "plain",
+                    "label": "plain",
+                    "mode": "explore",
+                    "prompt": "Scan the rest.",
+                }),
+            ),
+            (
+                "call-wait-categorized",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "categorized", "await_ms": 5000 }),
+            ),
+            (
+                "call-wait-plain",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "plain", "await_ms": 5000 }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    for output in &outputs[2..] {
+        let poll = parse_json(output);
+        assert_eq!(poll["status"], "complete", "{poll}");
+    }
+    // The label still names the agent; only the request tag changes.
+    assert_eq!(parse_json(&outputs[2])["label"], "scan-auth");
+    subagent_request(&subagents[0], "security-scan");
+    subagent_request(&subagents[1], "plain");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn poll_reports_prompt_latency_breakdown() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let _subagent = mount_response_once_match(
+        &server,
+        header("x-openai-subagent", "timed"),
+        sse_response(sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "done"),
+            ev_completed("resp-sub-1"),
+        ]))
+        .set_delay(std::time::Duration::from_millis(300)),
+    )
+    .await;
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-timed",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "timed-1",
+                    "label": "timed",
+                    "mode": "explore",
+                    "prompt": "Answer.",
+                }),
+            ),
+            (
+                "call-poll-timed",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "timed-1", "await_ms": 5000 }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    let poll = parse_json(&outputs[1]);
+    assert_eq!(poll["status"], "complete", "{poll}");
+    let ms = |field: &str| {
+        poll[field]
+            .as_u64()
+            .unwrap_or_else(|| panic!("missing {field}: {poll}"))
+    };
+    // The provider delay shows up as time to first token, which never
+    // exceeds the total or the agent's age.
+    assert!(ms("ttft_ms") >= 300, "{poll}");
+    assert!(ms("ttft_ms") <= ms("total_ms"), "{poll}");
+    assert!(ms("total_ms") <= ms("elapsed_ms"), "{poll}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn lazy_output_is_materialized_from_the_rollout_on_demand() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "lazy"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "The full answer."),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-lazy",
+                "subagent_spawn",
+                serde_json::json!({
+                    "agent_id": "lazy-1",
+                    "label": "lazy",
+                    "mode": "explore",
+                    "lazy_output": true,
+                    "prompt": "Answer.",
+                }),
+            ),
+            (
+                "call-poll-lazy",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "lazy-1", "await_ms": 5000 }),
+            ),
+            (
+                "call-result-marker",
+                "subagent_result",
+                serde_json::json!({ "agent_id": "lazy-1" }),
+            ),
+            (
+                "call-result-full",
+                "subagent_result",
+                serde_json::json!({ "agent_id": "lazy-1", "materialize": true }),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    let poll = parse_json(&outputs[1]);
+    assert_eq!(poll["status"], "complete", "{poll}");
+    let marker = poll["final_output"].as_str().expect("final_output");
+    assert!(marker.starts_with("ready (lazy_output"), "{marker}");
+
+    let unmaterialized = parse_json(&outputs[2]);
+    assert_eq!(unmaterialized["final_output"], marker, "{unmaterialized}");
+    assert_eq!(unmaterialized["materialized"], false, "{unmaterialized}");
+
+    let full = parse_json(&outputs[3]);
+    assert_eq!(full["final_output"], "The full answer.", "{full}");
+    assert_eq!(full["materialized"], true, "{full}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn trace_id_is_sent_on_subagent_requests_and_validated() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let subagent = mount_sse_once_match(
+        &server,
+        header("x-openai-subagent", "traced"),
+        sse(vec![
+            ev_response_created("resp-sub-1"),
+            ev_assistant_message("msg-sub-1", "ok"),
+            ev_completed("resp-sub-1"),
+        ]),
+    )
+    .await;
+
+    let spawn = |agent_id: &str, trace_id: &str| {
+        serde_json::json!({
+            "agent_id": agent_id,
+            "label": "traced",
+            "mode": "explore",
+            "prompt": "Answer.",
+            "trace_id": trace_id,
+        })
+    };
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-traced",
+                "subagent_spawn",
+                spawn("traced-1", "trace-0af7651916cd43dd"),
+            ),
+            (
+                "call-poll-traced",
+                "subagent_poll",
+                serde_json::json!({ "agent_id": "traced-1", "await_ms": 5000 }),
+            ),
+            (
+                "call-spawn-crlf",
+                "subagent_spawn",
+                spawn("crlf", "trace\r\nx-injected: 1"),
+            ),
+            (
+                "call-spawn-long",
+                "subagent_spawn",
+                spawn("long", &"t".repeat(129)),
+            ),
+        ],
+        |_| {},
+    )
+    .await;
+
+    assert_eq!(parse_json(&outputs[1])["status"], "complete");
+    assert_eq!(
+        subagent_request(&subagent, "traced")
+            .header("x-codex-trace-id")
+            .as_deref(),
+        Some("trace-0af7651916cd43dd")
+    );
+    assert_eq!(
+        outputs[2],
+        "trace_id must be printable ASCII (no CR/LF or control characters)"
+    );
+    assert_eq!(outputs[3], "trace_id must be at most 128 characters");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn custom_agents_list_reports_agents_and_load_errors() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let (test, outputs) = run_tool_calls(
+        &server,
+        &[(
+            "call-agents-list",
+            "custom_agents_list",
+            serde_json::json!({}),
+        )],
+        |config| {
+            let user_dir = config.codex_home.join("agents");
+            std::fs::create_dir_all(&user_dir).expect("create agents dir");
+            std::fs::write(
+                user_dir.join("scout.md"),
+                "---\ndescription: Maps the repo\nmode: explore\n---\nMap the repo.",
+            )
+            .expect("write agent");
+            std::fs::write(
+                user_dir.join("broken.md"),
+                "---\nmode: explore\ntools:\n  - shell\n---\nNever loads.",
+            )
+            .expect("write agent");
+
+            let status = std::process::Command::new("git")
+                .arg("init")
+                .current_dir(&config.cwd)
+                .output()
+                .expect("git init")
+                .status;
+            assert!(status.success());
+            let repo_dir = config.cwd.join(".codex/agents");
+            std::fs::create_dir_all(&repo_dir).expect("create agents dir");
+            std::fs::write(
+                repo_dir.join("fixer.md"),
+                "---\nmode: general\n---\nFix it.",
+            )
+            .expect("write agent");
+        },
+    )
+    .await;
+
+    let list = parse_json(&outputs[0]);
+    let mut agents: Vec<(&str, &str, &str)> = list["agents"]
+        .as_array()
+        .expect("agents")
+        .iter()
+        .map(|agent| {
+            (
+                agent["name"].as_str().expect("name"),
+                agent["scope"].as_str().expect("scope"),
+                agent["mode"].as_str().expect("mode"),
+            )
+        })
+        .collect();
+    agents.sort_unstable();
+    assert_eq!(
+        agents,
+        vec![("fixer", "repo", "general"), ("scout", "user", "explore")],
+        "{list}"
+    );
+
+    let errors = list["errors"].as_array().expect("errors");
+    assert_eq!(errors.len(), 1, "{list}");
+    assert_eq!(
+        errors[0]["path"],
+        test.home
+            .path()
+            .join("agents/broken.md")
+            .display()
+            .to_string()
+    );
+    assert_eq!(
+        errors[0]["message"],
+        "mode `explore` disables tools listed in `tools`: shell"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn spawn_overrides_event_and_output_limits_per_agent() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    let server = start_mock_server().await;
+    let long_answer = "x".repeat(1500);
+    for label in ["roomy", "default"] {
+        mount_sse_once_match(
+            &server,
+            header("x-openai-subagent", label),
+            sse(vec![
+                ev_response_created(&format!("resp-{label}")),
+                ev_assistant_message(&format!("msg-{label}"), &long_answer),
+                ev_completed(&format!("resp-{label}")),
+            ]),
+        )
+        .await;
+    }
+
+    let spawn = |label: &str, limits: serde_json::Value| {
+        let mut args = serde_json::json!({
+            "agent_id": label,
+            "label": label,
+            "mode": "explore",
+            "prompt": "Answer at length.",
+        });
+        args.as_object_mut()
+            .expect("object")
+            .extend(limits.as_object().expect("object").clone());
+        args
+    };
+    let poll = |agent_id: &str| serde_json::json!({ "agent_id": agent_id, "await_ms": 5000 });
+    let (_test, outputs) = run_tool_calls(
+        &server,
+        &[
+            (
+                "call-spawn-roomy",
+                "subagent_spawn",
+                spawn(
+                    "roomy",
+                    serde_json::json!({ "max_events": 1, "max_output_chars": 2048 }),
+                ),
+            ),
+            (
+                "call-spawn-default",
+                "subagent_spawn",
+                spawn("default", serde_json::json!({})),
+            ),
+            ("call-poll-roomy", "subagent_poll", poll("roomy")),
+            ("call-poll-default", "subagent_poll", poll("default")),
+            (
+                "call-spawn-no-events",
+                "subagent_spawn",
+                spawn("no-events", serde_json::json!({ "max_events": 0 })),
+            ),
+            (
+                "call-spawn-tiny",
+                "subagent_spawn",
+                spawn("tiny", serde_json::json!({ "max_output_chars": 10 })),
+            ),
+        ],
+        |config| {
+            config.subagents.max_output_chars = 1024;
+        },
+    )
+    .await;
+
+    // The override outlives the config-wide cap, including in poll output.
+    let roomy = parse_json(&outputs[2]);
+    assert_eq!(roomy["final_output"], long_answer.as_str(), "{roomy}");
+    let events = roomy["recent_events"].as_array().expect("events");
+    assert_eq!(events.len(), 1, "{roomy}");
+    assert!(
+        events[0]
+            .as_str()
+            .is_some_and(|event| event.starts_with("summary:")),
+        "{roomy}"
+    );
+
+    let default = parse_json(&outputs[3]);
+    assert_eq!(default["final_output"], &long_answer[..1024], "{default}");
+    assert_eq!(default["recent_events"].as_array().map(Vec::len), Some(4));
+
+    assert_eq!(outputs[4], "`max_events` must be at least 1");
+    assert_eq!(outputs[5], "`max_output_chars` must be at least 1024");
 }
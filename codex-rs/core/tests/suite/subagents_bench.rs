@@ -168,3 +168,186 @@ async fn bench_subagents_spawn_poll_16() {
     #[cfg(not(unix))]
     eprintln!("bench_subagents_spawn_poll_16: {}ms", wall.as_millis());
 }
+
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let idx = ((sorted.len() - 1) as f64 * pct).round() as usize;
+    sorted[idx.min(sorted.len() - 1)]
+}
+
+/// Spawns agents with tight, staggered deadlines against a slow provider and
+/// reports how many completed vs timed out, plus run time relative to each
+/// agent's deadline.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[ignore]
+async fn bench_subagents_deadlines_16() {
+    skip_if_no_network!();
+    skip_if_sandbox!();
+
+    const N: usize = 16;
+    const PROVIDER_DELAY_MS: u64 = 400;
+    let timeout_ms = |i: usize| 100 + 100 * i as u64;
+    let server = start_mock_server().await;
+
+    let subagent_body = sse(vec![
+        ev_response_created("resp-sub-deadline"),
+        ev_assistant_message("msg-sub-deadline", "ok"),
+        ev_completed("resp-sub-deadline"),
+    ]);
+    Mock::given(method("POST"))
+        .and(path_regex(".*/responses$"))
+        .and(header_exists("x-openai-subagent"))
+        .respond_with(
+            sse_response(subagent_body)
+                .set_delay(std::time::Duration::from_millis(PROVIDER_DELAY_MS)),
+        )
+        .up_to_n_times((N * 4) as u64)
+        .mount(&server)
+        .await;
+
+    let mut spawn_events = vec![ev_response_created("resp-main-deadline-1")];
+    for i in 0..N {
+        let args = serde_json::json!({
+            "agent_id": format!("deadline-agent-{i}"),
+            "label": "deadline",
+            "mode": "explore",
+            "prompt": "return ok",
+            "timeout_ms": timeout_ms(i),
+        })
+        .to_string();
+        spawn_events.push(ev_function_call(
+            &format!("call-spawn-{i}"),
+            "subagent_spawn",
+            &args,
+        ));
+    }
+    spawn_events.push(ev_completed("resp-main-deadline-1"));
+    let _main_1 = mount_sse_once_match(
+        &server,
+        body_string_contains("trigger-subagent-deadlines"),
+        sse(spawn_events),
+    )
+    .await;
+
+    let mut poll_events = vec![ev_response_created("resp-main-deadline-2")];
+    for i in 0..N {
+        let args = serde_json::json!({
+            "agent_id": format!("deadline-agent-{i}"),
+            "await_ms": 10_000,
+            "structured_events": true,
+        })
+        .to_string();
+        poll_events.push(ev_function_call(
+            &format!("call-poll-{i}"),
+            "subagent_poll",
+            &args,
+        ));
+    }
+    poll_events.push(ev_completed("resp-main-deadline-2"));
+    let _main_2 = mount_sse_once_match(
+        &server,
+        body_string_contains("call-spawn-0"),
+        sse(poll_events),
+    )
+    .await;
+
+    let main_3 = mount_sse_once_match(
+        &server,
+        body_string_contains("call-poll-0"),
+        sse(vec![
+            ev_response_created("resp-main-deadline-3"),
+            ev_assistant_message("msg-main-deadline-3", "done"),
+            ev_completed("resp-main-deadline-3"),
+        ]),
+    )
+    .await;
+
+    let mut builder = test_codex()
+        .with_model("gpt-5.1-codex")
+        .with_config(|config| {
+            config.features.enable(Feature::Subagents);
+        });
+    let test = builder.build(&server).await.expect("build test codex");
+
+    let start = Instant::now();
+    test.codex
+        .submit(Op::UserInput {
+            items: vec![codex_protocol::user_input::UserInput::Text {
+                text: "trigger-subagent-deadlines".to_string(),
+            }],
+        })
+        .await
+        .expect("submit");
+    wait_for_event(&test.codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    let wall = start.elapsed();
+
+    let mut completed = 0;
+    let mut timed_out = 0;
+    let mut other = 0;
+    let mut completed_ratios = Vec::new();
+    let mut timed_out_ratios = Vec::new();
+    for i in 0..N {
+        let call_id = format!("call-poll-{i}");
+        let text = main_3
+            .function_call_output_text(&call_id)
+            .unwrap_or_else(|| panic!("missing poll output for {call_id}"));
+        let poll: serde_json::Value = serde_json::from_str(&text).expect("poll JSON");
+        let events = poll["recent_events"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let ts = |event: &serde_json::Value| {
+            event["ts"]
+                .as_str()
+                .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+        };
+        let started = events
+            .iter()
+            .find(|event| event["message"] == "running")
+            .and_then(ts);
+        let finished = events.last().and_then(ts);
+        let run_ms = match (started, finished) {
+            (Some(started), Some(finished)) => (finished - started).num_milliseconds() as f64,
+            _ => 0.0,
+        };
+        let ratio = run_ms / timeout_ms(i) as f64;
+        let is_timeout = events.iter().any(|event| {
+            event["message"]
+                .as_str()
+                .is_some_and(|message| message.starts_with("timed out"))
+        });
+        match poll["status"].as_str() {
+            Some("complete") => {
+                completed += 1;
+                completed_ratios.push(ratio);
+            }
+            _ if is_timeout => {
+                timed_out += 1;
+                timed_out_ratios.push(ratio);
+            }
+            _ => other += 1,
+        }
+    }
+    completed_ratios.sort_by(f64::total_cmp);
+    timed_out_ratios.sort_by(f64::total_cmp);
+
+    eprintln!(
+        "bench_subagents_deadlines_16: wall={}ms completed={completed} timed_out={timed_out} other={other}",
+        wall.as_millis()
+    );
+    for (name, ratios) in [
+        ("completed", &completed_ratios),
+        ("timed_out", &timed_out_ratios),
+    ] {
+        eprintln!(
+            "  {name}: run/deadline p0={:.2} p50={:.2} p90={:.2} p100={:.2}",
+            percentile(ratios, 0.0),
+            percentile(ratios, 0.5),
+            percentile(ratios, 0.9),
+            percentile(ratios, 1.0),
+        );
+    }
+    assert_eq!(completed + timed_out + other, N);
+}
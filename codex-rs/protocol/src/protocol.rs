@@ -1326,6 +1326,25 @@ pub enum RolloutItem {
     Compacted(CompactedItem),
     TurnContext(TurnContextItem),
     EventMsg(EventMsg),
+    SubagentRegistry(SubagentRegistryItem),
+}
+
+/// Background subagents known to a session. The latest item in a rollout
+/// wins, so a resumed session can still poll or resume agents spawned before
+/// a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema, TS)]
+pub struct SubagentRegistryItem {
+    pub agents: Vec<SubagentRegistryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema, TS)]
+pub struct SubagentRegistryEntry {
+    pub agent_id: String,
+    pub label: String,
+    pub mode: SubagentMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout_path: Option<PathBuf>,
+    pub status: SubagentStatus,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, TS)]
@@ -1763,6 +1782,7 @@ pub enum CustomAgentToolsPolicy {
     Inherit,
     None,
     Allowlist,
+    Denylist,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
@@ -1779,6 +1799,15 @@ pub struct CustomAgentMetadata {
     pub tools_policy: CustomAgentToolsPolicy,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub allowed_tools: Vec<String>,
+    /// Tools removed from the inherited set when `tools_policy` is `denylist`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_tools: Vec<String>,
+    /// Display color: a palette name (`red`, `cyan`, ...) or `#rrggbb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Display icon slug.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
@@ -1828,6 +1857,7 @@ pub struct SkillsListEntry {
 pub enum SubagentMode {
     Explore,
     General,
+    Review,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
@@ -1916,6 +1946,8 @@ pub struct SessionConfiguredEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub initial_messages: Option<Vec<EventMsg>>,
 
+    /// Path of the session's rollout file. Empty when the session does not
+    /// record a rollout (e.g. a subagent spawned with `record_rollout: false`).
     pub rollout_path: PathBuf,
 }
 
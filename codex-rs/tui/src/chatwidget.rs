@@ -3212,6 +3212,7 @@ Constraints:\n\
         match mode {
             SubagentMode::Explore => "explore",
             SubagentMode::General => "general",
+            SubagentMode::Review => "review",
         }
     }
 
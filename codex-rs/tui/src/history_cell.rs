@@ -1353,19 +1353,21 @@ pub(crate) fn new_custom_agents_output(ev: ListCustomAgentsResponseEvent) -> Pla
             let mode_str = match mode {
                 SubagentMode::Explore => "explore",
                 SubagentMode::General => "general",
+                SubagentMode::Review => "review",
             };
             lines.push(vec!["    • Mode: ".dim(), mode_str.into()].into());
         }
 
-        let tools_policy = match agent.tools_policy {
-            CustomAgentToolsPolicy::Inherit => "inherit",
-            CustomAgentToolsPolicy::None => "none",
-            CustomAgentToolsPolicy::Allowlist => "allowlist",
+        let (tools_policy, listed_tools) = match agent.tools_policy {
+            CustomAgentToolsPolicy::Inherit => ("inherit", &agent.allowed_tools),
+            CustomAgentToolsPolicy::None => ("none", &agent.allowed_tools),
+            CustomAgentToolsPolicy::Allowlist => ("allowlist", &agent.allowed_tools),
+            CustomAgentToolsPolicy::Denylist => ("denylist", &agent.denied_tools),
         };
         let mut tools_line: Vec<Span<'static>> = vec!["    • Tools: ".dim(), tools_policy.into()];
-        if !agent.allowed_tools.is_empty() {
+        if !listed_tools.is_empty() {
             tools_line.push(" ".into());
-            tools_line.push(format!("({})", agent.allowed_tools.join(", ")).dim());
+            tools_line.push(format!("({})", listed_tools.join(", ")).dim());
         }
         lines.push(tools_line.into());
 
@@ -1429,6 +1431,7 @@ pub(crate) fn new_subagent_poll_output(subagent: SubagentDetail) -> PlainHistory
     let mode_str = match mode {
         SubagentMode::Explore => "explore",
         SubagentMode::General => "general",
+        SubagentMode::Review => "review",
     };
     let status_str = match status {
         SubagentStatus::Queued => "queued",
@@ -3236,6 +3236,7 @@ Constraints:\n\
         match mode {
             SubagentMode::Explore => "explore",
             SubagentMode::General => "general",
+            SubagentMode::Review => "review",
         }
     }
 